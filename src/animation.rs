@@ -0,0 +1,123 @@
+use {
+    crate::{tracer_struct::Camera, vec3::Vec3},
+    serde::{Deserialize, Serialize},
+    std::ops::{Add, Mul, Sub},
+};
+
+// the camera at one moment of a fly-through, angles are in radians like Camera
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraKeyframe {
+    // seconds from the start of the path
+    pub time: f32,
+    pub position: Vec3,
+    // see Camera::orientation
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        let (yaw, pitch) = camera.orientation();
+        Self {
+            time,
+            position: camera.position,
+            yaw,
+            pitch,
+            fov: camera.fov,
+        }
+    }
+}
+
+impl Default for CameraKeyframe {
+    fn default() -> Self {
+        Self::from_camera(0.0, &Camera::new())
+    }
+}
+
+// keyframes sorted by time, passed through smoothly by a catmull-rom spline
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+// the angle closest to reference that points the same way as angle
+fn unwrap_angle(angle: f32, reference: f32) -> f32 {
+    let tau = 2.0 * std::f32::consts::PI;
+    reference + (angle - reference + std::f32::consts::PI).rem_euclid(tau) - std::f32::consts::PI
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // keeps the keyframes in time order
+    pub fn add_keyframe(&mut self, keyframe: CameraKeyframe) {
+        let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    // the interpolated keyframe at time, held at the ends
+    pub fn sample(&self, time: f32) -> Option<CameraKeyframe> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        let time = time.clamp(keys[0].time, keys[last].time);
+
+        // the segment from keys[i] to keys[i + 1] holding time
+        let i = keys.partition_point(|k| k.time <= time).saturating_sub(1).min(last.saturating_sub(1));
+        let k1 = keys[i];
+        let k2 = keys[(i + 1).min(last)];
+        // the tangents at the ends use the end keys themselves
+        let k0 = keys[i.saturating_sub(1)];
+        let k3 = keys[(i + 2).min(last)];
+
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 0.0 };
+
+        // yaw wraps around, each key is turned to be closest to the one before
+        let yaw1 = k1.yaw;
+        let yaw0 = unwrap_angle(k0.yaw, yaw1);
+        let yaw2 = unwrap_angle(k2.yaw, yaw1);
+        let yaw3 = unwrap_angle(k3.yaw, yaw2);
+
+        Some(CameraKeyframe {
+            time,
+            position: catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            yaw: catmull_rom(yaw0, yaw1, yaw2, yaw3, t),
+            pitch: catmull_rom(k0.pitch, k1.pitch, k2.pitch, k3.pitch, t),
+            fov: catmull_rom(k0.fov, k1.fov, k2.fov, k3.fov, t),
+        })
+    }
+
+    // moves camera to where the path is at time, everything else about it is kept
+    pub fn apply(&self, time: f32, camera: &mut Camera) {
+        if let Some(keyframe) = self.sample(time) {
+            camera.position = keyframe.position;
+            camera.set_orientation(keyframe.yaw, keyframe.pitch);
+            camera.fov = keyframe.fov;
+        }
+    }
+}
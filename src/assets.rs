@@ -0,0 +1,137 @@
+use {
+    crate::file_load::{load_mesh_cached, MeshLoadError},
+    crate::tracer_struct::Triangle,
+    std::collections::hash_map::DefaultHasher,
+    std::collections::HashMap,
+    std::hash::{Hash, Hasher},
+    std::ops::Deref,
+    std::path::PathBuf,
+    std::sync::{Arc, Weak},
+};
+
+// keeps a texture resident in the texture array, its layer can be reused by
+// Gfx::collect_unused_textures once every clone is dropped
+#[derive(Debug, Clone)]
+pub struct TextureHandle(Arc<u32>);
+
+impl TextureHandle {
+    // what materials store in their *_texture fields
+    pub fn id(&self) -> u32 {
+        *self.0
+    }
+}
+
+// triangles of a loaded mesh, shared by every load of the same file
+#[derive(Debug, Clone)]
+pub struct MeshHandle(Arc<Vec<Triangle>>);
+
+impl Deref for MeshHandle {
+    type Target = [Triangle];
+
+    fn deref(&self) -> &[Triangle] {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TextureKey {
+    Path(PathBuf),
+    Content(u64),
+}
+
+// deduplicates images and meshes by path and images also by content, so loading the
+// same file twice (or two files with the same pixels) costs nothing
+// the caches only hold weak references, the handles decide what stays loaded
+#[derive(Default)]
+pub struct AssetManager {
+    textures: HashMap<TextureKey, (u32, Weak<u32>)>,
+    // textures added through the plain id api, they live as long as the manager
+    pinned: Vec<TextureHandle>,
+    meshes: HashMap<(PathBuf, u32), Weak<Vec<Triangle>>>,
+}
+
+// the same file reached through different relative paths shares one entry
+fn canonical_path(filename: &str) -> PathBuf {
+    std::fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename))
+}
+
+fn content_hash(image: &image::RgbaImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.dimensions().hash(&mut hasher);
+    image.as_raw().hash(&mut hasher);
+    hasher.finish()
+}
+
+impl AssetManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn find_texture(&self, key: &TextureKey) -> Option<TextureHandle> {
+        self.textures
+            .get(key)
+            .and_then(|(_, handle)| handle.upgrade())
+            .map(TextureHandle)
+    }
+
+    pub fn find_texture_by_path(&self, filename: &str) -> Option<TextureHandle> {
+        self.find_texture(&TextureKey::Path(canonical_path(filename)))
+    }
+
+    pub fn find_texture_by_content(&self, image: &image::RgbaImage) -> Option<TextureHandle> {
+        self.find_texture(&TextureKey::Content(content_hash(image)))
+    }
+
+    // record a freshly uploaded texture so later loads of the same pixels reuse it
+    pub fn insert_texture(&mut self, image: &image::RgbaImage, id: u32) -> TextureHandle {
+        let handle = TextureHandle(Arc::new(id));
+        self.textures.insert(
+            TextureKey::Content(content_hash(image)),
+            (id, Arc::downgrade(&handle.0)),
+        );
+        handle
+    }
+
+    pub fn alias_texture_path(&mut self, filename: &str, handle: &TextureHandle) {
+        self.textures.insert(
+            TextureKey::Path(canonical_path(filename)),
+            (handle.id(), Arc::downgrade(&handle.0)),
+        );
+    }
+
+    pub fn pin_texture(&mut self, handle: TextureHandle) {
+        if !self.pinned.iter().any(|pinned| pinned.id() == handle.id()) {
+            self.pinned.push(handle);
+        }
+    }
+
+    // forget textures without live handles and return their ids
+    pub fn take_unused_textures(&mut self) -> Vec<u32> {
+        let mut unused = vec![];
+        self.textures.retain(|_, (id, handle)| {
+            let alive = handle.strong_count() > 0;
+            if !alive {
+                unused.push(*id);
+            }
+            alive
+        });
+        // a texture can be cached under both its path and its content
+        unused.sort_unstable();
+        unused.dedup();
+        unused
+    }
+
+    // like load_mesh_cached, but repeated loads share the triangles while a handle is alive
+    pub fn load_mesh(&mut self, filename: &str, material_id: u32) -> Result<MeshHandle, MeshLoadError> {
+        let key = (canonical_path(filename), material_id);
+        if let Some(tris) = self.meshes.get(&key).and_then(Weak::upgrade) {
+            return Ok(MeshHandle(tris));
+        }
+
+        let tris = Arc::new(load_mesh_cached(filename, material_id)?);
+        self.meshes.retain(|_, tris| tris.strong_count() > 0);
+        self.meshes.insert(key, Arc::downgrade(&tris));
+
+        Ok(MeshHandle(tris))
+    }
+}
@@ -0,0 +1,148 @@
+// tileable blue noise masks made with void and cluster (Ulichney 1993), the values of
+// one mask are evenly spread over [0, 1) and neighbours are as different as possible
+// so a few samples per pixel already look like fine grain instead of clumpy noise
+
+// width of the gaussian that measures how crowded a spot is, in pixels
+const SIGMA: f32 = 1.5;
+// the gaussian is cut off past this many pixels
+const RADIUS: i32 = 8;
+
+struct Pattern {
+    size: usize,
+    ones: Vec<bool>,
+    // how crowded every pixel is by the ones around it
+    energy: Vec<f32>,
+    kernel: Vec<f32>,
+}
+
+impl Pattern {
+    fn new(size: usize) -> Self {
+        let side = 2 * RADIUS + 1;
+        let mut kernel = Vec::with_capacity((side * side) as usize);
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let distance_squared = (dx * dx + dy * dy) as f32;
+                kernel.push((-distance_squared / (2.0 * SIGMA * SIGMA)).exp());
+            }
+        }
+
+        Self {
+            size,
+            ones: vec![false; size * size],
+            energy: vec![0.0; size * size],
+            kernel,
+        }
+    }
+
+    // adds or takes away the gaussian of the pixel, wrapping around the edges
+    fn set(&mut self, index: usize, one: bool) {
+        self.ones[index] = one;
+        let sign = if one { 1.0 } else { -1.0 };
+        let size = self.size as i32;
+        let (x, y) = ((index % self.size) as i32, (index / self.size) as i32);
+        let side = 2 * RADIUS + 1;
+        for dy in -RADIUS..=RADIUS {
+            for dx in -RADIUS..=RADIUS {
+                let nx = (x + dx).rem_euclid(size);
+                let ny = (y + dy).rem_euclid(size);
+                let weight = self.kernel[((dy + RADIUS) * side + dx + RADIUS) as usize];
+                self.energy[(ny * size + nx) as usize] += sign * weight;
+            }
+        }
+    }
+
+    // the most crowded one
+    fn tightest_cluster(&self) -> usize {
+        let mut best = (usize::MAX, f32::NEG_INFINITY);
+        for (i, (one, energy)) in self.ones.iter().zip(&self.energy).enumerate() {
+            if *one && *energy > best.1 {
+                best = (i, *energy);
+            }
+        }
+        best.0
+    }
+
+    // the emptiest zero
+    fn largest_void(&self) -> usize {
+        let mut best = (usize::MAX, f32::INFINITY);
+        for (i, (one, energy)) in self.ones.iter().zip(&self.energy).enumerate() {
+            if !*one && *energy < best.1 {
+                best = (i, *energy);
+            }
+        }
+        best.0
+    }
+}
+
+// xorshift32, only to scatter the first points
+fn random_index(state: &mut u32, len: usize) -> usize {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state as usize % len
+}
+
+// a size by size mask, different seeds give unrelated masks
+pub fn void_and_cluster(size: usize, seed: u32) -> Vec<f32> {
+    let len = size * size;
+    let mut pattern = Pattern::new(size);
+
+    // a tenth of the pixels scattered at random
+    let mut state = seed.max(1);
+    let initial_count = (len / 10).max(1);
+    let mut count = 0;
+    while count < initial_count {
+        let index = random_index(&mut state, len);
+        if !pattern.ones[index] {
+            pattern.set(index, true);
+            count += 1;
+        }
+    }
+
+    // move points from clusters into voids until they are evenly spread, ties in the
+    // energy could make it cycle so it gives up eventually
+    for _ in 0..len {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false);
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; len];
+    let prototype = pattern.ones.clone();
+    let prototype_energy = pattern.energy.clone();
+
+    // the first points are ranked by taking them away, most crowded first
+    for r in (0..initial_count).rev() {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false);
+        rank[cluster] = r;
+    }
+
+    // then the rest by filling the emptiest spot
+    pattern.ones = prototype;
+    pattern.energy = prototype_energy;
+    for r in initial_count..len {
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        rank[void] = r;
+    }
+
+    rank.into_iter().map(|r| (r as f32 + 0.5) / len as f32).collect()
+}
+
+// four unrelated masks interleaved like rgba pixels, made in parallel
+pub fn blue_noise_rgba(size: usize) -> Vec<f32> {
+    let masks: Vec<Vec<f32>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|channel| scope.spawn(move || void_and_cluster(size, 0x9e3779b9 ^ (channel + 1))))
+            .collect();
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    });
+    (0..size * size)
+        .flat_map(|i| masks.iter().map(move |mask| mask[i]))
+        .collect()
+}
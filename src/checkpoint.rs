@@ -0,0 +1,185 @@
+use {
+    crate::tracer_struct::Camera,
+    anyhow::{Context, Result, bail},
+    flate2::{Compression, read::GzDecoder, write::GzEncoder},
+    serde::{Deserialize, Serialize},
+    std::io::{BufRead, BufReader, BufWriter, Read, Write},
+};
+
+// an unfinished render written to disk so it can be carried on after a restart or on
+// another machine, the scene is not part of it and has to be loaded the same way again
+// the file is gzipped, a line naming the format and a line of json with everything but
+// the pixels come first, then the texels of both textures as little endian words
+
+const MAGIC: &str = "shrimpy checkpoint 1";
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    width: u32,
+    height: u32,
+    frame_count: u32,
+    camera: Camera,
+}
+
+pub struct Checkpoint {
+    pub width: u32,
+    pub height: u32,
+    // samples summed up in radiance
+    pub frame_count: u32,
+    pub camera: Camera,
+    // rgba32float sums of every pixel as bits
+    pub radiance: Vec<u32>,
+    // first hits, see encode_guide in the shader
+    pub guide: Vec<u32>,
+}
+
+// words in one rgba texture, the size comes from a file so it may not fit
+fn texel_words(width: u32, height: u32) -> Result<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|texels| texels.checked_mul(4))
+        .with_context(|| format!("a {}x{} checkpoint is too large", width, height))
+}
+
+impl Checkpoint {
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let texel_words = texel_words(self.width, self.height)?;
+        if self.radiance.len() != texel_words || self.guide.len() != texel_words {
+            bail!(
+                "a {}x{} checkpoint needs {} words per texture, not {} and {}",
+                self.width,
+                self.height,
+                texel_words,
+                self.radiance.len(),
+                self.guide.len(),
+            );
+        }
+
+        let file = std::fs::File::create(filename)
+            .with_context(|| format!("failed to write checkpoint {}", filename))?;
+        let mut writer = GzEncoder::new(BufWriter::new(file), Compression::fast());
+
+        let header = Header {
+            width: self.width,
+            height: self.height,
+            frame_count: self.frame_count,
+            camera: self.camera,
+        };
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+
+        let bytes: Vec<u8> = self.radiance
+            .iter()
+            .chain(&self.guide)
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        writer.write_all(&bytes)?;
+        writer.finish()?.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        let file = std::fs::File::open(filename)
+            .with_context(|| format!("failed to read checkpoint {}", filename))?;
+        let mut reader = BufReader::new(GzDecoder::new(file));
+
+        let mut line = String::new();
+        let read = reader.read_line(&mut line);
+        if read.is_err() || line.trim_end() != MAGIC {
+            bail!("{} is not a checkpoint", filename);
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header: Header = serde_json::from_str(&line)?;
+
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let words: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+
+        let texel_words = texel_words(header.width, header.height)?;
+        if texel_words.checked_mul(2) != Some(words.len()) {
+            bail!("checkpoint {} is cut short", filename);
+        }
+        let (radiance, guide) = words.split_at(texel_words);
+
+        Ok(Self {
+            width: header.width,
+            height: header.height,
+            frame_count: header.frame_count,
+            camera: header.camera,
+            radiance: radiance.to_vec(),
+            guide: guide.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("shrimpy_test_{}_{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn checkpoint(width: u32, height: u32) -> Checkpoint {
+        let words = (width * height * 4) as usize;
+        Checkpoint {
+            width,
+            height,
+            frame_count: 17,
+            camera: Camera::new(),
+            radiance: (0..words as u32).map(|i| (i as f32 * 0.5).to_bits()).collect(),
+            guide: (0..words as u32).rev().collect(),
+        }
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let path = temp_path("round_trip.checkpoint");
+        let saved = checkpoint(5, 3);
+        saved.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.frame_count), (5, 3, 17));
+        assert_eq!(loaded.radiance, saved.radiance);
+        assert_eq!(loaded.guide, saved.guide);
+    }
+
+    #[test]
+    fn save_refuses_mismatched_textures() {
+        let mut saved = checkpoint(2, 2);
+        saved.guide.pop();
+        assert!(saved.save(&temp_path("mismatched.checkpoint")).is_err());
+    }
+
+    #[test]
+    fn load_refuses_broken_files() {
+        let path = temp_path("not_gzip.checkpoint");
+        std::fs::write(&path, b"hello").unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+
+        // a header whose size overflows u32 math
+        let path = temp_path("huge.checkpoint");
+        let mut writer = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::fast());
+        let header = Header { width: u32::MAX, height: u32::MAX, frame_count: 1, camera: Camera::new() };
+        writeln!(writer, "{}\n{}", MAGIC, serde_json::to_string(&header).unwrap()).unwrap();
+        writer.write_all(&[0; 32]).unwrap();
+        writer.finish().unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+
+        let path = temp_path("short.checkpoint");
+        checkpoint(4, 4).save(&path).unwrap();
+        let mut bytes = vec![];
+        GzDecoder::new(std::fs::File::open(&path).unwrap()).read_to_end(&mut bytes).unwrap();
+        let mut writer = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::fast());
+        writer.write_all(&bytes[..bytes.len() - 8]).unwrap();
+        writer.finish().unwrap();
+        let error = Checkpoint::load(&path).err().unwrap();
+        assert!(error.to_string().contains("cut short"), "{}", error);
+    }
+}
@@ -0,0 +1,125 @@
+use {
+    crate::{
+        primitives,
+        tracer_struct::{Scene, Triangle},
+    },
+    anyhow::{Context, Result},
+    std::{
+        fs::File,
+        io::{BufWriter, Write},
+    },
+};
+
+// tessellation used for spheres, fine enough to check placement and size
+const SPHERE_SEGMENTS: u32 = 24;
+const SPHERE_RINGS: u32 = 12;
+
+// the triangles of the scene plus its tessellated spheres, in world space
+pub fn scene_triangles(scene: &Scene, triangles: &[Triangle]) -> Vec<Triangle> {
+    let mut tris = triangles.to_vec();
+
+    for sphere in scene.spheres[..scene.sphere_count as usize].iter() {
+        tris.extend(primitives::uv_sphere(
+            sphere.center,
+            sphere.radius,
+            SPHERE_SEGMENTS,
+            SPHERE_RINGS,
+            sphere.material_id,
+        ));
+    }
+
+    tris
+}
+
+fn has_normals(tri: &Triangle) -> bool {
+    tri.normal_0.length_squared() > 0.0
+}
+
+// writes the scene geometry as OBJ, or PLY when the file name ends with .ply
+pub fn export_scene(scene: &Scene, triangles: &[Triangle], filename: &str) -> Result<()> {
+    let tris = scene_triangles(scene, triangles);
+
+    if filename.to_lowercase().ends_with(".ply") {
+        export_ply(&tris, filename)
+    } else {
+        export_obj(&tris, filename)
+    }
+}
+
+// faces are grouped into `usemtl material_<id>` runs, no .mtl file is written
+pub fn export_obj(tris: &[Triangle], filename: &str) -> Result<()> {
+    let file = File::create(filename).with_context(|| format!("failed to create {}", filename))?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "# exported by shrimpy, {} triangles", tris.len())?;
+
+    let mut normal_count = 0;
+    let mut current_material = None;
+    for (i, tri) in tris.iter().enumerate() {
+        if current_material != Some(tri.material_id) {
+            current_material = Some(tri.material_id);
+            writeln!(writer, "usemtl material_{}", tri.material_id)?;
+        }
+
+        for v in [tri.vertex_0, tri.vertex_1, tri.vertex_2] {
+            writeln!(writer, "v {} {} {}", v.x(), v.y(), v.z())?;
+        }
+
+        let first = i * 3 + 1;
+        if has_normals(tri) {
+            for n in [tri.normal_0, tri.normal_1, tri.normal_2] {
+                writeln!(writer, "vn {} {} {}", n.x(), n.y(), n.z())?;
+            }
+            let normal = normal_count + 1;
+            normal_count += 3;
+            writeln!(
+                writer,
+                "f {}//{} {}//{} {}//{}",
+                first, normal, first + 1, normal + 1, first + 2, normal + 2,
+            )?;
+        } else {
+            writeln!(writer, "f {} {} {}", first, first + 1, first + 2)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// ascii PLY with per vertex normals (flat when the triangle has none)
+// and the material id stored as an extra face property
+pub fn export_ply(tris: &[Triangle], filename: &str) -> Result<()> {
+    let file = File::create(filename).with_context(|| format!("failed to create {}", filename))?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format ascii 1.0")?;
+    writeln!(writer, "comment exported by shrimpy")?;
+    writeln!(writer, "element vertex {}", tris.len() * 3)?;
+    for property in ["x", "y", "z", "nx", "ny", "nz"] {
+        writeln!(writer, "property float {}", property)?;
+    }
+    writeln!(writer, "element face {}", tris.len())?;
+    writeln!(writer, "property list uchar int vertex_indices")?;
+    writeln!(writer, "property uint material_id")?;
+    writeln!(writer, "end_header")?;
+
+    for tri in tris.iter() {
+        let normals = if has_normals(tri) {
+            [tri.normal_0, tri.normal_1, tri.normal_2]
+        } else {
+            [tri.normal(); 3]
+        };
+
+        for (v, n) in [tri.vertex_0, tri.vertex_1, tri.vertex_2].iter().zip(normals) {
+            writeln!(writer, "{} {} {} {} {} {}", v.x(), v.y(), v.z(), n.x(), n.y(), n.z())?;
+        }
+    }
+
+    for (i, tri) in tris.iter().enumerate() {
+        writeln!(writer, "3 {} {} {} {}", i * 3, i * 3 + 1, i * 3 + 2, tri.material_id)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
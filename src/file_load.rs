@@ -1,85 +1,3152 @@
 use {
-    crate::tracer_struct::Triangle,
+    crate::mat4::Mat4,
+    crate::tracer_struct::{Camera, Disc, Material, MaterialType, Triangle},
     crate::vec3::Vec3,
+    std::cmp::Ordering,
+    std::collections::{BinaryHeap, HashMap},
+    std::fmt,
     std::fs::File,
     std::io::{BufRead, BufReader},
     std::str::FromStr,
+    std::sync::mpsc,
+    std::thread,
 };
 
-pub fn load_mesh_from(filename: &str, material_id: u32) -> Vec<Triangle> {
+#[derive(Debug)]
+pub enum MeshLoadError {
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    // line is 1 based
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    // the file is broken somewhere a line number does not tell, e.g. in binary data
+    Invalid {
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for MeshLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshLoadError::Io { path, source } => write!(f, "failed to read {}: {}", path, source),
+            MeshLoadError::Parse { path, line, message } => write!(f, "{}:{}: {}", path, line, message),
+            MeshLoadError::Invalid { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for MeshLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MeshLoadError::Io { source, .. } => Some(source),
+            MeshLoadError::Parse { .. } | MeshLoadError::Invalid { .. } => None,
+        }
+    }
+}
+
+pub fn load_mesh_from(filename: &str, material_id: u32) -> Result<Vec<Triangle>, MeshLoadError> {
+    read_obj(filename, material_id, None, &mut |_, _| ())
+}
+
+fn file_extension(filename: &str) -> String {
+    std::path::Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// triangles parsed since the last event, the fraction of the file read so far
+// and Finished once the loader thread is done
+pub enum MeshLoadEvent {
+    Progress(Vec<Triangle>, f32),
+    Finished(Result<(), MeshLoadError>),
+}
+
+// loads a mesh on a separate thread, OBJ triangles are streamed back in batches
+// while other formats (and cache hits) arrive all at once, dropping the receiver
+// stops the loader
+pub fn load_mesh_async(filename: &str, material_id: u32) -> mpsc::Receiver<MeshLoadEvent> {
+    let (sender, receiver) = mpsc::channel();
+    let filename = filename.to_string();
+
+    thread::spawn(move || {
+        let cache_path = mesh_cache_path(&filename, material_id);
+        if let Some(tris) = cache_path.as_ref().and_then(|path| read_mesh_cache(path)) {
+            let _ = sender.send(MeshLoadEvent::Progress(tris, 1.0));
+            let _ = sender.send(MeshLoadEvent::Finished(Ok(())));
+            return;
+        }
+
+        if matches!(file_extension(&filename).as_str(), "ply" | "stl" | "gltf" | "glb" | "usda" | "fbx") {
+            let result = load_any_mesh_from(&filename, material_id).map(|tris| {
+                if let Some(path) = &cache_path {
+                    write_mesh_cache(path, &tris);
+                }
+                let _ = sender.send(MeshLoadEvent::Progress(tris, 1.0));
+            });
+            let _ = sender.send(MeshLoadEvent::Finished(result));
+            return;
+        }
+
+        let mut disconnected = false;
+        let mut all_tris = vec![];
+        let result = read_obj(&filename, material_id, None, &mut |tris, fraction| {
+            if disconnected {
+                tris.clear();
+                return;
+            }
+            all_tris.extend_from_slice(tris);
+            disconnected = sender.send(MeshLoadEvent::Progress(std::mem::take(tris), fraction)).is_err();
+        });
+
+        let result = result.map(|rest| {
+            all_tris.extend_from_slice(&rest);
+            if let Some(path) = &cache_path
+                && !disconnected
+            {
+                write_mesh_cache(path, &all_tris);
+            }
+            let _ = sender.send(MeshLoadEvent::Progress(rest, 1.0));
+        });
+        let _ = sender.send(MeshLoadEvent::Finished(result));
+    });
+
+    receiver
+}
+
+// parsed meshes are cached as raw Triangle structs, named after a hash of the source file
+const MESH_CACHE_DIR: &str = "./cache";
+const MESH_CACHE_MAGIC: &[u8; 4] = b"SHMC";
+const MESH_CACHE_VERSION: u32 = 1;
+
+// 64 bit FNV-1a, stable across runs and toolchains unlike DefaultHasher
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn mesh_cache_path(filename: &str, material_id: u32) -> Option<std::path::PathBuf> {
+    let source = std::fs::read(filename).ok()?;
+    let name = format!("{:016x}-{}.bin", fnv1a(&source), material_id);
+    Some(std::path::Path::new(MESH_CACHE_DIR).join(name))
+}
+
+// header: magic, version, size of Triangle, triangle count, then the triangles
+// the size check makes the cache miss whenever the Triangle layout changes
+fn read_mesh_cache(path: &std::path::Path) -> Option<Vec<Triangle>> {
+    let data = std::fs::read(path).ok()?;
+    let (header, body) = data.split_at_checked(16)?;
+    let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+
+    if &header[0..4] != MESH_CACHE_MAGIC
+        || word(4) != MESH_CACHE_VERSION
+        || word(8) as usize != std::mem::size_of::<Triangle>()
+        || body.len() != word(12) as usize * std::mem::size_of::<Triangle>()
+    {
+        return None;
+    }
+
+    // the file buffer is not necessarily aligned for Triangle
+    Some(bytemuck::pod_collect_to_vec(body))
+}
+
+fn write_mesh_cache(path: &std::path::Path, tris: &[Triangle]) {
+    let mut data = Vec::with_capacity(16 + std::mem::size_of_val(tris));
+    data.extend_from_slice(MESH_CACHE_MAGIC);
+    data.extend_from_slice(&MESH_CACHE_VERSION.to_le_bytes());
+    data.extend_from_slice(&(std::mem::size_of::<Triangle>() as u32).to_le_bytes());
+    data.extend_from_slice(&(tris.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytemuck::cast_slice(tris));
+
+    let result = std::fs::create_dir_all(MESH_CACHE_DIR).and_then(|_| std::fs::write(path, data));
+    if result.is_err() {
+        println!("failed to write mesh cache {}", path.display());
+    }
+}
+
+// like load_any_mesh_from but goes through the mesh cache
+pub fn load_mesh_cached(filename: &str, material_id: u32) -> Result<Vec<Triangle>, MeshLoadError> {
+    let cache_path = mesh_cache_path(filename, material_id);
+    if let Some(tris) = cache_path.as_ref().and_then(|path| read_mesh_cache(path)) {
+        return Ok(tris);
+    }
+
+    let tris = load_any_mesh_from(filename, material_id)?;
+    if let Some(path) = &cache_path {
+        write_mesh_cache(path, &tris);
+    }
+
+    Ok(tris)
+}
+
+// picks the loader from the file extension, glTF, USD and FBX materials are replaced by material_id
+pub fn load_any_mesh_from(filename: &str, material_id: u32) -> Result<Vec<Triangle>, MeshLoadError> {
+    match file_extension(filename).as_str() {
+        "ply" => load_ply_from(filename, material_id),
+        "stl" => load_stl_from(filename, material_id),
+        "gltf" | "glb" => {
+            let mut tris = load_gltf_from(filename)?.triangles;
+            for tri in tris.iter_mut() {
+                tri.material_id = material_id;
+            }
+            Ok(tris)
+        },
+        "usda" => {
+            let mut tris = load_usda_from(filename)?.triangles;
+            for tri in tris.iter_mut() {
+                tri.material_id = material_id;
+            }
+            Ok(tris)
+        },
+        "fbx" => {
+            let mut tris = load_fbx_from(filename)?.triangles;
+            for tri in tris.iter_mut() {
+                tri.material_id = material_id;
+            }
+            Ok(tris)
+        },
+        _ => load_mesh_from(filename, material_id),
+    }
+}
+
+// loads an OBJ together with the materials of its mtllib files
+// faces before any usemtl (or with an unknown material) use material 0, a default material
+pub fn load_mesh_with_materials_from(filename: &str) -> Result<ImportedScene, MeshLoadError> {
+    let mut scene = ImportedScene {
+        materials: vec![Material::default()],
+        ..Default::default()
+    };
+    scene.triangles = read_obj(filename, 0, Some(&mut scene), &mut |_, _| ())?;
+
+    Ok(scene)
+}
+
+// shared OBJ reader
+// when `imported` is given, mtllib statements append materials and textures to it and faces
+// reference the material picked by the last usemtl, otherwise every face uses material_id
+// on_progress is called every few thousand lines with the triangles so far and the
+// fraction of the file read, it may take the triangles out of the vector
+fn read_obj(
+    filename: &str,
+    material_id: u32,
+    mut imported: Option<&mut ImportedScene>,
+    on_progress: &mut dyn FnMut(&mut Vec<Triangle>, f32),
+) -> Result<Vec<Triangle>, MeshLoadError> {
     let mut tris = vec![];
+    let default_material_id = material_id;
+    let mut material_id = material_id;
+    let mut material_names: HashMap<String, u32> = HashMap::new();
+
+    let io_error = |source| MeshLoadError::Io {
+        path: filename.to_string(),
+        source,
+    };
+    let file = File::open(filename).map_err(io_error)?;
+    let file_size = file.metadata().map(|m| m.len()).unwrap_or(0).max(1);
+    let mut bytes_read = 0;
+
+    let reader = BufReader::new(file);
+    let mut verts: Vec<Vec3> = Vec::new();
+    let mut texs: Vec<[f32; 2]> = Vec::new();
+    let mut norms: Vec<Vec3> = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        bytes_read += line.len() as u64 + 1;
+        if line_number % 4096 == 4095 {
+            on_progress(&mut tris, (bytes_read as f32 / file_size as f32).min(1.0));
+        }
+
+        let trimmed = line.trim();
+        let mut parts = trimmed.split_whitespace();
+        let keyword = parts.next().unwrap_or("");
+
+        let parse_error = |message: String| MeshLoadError::Parse {
+            path: filename.to_string(),
+            line: line_number + 1,
+            message,
+        };
+
+        if keyword == "v" || keyword == "vn" || keyword == "vt" {
+            let mut v = Vec3::zero();
+            for (i, part) in parts.take(3).enumerate() {
+                v[i] = f32::from_str(part)
+                    .map_err(|e| parse_error(format!("invalid number \"{}\": {}", part, e)))?;
+            }
+
+            match keyword {
+                "v" => verts.push(v),
+                "vn" => norms.push(v),
+                // obj v points up the image
+                _ => texs.push([v[0], 1.0 - v[1]]),
+            }
+        } else if keyword == "f" {
+            // every corner is v, v/vt, v//vn or v/vt/vn
+            let mut corners = vec![];
+            for token in parts {
+                let mut indices = token.split('/');
+                let vertex = indices.next().and_then(|i| obj_index(i, verts.len()));
+                let texture = indices.next().and_then(|i| obj_index(i, texs.len()));
+                let normal = indices.next().and_then(|i| obj_index(i, norms.len()));
+
+                let Some(vertex) = vertex else {
+                    return Err(parse_error(format!("invalid face vertex \"{}\"", token)));
+                };
+                corners.push((vertex, texture, normal));
+            }
+            if corners.len() < 3 {
+                return Err(parse_error(format!("face with {} vertices", corners.len())));
+            }
+
+            // fan triangulation, exact for convex polygons
+            for i in 1..corners.len().saturating_sub(1) {
+                let face = [corners[0], corners[i], corners[i + 1]];
+                let mut tri = Triangle::new(face.map(|(v, _, _)| verts[v]), material_id);
+                if let [(_, Some(t0), _), (_, Some(t1), _), (_, Some(t2), _)] = face {
+                    tri.set_uvs([texs[t0], texs[t1], texs[t2]]);
+                }
+                if let [(_, _, Some(n0)), (_, _, Some(n1)), (_, _, Some(n2))] = face {
+                    tri.set_normals([norms[n0], norms[n1], norms[n2]]);
+                }
+                tris.push(tri);
+            }
+        } else if keyword == "mtllib" {
+            let Some(imported) = imported.as_deref_mut() else {
+                continue;
+            };
+            let dir = std::path::Path::new(filename).parent().unwrap_or(std::path::Path::new(""));
+            for lib in trimmed.split_whitespace().skip(1) {
+                let path = dir.join(lib);
+                for (name, material) in load_materials_from(&path.to_string_lossy(), &mut imported.textures) {
+                    material_names.insert(name, imported.materials.len() as u32);
+                    imported.materials.push(material);
+                }
+            }
+        } else if keyword == "usemtl" {
+            // names are only known when materials are loaded
+            let name = trimmed[keyword.len()..].trim();
+            material_id = *material_names.get(name).unwrap_or(&default_material_id);
+        }
+    }
+
+    Ok(tris)
+}
+
+// resolves a 1 based OBJ index, negative values count back from the last element
+fn obj_index(token: &str, count: usize) -> Option<usize> {
+    let index = token.parse::<i64>().ok()?;
+    let resolved = match index.cmp(&0) {
+        Ordering::Greater => index - 1,
+        Ordering::Less => count as i64 + index,
+        Ordering::Equal => return None,
+    };
+
+    (0..count as i64).contains(&resolved).then_some(resolved as usize)
+}
+
+// a map_* statement, only the -s, -o and -bm options are used
+struct MtlMap {
+    path: String,
+    uv_scale: [f32; 2],
+    uv_offset: [f32; 2],
+    bump_multiplier: f32,
+}
+
+fn parse_mtl_map(args: &[&str]) -> Option<MtlMap> {
+    let number = |i: usize| args.get(i).and_then(|a| f32::from_str(a).ok());
+    let mut map = MtlMap {
+        path: String::new(),
+        uv_scale: [1.0; 2],
+        uv_offset: [0.0; 2],
+        bump_multiplier: 1.0,
+    };
+
+    // options come before the file name
+    let mut i = 0;
+    while let Some(option) = args.get(i).filter(|a| a.starts_with('-')) {
+        match (*option, [number(i + 1), number(i + 2)]) {
+            ("-s", [Some(u), Some(v)]) => map.uv_scale = [u, v],
+            ("-o", [Some(u), Some(v)]) => map.uv_offset = [u, v],
+            ("-bm", [Some(multiplier), _]) => map.bump_multiplier = multiplier,
+            _ => (),
+        }
+        // skip the option and the numbers following it
+        i += 1;
+        while number(i).is_some() {
+            i += 1;
+        }
+    }
+
+    map.path = args.get(i..).filter(|rest| !rest.is_empty())?.join(" ");
+    Some(map)
+}
+
+// parses a wavefront .mtl file into named materials
+// Kd is the color, Ke the emission, Ni the ior of transparent (d < 1, Tr > 0 or
+// illum 4/6/7) materials, Pr/Pm the PBR extension roughness/metallic (with Ps sheen and
+// Pc/Pcr clearcoat making it Principled), and illum 3 materials are treated as metals
+// with roughness derived from Ns
+// map_Kd/map_Pr/map_Pm/map_Ke/map_d/bump images are appended to `textures` and referenced by
+// index + 1, the uv transform is taken from the first of them
+pub fn load_materials_from(filename: &str, textures: &mut Vec<image::RgbaImage>) -> Vec<(String, Material)> {
+    let mut materials: Vec<(String, Material)> = vec![];
 
     let file = match File::open(filename) {
         Ok(f) => f,
         Err(_) => {
             println!("failed to load file {}", filename);
-            return tris;
+            return materials;
         }
     };
 
-    let reader = BufReader::new(file);
-    let mut has_texture = false;
-    let mut verts: Vec<Vec3> = Vec::new();
-    let mut texs: Vec<Vec3> = Vec::new();
+    #[derive(Default)]
+    struct MtlEntry {
+        diffuse: Option<Vec3>,
+        emissive: Option<Vec3>,
+        ior: Option<f32>,
+        specular_exponent: Option<f32>,
+        opacity: f32,
+        illum: u32,
+        roughness: Option<f32>,
+        metallic: Option<f32>,
+        sheen: Option<f32>,
+        clearcoat: Option<f32>,
+        clearcoat_roughness: Option<f32>,
+        albedo_map: Option<MtlMap>,
+        roughness_map: Option<MtlMap>,
+        metallic_map: Option<MtlMap>,
+        emission_map: Option<MtlMap>,
+        opacity_map: Option<MtlMap>,
+        bump_map: Option<MtlMap>,
+    }
 
-    for line in reader.lines() {
+    let mut entries: Vec<(String, MtlEntry)> = vec![];
+
+    for line in BufReader::new(file).lines() {
         let line = match line {
             Ok(l) => l,
             Err(_) => continue,
         };
-        let trimmed = line.trim();
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some((&keyword, args)) = parts.split_first() else {
+            continue;
+        };
 
-        if trimmed.starts_with("vt") {
-            has_texture = true;
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if parts.len() >= 3 {
-                let mut v = Vec3::zero();
-                v[0] = 1.0 - f32::from_str(parts[1]).unwrap_or(0.0);
-                v[1] = 1.0 - f32::from_str(parts[2]).unwrap_or(0.0);
-                texs.push(v);
-            }
-        } else if trimmed.starts_with('v') {
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if parts.len() >= 4 {
-                let mut v = Vec3::zero();
-                v[0] = f32::from_str(parts[1]).unwrap_or(0.0);
-                v[1] = f32::from_str(parts[2]).unwrap_or(0.0);
-                v[2] = f32::from_str(parts[3]).unwrap_or(0.0);
-                verts.push(v);
-            }
-        } else if trimmed.starts_with('f') {
-            if has_texture {
-                let mut tokens = vec![];
-                for token in trimmed.split_whitespace().skip(1) {
-                    for t in token.split('/') {
-                        tokens.push(t.to_string());
-                    }
+        let number = |i: usize| args.get(i).and_then(|a| f32::from_str(a).ok());
+        let color = || Some(Vec3::new(number(0)?, number(1)?, number(2)?));
+
+        if keyword == "newmtl" {
+            let entry = MtlEntry {
+                opacity: 1.0,
+                ..Default::default()
+            };
+            entries.push((args.join(" "), entry));
+            continue;
+        }
+
+        let Some((_, entry)) = entries.last_mut() else {
+            continue;
+        };
+        match keyword {
+            "Kd" => entry.diffuse = color(),
+            "Ke" => entry.emissive = color(),
+            "Ni" => entry.ior = number(0),
+            "Ns" => entry.specular_exponent = number(0),
+            "d" => entry.opacity = number(0).unwrap_or(1.0),
+            "Tr" => entry.opacity = 1.0 - number(0).unwrap_or(0.0),
+            "illum" => entry.illum = number(0).unwrap_or(0.0) as u32,
+            "Pr" => entry.roughness = number(0),
+            "Pm" => entry.metallic = number(0),
+            "Ps" => entry.sheen = number(0),
+            "Pc" => entry.clearcoat = number(0),
+            "Pcr" => entry.clearcoat_roughness = number(0),
+            "map_Kd" => entry.albedo_map = parse_mtl_map(args),
+            "map_Pr" => entry.roughness_map = parse_mtl_map(args),
+            "map_Pm" => entry.metallic_map = parse_mtl_map(args),
+            "map_Ke" => entry.emission_map = parse_mtl_map(args),
+            "map_d" => entry.opacity_map = parse_mtl_map(args),
+            "bump" | "map_bump" | "map_Bump" => entry.bump_map = parse_mtl_map(args),
+            _ => (),
+        }
+    }
+
+    let dir = std::path::Path::new(filename).parent().unwrap_or(std::path::Path::new(""));
+    // materials often share maps, load each file once
+    let mut loaded: HashMap<String, u32> = HashMap::new();
+    let mut load_map = |map: &Option<MtlMap>, load: fn(&std::path::Path) -> Option<image::RgbaImage>| {
+        let path = &map.as_ref()?.path;
+        if let Some(&id) = loaded.get(path) {
+            return Some(id);
+        }
+        textures.push(load(&dir.join(path))?);
+        loaded.insert(path.clone(), textures.len() as u32);
+        Some(textures.len() as u32)
+    };
+
+    for (name, entry) in entries {
+        let color = entry.diffuse.unwrap_or(Vec3::all(1.0));
+
+        // with map_d, d is a cutout opacity instead of glass
+        let is_cutout = entry.opacity_map.is_some();
+        let mut material = if (entry.opacity < 1.0 && !is_cutout) || matches!(entry.illum, 4 | 6 | 7) {
+            Material::dielectric(color, entry.ior.unwrap_or(1.5).max(1.0))
+        } else if entry.sheen.is_some() || entry.clearcoat.is_some() {
+            let mut material = Material::principled(color, entry.metallic.unwrap_or(0.0), entry.roughness.unwrap_or(1.0));
+            material.sheen = entry.sheen.unwrap_or(0.0);
+            material.clearcoat = entry.clearcoat.unwrap_or(0.0);
+            material.clearcoat_roughness = entry.clearcoat_roughness.unwrap_or(material.clearcoat_roughness);
+            material
+        } else if entry.metallic.is_some() || entry.metallic_map.is_some() || entry.roughness_map.is_some() {
+            // maps are multiplied with the factors, so a lone map gets a factor of 1
+            let metallic = entry.metallic.unwrap_or(if entry.metallic_map.is_some() { 1.0 } else { 0.0 });
+            Material::pbr(color, metallic, entry.roughness.unwrap_or(1.0))
+        } else if entry.illum == 3 {
+            // blender writes Ns = (1 - roughness)^2 * 1000
+            let exponent = entry.specular_exponent.unwrap_or(0.0).clamp(0.0, 1000.0);
+            Material::metal(color, 1.0 - (exponent / 1000.0).sqrt())
+        } else {
+            Material::diffuse(color)
+        };
+
+        // a map without Ke glows at full strength
+        let emission_texture = load_map(&entry.emission_map, load_texture_image).unwrap_or(0);
+        let emissive = entry.emissive.or((emission_texture != 0).then_some(Vec3::all(1.0)));
+        if let Some(emissive) = emissive {
+            apply_emission_texture(&mut material, emissive, emission_texture);
+        }
+
+        material.albedo_texture = load_map(&entry.albedo_map, load_texture_image).unwrap_or(0);
+        material.roughness_texture = load_map(&entry.roughness_map, load_texture_image).unwrap_or(0);
+        material.metallic_texture = load_map(&entry.metallic_map, load_texture_image).unwrap_or(0);
+        if let Some(bump_map) = &entry.bump_map {
+            material.bump_texture = load_map(&entry.bump_map, load_texture_image).unwrap_or(0);
+            material.bump_strength = bump_map.bump_multiplier;
+        }
+        if is_cutout {
+            material.opacity = entry.opacity;
+            material.opacity_texture = load_map(&entry.opacity_map, load_opacity_image).unwrap_or(0);
+        }
+        let maps = [
+            &entry.albedo_map,
+            &entry.roughness_map,
+            &entry.metallic_map,
+            &entry.emission_map,
+            &entry.opacity_map,
+            &entry.bump_map,
+        ];
+        if let Some(map) = maps
+            .into_iter()
+            .find_map(|map| map.as_ref())
+        {
+            material.uv_scale = map.uv_scale;
+            material.uv_offset = map.uv_offset;
+        }
+
+        materials.push((name, material));
+    }
+
+    materials
+}
+
+// emission shares the color with albedo, so an emissive material takes the emissive color
+fn apply_emission(material: &mut Material, emissive: Vec3) {
+    let emission_strength = emissive.x().max(emissive.y()).max(emissive.z());
+    if emission_strength > 0.0 {
+        material.color = emissive / emission_strength;
+        material.emission_strength = emission_strength;
+    }
+}
+
+// with an emission texture the texture is the emitted color and emissive only scales it
+fn apply_emission_texture(material: &mut Material, emissive: Vec3, emission_texture: u32) {
+    if emission_texture == 0 {
+        apply_emission(material, emissive);
+        return;
+    }
+    material.emission_texture = emission_texture;
+    material.emission_strength = emissive.x().max(emissive.y()).max(emissive.z());
+}
+
+fn load_texture_image(path: &std::path::Path) -> Option<image::RgbaImage> {
+    match image::open(path) {
+        Ok(img) => Some(img.into_rgba8()),
+        Err(e) => {
+            println!("failed to load texture {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// opacity lives in the alpha channel, images without one are treated as a grayscale mask
+fn load_opacity_image(path: &std::path::Path) -> Option<image::RgbaImage> {
+    match image::open(path) {
+        Ok(img) if img.color().has_alpha() => Some(img.into_rgba8()),
+        Ok(img) => {
+            let mut mask = img.into_rgba8();
+            for pixel in mask.pixels_mut() {
+                pixel.0[3] = pixel.0[0];
+            }
+            Some(mask)
+        },
+        Err(e) => {
+            println!("failed to load texture {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// a self contained chunk of scene, triangle material ids index into `materials`
+// and material texture ids into `textures` (1 based, 0 is no texture)
+// use Gfx::scene_add_imported to register the materials and textures and remap the ids
+#[derive(Debug, Default)]
+pub struct ImportedScene {
+    pub materials: Vec<Material>,
+    pub textures: Vec<image::RgbaImage>,
+    pub triangles: Vec<Triangle>,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<ImportedLight>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImportedLightKind {
+    Directional,
+    Point,
+    // cone angles in radians from the light direction
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// a punctual light in world space, intensity is in candela (lux for directional lights)
+#[derive(Debug, Copy, Clone)]
+pub struct ImportedLight {
+    pub kind: ImportedLightKind,
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: Option<f32>,
+}
+
+// maps a glTF metallic-roughness material onto the tracer's material model
+fn convert_gltf_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let color = Vec3::new(r, g, b);
+
+    let mut converted = Material::principled(color, pbr.metallic_factor(), pbr.roughness_factor());
+    converted.transmission = material
+        .transmission()
+        .map_or(0.0, |t| t.transmission_factor());
+    converted.ior = material.ior().unwrap_or(1.5);
+    // attenuation_color is what is left after attenuation_distance
+    if let Some(volume) = material.volume()
+        && volume.attenuation_distance().is_finite()
+        && volume.attenuation_distance() > 0.0
+    {
+        converted.absorption_color = Vec3::from(volume.attenuation_color());
+        converted.absorption_density = 1.0 / volume.attenuation_distance();
+    }
+
+    // images are imported in order, only the first uv set is read
+    let texture_id = |info: Option<gltf::texture::Info>| {
+        info.filter(|info| info.tex_coord() == 0)
+            .map_or(0, |info| info.texture().source().index() as u32 + 1)
+    };
+    // blender writes emission strengths above 1 through KHR_materials_emissive_strength
+    let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+    apply_emission_texture(
+        &mut converted,
+        Vec3::from(material.emissive_factor()) * emissive_strength,
+        texture_id(material.emissive_texture()),
+    );
+    converted.albedo_texture = texture_id(pbr.base_color_texture());
+    // masked and blended materials both become stochastic cutouts using the base color alpha
+    if material.alpha_mode() != gltf::material::AlphaMode::Opaque {
+        converted.opacity = a;
+        converted.opacity_texture = converted.albedo_texture;
+    }
+    // roughness is in the green channel and metalness in the blue one
+    converted.roughness_texture = texture_id(pbr.metallic_roughness_texture());
+    converted.metallic_texture = converted.roughness_texture;
+
+    converted
+}
+
+// 8 bit images only, anything else becomes a blank texture so ids stay in order
+fn convert_gltf_image(data: &gltf::image::Data) -> image::RgbaImage {
+    let channels = match data.format {
+        gltf::image::Format::R8 => 1,
+        gltf::image::Format::R8G8 => 2,
+        gltf::image::Format::R8G8B8 => 3,
+        gltf::image::Format::R8G8B8A8 => 4,
+        _ => {
+            println!("unsupported glTF image format {:?}", data.format);
+            return image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        },
+    };
+
+    let pixels = data.pixels.chunks_exact(channels).flat_map(|p| match p {
+        [l] => [*l, *l, *l, 255],
+        [l, a] => [*l, *l, *l, *a],
+        [r, g, b] => [*r, *g, *b, 255],
+        _ => [p[0], p[1], p[2], p[3]],
+    });
+    image::RgbaImage::from_raw(data.width, data.height, pixels.collect())
+        .unwrap_or_else(|| image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])))
+}
+
+fn load_gltf_node(
+    node: gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    default_material_id: u32,
+    scene: &mut ImportedScene,
+) -> Result<(), String> {
+    // gltf matrices are column major
+    let m = node.transform().matrix();
+    let mut local = Mat4::identity();
+    for (row, local_row) in local.0.iter_mut().enumerate() {
+        for (col, value) in local_row.iter_mut().enumerate() {
+            *value = m[col][row];
+        }
+    }
+    let world = parent_transform * local;
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(p) => p.map(|p| world.transform_point(Vec3::from(p))).collect(),
+                None => continue,
+            };
+            // attributes with another count than the positions are left out
+            let normals: Option<Vec<Vec3>> = reader
+                .read_normals()
+                .map(|n| n.map(|n| world.transform_normal(Vec3::from(n)).normalized()).collect())
+                .filter(|n: &Vec<Vec3>| n.len() == positions.len());
+            let uvs: Option<Vec<[f32; 2]>> = reader
+                .read_tex_coords(0)
+                .map(|t| t.into_f32().collect())
+                .filter(|t: &Vec<[f32; 2]>| t.len() == positions.len());
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(i) => i.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if let Some(i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+                return Err(format!(
+                    "mesh {} refers to vertex {} but has {}",
+                    mesh.index(),
+                    i,
+                    positions.len(),
+                ));
+            }
+
+            let material_id = primitive
+                .material()
+                .index()
+                .map_or(default_material_id, |i| i as u32);
+
+            for face in indices.chunks_exact(3) {
+                let face = [face[0], face[1], face[2]];
+                let vertices = face.map(|i| positions[i as usize]);
+                let mut tri = Triangle::new(vertices, material_id);
+                if let Some(normals) = &normals {
+                    tri.set_normals(face.map(|i| normals[i as usize]));
                 }
+                if let Some(uvs) = &uvs {
+                    tri.set_uvs(face.map(|i| uvs[i as usize]));
+                }
+                scene.triangles.push(tri);
+            }
+        }
+    }
 
-                if tokens.len() >= 6 {
-                    let mut tri = Triangle::default();
-                    tri.vertex_0 = verts[tokens[0].parse::<usize>().unwrap() - 1];
-                    tri.vertex_1 = verts[tokens[2].parse::<usize>().unwrap() - 1];
-                    tri.vertex_2 = verts[tokens[4].parse::<usize>().unwrap() - 1];
-                    // tri.vert_texture[0] = texs[tokens[1].parse::<usize>().unwrap() - 1];
-                    // tri.vert_texture[1] = texs[tokens[3].parse::<usize>().unwrap() - 1];
-                    // tri.vert_texture[2] = texs[tokens[5].parse::<usize>().unwrap() - 1];
-                    tri.material_id = material_id;
-                    tris.push(tri);
+    if let Some(camera) = node.camera()
+        && let gltf::camera::Projection::Perspective(perspective) = camera.projection()
+    {
+        // a width of 2 makes fov the vertical field of view, matching yfov
+        let mut converted = Camera::new();
+        converted.position = world.transform_point(Vec3::zero());
+        converted.set_direction(world.transform_vector(Vec3::new(0.0, 0.0, -1.0)));
+        converted.fov = perspective.yfov();
+        converted.width = 2.0;
+        converted.apeture = 0.0;
+        scene.cameras.push(converted);
+    }
+
+    if let Some(light) = node.light() {
+        let kind = match light.kind() {
+            gltf::khr_lights_punctual::Kind::Directional => ImportedLightKind::Directional,
+            gltf::khr_lights_punctual::Kind::Point => ImportedLightKind::Point,
+            gltf::khr_lights_punctual::Kind::Spot { inner_cone_angle, outer_cone_angle } => {
+                ImportedLightKind::Spot { inner_cone_angle, outer_cone_angle }
+            },
+        };
+
+        // lights shine down the local -z axis like cameras
+        scene.lights.push(ImportedLight {
+            kind,
+            position: world.transform_point(Vec3::zero()),
+            direction: world.transform_vector(Vec3::new(0.0, 0.0, -1.0)).normalized(),
+            color: Vec3::from(light.color()),
+            intensity: light.intensity(),
+            range: light.range(),
+        });
+    }
+
+    for child in node.children() {
+        load_gltf_node(child, world, buffers, default_material_id, scene)?;
+    }
+
+    Ok(())
+}
+
+// loads meshes, materials, perspective cameras and KHR_lights_punctual lights
+// of the default scene in a .gltf/.glb file
+pub fn load_gltf_from(filename: &str) -> Result<ImportedScene, MeshLoadError> {
+    let mut scene = ImportedScene::default();
+    let invalid = |message: String| MeshLoadError::Invalid {
+        path: filename.to_string(),
+        message,
+    };
+
+    let (document, buffers, images) = gltf::import(filename).map_err(|e| match e {
+        gltf::Error::Io(source) => MeshLoadError::Io {
+            path: filename.to_string(),
+            source,
+        },
+        e => invalid(e.to_string()),
+    })?;
+
+    scene.materials = document.materials().map(|m| convert_gltf_material(&m)).collect();
+    scene.textures = images.iter().map(convert_gltf_image).collect();
+    // primitives without a material use the gltf default material
+    let default_material_id = scene.materials.len() as u32;
+    scene.materials.push(Material::default());
+
+    let Some(gltf_scene) = document.default_scene().or_else(|| document.scenes().next()) else {
+        return Ok(scene);
+    };
+    for node in gltf_scene.nodes() {
+        load_gltf_node(node, Mat4::identity(), &buffers, default_material_id, &mut scene).map_err(invalid)?;
+    }
+
+    Ok(scene)
+}
+
+// minimal USD ascii (.usda) reader for flattened files
+// covers prim hierarchies with xform ops, Mesh prims and UsdPreviewSurface materials,
+// composition arcs (references, payloads, variants) and GeomSubsets are ignored
+
+#[derive(Debug, Clone, PartialEq)]
+enum UsdToken {
+    Ident(String),
+    Str(String),
+    Path(String),
+    Number(f64),
+    Punct(char),
+}
+
+#[derive(Debug, Clone)]
+enum UsdValue {
+    Number(f64),
+    Str(String),
+    Path(String),
+    Ident(String),
+    Tuple(Vec<UsdValue>),
+    List(Vec<UsdValue>),
+}
+
+impl UsdValue {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            UsdValue::Number(n) => Some(*n as f32),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            UsdValue::Str(s) | UsdValue::Ident(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    // rel targets may be written as a single path or a list of them
+    fn as_path(&self) -> Option<&str> {
+        match self {
+            UsdValue::Path(p) => Some(p),
+            UsdValue::List(list) => list.first()?.as_path(),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> &[UsdValue] {
+        match self {
+            UsdValue::Tuple(list) | UsdValue::List(list) => list,
+            _ => &[],
+        }
+    }
+
+    fn as_vec3(&self) -> Option<Vec3> {
+        match self.as_list() {
+            [x, y, z] => Some(Vec3::new(x.as_f32()?, y.as_f32()?, z.as_f32()?)),
+            _ => None,
+        }
+    }
+
+    fn as_vec2(&self) -> Option<[f32; 2]> {
+        match self.as_list() {
+            [x, y] => Some([x.as_f32()?, y.as_f32()?]),
+            _ => None,
+        }
+    }
+
+    fn as_vec3_list(&self) -> Vec<Vec3> {
+        self.as_list().iter().filter_map(|v| v.as_vec3()).collect()
+    }
+
+    fn as_index_list(&self) -> Vec<usize> {
+        self.as_list().iter().filter_map(|v| v.as_f32()).map(|v| v as usize).collect()
+    }
+}
+
+fn usd_tokenize(text: &str) -> Vec<(UsdToken, usize)> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            line += 1;
+            chars.next();
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            // comments, including the "#usda 1.0" header
+            while chars.peek().is_some_and(|&c| c != '\n') {
+                chars.next();
+            }
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let start_line = line;
+            // """triple quoted""" strings may span lines
+            let triple = chars.clone().take(2).all(|next| next == c);
+            if triple {
+                chars.next();
+                chars.next();
+            }
+
+            let mut s = String::new();
+            while let Some(next) = chars.next() {
+                if next == '\\' {
+                    if let Some(escaped) = chars.next() {
+                        s.push(escaped);
+                    }
+                    continue;
                 }
-            } else {
-                let parts: Vec<&str> = trimmed.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    let mut tri = Triangle::default();
-                    tri.vertex_0 = verts[parts[1].parse::<usize>().unwrap() - 1];
-                    tri.vertex_1 = verts[parts[2].parse::<usize>().unwrap() - 1];
-                    tri.vertex_2 = verts[parts[3].parse::<usize>().unwrap() - 1];
-                    tri.material_id = material_id;
-                    tris.push(tri);
+                if next == '\n' {
+                    line += 1;
+                }
+                if next == c && (!triple || chars.clone().take(2).all(|next| next == c)) {
+                    if triple {
+                        chars.next();
+                        chars.next();
+                    }
+                    break;
+                }
+                s.push(next);
+            }
+            tokens.push((UsdToken::Str(s), start_line));
+        } else if c == '<' {
+            chars.next();
+            let path: String = chars.by_ref().take_while(|&c| c != '>').collect();
+            tokens.push((UsdToken::Path(path), line));
+        } else if c == '@' {
+            // asset paths are kept as plain strings
+            chars.next();
+            let path: String = chars.by_ref().take_while(|&c| c != '@').collect();
+            tokens.push((UsdToken::Str(path), line));
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let mut s = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_ascii_alphanumeric() || matches!(next, '.' | '-' | '+') {
+                    s.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match s.parse::<f64>() {
+                Ok(n) => tokens.push((UsdToken::Number(n), line)),
+                Err(_) => tokens.push((UsdToken::Ident(s), line)),
+            }
+        } else if c.is_alphanumeric() || matches!(c, '_' | '!') {
+            let mut s = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_alphanumeric() || matches!(next, '_' | ':' | '.' | '!') {
+                    s.push(next);
+                    chars.next();
+                } else {
+                    break;
                 }
             }
+            // array types such as point3f[]
+            if chars.clone().take(2).eq(['[', ']']) {
+                chars.next();
+                chars.next();
+                s.push_str("[]");
+            }
+            tokens.push((UsdToken::Ident(s), line));
+        } else {
+            chars.next();
+            if matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | '=' | ',' | ';') {
+                tokens.push((UsdToken::Punct(c), line));
+            }
         }
     }
 
-    tris
+    tokens
+}
+
+#[derive(Debug, Default)]
+struct UsdPrim {
+    type_name: String,
+    path: String,
+    attributes: HashMap<String, UsdValue>,
+    children: Vec<UsdPrim>,
+}
+
+struct UsdParser {
+    tokens: Vec<(UsdToken, usize)>,
+    pos: usize,
+}
+
+impl UsdParser {
+    fn peek(&self) -> Option<&UsdToken> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).map_or(usize::MAX, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Option<UsdToken> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    // values in ( ) [ ] { } are parsed generically, dictionaries become lists
+    fn parse_value(&mut self) -> UsdValue {
+        match self.next() {
+            Some(UsdToken::Number(n)) => UsdValue::Number(n),
+            Some(UsdToken::Str(s)) => UsdValue::Str(s),
+            Some(UsdToken::Path(p)) => UsdValue::Path(p),
+            Some(UsdToken::Ident(s)) => UsdValue::Ident(s),
+            Some(UsdToken::Punct(open @ ('(' | '[' | '{'))) => {
+                let close = match open {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                let mut values = vec![];
+                loop {
+                    match self.peek() {
+                        None => break,
+                        Some(UsdToken::Punct(c)) if *c == close => {
+                            self.next();
+                            break;
+                        },
+                        Some(UsdToken::Punct(',' | '=' | ';')) => {
+                            self.next();
+                        },
+                        _ => values.push(self.parse_value()),
+                    }
+                }
+                if open == '(' {
+                    UsdValue::Tuple(values)
+                } else {
+                    UsdValue::List(values)
+                }
+            },
+            _ => UsdValue::Ident(String::new()),
+        }
+    }
+
+    // key = value pairs of a ( ) metadata block, list edits like `prepend` are dropped
+    fn parse_metadata(&mut self) -> HashMap<String, UsdValue> {
+        let mut metadata = HashMap::new();
+        self.next();
+
+        while let Some(token) = self.next() {
+            match token {
+                UsdToken::Punct(')') => break,
+                UsdToken::Ident(key) if self.peek() == Some(&UsdToken::Punct('=')) => {
+                    self.next();
+                    let value = self.parse_value();
+                    metadata.insert(key, value);
+                },
+                UsdToken::Punct('(' | '[' | '{') => {
+                    self.pos -= 1;
+                    self.parse_value();
+                },
+                _ => (),
+            }
+        }
+
+        metadata
+    }
+
+    // after the def/over/class specifier
+    fn parse_prim(&mut self, parent_path: &str) -> UsdPrim {
+        let mut prim = UsdPrim::default();
+        if let Some(UsdToken::Ident(type_name)) = self.peek() {
+            prim.type_name = type_name.clone();
+            self.next();
+        }
+        if let Some(UsdToken::Str(name)) = self.peek() {
+            prim.path = format!("{}/{}", parent_path, name);
+            self.next();
+        }
+        if self.peek() == Some(&UsdToken::Punct('(')) {
+            self.parse_metadata();
+        }
+        if self.peek() != Some(&UsdToken::Punct('{')) {
+            return prim;
+        }
+        self.next();
+
+        loop {
+            match self.peek() {
+                None => break,
+                Some(UsdToken::Punct('}')) => {
+                    self.next();
+                    break;
+                },
+                Some(UsdToken::Ident(s)) if matches!(s.as_str(), "def" | "over" | "class") => {
+                    self.next();
+                    let child = self.parse_prim(&prim.path);
+                    prim.children.push(child);
+                },
+                _ => self.parse_property(&mut prim),
+            }
+        }
+
+        prim
+    }
+
+    // `[qualifiers] type name [= value] [(metadata)]`, the name is the last word before `=`
+    fn parse_property(&mut self, prim: &mut UsdPrim) {
+        let line = self.line();
+        let mut name = None;
+        let mut value = None;
+
+        while self.line() == line {
+            match self.peek() {
+                Some(UsdToken::Ident(word)) => {
+                    name = Some(word.clone());
+                    self.next();
+                },
+                Some(UsdToken::Str(_)) => {
+                    self.next();
+                },
+                Some(UsdToken::Punct('=')) => {
+                    self.next();
+                    value = Some(self.parse_value());
+                    break;
+                },
+                _ => break,
+            }
+        }
+
+        let metadata = if self.peek() == Some(&UsdToken::Punct('(')) {
+            self.parse_metadata()
+        } else {
+            HashMap::new()
+        };
+
+        let Some(name) = name else {
+            // stray token, skip it so parsing always makes progress
+            if value.is_none() && metadata.is_empty() {
+                self.next();
+            }
+            return;
+        };
+        if let Some(interpolation) = metadata.get("interpolation") {
+            prim.attributes.insert(format!("{}:interpolation", name), interpolation.clone());
+        }
+        if let Some(value) = value {
+            prim.attributes.insert(name, value);
+        }
+    }
+}
+
+// composes the ops listed in xformOpOrder, rotations are in degrees
+fn usd_local_transform(prim: &UsdPrim) -> Mat4 {
+    let mut transform = Mat4::identity();
+    let Some(order) = prim.attributes.get("xformOpOrder") else {
+        return transform;
+    };
+
+    for op in order.as_list().iter().filter_map(|op| op.as_str()) {
+        let (invert, op) = match op.strip_prefix("!invert!") {
+            Some(op) => (true, op),
+            None => (false, op),
+        };
+        let Some(value) = prim.attributes.get(op) else {
+            continue;
+        };
+        // xformOp:<kind>[:<suffix>]
+        let kind = op.split(':').nth(1).unwrap_or("");
+
+        let op_transform = match kind {
+            "translate" => value.as_vec3().map(|t| Mat4::translation(if invert { -t } else { t })),
+            "scale" => value.as_vec3().map(Mat4::scale),
+            "rotateX" => value.as_f32().map(|a| Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), a.to_radians())),
+            "rotateY" => value.as_f32().map(|a| Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), a.to_radians())),
+            "rotateZ" => value.as_f32().map(|a| Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), a.to_radians())),
+            "orient" => match value.as_list() {
+                // quaternion written as (w, x, y, z)
+                [w, x, y, z] => {
+                    let w = w.as_f32().unwrap_or(1.0).clamp(-1.0, 1.0);
+                    let axis = Vec3::new(
+                        x.as_f32().unwrap_or(0.0),
+                        y.as_f32().unwrap_or(0.0),
+                        z.as_f32().unwrap_or(0.0),
+                    );
+                    if axis.length_squared() > 0.0 {
+                        Some(Mat4::rotation(axis, 2.0 * w.acos()))
+                    } else {
+                        None
+                    }
+                },
+                _ => None,
+            },
+            // usd matrices act on row vectors, so transpose
+            "transform" => {
+                let rows = value.as_list();
+                let mut m = Mat4::identity();
+                if rows.len() == 4 {
+                    for (i, row) in rows.iter().enumerate() {
+                        for (j, v) in row.as_list().iter().take(4).enumerate() {
+                            m.0[j][i] = v.as_f32().unwrap_or(0.0);
+                        }
+                    }
+                }
+                Some(m)
+            },
+            // rotateXYZ applies X first, then Y, then Z
+            _ => kind.strip_prefix("rotate").zip(value.as_vec3()).map(|(axes, angles)| {
+                let mut m = Mat4::identity();
+                for axis in axes.chars() {
+                    let (axis, angle) = match axis {
+                        'X' => (Vec3::new(1.0, 0.0, 0.0), angles.x()),
+                        'Y' => (Vec3::new(0.0, 1.0, 0.0), angles.y()),
+                        _ => (Vec3::new(0.0, 0.0, 1.0), angles.z()),
+                    };
+                    m = Mat4::rotation(axis, angle.to_radians()) * m;
+                }
+                m
+            }),
+        };
+
+        if let Some(op_transform) = op_transform {
+            transform = transform * op_transform;
+        }
+    }
+
+    transform
+}
+
+fn convert_preview_surface(shader: &UsdPrim) -> Material {
+    let input = |name: &str| shader.attributes.get(&format!("inputs:{}", name));
+    let color = input("diffuseColor")
+        .and_then(|v| v.as_vec3())
+        .unwrap_or(Vec3::all(0.18));
+
+    let number = |name: &str, default: f32| input(name).and_then(|v| v.as_f32()).unwrap_or(default);
+
+    // same mapping as the glTF importer
+    let mut converted = Material::principled(color, number("metallic", 0.0), number("roughness", 0.5));
+    // an opacity threshold marks cutouts (foliage), otherwise opacity is glass like
+    if number("opacityThreshold", 0.0) > 0.0 {
+        converted.opacity = number("opacity", 1.0);
+    } else {
+        converted.transmission = 1.0 - number("opacity", 1.0);
+    }
+    converted.ior = number("ior", 1.5);
+    converted.clearcoat = number("clearcoat", 0.0);
+    converted.clearcoat_roughness = number("clearcoatRoughness", 0.01);
+
+    if let Some(emissive) = input("emissiveColor").and_then(|v| v.as_vec3()) {
+        apply_emission(&mut converted, emissive);
+    }
+
+    converted
+}
+
+fn find_preview_surface(prim: &UsdPrim) -> Option<&UsdPrim> {
+    prim.children.iter().find_map(|child| {
+        let is_preview_surface = child.type_name == "Shader"
+            && child.attributes.get("info:id").and_then(|v| v.as_str()) == Some("UsdPreviewSurface");
+        if is_preview_surface {
+            Some(child)
+        } else {
+            find_preview_surface(child)
+        }
+    })
+}
+
+fn find_usd_prim<'a>(prim: &'a UsdPrim, path: &str) -> Option<&'a UsdPrim> {
+    if prim.path == path {
+        return Some(prim);
+    }
+    prim.children.iter().find_map(|child| find_usd_prim(child, path))
+}
+
+// the UsdUVTexture image connected to a shader input, resolved relative to the usda file
+fn load_usd_texture(
+    material: &UsdPrim,
+    shader: &UsdPrim,
+    input: &str,
+    dir: &std::path::Path,
+) -> Option<image::RgbaImage> {
+    let target = shader.attributes.get(&format!("inputs:{}.connect", input))?.as_path()?;
+    // </Material/Texture.outputs:rgb>
+    let texture_path = target.split('.').next()?;
+    let file = find_usd_prim(material, texture_path)?.attributes.get("inputs:file")?.as_str()?;
+
+    load_texture_image(&dir.join(file))
+}
+
+fn collect_usd_materials(
+    prim: &UsdPrim,
+    dir: &std::path::Path,
+    scene: &mut ImportedScene,
+    paths: &mut HashMap<String, u32>,
+) {
+    if prim.type_name == "Material" {
+        let shader = find_preview_surface(prim);
+        let mut material = shader.map_or(Material::default(), convert_preview_surface);
+        let mut load_texture = |input: &str| {
+            let image = load_usd_texture(prim, shader?, input, dir)?;
+            scene.textures.push(image);
+            Some(scene.textures.len() as u32)
+        };
+        material.albedo_texture = load_texture("diffuseColor").unwrap_or(0);
+        material.roughness_texture = load_texture("roughness").unwrap_or(0);
+        material.metallic_texture = load_texture("metallic").unwrap_or(0);
+        // connected inputs have no value of their own to scale the texture with
+        if material.roughness_texture != 0 {
+            material.roughness = 1.0;
+        }
+        if material.metallic_texture != 0 {
+            material.metallic = 1.0;
+        }
+        if let Some(emission_texture) = load_texture("emissiveColor") {
+            apply_emission_texture(&mut material, Vec3::all(1.0), emission_texture);
+        }
+        paths.insert(prim.path.clone(), scene.materials.len() as u32);
+        scene.materials.push(material);
+    }
+
+    for child in prim.children.iter() {
+        collect_usd_materials(child, dir, scene, paths);
+    }
+}
+
+// looks up a primvar element for a face corner by its interpolation
+fn usd_primvar_at<'a, T>(values: &'a [T], interpolation: &str, face: usize, corner: usize, point: usize) -> Option<&'a T> {
+    match interpolation {
+        "faceVarying" => values.get(corner),
+        "uniform" => values.get(face),
+        "constant" => values.first(),
+        _ => values.get(point),
+    }
+}
+
+fn load_usd_prim(
+    prim: &UsdPrim,
+    parent_transform: Mat4,
+    materials: &HashMap<String, u32>,
+    scene: &mut ImportedScene,
+) {
+    let world = parent_transform * usd_local_transform(prim);
+
+    if prim.type_name == "Mesh" {
+        let attributes = &prim.attributes;
+        let points: Vec<Vec3> = attributes
+            .get("points")
+            .map_or(vec![], |v| v.as_vec3_list())
+            .into_iter()
+            .map(|p| world.transform_point(p))
+            .collect();
+        let counts = attributes.get("faceVertexCounts").map_or(vec![], |v| v.as_index_list());
+        let indices = attributes.get("faceVertexIndices").map_or(vec![], |v| v.as_index_list());
+
+        // normals may be authored as an attribute or as an (optionally indexed) primvar
+        let (normal_name, normal_indices) = if attributes.contains_key("primvars:normals") {
+            ("primvars:normals", attributes.get("primvars:normals:indices").map(|v| v.as_index_list()))
+        } else {
+            ("normals", None)
+        };
+        let mut normals: Vec<Vec3> = attributes
+            .get(normal_name)
+            .map_or(vec![], |v| v.as_vec3_list())
+            .into_iter()
+            .map(|n| world.transform_normal(n).normalized())
+            .collect();
+        if let Some(normal_indices) = normal_indices {
+            normals = normal_indices.iter().filter_map(|&i| normals.get(i).copied()).collect();
+        }
+        let interpolation = attributes
+            .get(&format!("{}:interpolation", normal_name))
+            .and_then(|v| v.as_str())
+            .unwrap_or("vertex");
+
+        // usd t points up the image
+        let mut uvs: Vec<[f32; 2]> = attributes
+            .get("primvars:st")
+            .map_or(vec![], |v| v.as_list().iter().filter_map(|uv| uv.as_vec2()).collect())
+            .into_iter()
+            .map(|[s, t]| [s, 1.0 - t])
+            .collect();
+        if let Some(uv_indices) = attributes.get("primvars:st:indices").map(|v| v.as_index_list()) {
+            uvs = uv_indices.iter().filter_map(|&i| uvs.get(i).copied()).collect();
+        }
+        let uv_interpolation = attributes
+            .get("primvars:st:interpolation")
+            .and_then(|v| v.as_str())
+            .unwrap_or("vertex");
+
+        let left_handed = attributes.get("orientation").and_then(|v| v.as_str()) == Some("leftHanded");
+        let material_id = attributes
+            .get("material:binding")
+            .and_then(|v| v.as_path())
+            .and_then(|path| materials.get(path))
+            .copied()
+            .unwrap_or(0);
+
+        let mut corner = 0;
+        for (face, &count) in counts.iter().enumerate() {
+            let face_indices = indices.get(corner..corner + count).unwrap_or(&[]);
+            let valid = face_indices.len() == count && face_indices.iter().all(|&i| i < points.len());
+
+            if valid {
+                let normal_at = |k: usize| usd_primvar_at(&normals, interpolation, face, corner + k, face_indices[k]);
+                let uv_at = |k: usize| usd_primvar_at(&uvs, uv_interpolation, face, corner + k, face_indices[k]);
+
+                for i in 1..count.saturating_sub(1) {
+                    let mut ks = [0, i, i + 1];
+                    if left_handed {
+                        ks.swap(1, 2);
+                    }
+
+                    let mut tri = Triangle::new(ks.map(|k| points[face_indices[k]]), material_id);
+                    if let [Some(n0), Some(n1), Some(n2)] = ks.map(normal_at) {
+                        tri.set_normals([*n0, *n1, *n2]);
+                    }
+                    if let [Some(uv0), Some(uv1), Some(uv2)] = ks.map(uv_at) {
+                        tri.set_uvs([*uv0, *uv1, *uv2]);
+                    }
+                    scene.triangles.push(tri);
+                }
+            }
+
+            corner += count;
+        }
+    }
+
+    for child in prim.children.iter() {
+        load_usd_prim(child, world, materials, scene);
+    }
+}
+
+// loads meshes, transforms and UsdPreviewSurface materials from a flattened .usda file
+// material 0 is a default material for meshes without a binding
+pub fn load_usda_from(filename: &str) -> Result<ImportedScene, MeshLoadError> {
+    let mut scene = ImportedScene::default();
+
+    let text = std::fs::read_to_string(filename).map_err(|source| MeshLoadError::Io {
+        path: filename.to_string(),
+        source,
+    })?;
+    // the rest of the reader skips what it does not understand, so without this
+    // anything would load as an empty scene
+    if !text.trim_start_matches('\u{feff}').starts_with("#usda") {
+        return Err(MeshLoadError::Parse {
+            path: filename.to_string(),
+            line: 1,
+            message: "missing the #usda header".to_string(),
+        });
+    }
+
+    let mut parser = UsdParser {
+        tokens: usd_tokenize(&text),
+        pos: 0,
+    };
+    let layer_metadata = if parser.peek() == Some(&UsdToken::Punct('(')) {
+        parser.parse_metadata()
+    } else {
+        HashMap::new()
+    };
+
+    let mut root = UsdPrim::default();
+    while let Some(token) = parser.next() {
+        if let UsdToken::Ident(s) = token
+            && matches!(s.as_str(), "def" | "over" | "class")
+        {
+            let prim = parser.parse_prim("");
+            root.children.push(prim);
+        }
+    }
+
+    // the tracer is Y up
+    let root_transform = match layer_metadata.get("upAxis").and_then(|v| v.as_str()) {
+        Some("Z") => Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), -std::f32::consts::FRAC_PI_2),
+        _ => Mat4::identity(),
+    };
+
+    scene.materials.push(Material::default());
+    let mut material_paths = HashMap::new();
+    let dir = std::path::Path::new(filename).parent().unwrap_or(std::path::Path::new(""));
+    collect_usd_materials(&root, dir, &mut scene, &mut material_paths);
+    load_usd_prim(&root, root_transform, &material_paths, &mut scene);
+
+    Ok(scene)
+}
+
+// minimal binary FBX (6.1 to 7.x) reader
+// covers Model hierarchies with their Lcl/Geometric transforms, Mesh geometry with
+// normals and per polygon material slots, and Phong/Lambert materials
+// ascii FBX, animation, skinning and unit/axis conversion are not handled
+
+#[derive(Debug, Clone)]
+enum FbxProperty {
+    Int(i64),
+    Float(f64),
+    String(String),
+    IntArray(Vec<i64>),
+    FloatArray(Vec<f64>),
+    Other,
+}
+
+impl FbxProperty {
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            FbxProperty::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            FbxProperty::Int(i) => Some(*i as f64),
+            FbxProperty::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    // object names are stored as "name\0\x01class"
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            FbxProperty::String(s) => s.split('\0').next(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FbxNode {
+    name: String,
+    properties: Vec<FbxProperty>,
+    children: Vec<FbxNode>,
+}
+
+impl FbxNode {
+    fn child(&self, name: &str) -> Option<&FbxNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn int_array(&self, name: &str) -> Vec<i64> {
+        match self.child(name).and_then(|c| c.properties.first()) {
+            Some(FbxProperty::IntArray(a)) => a.clone(),
+            _ => vec![],
+        }
+    }
+
+    fn float_array(&self, name: &str) -> Vec<f64> {
+        match self.child(name).and_then(|c| c.properties.first()) {
+            Some(FbxProperty::FloatArray(a)) => a.clone(),
+            _ => vec![],
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.child(name)?.properties.first()?.as_str()
+    }
+
+    // numeric values of a Properties70 entry, `P: "name", "type", "label", "flags", values...`
+    fn property70(&self, name: &str) -> Option<Vec<f64>> {
+        let properties = self.child("Properties70").or_else(|| self.child("Properties60"))?;
+        let entry = properties
+            .children
+            .iter()
+            .find(|p| p.properties.first().and_then(|p| p.as_str()) == Some(name))?;
+        Some(entry.properties.iter().skip(1).filter_map(|p| p.as_f64()).collect())
+    }
+
+    fn property70_vec3(&self, name: &str) -> Option<Vec3> {
+        match self.property70(name)?.as_slice() {
+            [x, y, z, ..] => Some(Vec3::new(*x as f32, *y as f32, *z as f32)),
+            _ => None,
+        }
+    }
+
+    fn property70_f32(&self, name: &str) -> Option<f32> {
+        self.property70(name)?.first().map(|v| *v as f32)
+    }
+}
+
+struct FbxReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    // 7.5 and newer use 64 bit offsets in node records
+    wide: bool,
+}
+
+impl FbxReader<'_> {
+    fn bytes(&mut self, count: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(count)?)?;
+        self.pos += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn offset(&mut self) -> Option<u64> {
+        if self.wide {
+            Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+        } else {
+            self.u32().map(|v| v as u64)
+        }
+    }
+
+    // arrays may be zlib compressed
+    fn array(&mut self, element_size: usize) -> Option<Vec<u8>> {
+        let length = self.u32()? as usize;
+        let encoding = self.u32()?;
+        let compressed_length = self.u32()? as usize;
+        let raw = self.bytes(compressed_length)?;
+
+        let mut decoded = Vec::with_capacity(length * element_size);
+        if encoding == 1 {
+            use std::io::Read;
+            flate2::read::ZlibDecoder::new(raw).read_to_end(&mut decoded).ok()?;
+        } else {
+            decoded.extend_from_slice(raw);
+        }
+
+        (decoded.len() >= length * element_size).then_some(decoded)
+    }
+
+    fn property(&mut self) -> Option<FbxProperty> {
+        let le = |b: &[u8]| -> [u8; 8] {
+            let mut out = [0; 8];
+            out[..b.len()].copy_from_slice(b);
+            out
+        };
+
+        let property = match self.u8()? {
+            b'C' => FbxProperty::Int(self.u8()? as i64),
+            b'Y' => FbxProperty::Int(i16::from_le_bytes(self.bytes(2)?.try_into().ok()?) as i64),
+            b'I' => FbxProperty::Int(i32::from_le_bytes(self.bytes(4)?.try_into().ok()?) as i64),
+            b'L' => FbxProperty::Int(i64::from_le_bytes(self.bytes(8)?.try_into().ok()?)),
+            b'F' => FbxProperty::Float(f32::from_le_bytes(self.bytes(4)?.try_into().ok()?) as f64),
+            b'D' => FbxProperty::Float(f64::from_le_bytes(self.bytes(8)?.try_into().ok()?)),
+            b'S' => {
+                let length = self.u32()? as usize;
+                FbxProperty::String(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+            },
+            b'R' => {
+                let length = self.u32()? as usize;
+                self.bytes(length)?;
+                FbxProperty::Other
+            },
+            b'f' => FbxProperty::FloatArray(
+                self.array(4)?.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64).collect(),
+            ),
+            b'd' => FbxProperty::FloatArray(
+                self.array(8)?.chunks_exact(8).map(|b| f64::from_le_bytes(le(b))).collect(),
+            ),
+            b'i' => FbxProperty::IntArray(
+                self.array(4)?.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap()) as i64).collect(),
+            ),
+            b'l' => FbxProperty::IntArray(
+                self.array(8)?.chunks_exact(8).map(|b| i64::from_le_bytes(le(b))).collect(),
+            ),
+            b'b' => FbxProperty::IntArray(self.array(1)?.iter().map(|&b| b as i64).collect()),
+            _ => return None,
+        };
+
+        Some(property)
+    }
+
+    // None on the null record closing a list of nodes (or on malformed data)
+    fn node(&mut self) -> Option<FbxNode> {
+        let end_offset = self.offset()? as usize;
+        let property_count = self.offset()?;
+        let _property_list_length = self.offset()?;
+        let name_length = self.u8()? as usize;
+        if end_offset == 0 {
+            return None;
+        }
+
+        let name = String::from_utf8_lossy(self.bytes(name_length)?).into_owned();
+        let mut properties = vec![];
+        for _ in 0..property_count {
+            properties.push(self.property()?);
+        }
+
+        let mut children = vec![];
+        while self.pos < end_offset {
+            match self.node() {
+                Some(child) => children.push(child),
+                None => break,
+            }
+        }
+        self.pos = end_offset;
+
+        Some(FbxNode {
+            name,
+            properties,
+            children,
+        })
+    }
+}
+
+// euler angles in degrees applied X, then Y, then Z (FBX's default eEulerXYZ order)
+fn fbx_rotation(angles: Vec3) -> Mat4 {
+    Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), angles.z().to_radians())
+        * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angles.y().to_radians())
+        * Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), angles.x().to_radians())
+}
+
+// T * PreRotation * R * S, pivots and offsets are ignored
+fn fbx_local_transform(model: &FbxNode) -> Mat4 {
+    let translation = model.property70_vec3("Lcl Translation").unwrap_or_default();
+    let rotation = model.property70_vec3("Lcl Rotation").unwrap_or_default();
+    let pre_rotation = model.property70_vec3("PreRotation").unwrap_or_default();
+    let scale = model.property70_vec3("Lcl Scaling").unwrap_or(Vec3::all(1.0));
+
+    Mat4::translation(translation) * fbx_rotation(pre_rotation) * fbx_rotation(rotation) * Mat4::scale(scale)
+}
+
+// applied to the geometry only, not inherited by children
+fn fbx_geometric_transform(model: &FbxNode) -> Mat4 {
+    let translation = model.property70_vec3("GeometricTranslation").unwrap_or_default();
+    let rotation = model.property70_vec3("GeometricRotation").unwrap_or_default();
+    let scale = model.property70_vec3("GeometricScaling").unwrap_or(Vec3::all(1.0));
+
+    Mat4::translation(translation) * fbx_rotation(rotation) * Mat4::scale(scale)
+}
+
+fn convert_fbx_material(material: &FbxNode) -> Material {
+    let mut converted = Material::default();
+    if let Some(diffuse) = material.property70_vec3("DiffuseColor") {
+        converted.color = diffuse * material.property70_f32("DiffuseFactor").unwrap_or(1.0);
+    }
+
+    let opacity = material
+        .property70_f32("Opacity")
+        .or_else(|| material.property70_f32("TransparencyFactor").map(|t| 1.0 - t))
+        .unwrap_or(1.0);
+    if opacity < 0.5 {
+        converted.set_type(MaterialType::Dielectric);
+    }
+
+    if let Some(emissive) = material.property70_vec3("EmissiveColor") {
+        apply_emission(&mut converted, emissive * material.property70_f32("EmissiveFactor").unwrap_or(1.0));
+    }
+
+    converted
+}
+
+// corner normals of a Geometry node, indexed by polygon vertex
+fn fbx_corner_normals(geometry: &FbxNode, indices: &[usize]) -> Option<Vec<Vec3>> {
+    let layer = geometry.child("LayerElementNormal")?;
+    let values = layer.float_array("Normals");
+    let normals: Vec<Vec3> = values
+        .chunks_exact(3)
+        .map(|n| Vec3::new(n[0] as f32, n[1] as f32, n[2] as f32))
+        .collect();
+    let lookup = layer.int_array("NormalsIndex");
+    let by_vertex = matches!(layer.string("MappingInformationType"), Some("ByVertice" | "ByVertex"));
+    let indexed = layer.string("ReferenceInformationType") == Some("IndexToDirect");
+
+    (0..indices.len())
+        .map(|corner| {
+            let mut i = if by_vertex { indices[corner] } else { corner };
+            if indexed {
+                i = *lookup.get(i)? as usize;
+            }
+            normals.get(i).copied()
+        })
+        .collect()
+}
+
+fn load_fbx_model(
+    model_id: i64,
+    parent_transform: Mat4,
+    objects: &HashMap<i64, &FbxNode>,
+    children: &HashMap<i64, Vec<i64>>,
+    materials: &HashMap<i64, u32>,
+    scene: &mut ImportedScene,
+) {
+    let Some(model) = objects.get(&model_id) else {
+        return;
+    };
+    let world = parent_transform * fbx_local_transform(model);
+    let connected = children.get(&model_id).map_or(&[][..], |c| c.as_slice());
+
+    // material slots are the connected materials in connection order
+    let slots: Vec<u32> = connected.iter().filter_map(|id| materials.get(id).copied()).collect();
+    let geometry_transform = world * fbx_geometric_transform(model);
+
+    for geometry in connected.iter().filter_map(|id| objects.get(id)) {
+        if geometry.name != "Geometry" {
+            continue;
+        }
+
+        let points: Vec<Vec3> = geometry
+            .float_array("Vertices")
+            .chunks_exact(3)
+            .map(|p| geometry_transform.transform_point(Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32)))
+            .collect();
+        // a negative index (bitwise not) closes each polygon
+        let raw_indices = geometry.int_array("PolygonVertexIndex");
+        let indices: Vec<usize> = raw_indices
+            .iter()
+            .map(|&i| if i < 0 { !i } else { i } as usize)
+            .collect();
+        let normals = fbx_corner_normals(geometry, &indices);
+
+        let layer_material = geometry.child("LayerElementMaterial");
+        let polygon_materials = layer_material.map_or(vec![], |l| l.int_array("Materials"));
+        let all_same = layer_material.and_then(|l| l.string("MappingInformationType")) == Some("AllSame");
+
+        let mut start = 0;
+        let mut polygon = 0;
+        for (end, &raw) in raw_indices.iter().enumerate() {
+            if raw >= 0 {
+                continue;
+            }
+
+            let corners = start..end + 1;
+            start = end + 1;
+            let slot = if all_same { 0 } else { polygon };
+            polygon += 1;
+
+            if corners.clone().any(|c| indices[c] >= points.len()) {
+                continue;
+            }
+            let material_id = polygon_materials
+                .get(slot)
+                .and_then(|&s| slots.get(s as usize))
+                .copied()
+                .unwrap_or(0);
+
+            let first = corners.start;
+            for i in first + 1..corners.end.saturating_sub(1) {
+                let face = [first, i, i + 1];
+                let mut tri = Triangle::new(face.map(|c| points[indices[c]]), material_id);
+                if let Some(normals) = &normals {
+                    tri.set_normals(face.map(|c| geometry_transform.transform_normal(normals[c]).normalized()));
+                }
+                scene.triangles.push(tri);
+            }
+        }
+    }
+
+    for &child in connected.iter() {
+        if objects.get(&child).is_some_and(|node| node.name == "Model") {
+            load_fbx_model(child, world, objects, children, materials, scene);
+        }
+    }
+}
+
+// loads the meshes, model transforms and materials of a binary .fbx file
+// material 0 is a default material for polygons without a slot
+pub fn load_fbx_from(filename: &str) -> Result<ImportedScene, MeshLoadError> {
+    let mut scene = ImportedScene::default();
+    let invalid = |message: &str| MeshLoadError::Invalid {
+        path: filename.to_string(),
+        message: message.to_string(),
+    };
+
+    let data = std::fs::read(filename).map_err(|source| MeshLoadError::Io {
+        path: filename.to_string(),
+        source,
+    })?;
+    if !data.starts_with(b"Kaydara FBX Binary") || data.len() < 27 {
+        return Err(invalid("not a binary FBX file"));
+    }
+
+    let version = u32::from_le_bytes([data[23], data[24], data[25], data[26]]);
+    let mut reader = FbxReader {
+        data: &data,
+        pos: 27,
+        wide: version >= 7500,
+    };
+    let mut nodes = vec![];
+    while let Some(node) = reader.node() {
+        nodes.push(node);
+    }
+
+    let find = |name: &str| nodes.iter().find(|node| node.name == name);
+    let (Some(object_list), Some(connection_list)) = (find("Objects"), find("Connections")) else {
+        return Err(invalid("no Objects or Connections node"));
+    };
+
+    let mut objects: HashMap<i64, &FbxNode> = HashMap::new();
+    for object in object_list.children.iter() {
+        if let Some(id) = object.properties.first().and_then(|p| p.as_i64()) {
+            objects.insert(id, object);
+        }
+    }
+
+    // object-object links, id 0 is the scene root
+    let mut children: HashMap<i64, Vec<i64>> = HashMap::new();
+    for connection in connection_list.children.iter() {
+        if let [kind, child, parent, ..] = connection.properties.as_slice()
+            && kind.as_str() == Some("OO")
+            && let (Some(child), Some(parent)) = (child.as_i64(), parent.as_i64())
+        {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+
+    scene.materials.push(Material::default());
+    let mut materials: HashMap<i64, u32> = HashMap::new();
+    for (id, object) in objects.iter() {
+        if object.name == "Material" {
+            materials.insert(*id, scene.materials.len() as u32);
+            scene.materials.push(convert_fbx_material(object));
+        }
+    }
+
+    for &root_child in children.get(&0).map_or(&[][..], |c| c.as_slice()) {
+        if objects.get(&root_child).is_some_and(|node| node.name == "Model") {
+            load_fbx_model(root_child, Mat4::identity(), &objects, &children, &materials, &mut scene);
+        }
+    }
+
+    Ok(scene)
+}
+
+// same as load_mesh_from but simplifies the mesh down to at most triangle_budget triangles
+pub fn load_mesh_lod_from(
+    filename: &str,
+    material_id: u32,
+    triangle_budget: usize,
+) -> Result<Vec<Triangle>, MeshLoadError> {
+    let tris = load_mesh_from(filename, material_id)?;
+    if tris.len() <= triangle_budget {
+        return Ok(tris);
+    }
+
+    Ok(simplify_mesh(&tris, triangle_budget))
+}
+
+// symmetric 4x4 quadric error matrix stored as its upper triangle
+// see Garland M., Heckbert P., "Surface Simplification Using Quadric Error Metrics"
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(a: f64, b: f64, c: f64, d: f64) -> Self {
+        Self([
+            a * a, a * b, a * c, a * d,
+                   b * b, b * c, b * d,
+                          c * c, c * d,
+                                 d * d,
+        ])
+    }
+
+    fn add(&self, rhs: &Quadric) -> Quadric {
+        let mut q = *self;
+        for i in 0..10 {
+            q.0[i] += rhs.0[i];
+        }
+        q
+    }
+
+    fn scaled(&self, s: f64) -> Quadric {
+        let mut q = *self;
+        for v in q.0.iter_mut() {
+            *v *= s;
+        }
+        q
+    }
+
+    // v^T Q v with v = (x, y, z, 1)
+    fn error(&self, p: Vec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x() as f64, p.y() as f64, p.z() as f64);
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+struct EdgeCollapse {
+    cost: f64,
+    vertices: [usize; 2],
+    // versions of both vertices at the time the cost was computed
+    versions: [u32; 2],
+    target: Vec3,
+    // where target sits on the edge, 0 at the first vertex and 1 at the second
+    blend: f32,
+}
+
+impl PartialEq for EdgeCollapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for EdgeCollapse {}
+
+impl PartialOrd for EdgeCollapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EdgeCollapse {
+    // reversed so that BinaryHeap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+// shading normal and uv of a triangle corner, kept per face so seams survive welding
+type CornerAttributes = [f32; 5];
+
+fn corner_attributes(tri: &Triangle) -> [CornerAttributes; 3] {
+    let normals = [tri.normal_0, tri.normal_1, tri.normal_2];
+    let uvs = [tri.uv_0, tri.uv_1, tri.uv_2];
+    std::array::from_fn(|i| [normals[i].x(), normals[i].y(), normals[i].z(), uvs[i][0], uvs[i][1]])
+}
+
+// quadric error metric edge collapse, reduces the mesh to at most target_count triangles
+// vertices are welded by exact position first, so the mesh should share vertices
+// (as OBJ files do) for the simplification to preserve the surface
+// normals and uvs are interpolated along every collapsed edge
+pub fn simplify_mesh(tris: &[Triangle], target_count: usize) -> Vec<Triangle> {
+    // weld vertices
+    let mut positions: Vec<Vec3> = vec![];
+    let mut lookup: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut faces: Vec<[usize; 3]> = Vec::with_capacity(tris.len());
+    let mut corners: Vec<[CornerAttributes; 3]> = tris.iter().map(corner_attributes).collect();
+    let mut face_alive = vec![true; tris.len()];
+
+    for tri in tris.iter() {
+        let mut face = [0; 3];
+        for (i, v) in [tri.vertex_0, tri.vertex_1, tri.vertex_2].iter().enumerate() {
+            let key = [v.x().to_bits(), v.y().to_bits(), v.z().to_bits()];
+            face[i] = *lookup.entry(key).or_insert_with(|| {
+                positions.push(*v);
+                positions.len() - 1
+            });
+        }
+        faces.push(face);
+    }
+
+    let mut vertex_faces: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    let mut vertex_alive = vec![true; positions.len()];
+    let mut versions = vec![0_u32; positions.len()];
+    let mut alive_count = faces.len();
+
+    for (face_id, face) in faces.iter().enumerate() {
+        let [a, b, c] = face.map(|i| positions[i]);
+        let cross = (b - a).cross(&(c - a));
+        let area = cross.length() * 0.5;
+        if area <= 0.0 {
+            face_alive[face_id] = false;
+            alive_count -= 1;
+            continue;
+        }
+
+        let n = cross.normalized();
+        let d = -n.dot(&a);
+        let q = Quadric::from_plane(n.x() as f64, n.y() as f64, n.z() as f64, d as f64)
+            .scaled(area as f64);
+
+        for &v in face.iter() {
+            quadrics[v] = quadrics[v].add(&q);
+            vertex_faces[v].push(face_id);
+        }
+    }
+
+    let evaluate = |positions: &[Vec3], quadrics: &[Quadric], versions: &[u32], a: usize, b: usize| {
+        let q = quadrics[a].add(&quadrics[b]);
+        let (target, blend, cost) = [0.0, 1.0, 0.5]
+            .iter()
+            .map(|&blend| {
+                let p = positions[a] * (1.0 - blend) + positions[b] * blend;
+                (p, blend, q.error(p))
+            })
+            .min_by(|x, y| x.2.total_cmp(&y.2))
+            .unwrap();
+
+        EdgeCollapse {
+            cost,
+            vertices: [a, b],
+            versions: [versions[a], versions[b]],
+            target,
+            blend,
+        }
+    };
+
+    let mut heap = BinaryHeap::new();
+    for (face_id, face) in faces.iter().enumerate() {
+        if !face_alive[face_id] {
+            continue;
+        }
+        for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+            if a < b {
+                heap.push(evaluate(&positions, &quadrics, &versions, a, b));
+            } else {
+                heap.push(evaluate(&positions, &quadrics, &versions, b, a));
+            }
+        }
+    }
+
+    while alive_count > target_count {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        let [a, b] = collapse.vertices;
+        if !vertex_alive[a] || !vertex_alive[b]
+            || versions[a] != collapse.versions[0]
+            || versions[b] != collapse.versions[1]
+        {
+            continue; // stale entry
+        }
+
+        // reject collapses that flip a surrounding face
+        let flips = [a, b].iter().any(|&v| {
+            vertex_faces[v].iter().any(|&face_id| {
+                let face = faces[face_id];
+                if !face_alive[face_id] || (face.contains(&a) && face.contains(&b)) {
+                    return false;
+                }
+                let [p0, p1, p2] = face.map(|i| positions[i]);
+                let [q0, q1, q2] = face.map(|i| if i == v { collapse.target } else { positions[i] });
+                let before = (p1 - p0).cross(&(p2 - p0));
+                let after = (q1 - q0).cross(&(q2 - q0));
+                before.dot(&after) <= 0.0
+            })
+        });
+        if flips {
+            continue;
+        }
+
+        // the attributes at the target, taken from a face on the edge, give how much the
+        // corners of a and b change, seams stay as far apart as they were
+        let edge_face = vertex_faces[a]
+            .iter()
+            .find(|&&face_id| face_alive[face_id] && faces[face_id].contains(&b));
+        let deltas = edge_face.map(|&face_id| {
+            let corner = |v: usize| corners[face_id][faces[face_id].iter().position(|&i| i == v).unwrap()];
+            let (attributes_a, attributes_b) = (corner(a), corner(b));
+            let target: CornerAttributes = std::array::from_fn(|k| {
+                attributes_a[k] * (1.0 - collapse.blend) + attributes_b[k] * collapse.blend
+            });
+            [attributes_a, attributes_b].map(|attributes| -> CornerAttributes {
+                std::array::from_fn(|k| target[k] - attributes[k])
+            })
+        });
+        if let Some([delta_a, delta_b]) = deltas {
+            for (v, delta) in [(a, delta_a), (b, delta_b)] {
+                for &face_id in vertex_faces[v].iter().filter(|&&face_id| face_alive[face_id]) {
+                    let i = faces[face_id].iter().position(|&i| i == v).unwrap();
+                    for (value, delta) in corners[face_id][i].iter_mut().zip(delta) {
+                        *value += delta;
+                    }
+                }
+            }
+        }
+
+        // collapse b into a
+        positions[a] = collapse.target;
+        quadrics[a] = quadrics[a].add(&quadrics[b]);
+        vertex_alive[b] = false;
+        versions[a] += 1;
+
+        let b_faces = std::mem::take(&mut vertex_faces[b]);
+        for face_id in b_faces {
+            if !face_alive[face_id] {
+                continue;
+            }
+            if faces[face_id].contains(&a) {
+                face_alive[face_id] = false;
+                alive_count -= 1;
+                continue;
+            }
+            for v in faces[face_id].iter_mut() {
+                if *v == b {
+                    *v = a;
+                }
+            }
+            vertex_faces[a].push(face_id);
+        }
+        vertex_faces[a].retain(|&face_id| face_alive[face_id]);
+
+        // requeue edges around the merged vertex
+        let mut neighbors: Vec<usize> = vertex_faces[a]
+            .iter()
+            .flat_map(|&face_id| faces[face_id])
+            .filter(|&v| v != a)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        for n in neighbors {
+            let (x, y) = if a < n { (a, n) } else { (n, a) };
+            heap.push(evaluate(&positions, &quadrics, &versions, x, y));
+        }
+    }
+
+    faces
+        .iter()
+        .zip(corners.iter())
+        .zip(tris.iter())
+        .zip(face_alive.iter())
+        .filter(|(_, alive)| **alive)
+        .map(|(((face, corners), tri), _)| {
+            let mut tri = *tri;
+            tri.vertex_0 = positions[face[0]];
+            tri.vertex_1 = positions[face[1]];
+            tri.vertex_2 = positions[face[2]];
+            // flat shaded triangles have all zero normals and keep them
+            tri.set_normals(corners.map(|c| {
+                let normal = Vec3::new(c[0], c[1], c[2]);
+                if normal.length_squared() > 0.0 { normal.normalized() } else { normal }
+            }));
+            tri.set_uvs(corners.map(|c| [c[3], c[4]]));
+            tri
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Self::I8),
+            "uchar" | "uint8" => Some(Self::U8),
+            "short" | "int16" => Some(Self::I16),
+            "ushort" | "uint16" => Some(Self::U16),
+            "int" | "int32" => Some(Self::I32),
+            "uint" | "uint32" => Some(Self::U32),
+            "float" | "float32" => Some(Self::F32),
+            "double" | "float64" => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // scale that maps integer color channels to [0, 1]
+    fn color_scale(self) -> f32 {
+        match self {
+            Self::I8 | Self::U8 => 1.0 / 255.0,
+            Self::I16 | Self::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+
+    fn read(self, data: &[u8], pos: &mut usize, big_endian: bool) -> Option<f64> {
+        let bytes = data.get(*pos..*pos + self.size())?;
+        *pos += self.size();
+
+        macro_rules! decode {
+            ($t:ty) => {{
+                let raw = bytes.try_into().ok()?;
+                (if big_endian { <$t>::from_be_bytes(raw) } else { <$t>::from_le_bytes(raw) }) as f64
+            }};
+        }
+
+        Some(match self {
+            Self::I8 => decode!(i8),
+            Self::U8 => decode!(u8),
+            Self::I16 => decode!(i16),
+            Self::U16 => decode!(u16),
+            Self::I32 => decode!(i32),
+            Self::U32 => decode!(u32),
+            Self::F32 => decode!(f32),
+            Self::F64 => decode!(f64),
+        })
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    // type of the length prefix for list properties
+    list_count: Option<PlyType>,
+    value_type: PlyType,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+    // the values of every item one after another, see value
+    values: Vec<f64>,
+    // where the values of each property of each item start, plus the end of the last
+    offsets: Vec<usize>,
+}
+
+impl PlyElement {
+    // the value(s) of property j of item i
+    fn value(&self, i: usize, j: usize) -> &[f64] {
+        let cell = i * self.properties.len() + j;
+        &self.values[self.offsets[cell]..self.offsets[cell + 1]]
+    }
+
+    fn property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
+    }
+
+    fn vec3_indices(&self, names: [&str; 3]) -> Option<[usize; 3]> {
+        Some([
+            self.property_index(names[0])?,
+            self.property_index(names[1])?,
+            self.property_index(names[2])?,
+        ])
+    }
+
+    fn get_vec3(&self, row: usize, indices: [usize; 3]) -> Vec3 {
+        let get = |i: usize| self.value(row, i).first().copied().unwrap_or(0.0) as f32;
+        Vec3::new(get(indices[0]), get(indices[1]), get(indices[2]))
+    }
+}
+
+// reads the header and body of a PLY file into a list of elements
+fn read_ply(filename: &str) -> Result<Vec<PlyElement>, MeshLoadError> {
+    let data = std::fs::read(filename).map_err(|source| MeshLoadError::Io {
+        path: filename.to_string(),
+        source,
+    })?;
+
+    parse_ply(&data).ok_or_else(|| MeshLoadError::Invalid {
+        path: filename.to_string(),
+        message: "malformed PLY header or data".to_string(),
+    })
+}
+
+fn parse_ply(data: &[u8]) -> Option<Vec<PlyElement>> {
+    // parse header
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    let mut pos = 0;
+    loop {
+        let line_end = data[pos..].iter().position(|&b| b == b'\n')? + pos;
+        let line = String::from_utf8_lossy(&data[pos..line_end]).trim().to_string();
+        pos = line_end + 1;
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["format", "ascii", ..] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(PlyFormat::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(PlyFormat::BinaryBigEndian),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().ok()?,
+                properties: vec![],
+                values: vec![],
+                offsets: vec![0],
+            }),
+            ["property", "list", count_type, value_type, name] => {
+                elements.last_mut()?.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    list_count: Some(PlyType::parse(count_type)?),
+                    value_type: PlyType::parse(value_type)?,
+                });
+            },
+            ["property", value_type, name] => {
+                elements.last_mut()?.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    list_count: None,
+                    value_type: PlyType::parse(value_type)?,
+                });
+            },
+            ["end_header"] => break,
+            _ => (),
+        }
+    }
+    let format = format?;
+
+    // parse body
+    if format == PlyFormat::Ascii {
+        let body = String::from_utf8_lossy(&data[pos..]);
+        let mut tokens = body.split_whitespace().map(|t| t.parse::<f64>().ok());
+
+        for element in elements.iter_mut() {
+            for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    let len = match property.list_count {
+                        Some(_) => tokens.next()?? as usize,
+                        None => 1,
+                    };
+                    for _ in 0..len {
+                        element.values.push(tokens.next()??);
+                    }
+                    element.offsets.push(element.values.len());
+                }
+            }
+        }
+    } else {
+        let big_endian = format == PlyFormat::BinaryBigEndian;
+
+        for element in elements.iter_mut() {
+            for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    let len = match property.list_count {
+                        Some(count_type) => count_type.read(data, &mut pos, big_endian)? as usize,
+                        None => 1,
+                    };
+                    for _ in 0..len {
+                        element.values.push(property.value_type.read(data, &mut pos, big_endian)?);
+                    }
+                    element.offsets.push(element.values.len());
+                }
+            }
+        }
+    }
+
+    Some(elements)
+}
+
+// loads the vertices of a PLY file as oriented discs
+// per point "radius" overrides the given radius, points without normals face +Y
+pub fn load_point_cloud_from(filename: &str, radius: f32, material_id: u32) -> Result<Vec<Disc>, MeshLoadError> {
+    let mut discs = vec![];
+    let invalid = |message: &str| MeshLoadError::Invalid {
+        path: filename.to_string(),
+        message: message.to_string(),
+    };
+
+    let elements = read_ply(filename)?;
+    let vertex = elements
+        .iter()
+        .find(|e| e.name == "vertex")
+        .ok_or_else(|| invalid("no vertex element"))?;
+
+    let position = vertex
+        .vec3_indices(["x", "y", "z"])
+        .ok_or_else(|| invalid("the vertices have no x, y and z"))?;
+    let normal = vertex.vec3_indices(["nx", "ny", "nz"]);
+    let point_radius = vertex.property_index("radius");
+
+    for i in 0..vertex.count {
+        let center = vertex.get_vec3(i, position);
+        let n = match normal {
+            Some(normal) => vertex.get_vec3(i, normal),
+            None => Vec3::new(0.0, 1.0, 0.0),
+        };
+        if n.length_squared() == 0.0 {
+            continue;
+        }
+        let r = point_radius.map_or(radius, |p| vertex.value(i, p).first().copied().unwrap_or(0.0) as f32);
+
+        discs.push(Disc::new(center, n, r, material_id));
+    }
+
+    Ok(discs)
+}
+
+// loads an ASCII or binary PLY mesh, polygons are fan triangulated
+// per vertex normals (nx, ny, nz) and colors (red, green, blue) are kept when present
+pub fn load_ply_from(filename: &str, material_id: u32) -> Result<Vec<Triangle>, MeshLoadError> {
+    let mut tris = vec![];
+    let invalid = |message: &str| MeshLoadError::Invalid {
+        path: filename.to_string(),
+        message: message.to_string(),
+    };
+
+    let elements = read_ply(filename)?;
+    let (Some(vertex), Some(face)) = (
+        elements.iter().find(|e| e.name == "vertex"),
+        elements.iter().find(|e| e.name == "face"),
+    ) else {
+        return Err(invalid("no vertex or face element"));
+    };
+
+    let position = vertex
+        .vec3_indices(["x", "y", "z"])
+        .ok_or_else(|| invalid("the vertices have no x, y and z"))?;
+    let normal = vertex.vec3_indices(["nx", "ny", "nz"]);
+    let color = vertex.vec3_indices(["red", "green", "blue"]);
+    let color_scale = color.map_or(1.0, |c| vertex.properties[c[0]].value_type.color_scale());
+
+    let indices = face
+        .property_index("vertex_indices")
+        .or_else(|| face.property_index("vertex_index"))
+        .ok_or_else(|| invalid("the faces have no vertex_indices"))?;
+
+    for row in 0..face.count {
+        let polygon: Vec<usize> = face.value(row, indices).iter().map(|&i| i as usize).collect();
+        if polygon.iter().any(|&i| i >= vertex.count) {
+            continue;
+        }
+
+        for i in 1..polygon.len().saturating_sub(1) {
+            let corners = [polygon[0], polygon[i], polygon[i + 1]];
+
+            let mut tri = Triangle::new(corners.map(|c| vertex.get_vec3(c, position)), material_id);
+            if let Some(normal) = normal {
+                tri.set_normals(corners.map(|c| vertex.get_vec3(c, normal).normalized()));
+            }
+            if let Some(color) = color {
+                tri.set_colors(corners.map(|c| vertex.get_vec3(c, color) * color_scale));
+            }
+            tris.push(tri);
+        }
+    }
+
+    Ok(tris)
+}
+
+// loads an ASCII or binary STL mesh, STL carries no materials so every face uses material_id
+// facet normals are ignored, the winding already encodes the orientation
+pub fn load_stl_from(filename: &str, material_id: u32) -> Result<Vec<Triangle>, MeshLoadError> {
+    let mut tris = vec![];
+
+    let data = std::fs::read(filename).map_err(|source| MeshLoadError::Io {
+        path: filename.to_string(),
+        source,
+    })?;
+
+    // some binary exporters also start their header with "solid",
+    // so trust the size implied by the triangle count first
+    let binary_count = data
+        .get(80..84)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize);
+    let is_binary = binary_count.is_some_and(|count| data.len() == 84 + count * 50)
+        || !data.starts_with(b"solid");
+
+    if is_binary {
+        let count = binary_count.ok_or_else(|| MeshLoadError::Invalid {
+            path: filename.to_string(),
+            message: "too short for a binary STL header".to_string(),
+        })?;
+        if data.len() < 84 + count * 50 {
+            return Err(MeshLoadError::Invalid {
+                path: filename.to_string(),
+                message: format!("truncated, the header announces {} triangles", count),
+            });
+        }
+        let read_vec3 = |offset: usize| {
+            let f = |i: usize| {
+                let b = &data[offset + i * 4..offset + i * 4 + 4];
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            };
+            Vec3::new(f(0), f(1), f(2))
+        };
+
+        for i in 0..count {
+            // normal (12 bytes), 3 vertices (36 bytes), attribute byte count (2 bytes)
+            let offset = 84 + i * 50;
+            let vertices = [read_vec3(offset + 12), read_vec3(offset + 24), read_vec3(offset + 36)];
+            tris.push(Triangle::new(vertices, material_id));
+        }
+    } else {
+        let text = String::from_utf8_lossy(&data);
+        let mut vertices = vec![];
+
+        for (line_index, line) in text.lines().enumerate() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["vertex", coords @ ..] => {
+                    let vertex: Option<Vec<f32>> = coords.iter().map(|c| f32::from_str(c).ok()).collect();
+                    match vertex.as_deref() {
+                        Some(&[x, y, z]) => vertices.push(Vec3::new(x, y, z)),
+                        _ => {
+                            return Err(MeshLoadError::Parse {
+                                path: filename.to_string(),
+                                line: line_index + 1,
+                                message: format!("invalid vertex '{}'", line.trim()),
+                            });
+                        },
+                    }
+                },
+                ["endfacet"] => {
+                    if vertices.len() == 3 {
+                        tris.push(Triangle::new([vertices[0], vertices[1], vertices[2]], material_id));
+                    }
+                    vertices.clear();
+                },
+                _ => (),
+            }
+        }
+    }
+
+    Ok(tris)
+}
+
+// voxel densities over an axis aligned box, x varies fastest then y then z
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    pub resolution: [u32; 3],
+    pub densities: Vec<f32>,
+    pub bbox_min: Vec3,
+    pub bbox_max: Vec3,
+}
+
+// loads a Mitsuba style binary .vol grid, only the first channel is kept
+// the header is "VOL", version 3, encoding (1 = f32, 3 = u8), x/y/z resolution,
+// channel count and the bounding box, all little endian
+pub fn load_vol_from(filename: &str) -> Option<DensityGrid> {
+    let data = match std::fs::read(filename) {
+        Ok(d) => d,
+        Err(_) => {
+            println!("failed to load file {}", filename);
+            return None;
+        }
+    };
+
+    if data.len() < 48 || &data[0..3] != b"VOL" || data[3] != 3 {
+        println!("{} is not a version 3 .vol file", filename);
+        return None;
+    }
+
+    let read_i32 = |offset: usize| {
+        i32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+    };
+    let read_f32 = |offset: usize| {
+        f32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+    };
+
+    let encoding = read_i32(4);
+    let resolution = [read_i32(8), read_i32(12), read_i32(16)];
+    let channels = read_i32(20);
+    if resolution.iter().any(|&r| r <= 0) || channels <= 0 {
+        println!("invalid grid size in {}", filename);
+        return None;
+    }
+    let resolution = resolution.map(|r| r as u32);
+    let channels = channels as usize;
+
+    let bbox_min = Vec3::new(read_f32(24), read_f32(28), read_f32(32));
+    let bbox_max = Vec3::new(read_f32(36), read_f32(40), read_f32(44));
+
+    let voxel_count = resolution.iter().map(|&r| r as usize).product::<usize>();
+    let value_size = match encoding {
+        1 => 4,
+        3 => 1,
+        _ => {
+            println!("unsupported .vol encoding {} in {}", encoding, filename);
+            return None;
+        }
+    };
+    if data.len() < 48 + voxel_count * channels * value_size {
+        println!("truncated .vol file {}", filename);
+        return None;
+    }
+
+    let densities = (0..voxel_count)
+        .map(|i| {
+            let offset = 48 + i * channels * value_size;
+            match encoding {
+                1 => read_f32(offset),
+                _ => data[offset] as f32 / 255.0,
+            }
+        })
+        .collect();
+
+    Some(DensityGrid {
+        resolution,
+        densities,
+        bbox_min,
+        bbox_max,
+    })
+}
+
+// reads a .hdr/.exr (or any format the image crate knows) as linear rgba floats
+pub fn load_hdr_image_from(filename: &str) -> Option<image::Rgba32FImage> {
+    match image::open(filename) {
+        Ok(img) => Some(img.into_rgba32f()),
+        Err(e) => {
+            println!("failed to load image {}: {}", filename, e);
+            None
+        }
+    }
+}
+
+// candela values of a photometric (IES LM-63) file, type C photometry where vertical
+// angles start at 0 straight down the light's axis and horizontal angles go around it
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    pub vertical_angles: Vec<f32>,
+    pub horizontal_angles: Vec<f32>,
+    // one run of vertical angles per horizontal angle
+    pub candela: Vec<f32>,
+}
+
+impl IesProfile {
+    // candela towards vertical and horizontal angles in degrees, 0 outside the measured
+    // vertical range, horizontal symmetry in the file is unfolded
+    pub fn sample(&self, vertical: f32, horizontal: f32) -> f32 {
+        let horizontal = horizontal.rem_euclid(360.0);
+        let horizontal = match self.horizontal_angles.last().copied().unwrap_or(0.0) {
+            // rotationally symmetric
+            last if last <= 0.0 => 0.0,
+            // symmetric in each quadrant
+            last if last <= 90.0 => {
+                let h = if horizontal > 180.0 { 360.0 - horizontal } else { horizontal };
+                if h > 90.0 { 180.0 - h } else { h }
+            },
+            // symmetric about the 0-180 plane
+            last if last <= 180.0 => if horizontal > 180.0 { 360.0 - horizontal } else { horizontal },
+            _ => horizontal,
+        };
+
+        // index below the value and how far it is towards the next one
+        let bracket = |angles: &[f32], value: f32| -> Option<(usize, f32)> {
+            let (first, last) = (*angles.first()?, *angles.last()?);
+            if value < first || value > last {
+                return None;
+            }
+            if angles.len() < 2 {
+                return Some((0, 0.0));
+            }
+            let i = angles.partition_point(|&a| a <= value).saturating_sub(1).min(angles.len() - 2);
+            let span = angles[i + 1] - angles[i];
+            Some((i, if span > 0.0 { (value - angles[i]) / span } else { 0.0 }))
+        };
+
+        let vertical_count = self.vertical_angles.len();
+        let Some((v, v_t)) = bracket(&self.vertical_angles, vertical) else {
+            return 0.0;
+        };
+        let (h, h_t) = bracket(&self.horizontal_angles, horizontal).unwrap_or((0, 0.0));
+        let value = |h: usize, v: usize| {
+            let h = h.min(self.horizontal_angles.len() - 1);
+            let v = v.min(vertical_count - 1);
+            self.candela[h * vertical_count + v]
+        };
+
+        let low = value(h, v) * (1.0 - v_t) + value(h, v + 1) * v_t;
+        let high = value(h + 1, v) * (1.0 - v_t) + value(h + 1, v + 1) * v_t;
+        low * (1.0 - h_t) + high * h_t
+    }
+}
+
+// loads an .ies photometric file, the absolute scale is dropped later so the lamp and
+// multiplier fields are not applied
+pub fn load_ies_from(filename: &str) -> Option<IesProfile> {
+    let text = match std::fs::read_to_string(filename) {
+        Ok(t) => t,
+        Err(_) => {
+            println!("failed to load file {}", filename);
+            return None;
+        }
+    };
+
+    let Some(tilt_start) = text.find("TILT=") else {
+        println!("{} has no TILT line, not an .ies file", filename);
+        return None;
+    };
+    let after_tilt = &text[tilt_start..];
+    let tilt_end = after_tilt.find('\n').unwrap_or(after_tilt.len());
+    let tilt = after_tilt[5..tilt_end].trim();
+
+    let numbers: Option<Vec<f32>> = after_tilt[tilt_end..]
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f32>().ok())
+        .collect();
+    let Some(numbers) = numbers else {
+        println!("invalid number in .ies file {}", filename);
+        return None;
+    };
+
+    // tilt data is lamp geometry, the angle count, the angles and the factors
+    let mut start = 0;
+    if tilt == "INCLUDE" {
+        let count = numbers.get(1).copied().unwrap_or(0.0) as usize;
+        start = 2 + count * 2;
+    }
+
+    // lamp count, lumens, multiplier, angle counts, photometric type, units, size,
+    // ballast factor, a reserved field and input watts
+    let header = numbers.get(start..start + 13).unwrap_or_default();
+    if header.len() < 13 || header[5] as u32 != 1 {
+        println!("invalid or unsupported (only type C photometry) .ies file {}", filename);
+        return None;
+    }
+    let vertical_count = header[3] as usize;
+    let horizontal_count = header[4] as usize;
+
+    let angles_start = start + 13;
+    let candela_start = angles_start + vertical_count + horizontal_count;
+    let candela_end = candela_start + vertical_count * horizontal_count;
+    if vertical_count == 0 || horizontal_count == 0 || numbers.len() < candela_end {
+        println!("truncated .ies file {}", filename);
+        return None;
+    }
+
+    Some(IesProfile {
+        vertical_angles: numbers[angles_start..angles_start + vertical_count].to_vec(),
+        horizontal_angles: numbers[angles_start + vertical_count..candela_start].to_vec(),
+        candela: numbers[candela_start..candela_end].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::tracer_struct::pack_color,
+    };
+
+    // writes contents to a file of the temp directory only this test process uses
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("shrimpy_test_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // n by n quads in the xy plane, the uvs are the positions over n
+    fn grid(n: usize) -> Vec<Triangle> {
+        let corner = |x: usize, y: usize| Vec3::new(x as f32, y as f32, 0.0);
+        let uv = |p: Vec3| [p.x() / n as f32, p.y() / n as f32];
+        let mut tris = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                let quad = [corner(x, y), corner(x + 1, y), corner(x + 1, y + 1), corner(x, y + 1)];
+                for face in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                    let mut tri = Triangle::new(face, 0);
+                    tri.set_normals([Vec3::new(0.0, 0.0, 1.0); 3]);
+                    tri.set_uvs(face.map(uv));
+                    tris.push(tri);
+                }
+            }
+        }
+        tris
+    }
+
+    #[test]
+    fn simplify_mesh_reaches_the_target() {
+        let simplified = simplify_mesh(&grid(8), 20);
+        assert!(!simplified.is_empty() && simplified.len() <= 20, "{} triangles", simplified.len());
+    }
+
+    #[test]
+    fn simplify_mesh_keeps_a_flat_surface_in_place() {
+        let n = 8;
+        for tri in simplify_mesh(&grid(n), 20) {
+            for vertex in [tri.vertex_0, tri.vertex_1, tri.vertex_2] {
+                assert_eq!(vertex.z(), 0.0);
+                assert!((0.0..=n as f32).contains(&vertex.x()), "{:?}", vertex);
+                assert!((0.0..=n as f32).contains(&vertex.y()), "{:?}", vertex);
+            }
+        }
+    }
+
+    #[test]
+    fn simplify_mesh_interpolates_normals_and_uvs() {
+        let n = 8;
+        for tri in simplify_mesh(&grid(n), 20) {
+            let vertices = [tri.vertex_0, tri.vertex_1, tri.vertex_2];
+            for (vertex, uv) in vertices.iter().zip([tri.uv_0, tri.uv_1, tri.uv_2]) {
+                assert!((vertex.x() / n as f32 - uv[0]).abs() < 1e-5, "{:?} at {:?}", uv, vertex);
+                assert!((vertex.y() / n as f32 - uv[1]).abs() < 1e-5, "{:?} at {:?}", uv, vertex);
+            }
+            for normal in [tri.normal_0, tri.normal_1, tri.normal_2] {
+                assert!((normal.z() - 1.0).abs() < 1e-5, "{:?}", normal);
+            }
+        }
+    }
+
+    #[test]
+    fn simplify_mesh_keeps_flat_shading() {
+        let mut tris = grid(4);
+        for tri in tris.iter_mut() {
+            tri.set_normals([Vec3::new(0.0, 0.0, 0.0); 3]);
+        }
+        for tri in simplify_mesh(&tris, 8) {
+            assert_eq!(tri.normal_0.length_squared(), 0.0);
+        }
+    }
+
+    #[test]
+    fn obj_index_resolves_relative_and_absolute_indices() {
+        assert_eq!(obj_index("1", 3), Some(0));
+        assert_eq!(obj_index("3", 3), Some(2));
+        assert_eq!(obj_index("-1", 3), Some(2));
+        assert_eq!(obj_index("-3", 3), Some(0));
+        assert_eq!(obj_index("0", 3), None);
+        assert_eq!(obj_index("4", 3), None);
+        assert_eq!(obj_index("-4", 3), None);
+        assert_eq!(obj_index("x", 3), None);
+        assert_eq!(obj_index("", 3), None);
+    }
+
+    #[test]
+    fn obj_quad_is_fan_triangulated_with_attributes() {
+        let path = temp_file("quad.obj", b"\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+f 1/1/1 2/1/1 3/2/1 4/2/1
+");
+        let tris = load_mesh_from(&path, 7).unwrap();
+        assert_eq!(tris.len(), 2);
+        assert_eq!(tris[0].material_id, 7);
+        assert_eq!([tris[1].vertex_1.x(), tris[1].vertex_1.y()], [1.0, 1.0]);
+        // obj v points up the image, ours down
+        assert_eq!(tris[0].uv_0, [0.0, 1.0]);
+        assert_eq!(tris[0].uv_2, [1.0, 0.0]);
+        assert_eq!(tris[0].normal_0.z(), 1.0);
+    }
+
+    #[test]
+    fn obj_negative_indices_count_back() {
+        let path = temp_file("negative.obj", b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n");
+        let tris = load_mesh_from(&path, 0).unwrap();
+        assert_eq!(tris.len(), 1);
+        assert_eq!(tris[0].vertex_2.y(), 1.0);
+        // no normals given means flat shading
+        assert_eq!(tris[0].normal_0.length_squared(), 0.0);
+    }
+
+    #[test]
+    fn obj_materials_follow_exact_keywords() {
+        let mtl = temp_file("keywords.mtl", b"newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n");
+        let mtl_name = std::path::Path::new(&mtl).file_name().unwrap().to_string_lossy().into_owned();
+        let obj = format!(
+            "mtllib {}\nmtllibs {}\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtlx blue\nf 1 2 3\nusemtl blue\nf 1 2 3\n",
+            mtl_name, mtl_name,
+        );
+        let scene = load_mesh_with_materials_from(&temp_file("keywords.obj", obj.as_bytes())).unwrap();
+        // the default material and the two of the mtllib, loaded once
+        assert_eq!(scene.materials.len(), 3);
+        let ids: Vec<u32> = scene.triangles.iter().map(|tri| tri.material_id).collect();
+        assert_eq!(ids, vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn obj_errors_name_the_line() {
+        let path = temp_file("bad_number.obj", b"v 0 0 0\nv 1 nope 0\n");
+        match load_mesh_from(&path, 0) {
+            Err(MeshLoadError::Parse { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("nope"), "{}", message);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let path = temp_file("bad_face.obj", b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n");
+        assert!(matches!(load_mesh_from(&path, 0), Err(MeshLoadError::Parse { line: 4, .. })));
+
+        let path = temp_file("short_face.obj", b"v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert!(matches!(load_mesh_from(&path, 0), Err(MeshLoadError::Parse { line: 3, .. })));
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let path = std::env::temp_dir().join("shrimpy_test_missing").join("mesh");
+        let path = path.to_string_lossy();
+        for extension in ["obj", "ply", "stl"] {
+            let filename = format!("{}.{}", path, extension);
+            let result = load_any_mesh_from(&filename, 0);
+            assert!(matches!(result, Err(MeshLoadError::Io { .. })), "{}: {:?}", extension, result);
+        }
+    }
+
+    const PLY_QUAD: &str = "\
+ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+4 0 1 2 3
+";
+
+    #[test]
+    fn ascii_ply_is_parsed() {
+        let elements = parse_ply(PLY_QUAD.as_bytes()).unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].count, 4);
+        assert_eq!(elements[0].value(2, 1), &[1.0]);
+        assert_eq!(elements[1].value(0, 0), &[0.0, 1.0, 2.0, 3.0]);
+
+        let tris = load_ply_from(&temp_file("quad.ply", PLY_QUAD.as_bytes()), 3).unwrap();
+        assert_eq!(tris.len(), 2);
+        assert_eq!(tris[0].material_id, 3);
+        assert_eq!(tris[0].color_0, pack_color(Vec3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn binary_ply_is_parsed_in_both_byte_orders() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut data = format!(
+                "ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                 element face 1\nproperty list uchar uint vertex_indices\nend_header\n",
+                format,
+            )
+            .into_bytes();
+            for v in [0.0_f32, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0, 0.0] {
+                data.extend(if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+            }
+            data.push(3);
+            for i in 0_u32..3 {
+                data.extend(if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+            }
+
+            let elements = parse_ply(&data).unwrap();
+            assert_eq!(elements[0].value(1, 0), &[2.0], "{}", format);
+            assert_eq!(elements[0].value(2, 1), &[3.0], "{}", format);
+            assert_eq!(elements[1].value(0, 0), &[0.0, 1.0, 2.0], "{}", format);
+
+            // cut into the face list
+            assert!(parse_ply(&data[..data.len() - 2]).is_none(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn broken_ply_files_are_invalid() {
+        let truncated = &PLY_QUAD[..PLY_QUAD.len() - 4];
+        let result = load_ply_from(&temp_file("truncated.ply", truncated.as_bytes()), 0);
+        assert!(matches!(result, Err(MeshLoadError::Invalid { .. })), "{:?}", result);
+
+        let no_faces = PLY_QUAD.replace("element face 1", "element edge 1");
+        let result = load_ply_from(&temp_file("no_faces.ply", no_faces.as_bytes()), 0);
+        assert!(matches!(result, Err(MeshLoadError::Invalid { .. })), "{:?}", result);
+
+        // the point cloud loader only needs the vertices
+        let discs = load_point_cloud_from(&temp_file("cloud.ply", no_faces.as_bytes()), 0.5, 0).unwrap();
+        assert_eq!(discs.len(), 4);
+    }
+
+    fn binary_stl(triangles: &[[f32; 9]]) -> Vec<u8> {
+        let mut data = vec![0; 80];
+        data.extend((triangles.len() as u32).to_le_bytes());
+        for tri in triangles {
+            // the facet normal is ignored
+            data.extend([0_u8; 12]);
+            for v in tri {
+                data.extend(v.to_le_bytes());
+            }
+            data.extend([0_u8; 2]);
+        }
+        data
+    }
+
+    #[test]
+    fn ascii_stl_is_parsed() {
+        let stl = b"\
+solid test
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid test
+";
+        let tris = load_stl_from(&temp_file("ascii.stl", stl), 2).unwrap();
+        assert_eq!(tris.len(), 1);
+        assert_eq!(tris[0].material_id, 2);
+        assert_eq!(tris[0].vertex_1.x(), 1.0);
+    }
+
+    #[test]
+    fn binary_stl_is_parsed_even_when_it_starts_with_solid() {
+        let mut data = binary_stl(&[[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]; 2]);
+        data[..5].copy_from_slice(b"solid");
+        let tris = load_stl_from(&temp_file("binary.stl", &data), 0).unwrap();
+        assert_eq!(tris.len(), 2);
+        assert_eq!(tris[1].vertex_2.y(), 1.0);
+    }
+
+    #[test]
+    fn broken_stl_files_are_errors() {
+        let data = binary_stl(&[[0.0; 9]; 2]);
+        let result = load_stl_from(&temp_file("truncated.stl", &data[..data.len() - 10]), 0);
+        assert!(matches!(result, Err(MeshLoadError::Invalid { .. })), "{:?}", result);
+
+        let result = load_stl_from(&temp_file("short.stl", b"\x00\x01"), 0);
+        assert!(matches!(result, Err(MeshLoadError::Invalid { .. })), "{:?}", result);
+
+        let stl = b"solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n";
+        let result = load_stl_from(&temp_file("bad_vertex.stl", stl), 0);
+        assert!(matches!(result, Err(MeshLoadError::Parse { line: 4, .. })), "{:?}", result);
+    }
 }
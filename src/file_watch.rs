@@ -0,0 +1,52 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+// how often the files are stat-ed, editors often write in several steps
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// polls modification times of a set of files, no platform specific watcher needed
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[String]) -> Self {
+        Self {
+            files: paths
+                .iter()
+                .map(|path| {
+                    let path = PathBuf::from(path);
+                    let modified = modified_time(&path);
+                    (path, modified)
+                })
+                .collect(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    // true when any file was modified, created or removed since the last change was reported
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified_time(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
@@ -1,23 +1,59 @@
 use {
+    crate::animation::CameraPath,
+    crate::assets::{AssetManager, TextureHandle},
+    crate::blue_noise::blue_noise_rgba,
+    crate::file_load::{
+        load_hdr_image_from,
+        load_ies_from,
+        load_mesh_async,
+        DensityGrid,
+        IesProfile,
+        ImportedLightKind,
+        ImportedScene,
+        MeshLoadEvent,
+    },
+    crate::checkpoint::Checkpoint,
+    crate::file_export::export_scene,
+    crate::file_watch::FileWatcher,
+    crate::mat4::Mat4,
+    crate::oidn,
+    crate::photometry,
+    crate::vec3::Vec3,
+    crate::scene_file::{RenderSettings, SceneFile},
     crate::tracer_struct::{
+        AreaLight,
         Camera,
+        Curve,
+        PhysicalCamera,
+        Disc,
+        Fog,
+        LightTreeNode,
         Material,
+        MaterialType,
+        PhysicalSky,
+        PointLight,
+        Portal,
+        Projection,
         Scene,
         Sphere,
+        StereoMode,
+        SunLight,
         Triangle,
+        BVHBuildOptions,
         BVHNode,
+        Volume,
     },
     anyhow::Context,
     bytemuck::{Pod, Zeroable},
+    serde::{Deserialize, Serialize},
     chrono::Local,
-    std::{borrow::Cow, sync::Arc, time::Instant},
-    wgpu,
+    std::{borrow::Cow, collections::BTreeMap, sync::{mpsc, Arc}, time::{Duration, Instant}},
     winit::window::Window
 };
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
-// size 96
+// size 400
 pub struct Uniforms {
     camera: Camera,
     width: u32,
@@ -26,11 +62,445 @@ pub struct Uniforms {
     frame_count: u32,
     pub gamma_correction: f32,
     pub psuedo_chromatic_aberration: f32,
-    _pad0: [u32; 2],
+    // non zero when an environment map replaces the procedural sky
+    use_environment: u32,
+    // a RenderMode
+    render_mode: u32,
+    // non zero when sky replaces the simple gradient, an environment map still wins
+    use_physical_sky: u32,
+    // a Background, the colors below are used by Color and Gradient
+    background: u32,
+    // non zero to find caustics through glass spheres with manifold_caustics
+    manifold_caustics: u32,
+    // yaw of the environment map around the up axis in radians
+    environment_rotation: f32,
+    sky: PhysicalSky,
+    background_bottom: Vec3,
+    // what the environment map radiance is multiplied by
+    environment_intensity: f32,
+    background_top: Vec3,
+    // non zero to tint what is in focus on screen, see set_focus_overlay
+    focus_overlay: u32,
+    // where the camera was at time 0 of the frame, camera is where it is at time 1
+    camera_start_position: Vec3,
+    // the part of the frame the shutter is open for
+    shutter_open: f32,
+    camera_start_direction: Vec3,
+    shutter_close: f32,
+    // darkening towards the corners, by up to strength past radius (1 is a corner)
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    // non zero for the cos^4 falloff of a real lens, see set_physical_vignetting
+    physical_vignetting: u32,
+    // side of the square tiles cs_trace is dispatched over, see set_tile_size
+    tile_size: u32,
+    // non zero to jitter pixels and the lens with the blue noise masks, see set_blue_noise
+    blue_noise: u32,
+    // a Sampler
+    sampler_kind: u32,
+    // firefly suppression, see set_indirect_clamp and set_outlier_rejection
+    indirect_clamp: f32,
+    outlier_rejection: f32,
+    // the camera the last finished samples were traced from, see render_reproject
+    previous_camera: Camera,
+    // non zero for the one sample that carries the accumulation over a camera move
+    reproject: u32,
+    // how many samples the radiance being reprojected holds
+    previous_samples: u32,
+    // non zero when the denoiser draws the frame instead of the trace pass, see set_denoise
+    denoise: u32,
+    // side of the blocks of pixels traced as one, above 1 while the camera moves
+    preview_scale: u32,
+    // width and height are what is traced, the display pass fits that into the window
+    window_width: u32,
+    window_height: u32,
+    // added to the index of every sample for its random numbers, so gpus sharing a render
+    // each trace different samples, see render_claimed_samples
+    sample_offset: u32,
+    _pad0: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+// one step of cs_denoise, size 16
+struct DenoiseParams {
+    step: u32,
+    first: u32,
+    last: u32,
+    _pad0: u32,
+}
+
+// what rays leaving the scene see, for lighting as well as in the frame
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+pub enum Background {
+    Color(Vec3),
+    // blended by height from straight down to straight up
+    Gradient { bottom: Vec3, top: Vec3 },
+    // the environment map, or the physical sky, or the default gradient when neither is set
+    #[default]
+    Environment,
+    // lit like Environment but pixels where the camera sees it are left transparent
+    Transparent,
+}
+
+// one saved image per step of the sun moving through a day, for Gfx::start_sun_sequence
+#[derive(Debug, Copy, Clone)]
+pub struct SunSequence {
+    // clock times in hours of the first and the last frame
+    pub start_time: f32,
+    pub end_time: f32,
+    pub frames: u32,
+    // degrees, north and east are positive
+    pub latitude: f32,
+    pub longitude: f32,
+    // hours the clock times are ahead of utc
+    pub utc_offset: f32,
+    pub day_of_year: u32,
+    // samples accumulated before a frame is saved and the sun moves on
+    pub samples_per_frame: u32,
+    pub format: RenderFormat,
+}
+
+impl SunSequence {
+    pub fn sun_direction(&self, frame: u32) -> Vec3 {
+        let t = if self.frames > 1 {
+            frame as f32 / (self.frames - 1) as f32
+        } else {
+            0.0
+        };
+        let clock_time = self.start_time + (self.end_time - self.start_time) * t;
+        let solar_time = PhysicalSky::solar_time(clock_time, self.longitude, self.utc_offset);
+        PhysicalSky::sun_direction(solar_time, self.latitude, self.day_of_year)
+    }
+}
+
+struct SunSequenceProgress {
+    sequence: SunSequence,
+    frame: u32,
+    // file names are this followed by the frame number
+    path_prefix: String,
+}
+
+// steps the camera along a CameraPath, for Gfx::start_camera_animation
+#[derive(Debug, Copy, Clone)]
+pub struct CameraAnimation {
+    pub fps: f32,
+    // samples accumulated before the camera moves on to the next frame
+    pub samples_per_frame: u32,
+    // frames are saved to ./imgs in this format, None only plays the path back
+    pub format: Option<RenderFormat>,
+    // blur each frame over the path since the previous one, see Gfx::set_shutter
+    pub motion_blur: bool,
+}
+
+impl CameraAnimation {
+    pub fn frames(&self, path: &CameraPath) -> u32 {
+        (path.duration() * self.fps).ceil() as u32 + 1
+    }
+}
+
+struct CameraAnimationProgress {
+    path: CameraPath,
+    animation: CameraAnimation,
+    frame: u32,
+    path_prefix: String,
+}
+
+// which integrator the shader runs, both read the same scene
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    PathTraced = 0,
+    // flat shaded cartoon look: banded diffuse lighting, rim light and outlines
+    Toon = 1,
+}
+
+// where the shader's random numbers come from
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Sampler {
+    #[default]
+    Random = 0,
+    // owen scrambled sobol points, one dimension per random decision along the path,
+    // converges faster on depth of field, motion blur and soft shadows
+    Sobol = 1,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RenderFormat {
+    // tonemapped 8 bit
+    Png,
+    // tonemapped 16 bit per channel, more headroom for post processing
+    Png16,
+    // linear 32 bit float radiance for offline tonemapping
+    Exr,
+}
+
+impl RenderFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenderFormat::Png | RenderFormat::Png16 => "png",
+            RenderFormat::Exr => "exr",
+        }
+    }
+
+    // 8 bit for .png, None for anything else
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(RenderFormat::Png),
+            "exr" => Some(RenderFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+// a mesh still being read on another thread
+struct PendingMesh {
+    filename: String,
+    transform: Mat4,
+    receiver: mpsc::Receiver<MeshLoadEvent>,
+    on_progress: Box<dyn FnMut(f32)>,
+}
+
+// side of the square of pixels one workgroup of cs_trace covers
+const TRACE_WORKGROUP_SIZE: u32 = 8;
+// storage buffers bound to cs_trace across both bind groups
+const STORAGE_BUFFERS_PER_STAGE: u32 = 11;
+const DISPLAY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// side of the blue noise masks, they repeat across the frame
+const BLUE_NOISE_SIZE: u32 = 64;
+// steps of the a-trous filter, the taps of step i are 2^i pixels apart
+const DENOISE_ITERATIONS: usize = 5;
+// how long after the camera last moved the preview stays coarse
+const PREVIEW_IDLE_TIME: Duration = Duration::from_millis(150);
+
+// rounded up to whole workgroups, a single tile over the frame when tile_size is None
+fn trace_tile_size(tile_size: Option<u32>, width: u32, height: u32) -> u32 {
+    tile_size
+        .unwrap_or(width.max(height))
+        .max(1)
+        .next_multiple_of(TRACE_WORKGROUP_SIZE)
+}
+
+// top left corners of the tiles covering the frame in rings around the center, so the
+// middle of the image sharpens first
+fn spiral_tiles(width: u32, height: u32, tile_size: u32) -> Vec<[u32; 2]> {
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+    let center_x = (tiles_x as f32 - 1.0) / 2.0;
+    let center_y = (tiles_y as f32 - 1.0) / 2.0;
+
+    // (ring, angle around the center, origin)
+    let mut tiles: Vec<(f32, f32, [u32; 2])> = (0..tiles_y)
+        .flat_map(|y| (0..tiles_x).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let dx = x as f32 - center_x;
+            let dy = y as f32 - center_y;
+            (dx.abs().max(dy.abs()).ceil(), dy.atan2(dx), [x * tile_size, y * tile_size])
+        })
+        .collect();
+    tiles.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    tiles.into_iter().map(|(_, _, origin)| origin).collect()
+}
+
+// angles per side of the grid ies profiles are resampled to
+const IES_RESOLUTION: u32 = 64;
+
+// IES_RESOLUTION horizontal angles around the axis by IES_RESOLUTION vertical ones
+// from straight down the axis to straight up, scaled so the brightest is 1
+fn resample_ies_profile(profile: &IesProfile) -> Vec<f32> {
+    let mut grid = Vec::with_capacity((IES_RESOLUTION * IES_RESOLUTION) as usize);
+    for h in 0..IES_RESOLUTION {
+        let horizontal = h as f32 / IES_RESOLUTION as f32 * 360.0;
+        for v in 0..IES_RESOLUTION {
+            let vertical = v as f32 / (IES_RESOLUTION - 1) as f32 * 180.0;
+            grid.push(profile.sample(vertical, horizontal).max(0.0));
+        }
+    }
+    let peak = grid.iter().copied().fold(0.0, f32::max);
+    if peak > 0.0 {
+        grid.iter_mut().for_each(|value| *value /= peak);
+    }
+    grid
+}
+
+// marginal cdf over the rows followed by a conditional cdf per row of an equirectangular
+// image, each texel weighted by its luminance and the solid angle it covers
+fn environment_cdf(image: &image::Rgba32FImage) -> Vec<f32> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut cdf = vec![0.0; height + width * height];
+    let (marginal, conditional) = cdf.split_at_mut(height);
+
+    let mut total = 0.0;
+    for (y, row) in conditional.chunks_mut(width).enumerate() {
+        let sin_theta = ((y as f32 + 0.5) / height as f32 * std::f32::consts::PI).sin();
+        let mut sum = 0.0;
+        for (x, value) in row.iter_mut().enumerate() {
+            let [r, g, b, _] = image.get_pixel(x as u32, y as u32).0;
+            sum += (0.2126 * r + 0.7152 * g + 0.0722 * b).max(0.0) * sin_theta;
+            *value = sum;
+        }
+
+        if sum > 0.0 {
+            row.iter_mut().for_each(|value| *value /= sum);
+        } else {
+            // nothing to importance sample, fall back to uniform
+            row.iter_mut().enumerate().for_each(|(x, value)| *value = (x + 1) as f32 / width as f32);
+        }
+        // a row matters as much as everything in it
+        total += sum;
+        marginal[y] = total;
+    }
+
+    if total > 0.0 {
+        marginal.iter_mut().for_each(|value| *value /= total);
+    } else {
+        marginal.iter_mut().enumerate().for_each(|(y, value)| *value = (y + 1) as f32 / height as f32);
+    }
+    // rounding must not leave a gap the shader's binary search could fall into
+    *marginal.last_mut().unwrap() = 1.0;
+    conditional.chunks_mut(width).for_each(|row| *row.last_mut().unwrap() = 1.0);
+
+    cdf
+}
+
+// side length every image texture is resampled to, array layers must share a size
+const TEXTURE_SIZE: u32 = 1024;
+
+// image textures packed into a single 2D texture array bound to the tracer
+// ids start at 1 so that 0 can mean "no texture", id n lives in layer n - 1
+pub struct TextureManager {
+    // cpu copies so the array can be reallocated when it runs out of layers,
+    // None for layers whose texture was removed
+    images: Vec<Option<image::RgbaImage>>,
+    free_layers: Vec<usize>,
+    texture: wgpu::Texture,
+    sampler: wgpu::Sampler,
+}
+
+impl TextureManager {
+    fn new(device: &wgpu::Device) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            images: vec![],
+            free_layers: vec![],
+            texture: TextureManager::create_array(device, 1),
+            sampler,
+        }
+    }
+
+    fn create_array(device: &wgpu::Device, layers: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("textures"),
+            size: wgpu::Extent3d {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // srgb so sampling returns linear colors
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn upload_layer(&self, queue: &wgpu::Queue, layer: usize) {
+        let Some(image) = &self.images[layer] else {
+            return;
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * TEXTURE_SIZE),
+                rows_per_image: Some(TEXTURE_SIZE),
+            },
+            wgpu::Extent3d {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // returns the new id and whether the gpu texture was replaced (bind groups must be rebuilt)
+    // None when the device cannot hold more layers
+    fn add(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage) -> Option<(u32, bool)> {
+        let image = if image.dimensions() == (TEXTURE_SIZE, TEXTURE_SIZE) {
+            image.clone()
+        } else {
+            image::imageops::resize(image, TEXTURE_SIZE, TEXTURE_SIZE, image::imageops::FilterType::Triangle)
+        };
+
+        // fill the holes left by removed textures first
+        if let Some(layer) = self.free_layers.pop() {
+            self.images[layer] = Some(image);
+            self.upload_layer(queue, layer);
+            return Some((layer as u32 + 1, false));
+        }
+
+        let layer_count = self.images.len() as u32 + 1;
+        if layer_count > device.limits().max_texture_array_layers {
+            return None;
+        }
+        self.images.push(Some(image));
+
+        // grow by doubling and upload everything again
+        let reallocated = layer_count > self.texture.depth_or_array_layers();
+        if reallocated {
+            let capacity = layer_count.next_power_of_two().min(device.limits().max_texture_array_layers);
+            self.texture = TextureManager::create_array(device, capacity);
+            for layer in 0..self.images.len() {
+                self.upload_layer(queue, layer);
+            }
+        } else {
+            self.upload_layer(queue, self.images.len() - 1);
+        }
+
+        Some((layer_count, reallocated))
+    }
+
+    // frees the layer of a texture id for reuse, materials still pointing at it
+    // will show whatever is loaded there next
+    fn remove(&mut self, id: u32) {
+        let layer = id as usize - 1;
+        if self.images.get(layer).is_some_and(Option::is_some) {
+            self.images[layer] = None;
+            self.free_layers.push(layer);
+        }
+    }
+
+    pub fn texture_count(&self) -> u32 {
+        (self.images.len() - self.free_layers.len()) as u32
+    }
 }
 
 pub struct Gfx {
-    pub surface: wgpu::Surface<'static>,
+    // None when rendering headless, see new_headless
+    pub surface: Option<wgpu::Surface<'static>>,
     pub start_time: Instant,
 
     device: wgpu::Device,
@@ -42,59 +512,200 @@ pub struct Gfx {
     pub scene: Scene,
     material_count: u32,
     scene_buffer: wgpu::Buffer,
+    // voxels of every density grid, indexed through Volume::data_offset
+    volume_data: Vec<f32>,
+    volume_buffer: wgpu::Buffer,
+    // LightTreeNode array, grown by scene_update
+    light_tree_buffer: wgpu::Buffer,
+    // the tiles of the current render_frame, see tile_order
+    tile_origin_buffer: wgpu::Buffer,
+    // resampled ies profiles one after another, see add_ies_profile
+    ies_data: Vec<f32>,
+    // files the profiles came from, empty for ones added directly
+    ies_paths: Vec<String>,
+    ies_buffer: wgpu::Buffer,
+    pending_meshes: Vec<PendingMesh>,
+    // the last loaded scene file and everything it references, for hot reloading
+    scene_path: Option<String>,
+    scene_watcher: Option<FileWatcher>,
+    sun_sequence: Option<SunSequenceProgress>,
+    // overrides fov, width and apeture of the camera every frame when set
+    physical_camera: Option<PhysicalCamera>,
+    // the camera at the start of the frame for motion blur, the current one when None
+    motion_start: Option<Camera>,
+    // the fly-through of the loaded scene file, if it has one
+    camera_path: Option<CameraPath>,
+    // named cameras, the active one is copied into the uniforms and back when switching
+    cameras: BTreeMap<String, Camera>,
+    active_camera: Option<String>,
+    camera_animation: Option<CameraAnimationProgress>,
+    // None traces the whole frame every render_frame, otherwise it takes
+    // tile_order.len() / tiles_per_frame calls for every pixel to get one more sample
+    tile_size: Option<u32>,
+    tiles_per_frame: u32,
+    tile_order: Vec<[u32; 2]>,
+    // where in tile_order the current sample is
+    next_tile: usize,
+    // see set_render_size, None follows the window
+    render_size: Option<(u32, u32)>,
+    // see set_max_samples
+    max_samples: Option<u32>,
+    auto_save: Option<RenderFormat>,
+    // the frame at max_samples was saved, until the accumulation restarts
+    max_samples_saved: bool,
+    // most samples a pixel keeps through a camera move, 0 resets instead
+    reprojection_history: u32,
+    // the next sample reprojects, see render_reproject
+    reproject_next: bool,
+    // what the last sample traced over the whole frame was traced with
+    accumulated_camera: Camera,
+    accumulated_samples: u32,
+    accumulated_scale: u32,
+    // the preview_scale used while the camera moves, 1 keeps full resolution
+    preview_scale: u32,
+    // when render_reproject was last called
+    last_interaction: Option<Instant>,
+    // set once the denoiser has filtered a finished sample, until it is turned off or
+    // the accumulation restarts
+    denoised: bool,
+    // saved renders go through open image denoise, see set_oidn
+    oidn: bool,
+    bvh_options: BVHBuildOptions,
+
+    // every triangle, curve and disc of the scene, the bvh over them and the spheres in
+    // the scene struct and the primitive ids its leaves point into, grown by scene_update
+    triangles: Vec<Triangle>,
+    triangle_buffer: wgpu::Buffer,
+    curves: Vec<Curve>,
+    curve_buffer: wgpu::Buffer,
+    discs: Vec<Disc>,
+    disc_buffer: wgpu::Buffer,
+    bvh: Vec<BVHNode>,
+    bvh_buffer: wgpu::Buffer,
+    bvh_primitives: Vec<u32>,
+    bvh_primitive_buffer: wgpu::Buffer,
 
     radiance_samples: [wgpu::Texture; 2],
-
-    render_pipeline: wgpu::RenderPipeline,
-    render_bind_group: [wgpu::BindGroup; 2],
+    // depth, normal and albedo seen through every pixel center, for reprojecting and
+    // denoising, swapped along with radiance_samples
+    guide: [wgpu::Texture; 2],
+    environment: wgpu::Texture,
+    // for importance sampling the environment, see environment_cdf
+    environment_cdf: wgpu::Buffer,
+    blue_noise: wgpu::Texture,
+    pub textures: TextureManager,
+    pub assets: AssetManager,
+
+    // the trace pass accumulates radiance and writes display_image, which the display
+    // pass then draws to the window
+    display_image: wgpu::Texture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    trace_pipeline: wgpu::ComputePipeline,
+    trace_bind_group: [wgpu::BindGroup; 2],
+    display_bind_group_layout: wgpu::BindGroupLayout,
+    display_pipeline: wgpu::RenderPipeline,
+    display_bind_group: wgpu::BindGroup,
+    // the geometry that does not fit in the scene buffer, bound to group 1
+    geometry_bind_group_layout: wgpu::BindGroupLayout,
+    geometry_bind_group: wgpu::BindGroup,
+    // the denoiser filters the finished radiance back and forth between denoise_images,
+    // one bind group per step for each radiance texture the last sample could be in
+    denoise_images: [wgpu::Texture; 2],
+    denoise_bind_group_layout: wgpu::BindGroupLayout,
+    denoise_pipeline: wgpu::ComputePipeline,
+    denoise_bind_groups: [Vec<wgpu::BindGroup>; 2],
 }
 
 impl Gfx {
-    pub fn new(window: Arc<Window>, shader_code: &str) -> Self {
-        use wgpu::TextureFormat::{Bgra8Unorm, Rgba8Unorm};
-
-        let start_time = Instant::now();
-
+    pub fn new(window: Arc<Window>, shader_code: &str) -> anyhow::Result<Self> {
         let window_size = window.inner_size();
         let instance = wgpu::Instance::default();
-        let surface = instance.create_surface(window).unwrap();
-
-        let (device, queue, adapter) = pollster::block_on(async {
-            let adapter = instance
-                .request_adapter(&wgpu::RequestAdapterOptions {
-                    power_preference: wgpu::PowerPreference::HighPerformance,
-                    force_fallback_adapter: false,
-                    compatible_surface: Some(&surface),
-                })
-                .await
-                .context("failed to find a compatible adapter").unwrap();
+        let surface = instance.create_surface(window).context("failed to create a surface")?;
+        let adapter = Gfx::request_adapter(&instance, Some(&surface))?;
+        Gfx::with_adapter(&adapter, Some(surface), window_size, shader_code)
+    }
 
-            let (device, queue) = adapter
-                .request_device(&wgpu::DeviceDescriptor::default())
-                .await
-                .context("failed to connect to the GPU").unwrap();
+    // renders without a window into a width by height frame, render_frame then only
+    // traces and save_render_to gets the result out
+    pub fn new_headless(width: u32, height: u32, shader_code: &str) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = Gfx::request_adapter(&instance, None)?;
+        Gfx::new_headless_on(&adapter, width, height, shader_code)
+    }
 
-            (device, queue, adapter)
-        });
+    // new_headless on the given gpu, see multi_gpu::adapters
+    pub fn new_headless_on(
+        adapter: &wgpu::Adapter,
+        width: u32,
+        height: u32,
+        shader_code: &str,
+    ) -> anyhow::Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(width, height);
+        Gfx::with_adapter(adapter, None, size, shader_code)
+    }
 
-        let caps = surface.get_capabilities(&adapter);
-        let texture_format = caps
-            .formats
-            .into_iter()
-            .find(|it| matches!(it, Rgba8Unorm | Bgra8Unorm))
-            .context("could not find preferred texture format (Rgba8Unorm or Bgra8Unorm)").unwrap();
+    pub fn request_adapter(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>) -> anyhow::Result<wgpu::Adapter> {
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: surface,
+        }))
+        .context("failed to find a compatible adapter")
+    }
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: texture_format,
-            width: window_size.width,
-            height: window_size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
-            alpha_mode: caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 3,
+    fn with_adapter(
+        adapter: &wgpu::Adapter,
+        surface: Option<wgpu::Surface<'static>>,
+        window_size: winit::dpi::PhysicalSize<u32>,
+        shader_code: &str,
+    ) -> anyhow::Result<Self> {
+        use wgpu::TextureFormat::{Bgra8Unorm, Rgba8Unorm};
+
+        let start_time = Instant::now();
+
+        // more storage buffers than the default allows, as large as the gpu can make them
+        let supported = adapter.limits().max_storage_buffers_per_shader_stage;
+        anyhow::ensure!(
+            supported >= STORAGE_BUFFERS_PER_STAGE,
+            "the tracer needs {} storage buffers per shader stage but the gpu allows {} \
+             (max_storage_buffers_per_shader_stage)",
+            STORAGE_BUFFERS_PER_STAGE,
+            supported,
+        );
+        let required_limits = wgpu::Limits {
+            max_storage_buffers_per_shader_stage: STORAGE_BUFFERS_PER_STAGE,
+            max_storage_buffer_binding_size: adapter.limits().max_storage_buffer_binding_size,
+            max_buffer_size: adapter.limits().max_buffer_size,
+            ..wgpu::Limits::default()
         };
-        surface.configure(&device, &config);
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_limits,
+            ..Default::default()
+        }))
+        .context("failed to connect to the GPU")?;
+
+        // the display pipeline is still made without a surface, it is just never used
+        let mut texture_format = Rgba8Unorm;
+        if let Some(surface) = &surface {
+            let caps = surface.get_capabilities(adapter);
+            texture_format = caps
+                .formats
+                .into_iter()
+                .find(|it| matches!(it, Rgba8Unorm | Bgra8Unorm))
+                .context("could not find preferred texture format (Rgba8Unorm or Bgra8Unorm)")?;
+
+            let config = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: texture_format,
+                width: window_size.width,
+                height: window_size.height,
+                present_mode: wgpu::PresentMode::AutoVsync,
+                alpha_mode: caps.alpha_modes[0],
+                view_formats: vec![],
+                desired_maximum_frame_latency: 3,
+            };
+            surface.configure(&device, &config);
+        }
 
         let uniforms = Uniforms {
             camera: Camera::new(),
@@ -104,7 +715,38 @@ impl Gfx {
             frame_count: 0,
             gamma_correction: 2.2,
             psuedo_chromatic_aberration: 0.0,
-            _pad0: [0; 2],
+            use_environment: 0,
+            render_mode: RenderMode::PathTraced as u32,
+            use_physical_sky: 0,
+            background: 0,
+            manifold_caustics: 0,
+            environment_rotation: 0.0,
+            sky: PhysicalSky::default(),
+            background_bottom: Vec3::default(),
+            environment_intensity: 1.0,
+            background_top: Vec3::default(),
+            focus_overlay: 0,
+            camera_start_position: Vec3::zero(),
+            shutter_open: 0.0,
+            camera_start_direction: Vec3::new(0.0, 0.0, -1.0),
+            shutter_close: 1.0,
+            vignette_strength: 0.0,
+            vignette_radius: 0.5,
+            physical_vignetting: 0,
+            tile_size: trace_tile_size(None, window_size.width, window_size.height),
+            blue_noise: 1,
+            sampler_kind: Sampler::Random as u32,
+            indirect_clamp: 0.0,
+            outlier_rejection: 0.0,
+            previous_camera: Camera::new(),
+            reproject: 0,
+            previous_samples: 0,
+            denoise: 0,
+            preview_scale: 1,
+            window_width: window_size.width,
+            window_height: window_size.height,
+            sample_offset: 0,
+            _pad0: 0,
         };
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("uniforms"),
@@ -121,28 +763,90 @@ impl Gfx {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-
+        let volume_buffer = Gfx::create_storage_buffer::<f32>(&device, "volume data", 1);
+        let light_tree_buffer = Gfx::create_storage_buffer::<LightTreeNode>(&device, "light tree", 1);
+        let ies_buffer = Gfx::create_storage_buffer::<f32>(&device, "ies profiles", 1);
+
+        let triangle_buffer = Gfx::create_storage_buffer::<Triangle>(&device, "triangles", 1);
+        let curve_buffer = Gfx::create_storage_buffer::<Curve>(&device, "curves", 1);
+        let disc_buffer = Gfx::create_storage_buffer::<Disc>(&device, "discs", 1);
+        let bvh_buffer = Gfx::create_storage_buffer::<BVHNode>(&device, "bvh", 1);
+        let bvh_primitive_buffer = Gfx::create_storage_buffer::<u32>(&device, "bvh primitives", 1);
+        let tile_origin_buffer = Gfx::create_storage_buffer::<[u32; 2]>(&device, "tile origins", 1);
+
+        // a shader this gpu can not run is an error rather than a panic
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(shader_code)),
         });
 
-        let (bind_group_layout, render_pipeline) = Gfx::create_pipeline(
+        let geometry_bind_group_layout = Gfx::create_geometry_bind_group_layout(&device);
+        let (bind_group_layout, trace_pipeline) =
+            Gfx::create_pipeline(&device, &shader_module, &geometry_bind_group_layout);
+        let (display_bind_group_layout, display_pipeline) = Gfx::create_display_pipeline(
             &device,
             &shader_module,
-            texture_format
+            texture_format,
         );
 
+        let (denoise_bind_group_layout, denoise_pipeline) = Gfx::create_denoise_pipeline(&device, &shader_module);
+        if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+            anyhow::bail!("failed to create the pipelines: {}", e);
+        }
+
         let radiance_samples = Gfx::create_texture(&device, window_size.width, window_size.height);
-        let render_bind_group = Gfx::create_bind_groups(
+        let guide = Gfx::create_guide_textures(&device, window_size.width, window_size.height);
+        let display_image = Gfx::create_display_texture(&device, window_size.width, window_size.height);
+        let display_bind_group = Gfx::create_display_bind_group(
+            &device,
+            &display_bind_group_layout,
+            &uniform_buffer,
+            &display_image,
+        );
+        let denoise_images = Gfx::create_texture(&device, window_size.width, window_size.height);
+        let denoise_bind_groups = Gfx::create_denoise_bind_groups(
+            &device,
+            &denoise_bind_group_layout,
+            &uniform_buffer,
+            &radiance_samples,
+            &guide,
+            &denoise_images,
+            &display_image,
+        );
+        // 1x1 placeholder until an environment map is set
+        let environment = Gfx::create_environment_texture(&device, 1, 1);
+        let environment_cdf = Gfx::create_storage_buffer::<f32>(&device, "environment cdf", 1);
+        let blue_noise = Gfx::create_blue_noise_texture(&device, &queue);
+        let textures = TextureManager::new(&device);
+        let trace_bind_group = Gfx::create_bind_groups(
             &device,
             &bind_group_layout,
             &radiance_samples,
+            &guide,
+            &environment,
+            &textures,
             &uniform_buffer,
             &scene_buffer,
+            &volume_buffer,
+            &environment_cdf,
+            &light_tree_buffer,
+            &ies_buffer,
+            &display_image,
+            &tile_origin_buffer,
+            &blue_noise,
+        );
+        let geometry_bind_group = Gfx::create_geometry_bind_group(
+            &device,
+            &geometry_bind_group_layout,
+            &curve_buffer,
+            &bvh_buffer,
+            &disc_buffer,
+            &triangle_buffer,
+            &bvh_primitive_buffer,
         );
 
-        Self {
+        Ok(Self {
             surface,
             start_time,
 
@@ -155,25 +859,93 @@ impl Gfx {
             scene,
             material_count,
             scene_buffer,
+            volume_data: vec![],
+            volume_buffer,
+            light_tree_buffer,
+            tile_origin_buffer,
+            ies_data: vec![],
+            ies_paths: vec![],
+            ies_buffer,
+            pending_meshes: vec![],
+            scene_path: None,
+            scene_watcher: None,
+            sun_sequence: None,
+            physical_camera: None,
+            motion_start: None,
+            camera_path: None,
+            camera_animation: None,
+            tile_size: None,
+            tiles_per_frame: 1,
+            tile_order: spiral_tiles(
+                window_size.width,
+                window_size.height,
+                trace_tile_size(None, window_size.width, window_size.height),
+            ),
+            next_tile: 0,
+            render_size: None,
+            max_samples: None,
+            auto_save: None,
+            max_samples_saved: false,
+            reprojection_history: 8,
+            reproject_next: false,
+            accumulated_camera: Camera::new(),
+            accumulated_samples: 0,
+            accumulated_scale: 1,
+            preview_scale: 2,
+            last_interaction: None,
+            denoised: false,
+            oidn: false,
+            bvh_options: BVHBuildOptions::default(),
+            cameras: BTreeMap::new(),
+            active_camera: None,
+
+            triangles: vec![],
+            triangle_buffer,
+            curves: vec![],
+            curve_buffer,
+            discs: vec![],
+            disc_buffer,
+            bvh: vec![],
+            bvh_buffer,
+            bvh_primitives: vec![],
+            bvh_primitive_buffer,
 
             radiance_samples,
-
-            render_pipeline,
-            render_bind_group,
-        }
+            guide,
+            environment,
+            environment_cdf,
+            blue_noise,
+            textures,
+            assets: AssetManager::new(),
+
+            display_image,
+            bind_group_layout,
+            trace_pipeline,
+            trace_bind_group,
+            display_bind_group_layout,
+            display_pipeline,
+            display_bind_group,
+            geometry_bind_group_layout,
+            geometry_bind_group,
+            denoise_images,
+            denoise_bind_group_layout,
+            denoise_pipeline,
+            denoise_bind_groups,
+        })
     }
 
+    // the path tracer, one invocation per pixel
     fn create_pipeline(
         device: &wgpu::Device,
         shader_module: &wgpu::ShaderModule,
-        texture_format: wgpu::TextureFormat,
-    ) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+        geometry_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::BindGroupLayout, wgpu::ComputePipeline) {
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -183,7 +955,7 @@ impl Gfx {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage {
                             read_only: true,
@@ -195,7 +967,7 @@ impl Gfx {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 2,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Texture {
                         sample_type: wgpu::TextureSampleType::Float {
                             filterable: false,
@@ -207,7 +979,7 @@ impl Gfx {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 3,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::StorageTexture {
                         access: wgpu::StorageTextureAccess::WriteOnly,
                         format: wgpu::TextureFormat::Rgba32Float,
@@ -215,24 +987,211 @@ impl Gfx {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: false,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: true,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: DISPLAY_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: false,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 15,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 16,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
             ],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render"),
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("trace"),
             layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                bind_group_layouts: &[&bind_group_layout],
+                bind_group_layouts: &[&bind_group_layout, geometry_bind_group_layout],
                 ..Default::default()
             })),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                front_face: wgpu::FrontFace::Ccw,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                ..Default::default()
-            },
-            vertex: wgpu::VertexState {
-                module: shader_module,
-                entry_point: Some("vs_display"),
+            module: shader_module,
+            entry_point: Some("cs_trace"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        (bind_group_layout, pipeline)
+    }
+
+    // draws the display image written by the trace pass to the window
+    fn create_display_pipeline(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        texture_format: wgpu::TextureFormat,
+    ) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("display"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float {
+                            filterable: true,
+                        },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 22,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("display"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[&bind_group_layout],
+                ..Default::default()
+            })),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: Some("vs_display"),
                 buffers: &[],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
@@ -255,17 +1214,221 @@ impl Gfx {
         (bind_group_layout, pipeline)
     }
 
+    fn create_display_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        display_image: &wgpu::Texture,
+    ) -> wgpu::BindGroup {
+        let view = display_image.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("display sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("display"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: uniform_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 22,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    }
+
+    // the edge avoiding blur over the finished radiance, see set_denoise
+    fn create_denoise_pipeline(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+    ) -> (wgpu::BindGroupLayout, wgpu::ComputePipeline) {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_entry = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("denoise"),
+            entries: &[
+                uniform_entry(0),
+                storage_entry(11, DISPLAY_FORMAT),
+                texture_entry(17, float),
+                texture_entry(18, wgpu::TextureSampleType::Uint),
+                texture_entry(19, float),
+                storage_entry(20, wgpu::TextureFormat::Rgba32Float),
+                uniform_entry(21),
+            ],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("denoise"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[&bind_group_layout],
+                ..Default::default()
+            })),
+            module: shader_module,
+            entry_point: Some("cs_denoise"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        (bind_group_layout, pipeline)
+    }
+
+    // step i reads denoise_images[(i + 1) % 2] and writes denoise_images[i % 2], the
+    // first reads the radiance the trace bind group of the same index writes instead
+    fn create_denoise_bind_groups(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        radiance_samples: &[wgpu::Texture; 2],
+        guide: &[wgpu::Texture; 2],
+        denoise_images: &[wgpu::Texture; 2],
+        display_image: &wgpu::Texture,
+    ) -> [Vec<wgpu::BindGroup>; 2] {
+        let view = |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let display_view = view(display_image);
+
+        let params: Vec<wgpu::Buffer> = (0..DENOISE_ITERATIONS)
+            .map(|i| {
+                let params = DenoiseParams {
+                    step: 1 << i,
+                    first: (i == 0) as u32,
+                    last: (i == DENOISE_ITERATIONS - 1) as u32,
+                    _pad0: 0,
+                };
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("denoise params"),
+                    size: std::mem::size_of::<DenoiseParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM,
+                    mapped_at_creation: true,
+                });
+                buffer.slice(..).get_mapped_range_mut().copy_from_slice(bytemuck::bytes_of(&params));
+                buffer.unmap();
+                buffer
+            })
+            .collect();
+
+        [1, 0].map(|newest| {
+            params
+                .iter()
+                .enumerate()
+                .map(|(i, params)| {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("denoise"),
+                        layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: uniform_buffer.as_entire_binding(),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 11,
+                                resource: wgpu::BindingResource::TextureView(&display_view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 17,
+                                resource: wgpu::BindingResource::TextureView(&view(&radiance_samples[newest])),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 18,
+                                resource: wgpu::BindingResource::TextureView(&view(&guide[newest])),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 19,
+                                resource: wgpu::BindingResource::TextureView(&view(&denoise_images[(i + 1) % 2])),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 20,
+                                resource: wgpu::BindingResource::TextureView(&view(&denoise_images[i % 2])),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 21,
+                                resource: params.as_entire_binding(),
+                            },
+                        ],
+                    })
+                })
+                .collect()
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn create_bind_groups(
         device: &wgpu::Device,
         layout: &wgpu::BindGroupLayout,
         textures: &[wgpu::Texture; 2],
+        guide: &[wgpu::Texture; 2],
+        environment: &wgpu::Texture,
+        texture_manager: &TextureManager,
         uniform_buffer: &wgpu::Buffer,
         scene_buffer: &wgpu::Buffer,
+        volume_buffer: &wgpu::Buffer,
+        environment_cdf: &wgpu::Buffer,
+        light_tree_buffer: &wgpu::Buffer,
+        ies_buffer: &wgpu::Buffer,
+        display_image: &wgpu::Texture,
+        tile_origin_buffer: &wgpu::Buffer,
+        blue_noise: &wgpu::Texture,
     ) -> [wgpu::BindGroup; 2] {
         let views = [
             textures[0].create_view(&wgpu::TextureViewDescriptor::default()),
             textures[1].create_view(&wgpu::TextureViewDescriptor::default()),
         ];
+        let guide_views = [
+            guide[0].create_view(&wgpu::TextureViewDescriptor::default()),
+            guide[1].create_view(&wgpu::TextureViewDescriptor::default()),
+        ];
+        let display_view = display_image.create_view(&wgpu::TextureViewDescriptor::default());
+        let blue_noise_view = blue_noise.create_view(&wgpu::TextureViewDescriptor::default());
+        let environment_view = environment.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_array_view = texture_manager.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
 
         [
             // bind group with view[0] assigned to binding 1 and view[1] assigned to binding 2
@@ -297,6 +1460,74 @@ impl Gfx {
                         binding: 3,
                         resource: wgpu::BindingResource::TextureView(&views[1]),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&environment_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&texture_array_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&texture_manager.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: volume_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: environment_cdf,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: light_tree_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 10,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: ies_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 11,
+                        resource: wgpu::BindingResource::TextureView(&display_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 13,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: tile_origin_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 14,
+                        resource: wgpu::BindingResource::TextureView(&blue_noise_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 15,
+                        resource: wgpu::BindingResource::TextureView(&guide_views[0]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 16,
+                        resource: wgpu::BindingResource::TextureView(&guide_views[1]),
+                    },
                 ],
             }),
 
@@ -329,11 +1560,165 @@ impl Gfx {
                         binding: 3,
                         resource: wgpu::BindingResource::TextureView(&views[0]),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&environment_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&texture_array_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&texture_manager.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: volume_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: environment_cdf,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: light_tree_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 10,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: ies_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 11,
+                        resource: wgpu::BindingResource::TextureView(&display_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 13,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: tile_origin_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 14,
+                        resource: wgpu::BindingResource::TextureView(&blue_noise_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 15,
+                        resource: wgpu::BindingResource::TextureView(&guide_views[1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 16,
+                        resource: wgpu::BindingResource::TextureView(&guide_views[0]),
+                    },
                 ],
             }),
         ]
     }
 
+    fn create_geometry_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: true,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("geometry"),
+            entries: &[
+                // curves
+                storage(0),
+                // bvh
+                storage(1),
+                // discs
+                storage(2),
+                // triangles
+                storage(3),
+                // bvh primitives
+                storage(4),
+            ],
+        })
+    }
+
+    fn create_geometry_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        curve_buffer: &wgpu::Buffer,
+        bvh_buffer: &wgpu::Buffer,
+        disc_buffer: &wgpu::Buffer,
+        triangle_buffer: &wgpu::Buffer,
+        bvh_primitive_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("geometry"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: curve_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bvh_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: disc_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: triangle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: bvh_primitive_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // array of len T bound read only to the tracer
+    fn create_storage_buffer<T>(device: &wgpu::Device, label: &str, len: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            // storage bindings can not be empty
+            size: (len.max(1) * std::mem::size_of::<T>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // how many T fit in one storage buffer
+    fn storage_capacity<T>(&self) -> usize {
+        let limits = self.device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        max_size as usize / std::mem::size_of::<T>()
+    }
+
     fn create_texture(device: &wgpu::Device, width: u32, height: u32) -> [wgpu::Texture; 2] {
         let desc = &wgpu::TextureDescriptor {
             label: Some("texture"),
@@ -348,66 +1733,1714 @@ impl Gfx {
             format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::STORAGE_BINDING 
-                | wgpu::TextureUsages::COPY_SRC,
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         };
 
         [device.create_texture(desc), device.create_texture(desc)]
     }
 
-    pub fn scene_add_material(&mut self, material: Material) -> u32 {
-        self.scene.materials[self.material_count as usize] = material;
-        self.material_count += 1;
-        
-        self.material_count - 1
-    }
-
-    pub fn scene_add_sphere(&mut self, sphere: Sphere) {
-        self.scene.spheres[self.scene.sphere_count as usize] = sphere;
-        self.scene.sphere_count += 1;
-    }
+    fn create_guide_textures(device: &wgpu::Device, width: u32, height: u32) -> [wgpu::Texture; 2] {
+        let desc = &wgpu::TextureDescriptor {
+            label: Some("guide"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        };
 
-    pub fn scene_add_triangles(&mut self, triangles: &[Triangle]) {
-        for tri in triangles.iter() {
-            self.scene.triangles[self.scene.triangle_count as usize] = *tri;
-            self.scene.triangle_count += 1;
-        }
+        [device.create_texture(desc), device.create_texture(desc)]
     }
 
-    pub fn scene_update(&mut self) {
-        self.scene_build();
-
-        self.queue.write_buffer(
-            &self.scene_buffer,
-            0,
-            bytemuck::bytes_of(&self.scene)
-        );
+    fn create_environment_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("environment"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    // made once at startup, see blue_noise.rs
+    fn create_blue_noise_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("blue noise"),
+            size: wgpu::Extent3d {
+                width: BLUE_NOISE_SIZE,
+                height: BLUE_NOISE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let masks = blue_noise_rgba(BLUE_NOISE_SIZE as usize);
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&masks),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * BLUE_NOISE_SIZE),
+                rows_per_image: Some(BLUE_NOISE_SIZE),
+            },
+            wgpu::Extent3d {
+                width: BLUE_NOISE_SIZE,
+                height: BLUE_NOISE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        texture
+    }
+
+    // what the trace pass writes for the display pass to show, already tonemapped
+    fn create_display_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("display"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DISPLAY_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn rebuild_bind_groups(&mut self) {
+        self.trace_bind_group = Gfx::create_bind_groups(
+            &self.device,
+            &self.bind_group_layout,
+            &self.radiance_samples,
+            &self.guide,
+            &self.environment,
+            &self.textures,
+            &self.uniform_buffer,
+            &self.scene_buffer,
+            &self.volume_buffer,
+            &self.environment_cdf,
+            &self.light_tree_buffer,
+            &self.ies_buffer,
+            &self.display_image,
+            &self.tile_origin_buffer,
+            &self.blue_noise,
+        );
+    }
+
+    // use an equirectangular HDR image as the background and sky light
+    pub fn set_environment(&mut self, image: &image::Rgba32FImage) {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let resized;
+        let image = if image.width() > max_size || image.height() > max_size {
+            let scale = max_size as f32 / image.width().max(image.height()) as f32;
+            resized = image::imageops::resize(
+                image,
+                ((image.width() as f32 * scale) as u32).max(1),
+                ((image.height() as f32 * scale) as u32).max(1),
+                image::imageops::FilterType::Triangle,
+            );
+            &resized
+        } else {
+            image
+        };
+
+        self.environment = Gfx::create_environment_texture(&self.device, image.width(), image.height());
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.environment,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(image.as_raw()),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let cdf = environment_cdf(image);
+        self.environment_cdf = Gfx::create_storage_buffer::<f32>(&self.device, "environment cdf", cdf.len());
+        self.queue.write_buffer(&self.environment_cdf, 0, bytemuck::cast_slice(&cdf));
+
+        self.rebuild_bind_groups();
+        self.uniforms.use_environment = 1;
+        self.render_reset();
+    }
+
+    // uploads an image unless one with the same pixels is already resident
+    // the texture stays in the array while the handle (or a clone) is alive
+    pub fn add_texture_handle(&mut self, image: &image::RgbaImage) -> Option<TextureHandle> {
+        if let Some(handle) = self.assets.find_texture_by_content(image) {
+            return Some(handle);
+        }
+
+        let Some((id, reallocated)) = self.textures.add(&self.device, &self.queue, image) else {
+            println!("texture array is full");
+            return None;
+        };
+
+        if reallocated {
+            self.rebuild_bind_groups();
+        }
+        self.render_reset();
+
+        Some(self.assets.insert_texture(image, id))
+    }
+
+    // loads a PNG/JPEG (or anything else the image crate reads) into the texture array,
+    // files that were already loaded are not read again
+    pub fn load_texture_handle(&mut self, filename: &str) -> Option<TextureHandle> {
+        if let Some(handle) = self.assets.find_texture_by_path(filename) {
+            return Some(handle);
+        }
+
+        match image::open(filename) {
+            Ok(img) => {
+                let handle = self.add_texture_handle(&img.into_rgba8())?;
+                self.assets.alias_texture_path(filename, &handle);
+                Some(handle)
+            },
+            Err(e) => {
+                println!("failed to load texture {}: {}", filename, e);
+                None
+            }
+        }
+    }
+
+    // returns the id materials use to reference the texture, None if it could not be added
+    // textures added this way are never collected
+    pub fn add_texture(&mut self, image: &image::RgbaImage) -> Option<u32> {
+        let handle = self.add_texture_handle(image)?;
+        let id = handle.id();
+        self.assets.pin_texture(handle);
+        Some(id)
+    }
+
+    pub fn load_texture(&mut self, filename: &str) -> Option<u32> {
+        let handle = self.load_texture_handle(filename)?;
+        let id = handle.id();
+        self.assets.pin_texture(handle);
+        Some(id)
+    }
+
+    // frees the texture array layers of every texture whose handles were all dropped
+    pub fn collect_unused_textures(&mut self) {
+        for id in self.assets.take_unused_textures() {
+            self.textures.remove(id);
+        }
+    }
+
+    // go back to the procedural sky
+    pub fn clear_environment(&mut self) {
+        self.uniforms.use_environment = 0;
+        self.render_reset();
+    }
+
+    pub fn get_physical_sky(&self) -> Option<PhysicalSky> {
+        (self.uniforms.use_physical_sky != 0).then_some(self.uniforms.sky)
+    }
+
+    // None goes back to the simple gradient sky
+    pub fn set_physical_sky(&mut self, sky: Option<PhysicalSky>) {
+        self.uniforms.use_physical_sky = sky.is_some() as u32;
+        if let Some(sky) = sky {
+            self.uniforms.sky = sky;
+        }
+        self.render_reset();
+    }
+
+    // moves the sun of the physical sky (turning it on if needed) to a local solar time
+    // in hours, see PhysicalSky::sun_direction
+    pub fn set_time_of_day(&mut self, time_of_day: f32, latitude: f32, day_of_year: u32) {
+        let mut sky = self.uniforms.sky;
+        sky.sun_direction = PhysicalSky::sun_direction(time_of_day, latitude, day_of_year);
+        self.set_physical_sky(Some(sky));
+    }
+
+    // points the sun of the physical sky and the sun light, whichever are on, without a
+    // full scene update
+    pub fn set_sun_direction(&mut self, direction: Vec3) {
+        self.uniforms.sky.sun_direction = direction.normalized();
+        if self.scene_get_sun().is_some() {
+            self.scene.sun.direction = direction.normalized();
+            self.queue.write_buffer(
+                &self.scene_buffer,
+                std::mem::offset_of!(Scene, sun) as u64,
+                bytemuck::bytes_of(&self.scene.sun),
+            );
+        }
+        self.render_reset();
+    }
+
+    // renders the frames of the sequence one after another as render_frame is called,
+    // each is saved to ./imgs once it has its samples
+    pub fn start_sun_sequence(&mut self, sequence: SunSequence) {
+        self.set_sun_direction(sequence.sun_direction(0));
+        self.sun_sequence = Some(SunSequenceProgress {
+            sequence,
+            frame: 0,
+            path_prefix: format!("./imgs/{}-sun", Local::now().format("%Y-%m-%d-%H-%M-%S")),
+        });
+    }
+
+    pub fn is_sun_sequence_running(&self) -> bool {
+        self.sun_sequence.is_some()
+    }
+
+    pub fn stop_sun_sequence(&mut self) {
+        self.sun_sequence = None;
+    }
+
+    fn advance_sun_sequence(&mut self) {
+        let Some(progress) = &self.sun_sequence else {
+            return;
+        };
+        if self.get_sample_count() < progress.sequence.samples_per_frame {
+            return;
+        }
+
+        let sequence = progress.sequence;
+        let frame = progress.frame;
+        let path = format!("{}-{:04}.{}", progress.path_prefix, frame, sequence.format.extension());
+        match pollster::block_on(self.save_render_to(&path, sequence.format)) {
+            Ok(()) => println!("saved sun sequence frame {}/{}", frame + 1, sequence.frames),
+            Err(e) => println!("failed to save sun sequence frame {}: {:#}", frame + 1, e),
+        }
+
+        if frame + 1 >= sequence.frames {
+            self.sun_sequence = None;
+            return;
+        }
+        self.set_sun_direction(sequence.sun_direction(frame + 1));
+        if let Some(progress) = &mut self.sun_sequence {
+            progress.frame = frame + 1;
+        }
+    }
+
+    // adds or replaces a named camera, switch to it with set_active_camera
+    pub fn scene_add_camera(&mut self, name: &str, camera: Camera) {
+        if self.active_camera.as_deref() == Some(name) {
+            self.uniforms.camera = camera;
+            self.render_reset();
+        }
+        self.cameras.insert(name.to_string(), camera);
+    }
+
+    pub fn get_camera_names(&self) -> Vec<String> {
+        self.cameras.keys().cloned().collect()
+    }
+
+    pub fn get_active_camera(&self) -> Option<&str> {
+        self.active_camera.as_deref()
+    }
+
+    // changes made to the current camera are kept under its name, false when there is
+    // no camera called name
+    pub fn set_active_camera(&mut self, name: &str) -> bool {
+        let Some(camera) = self.cameras.get(name).copied() else {
+            return false;
+        };
+        if let Some(active) = &self.active_camera {
+            self.cameras.insert(active.clone(), self.uniforms.camera);
+        }
+        self.active_camera = Some(name.to_string());
+        self.uniforms.camera = camera;
+        self.render_reset();
+        true
+    }
+
+    // switches to the camera after the active one in name order, wrapping around
+    pub fn next_camera(&mut self) -> Option<String> {
+        let next = match &self.active_camera {
+            Some(active) => self.cameras
+                .range::<String, _>((std::ops::Bound::Excluded(active), std::ops::Bound::Unbounded))
+                .next()
+                .or_else(|| self.cameras.iter().next()),
+            None => self.cameras.iter().next(),
+        }
+        .map(|(name, _)| name.clone())?;
+        self.set_active_camera(&next);
+        Some(next)
+    }
+
+    pub fn get_camera_path(&self) -> Option<&CameraPath> {
+        self.camera_path.as_ref()
+    }
+
+    pub fn set_camera_path(&mut self, path: Option<CameraPath>) {
+        self.camera_path = path;
+    }
+
+    // moves the camera along path one frame at a time as render_frame is called, each
+    // frame is saved to ./imgs once it has its samples when the animation has a format
+    pub fn start_camera_animation(&mut self, path: CameraPath, animation: CameraAnimation) {
+        self.camera_animation = Some(CameraAnimationProgress {
+            path,
+            animation,
+            frame: 0,
+            path_prefix: format!("./imgs/{}-camera", Local::now().format("%Y-%m-%d-%H-%M-%S")),
+        });
+        self.show_camera_animation_frame(0);
+    }
+
+    pub fn is_camera_animation_running(&self) -> bool {
+        self.camera_animation.is_some()
+    }
+
+    pub fn stop_camera_animation(&mut self) {
+        if self.camera_animation.take().is_some_and(|progress| progress.animation.motion_blur) {
+            self.set_camera_motion(None);
+        }
+    }
+
+    fn show_camera_animation_frame(&mut self, frame: u32) {
+        let Some(progress) = &self.camera_animation else {
+            return;
+        };
+        let start_time = progress.path.keyframes.first().map_or(0.0, |k| k.time);
+        let time = start_time + frame as f32 / progress.animation.fps;
+
+        let mut camera = self.uniforms.camera;
+        progress.path.apply(time, &mut camera);
+        let motion_start = progress.animation.motion_blur.then(|| {
+            let mut start = camera;
+            progress.path.apply(time - 1.0 / progress.animation.fps, &mut start);
+            start
+        });
+
+        self.uniforms.camera = camera;
+        self.motion_start = motion_start;
+        self.render_reset();
+    }
+
+    fn advance_camera_animation(&mut self) {
+        let Some(progress) = &self.camera_animation else {
+            return;
+        };
+        if self.get_sample_count() < progress.animation.samples_per_frame {
+            return;
+        }
+
+        let animation = progress.animation;
+        let frame = progress.frame;
+        let frames = animation.frames(&progress.path);
+        if let Some(format) = animation.format {
+            let path = format!("{}-{:04}.{}", progress.path_prefix, frame, format.extension());
+            match pollster::block_on(self.save_render_to(&path, format)) {
+                Ok(()) => println!("saved camera animation frame {}/{}", frame + 1, frames),
+                Err(e) => println!("failed to save camera animation frame {}: {:#}", frame + 1, e),
+            }
+        }
+
+        if frame + 1 >= frames {
+            self.stop_camera_animation();
+            return;
+        }
+        if let Some(progress) = &mut self.camera_animation {
+            progress.frame = frame + 1;
+        }
+        self.show_camera_animation_frame(frame + 1);
+    }
+
+    pub fn scene_add_material(&mut self, material: Material) -> anyhow::Result<u32> {
+        anyhow::ensure!(
+            (self.material_count as usize) < self.scene.materials.len(),
+            "the scene holds at most {} materials",
+            self.scene.materials.len(),
+        );
+        self.scene.materials[self.material_count as usize] = material;
+        self.material_count += 1;
+
+        Ok(self.material_count - 1)
+    }
+
+    pub fn scene_add_sphere(&mut self, sphere: Sphere) {
+        self.scene.spheres[self.scene.sphere_count as usize] = sphere;
+        self.scene.sphere_count += 1;
+    }
+
+    // adds none of them when they do not all fit
+    pub fn scene_add_triangles(&mut self, triangles: &[Triangle]) -> anyhow::Result<()> {
+        let capacity = self.storage_capacity::<Triangle>();
+        anyhow::ensure!(
+            self.triangles.len() + triangles.len() <= capacity,
+            "{} more triangles do not fit, the gpu holds at most {}",
+            triangles.len(),
+            capacity,
+        );
+        self.triangles.extend_from_slice(triangles);
+        self.scene.triangle_count = self.triangles.len() as u32;
+        Ok(())
+    }
+
+    // adds none of them when they do not all fit
+    pub fn scene_add_curves(&mut self, curves: &[Curve]) -> anyhow::Result<()> {
+        let capacity = self.storage_capacity::<Curve>();
+        anyhow::ensure!(
+            self.curves.len() + curves.len() <= capacity,
+            "{} more curves do not fit, the gpu holds at most {}",
+            curves.len(),
+            capacity,
+        );
+        self.curves.extend_from_slice(curves);
+        self.scene.curve_count = self.curves.len() as u32;
+        Ok(())
+    }
+
+    pub fn scene_add_portal(&mut self, portal: Portal) {
+        self.scene.portals[self.scene.portal_count as usize] = portal;
+        self.scene.portal_count += 1;
+    }
+
+    // adds a measured light distribution for PointLight::ies_profile and returns its id,
+    // profiles stay until the next load_scene
+    pub fn add_ies_profile(&mut self, profile: &IesProfile) -> u32 {
+        self.push_ies_profile(profile, String::new())
+    }
+
+    pub fn load_ies_profile(&mut self, filename: &str) -> Option<u32> {
+        let profile = load_ies_from(filename)?;
+        Some(self.push_ies_profile(&profile, filename.to_string()))
+    }
+
+    fn push_ies_profile(&mut self, profile: &IesProfile, path: String) -> u32 {
+        self.ies_data.extend(resample_ies_profile(profile));
+        self.ies_paths.push(path);
+        self.upload_ies_profiles();
+
+        self.ies_paths.len() as u32
+    }
+
+    fn upload_ies_profiles(&mut self) {
+        self.ies_buffer = Gfx::create_storage_buffer::<f32>(&self.device, "ies profiles", self.ies_data.len());
+        if !self.ies_data.is_empty() {
+            self.queue.write_buffer(&self.ies_buffer, 0, bytemuck::cast_slice(&self.ies_data));
+        }
+        self.rebuild_bind_groups();
+        self.render_reset();
+    }
+
+    pub fn scene_add_point_light(&mut self, light: PointLight) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (self.scene.point_light_count as usize) < self.scene.point_lights.len(),
+            "the scene holds at most {} point lights",
+            self.scene.point_lights.len(),
+        );
+        self.scene.point_lights[self.scene.point_light_count as usize] = light;
+        self.scene.point_light_count += 1;
+
+        Ok(())
+    }
+
+    pub fn scene_add_area_light(&mut self, light: AreaLight) {
+        self.scene.area_lights[self.scene.area_light_count as usize] = light;
+        self.scene.area_light_count += 1;
+    }
+
+    // None removes the sun
+    pub fn scene_set_sun(&mut self, sun: Option<SunLight>) {
+        self.scene.sun = sun.unwrap_or_default();
+    }
+
+    // None clears the air
+    pub fn scene_set_fog(&mut self, fog: Option<Fog>) {
+        self.scene.fog = fog.unwrap_or_default();
+    }
+
+    pub fn scene_get_fog(&self) -> Option<Fog> {
+        (self.scene.fog.density > 0.0).then_some(self.scene.fog)
+    }
+
+    pub fn scene_get_sun(&self) -> Option<SunLight> {
+        let irradiance = self.scene.sun.irradiance;
+        (irradiance.x() > 0.0 || irradiance.y() > 0.0 || irradiance.z() > 0.0).then_some(self.scene.sun)
+    }
+
+    // adds none of them when they do not all fit, like scene_add_curves
+    pub fn scene_add_discs(&mut self, discs: &[Disc]) -> anyhow::Result<()> {
+        let capacity = self.storage_capacity::<Disc>();
+        anyhow::ensure!(
+            self.discs.len() + discs.len() <= capacity,
+            "{} more discs do not fit, the gpu holds at most {}",
+            discs.len(),
+            capacity,
+        );
+        self.discs.extend_from_slice(discs);
+        self.scene.disc_count = self.discs.len() as u32;
+        Ok(())
+    }
+
+    // adds a density grid scattering with the given material, the material's
+    // volume_density scales the grid and its color is the albedo
+    pub fn scene_add_volume(&mut self, grid: &DensityGrid, material_id: u32) {
+        let max_density = grid.densities.iter().copied().fold(0.0, f32::max);
+        self.scene.volumes[self.scene.volume_count as usize] = Volume {
+            bbox_min: grid.bbox_min,
+            material_id,
+            bbox_max: grid.bbox_max,
+            max_density,
+            resolution: grid.resolution,
+            data_offset: self.volume_data.len() as u32,
+        };
+        self.scene.volume_count += 1;
+        self.volume_data.extend_from_slice(&grid.densities);
+    }
+
+    // adds the materials and geometry of an imported scene and returns the created material ids
+    // the first imported camera (if any) becomes the active camera
+    pub fn scene_add_imported(&mut self, imported: &ImportedScene) -> anyhow::Result<Vec<u32>> {
+        // textures that fail to upload leave their materials untextured
+        let texture_ids: Vec<u32> = imported.textures
+            .iter()
+            .map(|image| self.add_texture(image).unwrap_or(0))
+            .collect();
+
+        let material_offset = self.material_count;
+        let material_ids = imported.materials
+            .iter()
+            .map(|material| {
+                let mut material = *material;
+                let remap = |id: u32| match id {
+                    0 => 0,
+                    id => texture_ids.get(id as usize - 1).copied().unwrap_or(0),
+                };
+                material.albedo_texture = remap(material.albedo_texture);
+                material.roughness_texture = remap(material.roughness_texture);
+                material.metallic_texture = remap(material.metallic_texture);
+                material.emission_texture = remap(material.emission_texture);
+                material.opacity_texture = remap(material.opacity_texture);
+                material.bump_texture = remap(material.bump_texture);
+                material.mix_texture = remap(material.mix_texture);
+                if material.get_type() == MaterialType::Mix {
+                    material.mix_material_a += material_offset;
+                    material.mix_material_b += material_offset;
+                }
+                self.scene_add_material(material)
+            })
+            .collect::<anyhow::Result<Vec<u32>>>()?;
+
+        let triangles: Vec<Triangle> = imported.triangles
+            .iter()
+            .map(|tri| {
+                let mut tri = *tri;
+                tri.material_id += material_offset;
+                tri
+            })
+            .collect();
+        self.scene_add_triangles(&triangles)?;
+
+        if let Some(camera) = imported.cameras.first() {
+            self.uniforms.camera = *camera;
+        }
+
+        // punctual lights are photometric, emission is not
+        for light in imported.lights.iter() {
+            let intensity = light.color * photometry::candela_to_intensity(light.intensity);
+            match light.kind {
+                ImportedLightKind::Directional => {
+                    let irradiance = light.color * photometry::lux_to_irradiance(light.intensity);
+                    let mut sun = SunLight::new(-light.direction, irradiance);
+                    sun.angular_diameter = 0.0;
+                    self.scene_set_sun(Some(sun));
+                },
+                ImportedLightKind::Point => {
+                    self.scene_add_point_light(PointLight::new(light.position, intensity))?;
+                },
+                ImportedLightKind::Spot { inner_cone_angle, outer_cone_angle } => {
+                    self.scene_add_point_light(PointLight::spot(
+                        light.position,
+                        light.direction,
+                        intensity,
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    ))?;
+                },
+            }
+        }
+
+        Ok(material_ids)
+    }
+
+    // remove all geometry, materials are kept
+    pub fn scene_clear(&mut self) {
+        // stale loads would otherwise land in the new scene
+        self.pending_meshes.clear();
+        self.scene_clear_geometry();
+        self.scene.volume_count = 0;
+        self.volume_data.clear();
+        self.scene.portal_count = 0;
+        self.scene.sun = SunLight::default();
+        self.scene.fog = Fog::default();
+        self.scene.point_light_count = 0;
+        self.scene.area_light_count = 0;
+    }
+
+    // remove the spheres, triangles, curves and discs but keep the lights, volumes, fog and
+    // meshes still loading, the bvh keeps its shape until scene_update so scene_refit
+    // can reuse it when the same primitives are added back
+    pub fn scene_clear_geometry(&mut self) {
+        self.scene.sphere_count = 0;
+        self.triangles.clear();
+        self.scene.triangle_count = 0;
+        self.curves.clear();
+        self.scene.curve_count = 0;
+        self.discs.clear();
+        self.scene.disc_count = 0;
+    }
+
+    // load a mesh without blocking, its triangles are added by render_frame as they arrive
+    // on_progress gets the fraction of the file read so far
+    pub fn scene_add_mesh_async(
+        &mut self,
+        filename: &str,
+        material_id: u32,
+        transform: Mat4,
+        on_progress: impl FnMut(f32) + 'static,
+    ) {
+        self.pending_meshes.push(PendingMesh {
+            filename: filename.to_string(),
+            transform,
+            receiver: load_mesh_async(filename, material_id),
+            on_progress: Box::new(on_progress),
+        });
+    }
+
+    pub fn is_loading(&self) -> bool {
+        !self.pending_meshes.is_empty()
+    }
+
+    // move finished batches from the loader threads into the scene
+    fn poll_pending_meshes(&mut self) {
+        let mut changed = false;
+        let mut pending_meshes = std::mem::take(&mut self.pending_meshes);
+
+        pending_meshes.retain_mut(|pending| {
+            while let Ok(event) = pending.receiver.try_recv() {
+                match event {
+                    MeshLoadEvent::Progress(mut tris, fraction) => {
+                        for tri in tris.iter_mut() {
+                            tri.vertex_0 = pending.transform.transform_point(tri.vertex_0);
+                            tri.vertex_1 = pending.transform.transform_point(tri.vertex_1);
+                            tri.vertex_2 = pending.transform.transform_point(tri.vertex_2);
+                            for normal in [&mut tri.normal_0, &mut tri.normal_1, &mut tri.normal_2] {
+                                if normal.length_squared() > 0.0 {
+                                    *normal = pending.transform.transform_normal(*normal).normalized();
+                                }
+                            }
+                        }
+                        // the rest of an overflowing mesh is dropped, dropping the receiver
+                        // also stops the loader thread
+                        if let Err(e) = self.scene_add_triangles(&tris) {
+                            println!("dropped the rest of mesh {}: {:#}", pending.filename, e);
+                            return false;
+                        }
+                        changed |= !tris.is_empty();
+                        (pending.on_progress)(fraction);
+                    },
+                    MeshLoadEvent::Finished(result) => {
+                        if let Err(e) = result {
+                            println!("failed to load mesh {}: {}", pending.filename, e);
+                        }
+                        return false;
+                    },
+                }
+            }
+            true
+        });
+
+        // keep loads queued while polling, e.g. from on_progress
+        pending_meshes.append(&mut self.pending_meshes);
+        self.pending_meshes = pending_meshes;
+
+        if changed {
+            self.scene_update();
+            self.render_reset();
+        }
+    }
+
+    // replace an existing material and upload only its bytes, cheap enough to call every
+    // frame while tweaking since the geometry and bvh are left alone
+    pub fn scene_update_material(&mut self, id: u32, material: Material) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            id < self.material_count,
+            "material {} does not exist, there are {} materials",
+            id,
+            self.material_count
+        );
+
+        self.scene.materials[id as usize] = material;
+        let offset = std::mem::offset_of!(Scene, materials) + id as usize * std::mem::size_of::<Material>();
+        self.queue.write_buffer(
+            &self.scene_buffer,
+            offset as u64,
+            bytemuck::bytes_of(&material)
+        );
+        self.render_reset();
+
+        Ok(())
+    }
+
+    pub fn scene_clear_materials(&mut self) {
+        self.material_count = 0;
+    }
+
+    // the named cameras with the active one as it is now
+    fn saved_cameras(&self) -> BTreeMap<String, Camera> {
+        let mut cameras = self.cameras.clone();
+        if let Some(active) = &self.active_camera {
+            cameras.insert(active.clone(), self.uniforms.camera);
+        }
+        cameras
+    }
+
+    pub fn save_scene(&self, filename: &str) -> anyhow::Result<()> {
+        let scene = &self.scene;
+        let scene_file = SceneFile {
+            camera: self.uniforms.camera,
+            physical_camera: self.physical_camera,
+            camera_path: self.camera_path.clone(),
+            cameras: self.saved_cameras(),
+            active_camera: self.active_camera.clone(),
+            settings: RenderSettings {
+                gamma_correction: self.uniforms.gamma_correction,
+                psuedo_chromatic_aberration: self.uniforms.psuedo_chromatic_aberration,
+                render_mode: self.get_render_mode(),
+                sky: self.get_physical_sky(),
+                background: self.get_background(),
+                manifold_caustics: self.get_manifold_caustics(),
+                environment_rotation: self.get_environment_rotation(),
+                environment_intensity: self.get_environment_intensity(),
+                shutter_open: self.uniforms.shutter_open,
+                shutter_close: self.uniforms.shutter_close,
+                vignette_strength: self.uniforms.vignette_strength,
+                vignette_radius: self.uniforms.vignette_radius,
+                physical_vignetting: self.get_physical_vignetting(),
+                sampler: self.get_sampler(),
+                indirect_clamp: self.get_indirect_clamp(),
+                outlier_rejection: self.get_outlier_rejection(),
+                render_size: self.render_size,
+                max_samples: self.max_samples,
+                auto_save: self.auto_save,
+            },
+            materials: scene.materials[..self.material_count as usize].to_vec(),
+            spheres: scene.spheres[..scene.sphere_count as usize].to_vec(),
+            triangles: self.triangles.clone(),
+            curves: self.curves.clone(),
+            discs: self.discs.clone(),
+            portals: scene.portals[..scene.portal_count as usize].to_vec(),
+            sun: self.scene_get_sun(),
+            fog: self.scene_get_fog(),
+            point_lights: scene.point_lights[..scene.point_light_count as usize].to_vec(),
+            area_lights: scene.area_lights[..scene.area_light_count as usize].to_vec(),
+            ies_profiles: self.ies_paths.clone(),
+            meshes: vec![],
+            environment: None,
+        };
+
+        scene_file.save(filename)
+    }
+
+    // dump the geometry the renderer currently holds, see file_export::export_scene
+    pub fn export_scene(&self, filename: &str) -> anyhow::Result<()> {
+        export_scene(&self.scene, &self.triangles, filename)
+    }
+
+    // replaces the current scene, camera and render settings with the ones in the file
+    pub fn load_scene(&mut self, filename: &str) -> anyhow::Result<()> {
+        let scene_file = SceneFile::load(filename)?;
+        self.load_scene_file(scene_file, filename)
+    }
+
+    // load_scene for a file that is already parsed, filename is watched for hot reloading
+    pub fn load_scene_file(&mut self, scene_file: SceneFile, filename: &str) -> anyhow::Result<()> {
+        self.scene_clear();
+        self.scene_clear_materials();
+
+        for material in scene_file.materials.iter() {
+            self.scene_add_material(*material)?;
+        }
+        for sphere in scene_file.spheres.iter() {
+            self.scene_add_sphere(*sphere);
+        }
+        self.scene_add_triangles(&scene_file.triangles)?;
+        self.scene_add_curves(&scene_file.curves)?;
+        self.scene_add_discs(&scene_file.discs)?;
+        for portal in scene_file.portals.iter() {
+            self.scene_add_portal(*portal);
+        }
+        self.scene_set_sun(scene_file.sun);
+        self.scene_set_fog(scene_file.fog);
+        // profiles are numbered by their place in the list, one that fails to load
+        // still takes its id and shines evenly
+        self.ies_data.clear();
+        self.ies_paths.clear();
+        for path in scene_file.ies_profiles.iter() {
+            let profile = load_ies_from(path).unwrap_or(IesProfile {
+                vertical_angles: vec![0.0, 180.0],
+                horizontal_angles: vec![0.0],
+                candela: vec![1.0, 1.0],
+            });
+            self.ies_data.extend(resample_ies_profile(&profile));
+            self.ies_paths.push(path.clone());
+        }
+        self.upload_ies_profiles();
+        for light in scene_file.point_lights.iter() {
+            self.scene_add_point_light(*light)?;
+        }
+        for light in scene_file.area_lights.iter() {
+            self.scene_add_area_light(*light);
+        }
+
+        // meshes stream in while rendering so big files do not block the window
+        for mesh in scene_file.meshes.iter() {
+            let transform = Mat4::translation(mesh.translation) * Mat4::scale(Vec3::all(mesh.scale));
+            let path = mesh.path.clone();
+            self.scene_add_mesh_async(&mesh.path, mesh.material_id, transform, move |fraction| {
+                println!("loading {}: {:.0}%", path, fraction * 100.0);
+            });
+        }
+
+        match scene_file.environment.as_deref().and_then(load_hdr_image_from) {
+            Some(image) => self.set_environment(&image),
+            None => self.clear_environment(),
+        }
+
+        let mut watched_files = scene_file.dependencies();
+        watched_files.push(filename.to_string());
+        self.scene_path = Some(filename.to_string());
+        self.scene_watcher = Some(FileWatcher::new(&watched_files));
+
+        self.uniforms.camera = scene_file.camera;
+        self.physical_camera = scene_file.physical_camera;
+        self.camera_path = scene_file.camera_path;
+        self.cameras = scene_file.cameras;
+        self.active_camera = None;
+        if let Some(name) = scene_file.active_camera
+            && !self.set_active_camera(&name)
+        {
+            println!("no camera called {}", name);
+        }
+        self.uniforms.gamma_correction = scene_file.settings.gamma_correction;
+        self.uniforms.psuedo_chromatic_aberration = scene_file.settings.psuedo_chromatic_aberration;
+        self.uniforms.render_mode = scene_file.settings.render_mode as u32;
+        self.uniforms.use_physical_sky = scene_file.settings.sky.is_some() as u32;
+        if let Some(sky) = scene_file.settings.sky {
+            self.uniforms.sky = sky;
+        }
+        self.set_background(scene_file.settings.background);
+        self.uniforms.manifold_caustics = scene_file.settings.manifold_caustics as u32;
+        self.uniforms.environment_rotation = scene_file.settings.environment_rotation;
+        self.uniforms.environment_intensity = scene_file.settings.environment_intensity;
+        self.uniforms.shutter_open = scene_file.settings.shutter_open;
+        self.uniforms.shutter_close = scene_file.settings.shutter_close;
+        self.uniforms.vignette_strength = scene_file.settings.vignette_strength;
+        self.uniforms.vignette_radius = scene_file.settings.vignette_radius;
+        self.uniforms.physical_vignetting = scene_file.settings.physical_vignetting as u32;
+        self.uniforms.sampler_kind = scene_file.settings.sampler as u32;
+        self.uniforms.indirect_clamp = scene_file.settings.indirect_clamp;
+        self.uniforms.outlier_rejection = scene_file.settings.outlier_rejection;
+        self.set_render_size(scene_file.settings.render_size);
+        self.set_max_samples(scene_file.settings.max_samples, scene_file.settings.auto_save);
+
+        self.scene_update();
+        self.render_reset();
+
+        Ok(())
+    }
+
+    // reload the scene when its file or any referenced file changed on disk
+    // the current camera is kept so the view does not jump while editing
+    pub fn reload_scene_if_changed(&mut self) {
+        let Some(watcher) = self.scene_watcher.as_mut() else {
+            return;
+        };
+        if !watcher.poll() {
+            return;
+        }
+
+        let path = self.scene_path.clone().unwrap();
+        let camera = self.uniforms.camera;
+        match self.load_scene(&path) {
+            Ok(()) => {
+                self.uniforms.camera = camera;
+                println!("reloaded {}", path);
+            },
+            Err(e) => println!("failed to reload scene {}: {:#}", path, e),
+        }
+    }
+
+    pub fn scene_update(&mut self) {
+        self.scene_build();
+        let light_tree = self.scene_build_light_tree();
+
+        self.queue.write_buffer(
+            &self.scene_buffer,
+            0,
+            bytemuck::bytes_of(&self.scene)
+        );
+
+        if std::mem::size_of_val(self.volume_data.as_slice()) as u64 > self.volume_buffer.size() {
+            self.volume_buffer = Gfx::create_storage_buffer::<f32>(&self.device, "volume data", self.volume_data.len());
+            self.rebuild_bind_groups();
+        }
+        if !self.volume_data.is_empty() {
+            self.queue.write_buffer(&self.volume_buffer, 0, bytemuck::cast_slice(&self.volume_data));
+        }
+
+        self.upload_light_tree(&light_tree);
+        self.upload_geometry();
+    }
+
+    // faster than scene_update after primitives moved in place, for animation
+    // the bvh keeps its shape so it slows down the further things get from where it was
+    // built, adding or removing anything still needs scene_update
+    pub fn scene_refit(&mut self) {
+        let sphere_count = self.scene.sphere_count as usize;
+        let primitive_count = self.triangles.len() + sphere_count + self.curves.len() + self.discs.len();
+        if self.bvh.is_empty() || self.bvh_primitives.len() != primitive_count {
+            self.scene_update();
+            return;
+        }
+
+        let sphere_root = self.scene.sphere_bvh_root as usize;
+        let curve_root = self.scene.curve_bvh_root as usize;
+        let disc_root = self.scene.disc_bvh_root as usize;
+        let node_count = self.bvh.len();
+        let spheres = &self.scene.spheres[..sphere_count];
+        BVHNode::bvh_refit(&self.triangles, &self.bvh_primitives, &mut self.bvh, 0..sphere_root);
+        BVHNode::bvh_refit(spheres, &self.bvh_primitives, &mut self.bvh, sphere_root..curve_root);
+        BVHNode::bvh_refit(&self.curves, &self.bvh_primitives, &mut self.bvh, curve_root..disc_root);
+        BVHNode::bvh_refit(&self.discs, &self.bvh_primitives, &mut self.bvh, disc_root..node_count);
+        // emitters may have moved too
+        let light_tree = self.scene_build_light_tree();
+
+        self.queue.write_buffer(
+            &self.scene_buffer,
+            0,
+            bytemuck::bytes_of(&self.scene)
+        );
+        self.upload_light_tree(&light_tree);
+        self.upload_geometry();
+    }
+
+    fn upload_light_tree(&mut self, light_tree: &[LightTreeNode]) {
+        if std::mem::size_of_val(light_tree) as u64 > self.light_tree_buffer.size() {
+            self.light_tree_buffer = Gfx::create_storage_buffer::<LightTreeNode>(
+                &self.device,
+                "light tree",
+                light_tree.len(),
+            );
+            self.rebuild_bind_groups();
+        }
+        if !light_tree.is_empty() {
+            self.queue.write_buffer(&self.light_tree_buffer, 0, bytemuck::cast_slice(light_tree));
+        }
+    }
+
+    fn upload_geometry(&mut self) {
+        let mut grown = false;
+        if std::mem::size_of_val(self.triangles.as_slice()) as u64 > self.triangle_buffer.size() {
+            self.triangle_buffer = Gfx::create_storage_buffer::<Triangle>(&self.device, "triangles", self.triangles.len());
+            grown = true;
+        }
+        if std::mem::size_of_val(self.curves.as_slice()) as u64 > self.curve_buffer.size() {
+            self.curve_buffer = Gfx::create_storage_buffer::<Curve>(&self.device, "curves", self.curves.len());
+            grown = true;
+        }
+        if std::mem::size_of_val(self.discs.as_slice()) as u64 > self.disc_buffer.size() {
+            self.disc_buffer = Gfx::create_storage_buffer::<Disc>(&self.device, "discs", self.discs.len());
+            grown = true;
+        }
+        if std::mem::size_of_val(self.bvh.as_slice()) as u64 > self.bvh_buffer.size() {
+            self.bvh_buffer = Gfx::create_storage_buffer::<BVHNode>(&self.device, "bvh", self.bvh.len());
+            grown = true;
+        }
+        if std::mem::size_of_val(self.bvh_primitives.as_slice()) as u64 > self.bvh_primitive_buffer.size() {
+            self.bvh_primitive_buffer = Gfx::create_storage_buffer::<u32>(
+                &self.device,
+                "bvh primitives",
+                self.bvh_primitives.len(),
+            );
+            grown = true;
+        }
+        if grown {
+            self.geometry_bind_group = Gfx::create_geometry_bind_group(
+                &self.device,
+                &self.geometry_bind_group_layout,
+                &self.curve_buffer,
+                &self.bvh_buffer,
+                &self.disc_buffer,
+                &self.triangle_buffer,
+                &self.bvh_primitive_buffer,
+            );
+        }
+
+        if !self.triangles.is_empty() {
+            self.queue.write_buffer(&self.triangle_buffer, 0, bytemuck::cast_slice(&self.triangles));
+        }
+        if !self.curves.is_empty() {
+            self.queue.write_buffer(&self.curve_buffer, 0, bytemuck::cast_slice(&self.curves));
+        }
+        if !self.discs.is_empty() {
+            self.queue.write_buffer(&self.disc_buffer, 0, bytemuck::cast_slice(&self.discs));
+        }
+        if !self.bvh.is_empty() {
+            self.queue.write_buffer(&self.bvh_buffer, 0, bytemuck::cast_slice(&self.bvh));
+        }
+        if !self.bvh_primitives.is_empty() {
+            self.queue.write_buffer(&self.bvh_primitive_buffer, 0, bytemuck::cast_slice(&self.bvh_primitives));
+        }
     }
 
     pub fn get_camera(&mut self) -> &mut Camera {
         &mut self.uniforms.camera
     }
 
+    // places the camera so the whole scene is in view, keeping its direction
+    pub fn frame_scene(&mut self) {
+        let Some((bbox_min, bbox_max)) = self.scene.bounds(&self.triangles, &self.curves, &self.discs) else {
+            return;
+        };
+        let aspect_ratio = self.uniforms.camera.aspect_ratio(self.uniforms.width, self.uniforms.height);
+        self.uniforms.camera.frame_bounds(bbox_min, bbox_max, aspect_ratio);
+        self.render_reset();
+    }
+
     pub fn get_uniforms(&mut self) -> &mut Uniforms {
         &mut self.uniforms
     }
 
+    pub fn get_render_mode(&self) -> RenderMode {
+        match self.uniforms.render_mode {
+            1 => RenderMode::Toon,
+            _ => RenderMode::PathTraced,
+        }
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.uniforms.render_mode = mode as u32;
+        self.render_reset();
+    }
+
+    pub fn get_sampler(&self) -> Sampler {
+        match self.uniforms.sampler_kind {
+            1 => Sampler::Sobol,
+            _ => Sampler::Random,
+        }
+    }
+
+    // Sobol also takes over the pixel and lens positions from the blue noise masks
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.uniforms.sampler_kind = sampler as u32;
+        self.render_reset();
+    }
+
+    pub fn get_indirect_clamp(&self) -> f32 {
+        self.uniforms.indirect_clamp
+    }
+
+    // caps every channel of light arriving over more than one bounce, around 10 keeps
+    // caustics through small glass from speckling the image, 0 turns it off
+    pub fn set_indirect_clamp(&mut self, max_radiance: f32) {
+        self.uniforms.indirect_clamp = max_radiance.max(0.0);
+        self.render_reset();
+    }
+
+    pub fn get_outlier_rejection(&self) -> f32 {
+        self.uniforms.outlier_rejection
+    }
+
+    // once a pixel has 16 samples, new ones brighter than ratio times its average are
+    // scaled down to that, 0 turns it off
+    pub fn set_outlier_rejection(&mut self, ratio: f32) {
+        self.uniforms.outlier_rejection = ratio.max(0.0);
+        self.render_reset();
+    }
+
+    pub fn get_background(&self) -> Background {
+        let bottom = self.uniforms.background_bottom;
+        let top = self.uniforms.background_top;
+        match self.uniforms.background {
+            1 => Background::Color(bottom),
+            2 => Background::Gradient { bottom, top },
+            3 => Background::Transparent,
+            _ => Background::Environment,
+        }
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        let (mode, bottom, top) = match background {
+            Background::Environment => (0, Vec3::default(), Vec3::default()),
+            Background::Color(color) => (1, color, color),
+            Background::Gradient { bottom, top } => (2, bottom, top),
+            Background::Transparent => (3, Vec3::default(), Vec3::default()),
+        };
+        self.uniforms.background = mode;
+        self.uniforms.background_bottom = bottom;
+        self.uniforms.background_top = top;
+        self.render_reset();
+    }
+
+    pub fn get_bvh_bins(&self) -> usize {
+        self.bvh_options.sah_bins
+    }
+
+    // more bins find better splits but build slower, fewer than 2 splits at the median
+    // like the old builder did, takes effect on the next scene_update
+    pub fn set_bvh_bins(&mut self, bins: usize) {
+        self.bvh_options.sah_bins = bins;
+    }
+
+    pub fn get_bvh_node_budget(&self) -> usize {
+        self.bvh_options.max_nodes
+    }
+
+    // caps the gpu memory of the bvh, past it the leaves get larger and slower instead
+    // takes effect on the next scene_update
+    pub fn set_bvh_node_budget(&mut self, max_nodes: usize) {
+        self.bvh_options.max_nodes = max_nodes;
+    }
+
+    // the last built bvh and the primitive ids its leaves point into, the triangle tree
+    // is rooted at 0 and followed by the sphere, curve and disc trees
+    pub fn get_bvh(&self) -> (&[BVHNode], &[u32]) {
+        (&self.bvh, &self.bvh_primitives)
+    }
+
+    // expected cost of a ray against the last built triangle, sphere, curve and disc
+    // trees together, see BVHNode::sah_cost
+    pub fn get_bvh_cost(&self) -> f32 {
+        let sphere_root = self.scene.sphere_bvh_root as usize;
+        let curve_root = self.scene.curve_bvh_root as usize;
+        let disc_root = self.scene.disc_bvh_root as usize;
+        BVHNode::sah_cost(&self.bvh[..sphere_root])
+            + BVHNode::sah_cost(&self.bvh[sphere_root..curve_root])
+            + BVHNode::sah_cost(&self.bvh[curve_root..disc_root])
+            + BVHNode::sah_cost(&self.bvh[disc_root..])
+    }
+
+    pub fn get_manifold_caustics(&self) -> bool {
+        self.uniforms.manifold_caustics != 0
+    }
+
+    // sharp caustics of smooth glass spheres on diffuse surfaces lit by the sun, point
+    // and area lights, costs a root search per sphere and light on every diffuse hit
+    pub fn set_manifold_caustics(&mut self, enabled: bool) {
+        self.uniforms.manifold_caustics = enabled as u32;
+        self.render_reset();
+    }
+
+    pub fn get_environment_rotation(&self) -> f32 {
+        self.uniforms.environment_rotation
+    }
+
+    // turns the environment map around the up axis, wrapped to [0, 2pi)
+    pub fn set_environment_rotation(&mut self, yaw: f32) {
+        self.uniforms.environment_rotation = yaw.rem_euclid(2.0 * std::f32::consts::PI);
+        self.render_reset();
+    }
+
+    pub fn get_environment_intensity(&self) -> f32 {
+        self.uniforms.environment_intensity
+    }
+
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        self.uniforms.environment_intensity = intensity.max(0.0);
+        self.render_reset();
+    }
+
+    pub fn get_physical_camera(&self) -> Option<PhysicalCamera> {
+        self.physical_camera
+    }
+
+    // None goes back to setting fov, width and apeture on the camera directly
+    pub fn set_physical_camera(&mut self, physical_camera: Option<PhysicalCamera>) {
+        self.physical_camera = physical_camera;
+        self.render_reset();
+    }
+
+    pub fn get_focus_overlay(&self) -> bool {
+        self.uniforms.focus_overlay != 0
+    }
+
+    // tints what is sharp at the current focus distance and aperture green on screen,
+    // saved renders are left alone and accumulation goes on
+    pub fn set_focus_overlay(&mut self, enabled: bool) {
+        self.uniforms.focus_overlay = enabled as u32;
+    }
+
+    pub fn get_vignette(&self) -> (f32, f32) {
+        (self.uniforms.vignette_strength, self.uniforms.vignette_radius)
+    }
+
+    // artistic vignette, strength 0 turns it off
+    pub fn set_vignette(&mut self, strength: f32, radius: f32) {
+        self.uniforms.vignette_strength = strength.clamp(0.0, 1.0);
+        self.uniforms.vignette_radius = radius.max(0.0);
+        self.render_reset();
+    }
+
+    pub fn get_physical_vignetting(&self) -> bool {
+        self.uniforms.physical_vignetting != 0
+    }
+
+    // light reaching the sensor off axis falls off with the fourth power of the cosine
+    // of its angle, strongest with wide lenses
+    pub fn set_physical_vignetting(&mut self, enabled: bool) {
+        self.uniforms.physical_vignetting = enabled as u32;
+        self.render_reset();
+    }
+
+    pub fn get_blue_noise(&self) -> bool {
+        self.uniforms.blue_noise != 0
+    }
+
+    // pixel and lens positions from blue noise masks instead of the plain random numbers,
+    // the first few samples look much smoother while the converged image is the same
+    pub fn set_blue_noise(&mut self, enabled: bool) {
+        self.uniforms.blue_noise = enabled as u32;
+        self.render_reset();
+    }
+
+    pub fn get_shutter(&self) -> (f32, f32) {
+        (self.uniforms.shutter_open, self.uniforms.shutter_close)
+    }
+
+    // fractions of the frame between the motion start camera (0) and the camera (1)
+    pub fn set_shutter(&mut self, open: f32, close: f32) {
+        self.uniforms.shutter_open = open.clamp(0.0, 1.0);
+        self.uniforms.shutter_close = close.clamp(open, 1.0);
+        self.render_reset();
+    }
+
+    // the camera moves from start to the current camera over the frame and is blurred
+    // while the shutter is open, None turns camera motion blur off
+    pub fn set_camera_motion(&mut self, start: Option<Camera>) {
+        self.motion_start = start;
+        self.render_reset();
+    }
+
     pub fn render_reset(&mut self) {
         self.uniforms.frame_count = 0;
+        self.next_tile = 0;
+        self.reproject_next = false;
+        self.denoised = false;
+        self.max_samples_saved = false;
+    }
+
+    // like render_reset after moving the camera, but every pixel keeps up to
+    // reprojection_history samples of what its first hit looked like in the last finished
+    // sample when that hit was in view then, so navigating stays fairly clean
+    // only a plain perspective camera without motion blur can be reprojected
+    pub fn render_reproject(&mut self) {
+        self.last_interaction = Some(Instant::now());
+        let reprojectable = |camera: &Camera| {
+            camera.get_projection() == Projection::Perspective
+                && camera.get_stereo_mode() == StereoMode::Off
+                && camera.distortion_k1 == 0.0
+                && camera.distortion_k2 == 0.0
+        };
+        // the sample after reads the radiance of the last finished one, which is the
+        // texture of its parity, so the kept history has to have that parity too
+        let samples = self.accumulated_samples;
+        let mut history = samples.min(self.reprojection_history);
+        if history % 2 != samples % 2 {
+            history = history.saturating_sub(1);
+        }
+        if self.get_sample_count() == 0
+            || history == 0
+            || self.motion_start.is_some()
+            || self.accumulated_scale != self.preview_scale
+            || !reprojectable(&self.uniforms.camera)
+            || !reprojectable(&self.accumulated_camera)
+        {
+            self.render_reset();
+            return;
+        }
+
+        self.uniforms.previous_camera = self.accumulated_camera;
+        self.uniforms.previous_samples = samples;
+        self.uniforms.frame_count = history;
+        self.next_tile = 0;
+        self.reproject_next = true;
+        self.denoised = false;
+    }
+
+    pub fn get_preview_scale(&self) -> u32 {
+        self.preview_scale
+    }
+
+    // while the camera moves (see render_reproject) the frame is traced in scale by
+    // scale blocks of pixels, so navigating heavy scenes stays smooth, full resolution
+    // comes back once it stops, 1 turns it off
+    pub fn set_preview_scale(&mut self, scale: u32) {
+        self.preview_scale = scale.max(1);
+    }
+
+    pub fn get_denoise(&self) -> bool {
+        self.uniforms.denoise != 0
+    }
+
+    // blur the noise out of the shown frame while keeping the edges of the geometry and
+    // textures, saved renders are denoised too, the focus overlay is not drawn meanwhile
+    pub fn set_denoise(&mut self, on: bool) {
+        self.uniforms.denoise = on as u32;
+        if !on {
+            self.denoised = false;
+        }
+    }
+
+    pub fn get_oidn(&self) -> bool {
+        self.oidn
+    }
+
+    // run saved renders through open image denoise on the cpu before writing them, with
+    // the albedo and normal of the first hits as guides, see oidn.rs
+    pub fn set_oidn(&mut self, on: bool) {
+        self.oidn = on;
+    }
+
+    pub fn get_reprojection_history(&self) -> u32 {
+        self.reprojection_history
+    }
+
+    // the fewer samples are kept the quicker moving highlights and shadows catch up
+    pub fn set_reprojection_history(&mut self, samples: u32) {
+        self.reprojection_history = samples;
+    }
+
+    // samples every pixel has, frame_count counts the one still being traced in tiles
+    pub fn get_sample_count(&self) -> u32 {
+        if self.next_tile == 0 {
+            self.uniforms.frame_count
+        } else {
+            self.uniforms.frame_count - 1
+        }
+    }
+
+    pub fn get_tile_size(&self) -> Option<u32> {
+        self.tile_size
+    }
+
+    pub fn get_render_size(&self) -> (u32, u32) {
+        (self.uniforms.width, self.uniforms.height)
+    }
+
+    // trace at width by height whatever the window size, the frame is fitted into the
+    // window with bars around it and filtered down or up, None follows the window
+    pub fn set_render_size(&mut self, size: Option<(u32, u32)>) {
+        let window_size = (self.uniforms.window_width, self.uniforms.window_height);
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let (width, height) = size.unwrap_or(window_size);
+        let (width, height) = (width.clamp(1, max_size), height.clamp(1, max_size));
+        self.render_size = size;
+        if (width, height) == self.get_render_size() {
+            return;
+        }
+        self.uniforms.width = width;
+        self.uniforms.height = height;
+
+        self.radiance_samples = Gfx::create_texture(&self.device, width, height);
+        self.guide = Gfx::create_guide_textures(&self.device, width, height);
+        self.denoise_images = Gfx::create_texture(&self.device, width, height);
+        self.display_image = Gfx::create_display_texture(&self.device, width, height);
+        self.display_bind_group = Gfx::create_display_bind_group(
+            &self.device,
+            &self.display_bind_group_layout,
+            &self.uniform_buffer,
+            &self.display_image,
+        );
+        self.denoise_bind_groups = Gfx::create_denoise_bind_groups(
+            &self.device,
+            &self.denoise_bind_group_layout,
+            &self.uniform_buffer,
+            &self.radiance_samples,
+            &self.guide,
+            &self.denoise_images,
+            &self.display_image,
+        );
+        self.rebuild_bind_groups();
+        // the tiles cover the new size, this also restarts the accumulation
+        self.set_tile_size(self.tile_size, self.tiles_per_frame);
+    }
+
+    // trace the frame in square tiles of about size pixels, tiles_per_frame of them every
+    // render_frame from the center out, so one submit stays short on large resolutions
+    // and slow gpus instead of tripping the driver's timeout, None traces it whole
+    pub fn set_tile_size(&mut self, size: Option<u32>, tiles_per_frame: u32) {
+        self.tile_size = size;
+        self.tiles_per_frame = tiles_per_frame.max(1);
+        self.uniforms.tile_size = trace_tile_size(size, self.uniforms.width, self.uniforms.height);
+        self.tile_order = spiral_tiles(self.uniforms.width, self.uniforms.height, self.uniforms.tile_size);
+        self.render_reset();
+    }
+
+    pub fn get_max_samples(&self) -> Option<u32> {
+        self.max_samples
+    }
+
+    pub fn get_auto_save(&self) -> Option<RenderFormat> {
+        self.auto_save
+    }
+
+    // stop tracing once every pixel has samples and only show the frame again, saving it
+    // to ./imgs in auto_save format first when that is set, None keeps tracing forever
+    // sun sequences and camera animations keep their own sample counts
+    pub fn set_max_samples(&mut self, samples: Option<u32>, auto_save: Option<RenderFormat>) {
+        self.max_samples = samples;
+        self.auto_save = auto_save;
+        self.max_samples_saved = false;
+    }
+
+    fn max_samples_reached(&self) -> bool {
+        self.next_tile == 0
+            && self.sun_sequence.is_none()
+            && self.camera_animation.is_none()
+            && self.max_samples.is_some_and(|max| self.get_sample_count() >= max)
     }
 
     pub fn render_frame(&mut self) {
-        let elapsed = self.start_time.elapsed().as_millis();
-        self.uniforms.elapsed_seconds = elapsed as f32 / 1000.0;
-        self.uniforms.frame_count += 1;
+        self.poll_pending_meshes();
+
+        if self.max_samples_reached() {
+            if !self.max_samples_saved {
+                self.max_samples_saved = true;
+                println!("reached {} samples", self.get_sample_count());
+                if let Some(format) = self.auto_save
+                    && let Err(e) = pollster::block_on(self.save_render(format))
+                {
+                    println!("failed to save render: {:#}", e);
+                }
+            }
+            let encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("present frame"),
+            });
+            self.present(encoder);
+            return;
+        }
+
+        // the tiles of one sample share its frame_count, time and resolution
+        if self.next_tile == 0 {
+            // what was accumulated at the other resolution can not be kept
+            let interacting = self.last_interaction.is_some_and(|time| time.elapsed() < PREVIEW_IDLE_TIME);
+            let scale = if interacting { self.preview_scale } else { 1 };
+            if scale != self.uniforms.preview_scale {
+                self.render_reset();
+                self.uniforms.preview_scale = scale;
+            }
+            self.uniforms.tile_size = if scale > 1 {
+                let (width, height) = (self.uniforms.width.div_ceil(scale), self.uniforms.height.div_ceil(scale));
+                trace_tile_size(None, width, height)
+            } else {
+                trace_tile_size(self.tile_size, self.uniforms.width, self.uniforms.height)
+            };
+
+            let elapsed = self.start_time.elapsed().as_millis();
+            self.uniforms.elapsed_seconds = elapsed as f32 / 1000.0;
+            self.uniforms.frame_count += 1;
+            self.uniforms.reproject = self.reproject_next as u32;
+            self.reproject_next = false;
+        }
+        // a preview is small enough to be traced whole
+        let preview = self.uniforms.preview_scale > 1;
+        let tile_end = (self.next_tile + self.tiles_per_frame as usize).min(self.tile_order.len());
+        let tile_count = if preview { 1 } else { (tile_end - self.next_tile) as u32 };
+        if (tile_count as usize * std::mem::size_of::<[u32; 2]>()) as u64 > self.tile_origin_buffer.size() {
+            self.tile_origin_buffer = Gfx::create_storage_buffer::<[u32; 2]>(
+                &self.device,
+                "tile origins",
+                tile_count as usize,
+            );
+            self.rebuild_bind_groups();
+        }
+        let tiles: &[[u32; 2]] = if preview { &[[0, 0]] } else { &self.tile_order[self.next_tile..tile_end] };
+        self.queue.write_buffer(
+            &self.tile_origin_buffer,
+            0,
+            bytemuck::cast_slice(tiles)
+        );
+        self.next_tile = if preview || tile_end == self.tile_order.len() { 0 } else { tile_end };
+
+        if let Some(physical_camera) = self.physical_camera {
+            let aspect_ratio = self.uniforms.camera.aspect_ratio(self.uniforms.width, self.uniforms.height);
+            physical_camera.apply(&mut self.uniforms.camera, aspect_ratio);
+        }
+        let motion_start = self.motion_start.unwrap_or(self.uniforms.camera);
+        self.uniforms.camera_start_position = motion_start.position;
+        self.uniforms.camera_start_direction = motion_start.direction;
 
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
             bytemuck::bytes_of(&self.uniforms)
         );
+        if self.next_tile == 0 {
+            self.accumulated_camera = self.uniforms.camera;
+            self.accumulated_samples = self.uniforms.frame_count;
+            self.accumulated_scale = self.uniforms.preview_scale;
+        }
+
+        let mut encoder = self.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("render frame"),
+            });
+
+        let mut trace_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("trace pass"),
+            timestamp_writes: None,
+        });
+        trace_pass.set_pipeline(&self.trace_pipeline);
+        trace_pass.set_bind_group(
+            0,
+            &self.trace_bind_group[(self.uniforms.frame_count % 2) as usize],
+            &[],
+        );
+        trace_pass.set_bind_group(1, &self.geometry_bind_group, &[]);
+        // a tile bigger than the frame only covers the frame
+        let scale = self.uniforms.preview_scale;
+        trace_pass.dispatch_workgroups(
+            self.uniforms.tile_size.min(self.uniforms.width.div_ceil(scale)).div_ceil(TRACE_WORKGROUP_SIZE),
+            self.uniforms.tile_size.min(self.uniforms.height.div_ceil(scale)).div_ceil(TRACE_WORKGROUP_SIZE),
+            tile_count,
+        );
+        drop(trace_pass);
+
+        // only finished samples are denoised, until the next one the last result stays,
+        // previews are shown as they are
+        if self.uniforms.denoise != 0 && !preview && self.next_tile == 0 {
+            let mut denoise_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("denoise pass"),
+                timestamp_writes: None,
+            });
+            denoise_pass.set_pipeline(&self.denoise_pipeline);
+            for bind_group in &self.denoise_bind_groups[(self.uniforms.frame_count % 2) as usize] {
+                denoise_pass.set_bind_group(0, bind_group, &[]);
+                denoise_pass.dispatch_workgroups(
+                    self.uniforms.width.div_ceil(TRACE_WORKGROUP_SIZE),
+                    self.uniforms.height.div_ceil(TRACE_WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            drop(denoise_pass);
+            self.denoised = true;
+        }
+
+        self.present(encoder);
+
+        self.advance_sun_sequence();
+        self.advance_camera_animation();
+    }
+
+    // traces until every pixel has samples of the whole scene, for rendering headless
+    // set_max_samples does not stop it early
+    pub fn render_samples(&mut self, samples: u32) {
+        let next_sample = std::cell::Cell::new(self.get_sample_count());
+        self.render_claimed_samples(|| {
+            let sample = next_sample.get();
+            next_sample.set(sample + 1);
+            (sample < samples).then_some(sample)
+        });
+    }
+
+    // like render_samples, but traces whichever sample claim hands out next until it runs
+    // out, claim is shared by every gpu of a render, see multi_gpu::render_samples
+    pub fn render_claimed_samples(&mut self, claim: impl Fn() -> Option<u32>) {
+        while self.is_loading() {
+            self.poll_pending_meshes();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let max_samples = self.max_samples.take();
+        while let Some(sample) = claim() {
+            // the sample after is traced with frame_count one higher, see init_rng
+            let sample_count = self.get_sample_count();
+            self.uniforms.sample_offset = sample - sample_count;
+            while self.get_sample_count() == sample_count {
+                self.render_frame();
+            }
+        }
+        self.uniforms.sample_offset = 0;
+        self.max_samples = max_samples;
+    }
+
+    // adds the radiance sums of samples traced elsewhere, like from read_accumulation on
+    // another gpu of the same scene and size
+    pub fn add_accumulation(&mut self, radiance: &[u32], samples: u32) {
+        let old = self.newest_samples();
+        let mut sums = pollster::block_on(self.read_texture(&self.radiance_samples[old]));
+        for (sum, other) in sums.iter_mut().zip(radiance) {
+            *sum = (f32::from_bits(*sum) + f32::from_bits(*other)).to_bits();
+        }
 
-        let frame = self.surface
+        self.uniforms.frame_count = self.get_sample_count() + samples;
+        self.next_tile = 0;
+        self.denoised = false;
+        let newest = self.newest_samples();
+        self.write_texture(&self.radiance_samples[newest], &sums);
+
+        // the first hits stay the same
+        if newest != old {
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("copy guide"),
+            });
+            encoder.copy_texture_to_texture(
+                self.guide[old].as_image_copy(),
+                self.guide[newest].as_image_copy(),
+                self.guide[old].size(),
+            );
+            self.queue.submit(Some(encoder.finish()));
+        }
+    }
+
+    // draws display_image to the window after what encoder already holds
+    fn present(&self, mut encoder: wgpu::CommandEncoder) {
+        let Some(surface) = &self.surface else {
+            // nothing paces a headless render, so it waits for the gpu instead of
+            // queueing up samples without end
+            self.queue.submit(Some(encoder.finish()));
+            let _ = self.device.poll(wgpu::PollType::Wait);
+            return;
+        };
+        let frame = surface
             .get_current_texture()
             .expect("failed to get current texture");
 
@@ -415,11 +3448,6 @@ impl Gfx {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let mut encoder = self.device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("render frame"),
-            });
-
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -433,12 +3461,8 @@ impl Gfx {
             ..Default::default()
         });
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(
-            0,
-            &self.render_bind_group[(self.uniforms.frame_count % 2) as usize],
-            &[],
-        );
+        render_pass.set_pipeline(&self.display_pipeline);
+        render_pass.set_bind_group(0, &self.display_bind_group, &[]);
 
         render_pass.draw(0..6, 0..1);
 
@@ -450,9 +3474,14 @@ impl Gfx {
         frame.present();
     }
 
-    pub async fn save_render(&self) {
+    // copy a texture of 16 byte pixels back, as 32 bit words
+    async fn read_texture(&self, texture: &wgpu::Texture) -> Vec<u32> {
+        // rows of a copy are padded to COPY_BYTES_PER_ROW_ALIGNMENT
+        let row_size = 16 * self.uniforms.width;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
         // create buffer for readback
-        let buffer_size = (self.uniforms.width * self.uniforms.height * 16) as wgpu::BufferAddress;
+        let buffer_size = (padded_row_size * self.uniforms.height) as wgpu::BufferAddress;
         let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Readback Buffer"),
             size: buffer_size,
@@ -466,7 +3495,7 @@ impl Gfx {
 
         encoder.copy_texture_to_buffer(
             wgpu::TexelCopyTextureInfo {
-                texture: &self.radiance_samples[(self.uniforms.frame_count % 2) as usize],
+                texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
@@ -475,7 +3504,7 @@ impl Gfx {
                 buffer: &buffer,
                 layout: wgpu::TexelCopyBufferLayout {
                     offset: 0,
-                    bytes_per_row: Some(16 * self.uniforms.width),
+                    bytes_per_row: Some(padded_row_size),
                     rows_per_image: Some(self.uniforms.height),
                 },
             },
@@ -495,42 +3524,286 @@ impl Gfx {
         let _ = self.device.poll(wgpu::PollType::Wait); // wait for GPU work
 
         let data = buffer_slice.get_mapped_range();
-        let data_f32: &[f32] = bytemuck::cast_slice(&data);
-        let mut data_u8 = vec![0 as u8; data_f32.len()];
-
-        // copy and convert data to u8 format
-        // TODO: implement other tonemapping technique
-        // here im using rgb clampping
-        for i in 0..data_f32.len() {
-            let converted = data_f32[i] / (self.uniforms.frame_count as f32);
-            data_u8[i] = (converted.powf(1.0/self.uniforms.gamma_correction) * 255.0) as u8;
-        }
+        let words = data
+            .chunks_exact(padded_row_size as usize)
+            .flat_map(|row| bytemuck::cast_slice::<u8, u32>(&row[..row_size as usize]))
+            .copied()
+            .collect();
 
         drop(data);
         buffer.unmap();
 
-        let img: image::ImageBuffer<image::Rgba<u8>, _> = image::ImageBuffer::from_raw(
-            self.uniforms.width,
-            self.uniforms.height,
-            data_u8
-        ).ok_or("failed to create ImageBuffer from raw data").unwrap();
+        words
+    }
+
+    // the other way around, words has to cover the whole frame
+    fn write_texture(&self, texture: &wgpu::Texture, words: &[u32]) {
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(words),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * self.uniforms.width),
+                rows_per_image: Some(self.uniforms.height),
+            },
+            wgpu::Extent3d {
+                width: self.uniforms.width,
+                height: self.uniforms.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // the radiance summed over every finished sample as bits, see add_accumulation
+    pub async fn read_accumulation(&self) -> Vec<u32> {
+        self.read_texture(&self.radiance_samples[self.newest_samples()]).await
+    }
+
+    // copy the accumulated radiance back and average it over the finished samples
+    async fn read_radiance(&self) -> Vec<f32> {
+        // the denoised image is an average already, open image denoise gets the noisy one
+        if self.denoised && !self.oidn {
+            let texture = &self.denoise_images[(DENOISE_ITERATIONS - 1) % 2];
+            return self.read_texture(texture).await.into_iter().map(f32::from_bits).collect();
+        }
+
+        let sample_count = self.get_sample_count().max(1) as f32;
+        self.read_accumulation()
+            .await
+            .into_iter()
+            .map(|value| f32::from_bits(value) / sample_count)
+            .collect()
+    }
+
+    // albedo and normal rgb of the first hit through every pixel, see encode_guide in the
+    // shader
+    async fn read_guide(&self) -> (Vec<f32>, Vec<f32>) {
+        let guide = self.read_texture(&self.guide[self.newest_samples()]).await;
+
+        let snorm = |value: u32| (value as u16 as i16 as f32 / 32767.0).max(-1.0);
+        let mut albedo = Vec::with_capacity(guide.len() / 4 * 3);
+        let mut normal = Vec::with_capacity(guide.len() / 4 * 3);
+        for texel in guide.chunks_exact(4) {
+            albedo.extend((0..3).map(|i| ((texel[2] >> (8 * i)) & 0xff) as f32 / 255.0));
+
+            // the octahedron folded back out
+            let (mut x, mut y) = (snorm(texel[1]), snorm(texel[1] >> 16));
+            let z = 1.0 - x.abs() - y.abs();
+            let fold = (-z).max(0.0);
+            x += if x >= 0.0 { -fold } else { fold };
+            y += if y >= 0.0 { -fold } else { fold };
+            let n = Vec3::new(x, y, z).normalized();
+            normal.extend([n.x(), n.y(), n.z()]);
+        }
+
+        (albedo, normal)
+    }
+
+    // exposure and gamma for the value at index i of rgba pixels, alpha is coverage and
+    // stays linear
+    fn encode_gamma(&self, i: usize, value: f32) -> f32 {
+        if i % 4 == 3 {
+            value
+        } else {
+            (value * self.uniforms.camera.exposure).powf(1.0 / self.uniforms.gamma_correction)
+        }
+    }
 
-        // save as PNG
+    pub async fn save_render(&self, format: RenderFormat) -> anyhow::Result<()> {
         let date = Local::now();
-        let file = std::fs::File::create(format!("./imgs/{}.png",date.format("%Y-%m-%d-%H-%M-%S"))).unwrap();
+        let path = format!(
+            "./imgs/{}.{}",
+            date.format("%Y-%m-%d-%H-%M-%S"),
+            format.extension(),
+        );
+        self.save_render_to(&path, format).await
+    }
+
+    pub async fn save_render_to(&self, path: &str, format: RenderFormat) -> anyhow::Result<()> {
+        let mut radiance = self.read_radiance().await;
+        if self.oidn {
+            let (albedo, normal) = self.read_guide().await;
+            let (width, height) = (self.uniforms.width as usize, self.uniforms.height as usize);
+            // the noisy render is still worth saving
+            if let Err(e) = oidn::denoise(&mut radiance, &albedo, &normal, width, height) {
+                println!("{:#}", e);
+            }
+        }
+        let file = std::fs::File::create(path).with_context(|| format!("failed to create {}", path))?;
         let mut writer = std::io::BufWriter::new(file);
-        img.write_to(&mut writer, image::ImageFormat::Png).unwrap();
 
-        println!("image saved");
+        let written = match format {
+            RenderFormat::Png => {
+                // TODO: implement other tonemapping technique
+                // here im using rgb clampping
+                let data_u8: Vec<u8> = radiance
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (self.encode_gamma(i, *value) * 255.0) as u8)
+                    .collect();
+
+                let img: image::ImageBuffer<image::Rgba<u8>, _> = image::ImageBuffer::from_raw(
+                    self.uniforms.width,
+                    self.uniforms.height,
+                    data_u8
+                ).context("failed to create ImageBuffer from raw data")?;
+                img.write_to(&mut writer, image::ImageFormat::Png)
+            },
+            RenderFormat::Png16 => {
+                let data_u16: Vec<u16> = radiance
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (self.encode_gamma(i, *value) * 65535.0) as u16)
+                    .collect();
+
+                let img: image::ImageBuffer<image::Rgba<u16>, _> = image::ImageBuffer::from_raw(
+                    self.uniforms.width,
+                    self.uniforms.height,
+                    data_u16
+                ).context("failed to create ImageBuffer from raw data")?;
+                img.write_to(&mut writer, image::ImageFormat::Png)
+            },
+            RenderFormat::Exr => {
+                // raw linear radiance, no gamma or clamping
+                let img: image::Rgba32FImage = image::ImageBuffer::from_raw(
+                    self.uniforms.width,
+                    self.uniforms.height,
+                    radiance
+                ).context("failed to create ImageBuffer from raw data")?;
+                img.write_to(&mut writer, image::ImageFormat::OpenExr)
+            },
+        };
+        written.with_context(|| format!("failed to write {}", path))?;
+
+        println!("image saved to {}", path);
+        Ok(())
     }
 
-    fn scene_build(&mut self) {
-        let mut tri_indices: Vec<usize> = (0..self.scene.triangle_count as usize).collect();
-        let mut tmp_bvh = Vec::new();
-        BVHNode::bvh_build(&mut self.scene.triangles, &mut tri_indices, &mut tmp_bvh, 8);
+    // the ping-pong texture holding every finished sample, pass n reads n % 2 and adds
+    // its sample into the other one
+    fn newest_samples(&self) -> usize {
+        ((self.get_sample_count() + 1) % 2) as usize
+    }
+
+    // writes the accumulation so far with the camera it belongs to, see checkpoint.rs
+    pub async fn save_checkpoint(&self, filename: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(self.uniforms.preview_scale == 1, "a preview can not be checkpointed");
+        let newest = self.newest_samples();
+        let checkpoint = Checkpoint {
+            width: self.uniforms.width,
+            height: self.uniforms.height,
+            frame_count: self.get_sample_count(),
+            camera: self.accumulated_camera,
+            radiance: self.read_texture(&self.radiance_samples[newest]).await,
+            guide: self.read_texture(&self.guide[newest]).await,
+        };
+        checkpoint.save(filename)?;
+
+        println!("checkpoint of {} samples saved to {}", checkpoint.frame_count, filename);
+        Ok(())
+    }
+
+    // carries on with a saved accumulation, the scene has to be the one it was saved with
+    // the render size is changed to the one of the checkpoint
+    pub fn load_checkpoint(&mut self, filename: &str) -> anyhow::Result<()> {
+        let checkpoint = Checkpoint::load(filename)?;
+
+        self.set_render_size(Some((checkpoint.width, checkpoint.height)));
+        anyhow::ensure!(
+            self.get_render_size() == (checkpoint.width, checkpoint.height),
+            "checkpoint of {}x{} is too big for this device",
+            checkpoint.width,
+            checkpoint.height
+        );
+        self.render_reset();
+        self.last_interaction = None;
+        self.uniforms.preview_scale = 1;
+        self.uniforms.camera = checkpoint.camera;
+        self.uniforms.frame_count = checkpoint.frame_count;
+        self.accumulated_camera = checkpoint.camera;
+        self.accumulated_samples = checkpoint.frame_count;
+        self.accumulated_scale = 1;
+
+        let newest = self.newest_samples();
+        self.write_texture(&self.radiance_samples[newest], &checkpoint.radiance);
+        self.write_texture(&self.guide[newest], &checkpoint.guide);
+
+        println!("resumed {} samples from {}", checkpoint.frame_count, filename);
+        Ok(())
+    }
 
-        for (i, node) in tmp_bvh.iter().take(96).enumerate() {
-            self.scene.bvh[i] = node.clone();
+    // tree over every glowing triangle weighted by its power, triangle ids refer to the
+    // order after scene_build
+    fn scene_build_light_tree(&mut self) -> Vec<LightTreeNode> {
+        let mut emitters: Vec<(u32, f32)> = self.triangles
+            .iter()
+            .enumerate()
+            .map(|(triangle_id, tri)| {
+                let power = self.scene.materials[tri.material_id as usize].emitter_power() * tri.area();
+                (triangle_id as u32, power)
+            })
+            .filter(|(_, power)| *power > 0.0)
+            .collect();
+
+        self.scene.emissive_triangle_count = emitters.len() as u32;
+        let mut tree = vec![];
+        if !emitters.is_empty() {
+            LightTreeNode::build(&self.triangles, &mut emitters, &mut tree);
         }
+        tree
+    }
+
+    fn scene_build(&mut self) {
+        let mut tri_indices: Vec<usize> = (0..self.triangles.len()).collect();
+        self.bvh.clear();
+        BVHNode::bvh_build(&self.triangles, &mut tri_indices, &mut self.bvh, self.bvh_options);
+
+        // the sphere tree goes after it, its leaves point past the triangle ids
+        let sphere_root = self.bvh.len();
+        let spheres = &self.scene.spheres[..self.scene.sphere_count as usize];
+        let mut sphere_indices: Vec<usize> = (0..spheres.len()).collect();
+        BVHNode::bvh_build(spheres, &mut sphere_indices, &mut self.bvh, self.bvh_options);
+        for node in &mut self.bvh[sphere_root..] {
+            if node.primitive_count > 0 {
+                node.first_primitive += tri_indices.len() as u32;
+            }
+        }
+        self.scene.sphere_bvh_root = sphere_root as u32;
+
+        // then the curve tree
+        let curve_root = self.bvh.len();
+        let mut curve_indices: Vec<usize> = (0..self.curves.len()).collect();
+        BVHNode::bvh_build(&self.curves, &mut curve_indices, &mut self.bvh, self.bvh_options);
+        for node in &mut self.bvh[curve_root..] {
+            if node.primitive_count > 0 {
+                node.first_primitive += (tri_indices.len() + sphere_indices.len()) as u32;
+            }
+        }
+        self.scene.curve_bvh_root = curve_root as u32;
+
+        // and the disc tree last
+        let disc_root = self.bvh.len();
+        let mut disc_indices: Vec<usize> = (0..self.discs.len()).collect();
+        BVHNode::bvh_build(&self.discs, &mut disc_indices, &mut self.bvh, self.bvh_options);
+        for node in &mut self.bvh[disc_root..] {
+            if node.primitive_count > 0 {
+                node.first_primitive +=
+                    (tri_indices.len() + sphere_indices.len() + curve_indices.len()) as u32;
+            }
+        }
+        self.scene.disc_bvh_root = disc_root as u32;
+
+        self.bvh_primitives = tri_indices
+            .into_iter()
+            .chain(sphere_indices)
+            .chain(curve_indices)
+            .chain(disc_indices)
+            .map(|i| i as u32)
+            .collect();
     }
 }
@@ -0,0 +1,19 @@
+pub mod vec3;
+pub mod mat4;
+pub mod metals;
+pub mod presets;
+pub mod photometry;
+pub mod blue_noise;
+pub mod oidn;
+pub mod tracer_struct;
+pub mod graphics;
+pub mod file_load;
+pub mod file_export;
+pub mod primitives;
+pub mod scene_graph;
+pub mod animation;
+pub mod scene_file;
+pub mod checkpoint;
+pub mod multi_gpu;
+pub mod file_watch;
+pub mod assets;
@@ -1,22 +1,24 @@
-mod vec3;
-mod tracer_struct;
-mod graphics;
-mod file_load;
-
 use {
-    crate::{
-        tracer_struct::{Material, Sphere, BVHNode},
-        vec3::Vec3
-    }, anyhow::Result, file_load::load_mesh_from, graphics::Gfx, std::sync::Arc, winit::{
+    shrimpy::{
+        tracer_struct::{Material, Projection, Sphere, BVHNode},
+        vec3::Vec3,
+        mat4::Mat4,
+        graphics::{CameraAnimation, Gfx, RenderFormat, RenderMode},
+        scene_file::{CameraPresets, SceneFile},
+        multi_gpu,
+        primitives,
+    }, anyhow::{Context, Result, bail}, std::{collections::HashSet, sync::Arc, time::Instant}, winit::{
         application::ApplicationHandler,
         event::{
             DeviceEvent,
             DeviceId,
             ElementState,
+            KeyEvent,
             MouseScrollDelta,
             WindowEvent
         },
         event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+        keyboard::{Key, KeyCode, ModifiersState, NamedKey, PhysicalKey},
         window::{Window, WindowId}
     }
 };
@@ -25,9 +27,238 @@ struct Shrimpy {
     width: u32,
     height: u32,
     gfx_callback: fn(&mut Gfx),
+    // scene file given on the command line, replaces gfx_callback when set
+    scene_path: Option<String>,
     window: Option<Arc<Window>>,
     gfx: Option<Gfx>,
     button_state: [bool; 4],
+    modifiers: ModifiersState,
+    // the point dragging turns the camera around when in orbit mode, fly mode otherwise
+    orbit_pivot: Option<Vec3>,
+    // keyboard navigation, held movement keys and the velocity they drive
+    movement_keys: HashSet<KeyCode>,
+    velocity: Vec3,
+    last_frame: Instant,
+    // fraction of the distance to the focus point (or orbit pivot) one wheel notch dollies
+    dolly_sensitivity: f32,
+    // how much one wheel notch with ctrl held scales the field of view
+    zoom_sensitivity: f32,
+}
+
+const CAMERA_PRESETS_PATH: &str = "camera_presets.ron";
+const CHECKPOINT_PATH: &str = "checkpoint.shrimpy";
+// units per second, shift makes it FAST_MOVE_MULTIPLIER times faster
+const MOVE_SPEED: f32 = 2.0;
+const FAST_MOVE_MULTIPLIER: f32 = 5.0;
+// how quickly the velocity catches up with the held keys, per second
+const MOVE_RESPONSE: f32 = 12.0;
+
+// touchpads scroll in pixels, this many make up one wheel notch
+const PIXELS_PER_NOTCH: f32 = 50.0;
+
+// shrimpy [scene file] [--output image] [--format png|png16|exr] [--samples count]
+//         [--size widthxheight] [--gpus count]
+// with --output the scene is rendered without a window into the .png or .exr image, with
+// the max_samples of the scene or 256 samples unless --samples says otherwise, shared by
+// every gpu or the first --gpus of them
+// the format follows the extension, --format png16 saves 16 bit per channel instead
+struct Args {
+    scene_path: Option<String>,
+    output: Option<String>,
+    format: Option<RenderFormat>,
+    samples: Option<u32>,
+    size: Option<(u32, u32)>,
+    gpus: Option<usize>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        scene_path: None,
+        output: None,
+        format: None,
+        samples: None,
+        size: None,
+        gpus: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--output" => args.output = Some(iter.next().context("--output needs an image path")?),
+            "--format" => {
+                let format = iter.next().context("--format needs png, png16 or exr")?;
+                args.format = Some(match format.as_str() {
+                    "png" => RenderFormat::Png,
+                    "png16" => RenderFormat::Png16,
+                    "exr" => RenderFormat::Exr,
+                    _ => bail!("unknown format {}, --format needs png, png16 or exr", format),
+                });
+            },
+            "--samples" => {
+                let samples = iter.next().context("--samples needs a count")?;
+                args.samples = Some(samples.parse().context("--samples needs a count")?);
+            },
+            "--size" => {
+                let size = iter.next().context("--size needs widthxheight")?;
+                let (width, height) = size.split_once('x').context("--size needs widthxheight")?;
+                args.size = Some((width.parse()?, height.parse()?));
+            },
+            "--gpus" => {
+                let gpus = iter.next().context("--gpus needs a count")?;
+                args.gpus = Some(gpus.parse().context("--gpus needs a count")?);
+            },
+            _ if arg.starts_with("--") => bail!("unknown option {}", arg),
+            _ => args.scene_path = Some(arg),
+        }
+    }
+
+    Ok(args)
+}
+
+fn shader_code() -> String {
+    // include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders.wgsl")).to_string()
+    // for faster testing
+    std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders.wgsl")).unwrap()
+}
+
+fn render_headless(args: &Args, output: &str) -> Result<()> {
+    let path_format = RenderFormat::from_path(output).context("the output has to be a .png or .exr image")?;
+    let format = args.format.unwrap_or(path_format);
+    if format.extension() != path_format.extension() {
+        bail!("--format saves a .{} image, not {}", format.extension(), output);
+    }
+    let (width, height) = args.size.unwrap_or((800, 600));
+    let scene = match &args.scene_path {
+        Some(path) => Some((path, SceneFile::load(path).with_context(|| format!("failed to load scene {}", path))?)),
+        None => None,
+    };
+    let samples = args.samples
+        .or(scene.as_ref().and_then(|(_, scene_file)| scene_file.settings.max_samples))
+        .unwrap_or(256);
+
+    // the file is parsed once but every gpu sets up its own copy of the scene
+    let setup = |gfx: &mut Gfx| -> Result<()> {
+        match &scene {
+            Some((path, scene_file)) => gfx
+                .load_scene_file(scene_file.clone(), path)
+                .with_context(|| format!("failed to load scene {}", path))?,
+            None => scene_build(gfx),
+        }
+        // the size asked for wins over the one of the scene
+        if args.size.is_some() {
+            gfx.set_render_size(args.size);
+        }
+        Ok(())
+    };
+
+    let mut adapters = multi_gpu::adapters()?;
+    adapters.truncate(args.gpus.unwrap_or(usize::MAX).max(1));
+    let gfx = multi_gpu::render_samples(&adapters, width, height, &shader_code(), samples, setup)?;
+    pollster::block_on(gfx.save_render_to(output, format))?;
+
+    Ok(())
+}
+
+fn is_movement_key(code: KeyCode) -> bool {
+    matches!(code, KeyCode::KeyW | KeyCode::KeyA | KeyCode::KeyS | KeyCode::KeyD | KeyCode::KeyQ | KeyCode::KeyE)
+}
+
+impl Shrimpy {
+    // store the current camera in a named slot, or restore it from there
+    fn camera_preset(&mut self, slot: &str, save: bool) -> Result<()> {
+        let gfx = self.gfx.as_mut().unwrap();
+        let mut presets = CameraPresets::load(CAMERA_PRESETS_PATH)?;
+
+        if save {
+            presets.set(slot, *gfx.get_camera());
+            presets.save(CAMERA_PRESETS_PATH)?;
+            println!("saved camera preset {}", slot);
+        } else if let Some(camera) = presets.get(slot) {
+            *gfx.get_camera() = camera;
+            if self.orbit_pivot.is_some() {
+                self.orbit_pivot = Some(camera.focus_point());
+            }
+            gfx.render_reset();
+        } else {
+            println!("no camera preset {}", slot);
+        }
+
+        Ok(())
+    }
+
+    // the wheel dollies, or zooms with ctrl held, by notches
+    fn scroll(&mut self, notches: f32) {
+        let gfx = self.gfx.as_mut().unwrap();
+        let zoom = (notches * self.zoom_sensitivity).exp();
+
+        if self.modifiers.control_key() {
+            // a physical camera sets the fov itself, its focal length is zoomed instead
+            if let Some(mut physical_camera) = gfx.get_physical_camera() {
+                physical_camera.focal_length /= zoom;
+                gfx.set_physical_camera(Some(physical_camera));
+                return;
+            }
+            let camera = gfx.get_camera();
+            let max_fov = match camera.get_projection() {
+                Projection::Perspective => 179.0_f32,
+                _ => 360.0_f32,
+            };
+            camera.fov = (camera.fov * zoom).clamp(1.0_f32.to_radians(), max_fov.to_radians());
+            gfx.render_reproject();
+            return;
+        }
+
+        let camera = gfx.get_camera();
+        let step = notches * self.dolly_sensitivity;
+        match self.orbit_pivot {
+            Some(pivot) => {
+                let distance = (camera.position - pivot).length();
+                camera.set_orbit_distance(pivot, distance * (1.0 + step));
+            },
+            None => {
+                let distance = camera.focus_distance;
+                camera.move_foward(-step * distance);
+            },
+        }
+        gfx.render_reproject();
+    }
+
+    // moves the camera by the velocity of the held keys over the time since the last frame
+    fn update_movement(&mut self) {
+        let now = Instant::now();
+        // long stalls like loading a scene should not launch the camera
+        let dt = (now - self.last_frame).as_secs_f32().min(0.1);
+        self.last_frame = now;
+
+        let gfx = self.gfx.as_mut().unwrap();
+        let camera = gfx.get_camera();
+        let key_direction = |code: KeyCode, direction: Vec3| {
+            if self.movement_keys.contains(&code) { direction } else { Vec3::zero() }
+        };
+        let mut wish = key_direction(KeyCode::KeyW, camera.direction)
+            - key_direction(KeyCode::KeyS, camera.direction)
+            + key_direction(KeyCode::KeyD, camera.get_right_direction())
+            - key_direction(KeyCode::KeyA, camera.get_right_direction())
+            + key_direction(KeyCode::KeyE, Vec3::new(0.0, 1.0, 0.0))
+            - key_direction(KeyCode::KeyQ, Vec3::new(0.0, 1.0, 0.0));
+        if wish.length() > 0.0 {
+            let speed = if self.modifiers.shift_key() { MOVE_SPEED * FAST_MOVE_MULTIPLIER } else { MOVE_SPEED };
+            wish = wish.normalized() * speed;
+        }
+
+        self.velocity += (wish - self.velocity) * (1.0 - (-MOVE_RESPONSE * dt).exp());
+        if self.velocity.length() < 1e-3 {
+            self.velocity = Vec3::zero();
+            return;
+        }
+
+        let step = self.velocity * dt;
+        camera.position += step;
+        if let Some(pivot) = self.orbit_pivot.as_mut() {
+            *pivot += step;
+        }
+        gfx.render_reproject();
+    }
 }
 
 impl ApplicationHandler for Shrimpy {
@@ -37,20 +268,30 @@ impl ApplicationHandler for Shrimpy {
             .with_resizable(false)
             .with_title("Shrimpy".to_string());
 
-        // let shader_code = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders.wgsl"));
-        // for faster testing
-        let shader_code = &std::fs::read_to_string(
-            concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders.wgsl")
-        ).unwrap();
-
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
-        let gfx = Gfx::new(Arc::clone(&window), shader_code);
+        let gfx = match Gfx::new(Arc::clone(&window), &shader_code()) {
+            Ok(gfx) => gfx,
+            Err(e) => {
+                println!("{:#}", e);
+                event_loop.exit();
+                return;
+            },
+        };
         window.request_redraw();
 
         self.window = Some(window);
         self.gfx = Some(gfx);
 
-        (self.gfx_callback)(self.gfx.as_mut().unwrap());
+        let gfx = self.gfx.as_mut().unwrap();
+        match &self.scene_path {
+            Some(path) => {
+                if let Err(e) = gfx.load_scene(path) {
+                    println!("failed to load scene {}: {:#}", path, e);
+                    (self.gfx_callback)(gfx);
+                }
+            },
+            None => (self.gfx_callback)(gfx),
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
@@ -60,10 +301,150 @@ impl ApplicationHandler for Shrimpy {
                 event_loop.exit();
             },
             WindowEvent::RedrawRequested => {
-                self.gfx.as_mut().unwrap().render_frame();
+                self.update_movement();
+                let gfx = self.gfx.as_mut().unwrap();
+                gfx.reload_scene_if_changed();
+                gfx.render_frame();
 
                 self.window.as_ref().unwrap().request_redraw();
             },
+            WindowEvent::DroppedFile(path) => {
+                // import at the origin with its own default material
+                let gfx = self.gfx.as_mut().unwrap();
+                let filename = path.to_string_lossy().to_string();
+                let material_id = match gfx.scene_add_material(Material::default()) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        println!("failed to import {}: {:#}", filename, e);
+                        return;
+                    },
+                };
+                let name = filename.clone();
+                gfx.scene_add_mesh_async(&filename, material_id, Mat4::identity(), move |fraction| {
+                    println!("loading {}: {:.0}%", name, fraction * 100.0);
+                });
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(code), state, .. },
+                ..
+            } if is_movement_key(code) => {
+                // physical keys so shift and keyboard layouts do not change the mapping
+                match state {
+                    ElementState::Pressed => self.movement_keys.insert(code),
+                    ElementState::Released => self.movement_keys.remove(&code),
+                };
+            },
+            WindowEvent::Focused(false) => {
+                // releases are not seen while another window has focus
+                self.movement_keys.clear();
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { logical_key: Key::Character(key), state: ElementState::Pressed, .. },
+                ..
+            } if key.len() == 1 && key.chars().all(|c| c.is_ascii_digit()) => {
+                // ctrl + digit saves the camera to that slot, the digit alone restores it
+                if let Err(e) = self.camera_preset(&key, self.modifiers.control_key()) {
+                    println!("camera preset {} failed: {:#}", key, e);
+                }
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { logical_key: Key::Character(key), state: ElementState::Pressed, .. },
+                ..
+            } => {
+                // [ and ] turn the environment map, - and = change its brightness
+                // o switches between fly and orbit mode around the point in focus
+                // p plays the camera path of the scene, ctrl + p renders it to ./imgs
+                // f shows what is in focus
+                // n toggles the denoiser, ctrl + n open image denoise for saved renders
+                // k saves the accumulation to CHECKPOINT_PATH, ctrl + k resumes it
+                let gfx = self.gfx.as_mut().unwrap();
+                match key.as_str() {
+                    "f" => gfx.set_focus_overlay(!gfx.get_focus_overlay()),
+                    "n" if self.modifiers.control_key() => {
+                        gfx.set_oidn(!gfx.get_oidn());
+                        println!("open image denoise {}", if gfx.get_oidn() { "on" } else { "off" });
+                    },
+                    "n" => {
+                        gfx.set_denoise(!gfx.get_denoise());
+                        println!("denoiser {}", if gfx.get_denoise() { "on" } else { "off" });
+                    },
+                    "k" if self.modifiers.control_key() => {
+                        if let Err(e) = gfx.load_checkpoint(CHECKPOINT_PATH) {
+                            println!("failed to resume checkpoint: {:#}", e);
+                        }
+                    },
+                    "k" => {
+                        if let Err(e) = pollster::block_on(gfx.save_checkpoint(CHECKPOINT_PATH)) {
+                            println!("failed to save checkpoint: {:#}", e);
+                        }
+                    },
+                    "p" if gfx.is_camera_animation_running() => gfx.stop_camera_animation(),
+                    "p" => match gfx.get_camera_path().cloned() {
+                        Some(path) => {
+                            let render = self.modifiers.control_key();
+                            gfx.start_camera_animation(path, CameraAnimation {
+                                fps: 30.0,
+                                samples_per_frame: if render { 256 } else { 1 },
+                                format: render.then_some(RenderFormat::Png),
+                                motion_blur: render,
+                            });
+                        },
+                        None => println!("the scene has no camera path"),
+                    },
+                    "o" => {
+                        self.orbit_pivot = match self.orbit_pivot {
+                            Some(_) => None,
+                            None => Some(gfx.get_camera().focus_point()),
+                        };
+                        println!("{} mode", if self.orbit_pivot.is_some() { "orbit" } else { "fly" });
+                    },
+                    "[" => gfx.set_environment_rotation(gfx.get_environment_rotation() - 15.0_f32.to_radians()),
+                    "]" => gfx.set_environment_rotation(gfx.get_environment_rotation() + 15.0_f32.to_radians()),
+                    "-" => gfx.set_environment_intensity(gfx.get_environment_intensity() / 2.0_f32.sqrt()),
+                    "=" => gfx.set_environment_intensity(gfx.get_environment_intensity() * 2.0_f32.sqrt()),
+                    _ => (),
+                }
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { logical_key: Key::Named(key), state: ElementState::Pressed, .. },
+                ..
+            } => {
+                // tab cycles through the named cameras of the scene
+                if key == NamedKey::Tab {
+                    let gfx = self.gfx.as_mut().unwrap();
+                    match gfx.next_camera() {
+                        Some(name) => println!("camera {}", name),
+                        None => println!("the scene has no named cameras"),
+                    }
+                    if self.orbit_pivot.is_some() {
+                        self.orbit_pivot = Some(gfx.get_camera().focus_point());
+                    }
+                    return;
+                }
+
+                if key == NamedKey::F9 {
+                    let gfx = self.gfx.as_mut().unwrap();
+                    let mode = match gfx.get_render_mode() {
+                        RenderMode::PathTraced => RenderMode::Toon,
+                        RenderMode::Toon => RenderMode::PathTraced,
+                    };
+                    gfx.set_render_mode(mode);
+                    return;
+                }
+
+                let format = match key {
+                    NamedKey::F11 => RenderFormat::Png16,
+                    // dump the raw radiance for offline tonemapping
+                    NamedKey::F12 => RenderFormat::Exr,
+                    _ => return,
+                };
+                if let Err(e) = pollster::block_on(self.gfx.as_mut().unwrap().save_render(format)) {
+                    println!("failed to save render: {:#}", e);
+                }
+            },
             _ => (),
         }
     }
@@ -71,34 +452,42 @@ impl ApplicationHandler for Shrimpy {
     fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
         match event {
             DeviceEvent::MouseWheel { delta } => {
-                let delta = match delta {
-                    MouseScrollDelta::PixelDelta(delta) => 0.001 * delta.y as f32,
-                    MouseScrollDelta::LineDelta(_, y) => y * 0.001,
+                let notches = match delta {
+                    MouseScrollDelta::PixelDelta(delta) => delta.y as f32 / PIXELS_PER_NOTCH,
+                    MouseScrollDelta::LineDelta(_, y) => y,
                 };
-                let gfx = self.gfx.as_mut().unwrap();
-                let camera = gfx.get_camera();
-                camera.move_foward(-delta);
-                gfx.render_reset()
+                self.scroll(notches);
             },
             DeviceEvent::Button { button, state } => {
                 self.button_state[button as usize] = state == ElementState::Pressed;
-                if state == ElementState::Pressed && button == 2 {
-                    pollster::block_on(async {
-                        self.gfx.as_mut().unwrap().save_render().await;
-                    });
+                if state == ElementState::Pressed
+                    && button == 2
+                    && let Err(e) = pollster::block_on(self.gfx.as_mut().unwrap().save_render(RenderFormat::Png))
+                {
+                    println!("failed to save render: {:#}", e);
                 }
             },
             DeviceEvent::MouseMotion { delta: (dx, dy) } => {
                 let gfx = self.gfx.as_mut().unwrap();
                 let camera = gfx.get_camera();
                 if self.button_state[3] {
-                    camera.pan(-dx as f32 * 0.004);
-                    camera.tilt(dy as f32 * 0.004);
-                    gfx.render_reset()
+                    match self.orbit_pivot {
+                        Some(pivot) => camera.orbit(pivot, dx as f32 * 0.004, dy as f32 * 0.004),
+                        None => {
+                            camera.pan(-dx as f32 * 0.004);
+                            camera.tilt(dy as f32 * 0.004);
+                        },
+                    }
+                    gfx.render_reproject()
                 } else if self.button_state[1] {
+                    // the pivot moves along so orbiting continues around the new view
+                    let before = camera.position;
                     camera.move_up(dy as f32 * 0.004);
                     camera.move_right(-dx as f32 * 0.004);
-                    gfx.render_reset()
+                    if let Some(pivot) = self.orbit_pivot.as_mut() {
+                        *pivot += camera.position - before;
+                    }
+                    gfx.render_reproject()
                 }
             },
             _ => (),
@@ -106,96 +495,61 @@ impl ApplicationHandler for Shrimpy {
     }
 }
 
-fn print_bvh(bvh: &[BVHNode], current_node_id: usize, level: u32) {
+fn print_bvh(bvh: &[BVHNode], bvh_primitives: &[u32], current_node_id: usize, level: u32) {
     for _ in 0..level {
         print!("    ");
     }
     print!("node {} ", current_node_id);
 
     let current_node = &bvh[current_node_id];
-    if current_node.triangle_count != 0 {
+    if current_node.primitive_count != 0 {
         print!("-> ");
-        for i in 0..current_node.triangle_count {
-            print!("{} ", current_node.triangle_ids[i as usize]);
+        let first = current_node.first_primitive as usize;
+        for primitive_id in &bvh_primitives[first..first + current_node.primitive_count as usize] {
+            print!("{} ", primitive_id);
         }
-        print!("\n");
+        println!();
     } else {
-        print!("\n");
-        print_bvh(bvh, current_node.child1 as usize, level + 1);
-        print_bvh(bvh, current_node.child2 as usize, level + 1);
+        println!();
+        print_bvh(bvh, bvh_primitives, current_node.child1 as usize, level + 1);
+        print_bvh(bvh, bvh_primitives, current_node.child2 as usize, level + 1);
     }
 }
 
 fn scene_build(gfx: &mut Gfx) {
     // materials
-    let mut ground_mat = Material::default();
-    ground_mat.color = Vec3::new(217.0, 177.0, 104.0) / 255.0;
-    ground_mat.roughness_or_ior = 1.0;
-    let ground_mat_id = gfx.scene_add_material(ground_mat);
+    let ground_mat = Material::diffuse(Vec3::new(217.0, 177.0, 104.0) / 255.0);
+    let ground_mat_id = gfx.scene_add_material(ground_mat).unwrap();
 
-    let mut transparent_mat = Material::default();
-    transparent_mat.roughness_or_ior = -1.33;
-    let trans_mat_id = gfx.scene_add_material(transparent_mat);
+    let trans_mat_id = gfx.scene_add_material(Material::water()).unwrap();
 
     // scene
-    let mut ground = load_mesh_from(
-        concat!(env!("CARGO_MANIFEST_DIR"), "/assets/plane.obj"),
-        ground_mat_id,
-    );
-    for tri in ground.iter_mut() {
-        tri.vertex_0 *= 5.0;
-        tri.vertex_1 *= 5.0;
-        tri.vertex_2 *= 5.0;
-    }
-    gfx.scene_add_triangles(&ground);
-
-    let mut sphere1 = Sphere::default();
-    sphere1.center = Vec3::new(2.5, 1.0, 0.0);
-    sphere1.material_id = trans_mat_id;
-    sphere1.radius = 0.7;
-    gfx.scene_add_sphere(sphere1);
-
-    let mut sphere2 = Sphere::default();
-    sphere2.center = Vec3::new(1.5, 1.0, -2.0);
-    sphere2.material_id = ground_mat_id;
-    gfx.scene_add_sphere(sphere2);
-
-    let mut dodec = load_mesh_from(
-        concat!(env!("CARGO_MANIFEST_DIR"), "/assets/dodecahedron.obj"),
-        trans_mat_id,
-    );
-    for tri in dodec.iter_mut() {
-        tri.vertex_0 += Vec3::new(0.0, 1.35, 0.0);
-        tri.vertex_1 += Vec3::new(0.0, 1.35, 0.0);
-        tri.vertex_2 += Vec3::new(0.0, 1.35, 0.0);
-    }
-    gfx.scene_add_triangles(&dodec);
-    
-    for tri in dodec.iter_mut() {
-        tri.vertex_0 += Vec3::new(0.0, 3.35, 0.0);
-        tri.vertex_1 += Vec3::new(0.0, 3.35, 0.0);
-        tri.vertex_2 += Vec3::new(0.0, 3.35, 0.0);
-    }
-    gfx.scene_add_triangles(&dodec);
-    
-    for tri in dodec.iter_mut() {
-        tri.vertex_0 += Vec3::new(4.0, 3.35, 0.0);
-        tri.vertex_1 += Vec3::new(4.0, 3.35, 0.0);
-        tri.vertex_2 += Vec3::new(4.0, 3.35, 0.0);
-    }
-    gfx.scene_add_triangles(&dodec);
+    let ground = primitives::plane(Vec3::zero(), 10.0, 1, ground_mat_id);
+    gfx.scene_add_triangles(&ground).unwrap();
 
+    gfx.scene_add_sphere(Sphere::new(Vec3::new(2.5, 1.0, 0.0), 0.7, trans_mat_id));
+    gfx.scene_add_sphere(Sphere::new(Vec3::new(1.5, 1.0, -2.0), 1.0, ground_mat_id));
+
+    let cube = primitives::cube(Vec3::new(0.0, 1.35, 0.0), 1.2, 1, trans_mat_id);
+    gfx.scene_add_triangles(&cube).unwrap();
+
+    let torus = primitives::torus(Vec3::new(0.0, 4.7, 0.0), 0.7, 0.25, 12, 6, trans_mat_id);
+    gfx.scene_add_triangles(&torus).unwrap();
+
+    let sphere = primitives::uv_sphere(Vec3::new(4.0, 8.05, 0.0), 0.8, 8, 5, trans_mat_id);
+    gfx.scene_add_triangles(&sphere).unwrap();
 
     gfx.scene_update();
 
     println!("bvh tree layout");
-    print_bvh(gfx.scene.bvh.as_ref(), 0, 0);
+    let (bvh, bvh_primitives) = gfx.get_bvh();
+    print_bvh(bvh, bvh_primitives, 0, 0);
 
     // camera
     let camera = gfx.get_camera();
     camera.max_ray_bounces = 50;
     camera.width = 1.0;
-    camera.fov = 90.0 * 3.141592654 / 180.0;
+    camera.fov = 90.0_f32.to_radians();
     camera.apeture = 0.0;
     camera.position = Vec3::new(0.0, 1.5, 2.0);
 
@@ -206,6 +560,11 @@ fn scene_build(gfx: &mut Gfx) {
 }
 
 fn main() -> Result<()> {
+    let args = parse_args()?;
+    if let Some(output) = &args.output {
+        return render_headless(&args, output);
+    }
+
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
@@ -213,9 +572,17 @@ fn main() -> Result<()> {
         width: 800,
         height: 600,
         gfx_callback: scene_build,
+        scene_path: args.scene_path,
         window: None,
         gfx: None,
         button_state: [false; 4],
+        modifiers: ModifiersState::empty(),
+        orbit_pivot: None,
+        movement_keys: HashSet::new(),
+        velocity: Vec3::zero(),
+        last_frame: Instant::now(),
+        dolly_sensitivity: 0.1,
+        zoom_sensitivity: 0.05,
     };
 
     event_loop.run_app(&mut app)?;
@@ -0,0 +1,152 @@
+use {
+    crate::vec3::Vec3,
+    std::ops,
+};
+
+// row major affine transform, applied to column vectors (p' = M * p)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(t: Vec3) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.0[0][3] = t.x();
+        m.0[1][3] = t.y();
+        m.0[2][3] = t.z();
+        m
+    }
+
+    pub fn scale(s: Vec3) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.0[0][0] = s.x();
+        m.0[1][1] = s.y();
+        m.0[2][2] = s.z();
+        m
+    }
+
+    // rotation of `angle` radians around `axis` (Rodrigues' formula)
+    pub fn rotation(axis: Vec3, angle: f32) -> Mat4 {
+        let a = axis.normalized();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+
+        Mat4([
+            [t * x * x + c,     t * x * y - s * z, t * x * z + s * y, 0.0],
+            [t * x * y + s * z, t * y * y + c,     t * y * z - s * x, 0.0],
+            [t * x * z - s * y, t * y * z + s * x, t * z * z + c,     0.0],
+            [0.0,               0.0,               0.0,               1.0],
+        ])
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + self.get_translation()
+    }
+
+    // ignores translation, use for directions
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // inverse transpose of the 3x3 part so normals stay perpendicular under non uniform
+    // scales, that is the cofactor matrix over the determinant
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.0;
+        let cofactor = |i: usize, j: usize| {
+            let (i1, i2, j1, j2) = ((i + 1) % 3, (i + 2) % 3, (j + 1) % 3, (j + 2) % 3);
+            m[i1][j1] * m[i2][j2] - m[i1][j2] * m[i2][j1]
+        };
+        let det: f32 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+        let inv_det = if det != 0.0 { 1.0 / det } else { 1.0 };
+        Vec3::new(
+            cofactor(0, 0) * n.x() + cofactor(0, 1) * n.y() + cofactor(0, 2) * n.z(),
+            cofactor(1, 0) * n.x() + cofactor(1, 1) * n.y() + cofactor(1, 2) * n.z(),
+            cofactor(2, 0) * n.x() + cofactor(2, 1) * n.y() + cofactor(2, 2) * n.z(),
+        ) * inv_det
+    }
+
+    pub fn get_translation(&self) -> Vec3 {
+        Vec3::new(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    // largest stretch applied to any axis, used to scale radii
+    pub fn max_scale(&self) -> f32 {
+        let x = self.transform_vector(Vec3::new(1.0, 0.0, 0.0)).length();
+        let y = self.transform_vector(Vec3::new(0.0, 1.0, 0.0)).length();
+        let z = self.transform_vector(Vec3::new(0.0, 0.0, 1.0)).length();
+        x.max(y).max(z)
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Mat4(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn transform_point_applies_translation_last() {
+        let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::scale(Vec3::new(2.0, 2.0, 2.0));
+        assert_close(m.transform_point(Vec3::new(1.0, 1.0, 1.0)), Vec3::new(3.0, 4.0, 5.0));
+        assert_close(m.transform_vector(Vec3::new(1.0, 1.0, 1.0)), Vec3::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn rotation_is_counter_clockwise() {
+        let m = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), std::f32::consts::FRAC_PI_2);
+        assert_close(m.transform_vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let m = Mat4::scale(Vec3::new(4.0, 1.0, 1.0)) * Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 0.7);
+        // a tangent and the normal of a plane through the origin
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+        let dot = m.transform_vector(tangent).dot(&m.transform_normal(normal));
+        assert!(dot.abs() < 1e-4, "dot = {}", dot);
+    }
+
+    #[test]
+    fn normals_keep_their_side_under_a_mirror() {
+        let m = Mat4::scale(Vec3::new(-1.0, 1.0, 1.0));
+        assert_close(m.transform_normal(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(m.transform_normal(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(-1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::vec3::Vec3;
+
+// complex index of refraction eta + ik of a conductor, fitted at the red, green and
+// blue primaries (roughly 630, 532 and 465 nm) from measured spectral data
+#[derive(Debug, Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+pub const GOLD: ComplexIor = ComplexIor {
+    eta: Vec3::new(0.143, 0.374, 1.442),
+    k: Vec3::new(3.983, 2.385, 1.603),
+};
+
+pub const SILVER: ComplexIor = ComplexIor {
+    eta: Vec3::new(0.155, 0.117, 0.138),
+    k: Vec3::new(4.828, 3.122, 2.147),
+};
+
+pub const COPPER: ComplexIor = ComplexIor {
+    eta: Vec3::new(0.200, 0.924, 1.102),
+    k: Vec3::new(3.912, 2.452, 2.142),
+};
+
+pub const ALUMINUM: ComplexIor = ComplexIor {
+    eta: Vec3::new(1.657, 0.880, 0.521),
+    k: Vec3::new(9.224, 6.270, 4.837),
+};
+
+pub const IRON: ComplexIor = ComplexIor {
+    eta: Vec3::new(2.911, 2.950, 2.585),
+    k: Vec3::new(3.089, 2.932, 2.767),
+};
+
+pub const CHROMIUM: ComplexIor = ComplexIor {
+    eta: Vec3::new(3.105, 3.183, 2.340),
+    k: Vec3::new(3.330, 3.330, 3.160),
+};
+
+// look up a metal by its name, for scene and material files
+pub fn find(name: &str) -> Option<ComplexIor> {
+    match name.to_lowercase().as_str() {
+        "gold" => Some(GOLD),
+        "silver" => Some(SILVER),
+        "copper" => Some(COPPER),
+        "aluminum" | "aluminium" => Some(ALUMINUM),
+        "iron" => Some(IRON),
+        "chromium" | "chrome" => Some(CHROMIUM),
+        _ => None,
+    }
+}
@@ -0,0 +1,97 @@
+use {
+    crate::graphics::Gfx,
+    anyhow::{Context, Result, bail},
+    std::sync::atomic::{AtomicU32, Ordering},
+};
+
+// one render shared by every gpu of the machine, each gets its own Gfx with the whole
+// scene and takes the next sample whenever it is done with one so faster cards trace
+// more of them, the sums are added up on the first gpu at the end
+
+// every gpu on the backend of the default one so no card is counted again through
+// another api, the default one comes first and software renderers are left out
+pub fn adapters() -> Result<Vec<wgpu::Adapter>> {
+    let instance = wgpu::Instance::default();
+    let default = Gfx::request_adapter(&instance, None)?;
+    let info = default.get_info();
+
+    let mut adapters: Vec<wgpu::Adapter> = instance
+        .enumerate_adapters(info.backend.into())
+        .into_iter()
+        .filter(|adapter| {
+            let device_type = adapter.get_info().device_type;
+            device_type != wgpu::DeviceType::Cpu || info.device_type == wgpu::DeviceType::Cpu
+        })
+        .collect();
+    match adapters.iter().position(|adapter| adapter.get_info() == info) {
+        Some(i) => adapters.swap(0, i),
+        None => adapters.insert(0, default),
+    }
+    Ok(adapters)
+}
+
+// traces samples of the width by height frame setup builds, on every adapter at once,
+// and gives back the Gfx of the first one holding all of them to save the render from
+// the first gpu has to set up, any other that fails to or ends up with a different
+// render size is left out before it takes a sample
+pub fn render_samples<F>(
+    adapters: &[wgpu::Adapter],
+    width: u32,
+    height: u32,
+    shader_code: &str,
+    samples: u32,
+    setup: F,
+) -> Result<Gfx>
+where
+    F: Fn(&mut Gfx) -> Result<()> + Sync,
+{
+    let (first, others) = adapters.split_first().context("no adapters to render on")?;
+    let next_sample = AtomicU32::new(0);
+    let claim = || {
+        let sample = next_sample.fetch_add(1, Ordering::Relaxed);
+        (sample < samples).then_some(sample)
+    };
+
+    let mut gfx = Gfx::new_headless_on(first, width, height, shader_code)?;
+    setup(&mut gfx)?;
+    let render_size = gfx.get_render_size();
+
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = others
+            .iter()
+            .map(|adapter| {
+                let (setup, claim) = (&setup, &claim);
+                scope.spawn(move || -> Result<(u32, Vec<u32>)> {
+                    let mut gfx = Gfx::new_headless_on(adapter, width, height, shader_code)?;
+                    setup(&mut gfx)?;
+                    // the size can be smaller where the gpu has a lower texture size limit
+                    let (w, h) = gfx.get_render_size();
+                    if (w, h) != render_size {
+                        bail!("it can only render at {}x{}", w, h);
+                    }
+                    gfx.render_claimed_samples(claim);
+                    let radiance = pollster::block_on(gfx.read_accumulation());
+                    Ok((gfx.get_sample_count(), radiance))
+                })
+            })
+            .collect();
+
+        gfx.render_claimed_samples(claim);
+        println!("{} traced {} samples", first.get_info().name, gfx.get_sample_count());
+
+        for (adapter, thread) in others.iter().zip(threads) {
+            let name = adapter.get_info().name;
+            match thread.join() {
+                Ok(Ok((sample_count, radiance))) => {
+                    println!("{} traced {} samples", name, sample_count);
+                    gfx.add_accumulation(&radiance, sample_count);
+                },
+                Ok(Err(e)) => println!("{} left out: {:#}", name, e),
+                // whatever it claimed is missing from the render
+                Err(_) => println!("{} left out: its render thread panicked", name),
+            }
+        }
+    });
+
+    Ok(gfx)
+}
@@ -0,0 +1,85 @@
+use {
+    anyhow::{Context, Result, bail},
+    libloading::Library,
+    std::ffi::{CStr, c_char, c_void},
+};
+
+// intel open image denoise, loaded when a render is saved instead of linked so the
+// tracer runs without it, the library (2.x) only has to be on the library path then
+
+type Device = *mut c_void;
+type Filter = *mut c_void;
+
+const DEVICE_TYPE_DEFAULT: i32 = 0;
+const FORMAT_FLOAT3: i32 = 3;
+const ERROR_NONE: i32 = 0;
+
+type NewDevice = unsafe extern "C" fn(i32) -> Device;
+type CommitDevice = unsafe extern "C" fn(Device);
+type GetDeviceError = unsafe extern "C" fn(Device, *mut *const c_char) -> i32;
+type ReleaseDevice = unsafe extern "C" fn(Device);
+type NewFilter = unsafe extern "C" fn(Device, *const c_char) -> Filter;
+// name, data, format, width, height, byte offset, pixel and row stride in bytes
+type SetSharedFilterImage =
+    unsafe extern "C" fn(Filter, *const c_char, *mut c_void, i32, usize, usize, usize, usize, usize);
+type SetFilterBool = unsafe extern "C" fn(Filter, *const c_char, bool);
+type CommitFilter = unsafe extern "C" fn(Filter);
+type ExecuteFilter = unsafe extern "C" fn(Filter);
+type ReleaseFilter = unsafe extern "C" fn(Filter);
+
+// denoises the rgb of linear rgba pixels in place with the ray tracing filter, albedo
+// and normal are rgb of the first hit through every pixel and keep edges and textures
+pub fn denoise(color: &mut [f32], albedo: &[f32], normal: &[f32], width: usize, height: usize) -> Result<()> {
+    let pixels = width * height;
+    assert_eq!(color.len(), pixels * 4);
+    assert_eq!(albedo.len(), pixels * 3);
+    assert_eq!(normal.len(), pixels * 3);
+
+    unsafe {
+        let library = Library::new(libloading::library_filename("OpenImageDenoise"))
+            .context("could not load open image denoise")?;
+        let new_device = library.get::<NewDevice>(b"oidnNewDevice\0")?;
+        let commit_device = library.get::<CommitDevice>(b"oidnCommitDevice\0")?;
+        let get_device_error = library.get::<GetDeviceError>(b"oidnGetDeviceError\0")?;
+        let release_device = library.get::<ReleaseDevice>(b"oidnReleaseDevice\0")?;
+        let new_filter = library.get::<NewFilter>(b"oidnNewFilter\0")?;
+        let set_image = library.get::<SetSharedFilterImage>(b"oidnSetSharedFilterImage\0")?;
+        let set_bool = library.get::<SetFilterBool>(b"oidnSetFilterBool\0")?;
+        let commit_filter = library.get::<CommitFilter>(b"oidnCommitFilter\0")?;
+        let execute_filter = library.get::<ExecuteFilter>(b"oidnExecuteFilter\0")?;
+        let release_filter = library.get::<ReleaseFilter>(b"oidnReleaseFilter\0")?;
+
+        let device = new_device(DEVICE_TYPE_DEFAULT);
+        if device.is_null() {
+            bail!("open image denoise has no device");
+        }
+        commit_device(device);
+
+        // the color skips over alpha and is written over with the result
+        let filter = new_filter(device, c"RT".as_ptr());
+        let color = color.as_mut_ptr() as *mut c_void;
+        set_image(filter, c"color".as_ptr(), color, FORMAT_FLOAT3, width, height, 0, 16, 0);
+        set_image(filter, c"output".as_ptr(), color, FORMAT_FLOAT3, width, height, 0, 16, 0);
+        set_image(filter, c"albedo".as_ptr(), albedo.as_ptr() as *mut c_void, FORMAT_FLOAT3, width, height, 0, 0, 0);
+        set_image(filter, c"normal".as_ptr(), normal.as_ptr() as *mut c_void, FORMAT_FLOAT3, width, height, 0, 0, 0);
+        set_bool(filter, c"hdr".as_ptr(), true);
+        commit_filter(filter);
+        execute_filter(filter);
+
+        let mut message: *const c_char = std::ptr::null();
+        let error = get_device_error(device, &mut message);
+        let message = if message.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        };
+        release_filter(filter);
+        release_device(device);
+
+        if error != ERROR_NONE {
+            bail!("open image denoise failed: {}", message);
+        }
+    }
+
+    Ok(())
+}
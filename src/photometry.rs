@@ -0,0 +1,108 @@
+use crate::{
+    tracer_struct::{AreaLight, Camera, Material, PointLight, SunLight},
+    vec3::Vec3,
+};
+
+// real world light and camera units, converted to the radiometric values the tracer
+// works with before anything is uploaded
+// watts here are watts of light at the peak of the eye's sensitivity, so a lumen is
+// always 1/683 of one whatever the color
+pub const LUMENS_PER_WATT: f32 = 683.0;
+
+pub fn lumens_to_watts(lumens: f32) -> f32 {
+    lumens / LUMENS_PER_WATT
+}
+
+// cd/m^2 to radiance
+pub fn nits_to_radiance(nits: f32) -> f32 {
+    nits / LUMENS_PER_WATT
+}
+
+// lm/m^2 to irradiance
+pub fn lux_to_irradiance(lux: f32) -> f32 {
+    lux / LUMENS_PER_WATT
+}
+
+// cd to radiant intensity
+pub fn candela_to_intensity(candela: f32) -> f32 {
+    candela / LUMENS_PER_WATT
+}
+
+// scales a color to a luminance of 1 so it only tints the light, black stays black
+fn tint(color: Vec3) -> Vec3 {
+    let luminance = 0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z();
+    if luminance > 0.0 {
+        color / luminance
+    } else {
+        Vec3::zero()
+    }
+}
+
+// exposure value at ISO 100 of an f-number, a shutter time in seconds and an ISO
+pub fn ev100(f_number: f32, shutter_time: f32, iso: f32) -> f32 {
+    (f_number * f_number / shutter_time * 100.0 / iso).log2()
+}
+
+// what radiance is multiplied by on screen for an exposure value, from the saturation
+// based sensitivity with the usual 1.2 headroom of a camera
+pub fn exposure_from_ev100(ev100: f32) -> f32 {
+    LUMENS_PER_WATT / (1.2 * 2.0f32.powf(ev100))
+}
+
+impl Camera {
+    // ev100 of 15 is a sunny day, 8 a bright office and 5 a living room at night
+    pub fn set_exposure_value(&mut self, ev100: f32) {
+        self.exposure = exposure_from_ev100(ev100);
+    }
+
+    pub fn set_physical_exposure(&mut self, f_number: f32, shutter_time: f32, iso: f32) {
+        self.set_exposure_value(ev100(f_number, shutter_time, iso));
+    }
+}
+
+impl PointLight {
+    // a bulb sending lumens out evenly in every direction, a 60 W incandescent is about 800
+    pub fn from_lumens(position: Vec3, color: Vec3, lumens: f32) -> Self {
+        let candela = lumens / (4.0 * std::f32::consts::PI);
+        Self::new(position, tint(color) * candela_to_intensity(candela))
+    }
+
+    // lumens leaving through the cone, taken halfway between the inner and outer angle
+    pub fn spot_from_lumens(
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        lumens: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        let half_angle = 0.5 * (inner_angle.min(outer_angle) + outer_angle);
+        let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - half_angle.cos());
+        let candela = lumens / solid_angle.max(1e-6);
+        let intensity = tint(color) * candela_to_intensity(candela);
+        Self::spot(position, direction, intensity, inner_angle, outer_angle)
+    }
+}
+
+impl AreaLight {
+    // lumens leaving the lit side of the rectangle, spread like a diffuse surface
+    pub fn from_lumens(corner: Vec3, edge_u: Vec3, edge_v: Vec3, color: Vec3, lumens: f32) -> Self {
+        let area = edge_u.cross(&edge_v).length();
+        let nits = lumens / (std::f32::consts::PI * area.max(1e-6));
+        Self::new(corner, edge_u, edge_v, tint(color) * nits_to_radiance(nits))
+    }
+}
+
+impl SunLight {
+    // direct sunlight is around 100000 lux at noon
+    pub fn from_lux(direction: Vec3, color: Vec3, lux: f32) -> Self {
+        Self::new(direction, tint(color) * lux_to_irradiance(lux))
+    }
+}
+
+impl Material {
+    // a glowing surface of the given luminance, a computer screen is a few hundred nits
+    pub fn emissive_nits(color: Vec3, nits: f32) -> Self {
+        Self::emissive(tint(color), nits_to_radiance(nits))
+    }
+}
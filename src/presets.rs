@@ -0,0 +1,84 @@
+use crate::{
+    metals,
+    tracer_struct::Material,
+    vec3::Vec3,
+};
+
+// ready made materials for common surfaces, built on the plain constructors in tracer_struct
+impl Material {
+    // clear glass, 1.5 for window glass
+    pub fn glass(ior: f32) -> Self {
+        Self::dielectric(Vec3::all(1.0), ior)
+    }
+
+    pub fn water() -> Self {
+        Self::glass(1.33)
+    }
+
+    // clear glass that splits light into rainbows
+    pub fn diamond() -> Self {
+        Self::dispersive_dielectric(Vec3::all(1.0), 2.417, 55.3)
+    }
+
+    // glass with a rough surface that blurs what is behind it
+    pub fn frosted_glass(ior: f32, roughness: f32) -> Self {
+        let mut material = Self::principled(Vec3::all(1.0), 0.0, roughness);
+        material.transmission = 1.0;
+        material.ior = ior;
+        material
+    }
+
+    // thickness tinted glass, color is what is left after one unit of glass
+    pub fn tinted_glass(color: Vec3, ior: f32) -> Self {
+        Self::absorbing_dielectric(color, 1.0, ior)
+    }
+
+    pub fn mirror() -> Self {
+        Self::metal(Vec3::all(1.0), 0.0)
+    }
+
+    pub fn chrome() -> Self {
+        Self::conductor(metals::CHROMIUM, 0.05)
+    }
+
+    pub fn brushed_metal(color: Vec3) -> Self {
+        let mut material = Self::pbr(color, 1.0, 0.35);
+        material.anisotropy = 0.8;
+        material
+    }
+
+    // glossy dielectric with a diffuse body
+    pub fn plastic(color: Vec3) -> Self {
+        Self::pbr(color, 0.0, 0.2)
+    }
+
+    pub fn rubber(color: Vec3) -> Self {
+        Self::pbr(color, 0.0, 0.9)
+    }
+
+    // metallic flakes under a glossy clearcoat
+    pub fn car_paint(color: Vec3) -> Self {
+        let mut material = Self::principled(color, 0.6, 0.4);
+        material.clearcoat = 1.0;
+        material
+    }
+
+    pub fn velvet(color: Vec3) -> Self {
+        let mut material = Self::principled(color, 0.0, 1.0);
+        material.sheen = 1.0;
+        material
+    }
+
+    pub fn skin(color: Vec3) -> Self {
+        Self::subsurface(color, Vec3::new(0.37, 0.14, 0.08))
+    }
+
+    // a lambertian emitter radiating `watts` from every square unit of its surface
+    pub fn light(color: Vec3, watts: f32) -> Self {
+        Self::emissive(color, watts / std::f32::consts::PI)
+    }
+
+    pub fn smoke(density: f32) -> Self {
+        Self::volume(Vec3::all(0.8), density)
+    }
+}
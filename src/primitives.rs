@@ -0,0 +1,252 @@
+use {
+    crate::tracer_struct::{Curve, Triangle},
+    crate::vec3::Vec3,
+    std::f32::consts::PI,
+};
+
+// all generators emit triangles wound counter-clockwise when viewed from outside
+// so that cross(v1 - v0, v2 - v0) points away from the surface
+
+// split quad a-b-c-d into two triangles, dropping degenerate ones (e.g. at sphere poles)
+fn push_quad(tris: &mut Vec<Triangle>, quad: [Vec3; 4], uvs: [[f32; 2]; 4], material_id: u32) {
+    let [a, b, c, d] = quad;
+    let [uv_a, uv_b, uv_c, uv_d] = uvs;
+    for (vertices, uvs) in [([a, b, c], [uv_a, uv_b, uv_c]), ([a, c, d], [uv_a, uv_c, uv_d])] {
+        let normal = (vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0]));
+        if normal.length_squared() > 1e-12 {
+            let mut tri = Triangle::new(vertices, material_id);
+            tri.set_uvs(uvs);
+            tris.push(tri);
+        }
+    }
+}
+
+// uvs of cell (i, j) in an n x m grid spanning [0, 1]
+fn grid_uvs(i: u32, j: u32, n: u32, m: u32) -> [[f32; 2]; 4] {
+    let uv = |i: u32, j: u32| [i as f32 / n as f32, j as f32 / m as f32];
+    [uv(i, j), uv(i + 1, j), uv(i + 1, j + 1), uv(i, j + 1)]
+}
+
+// a subdivided parallelogram spanned by u and v, facing cross(u, v)
+fn push_grid(
+    tris: &mut Vec<Triangle>,
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    subdivisions: u32,
+    material_id: u32,
+) {
+    let n = subdivisions.max(1);
+    let du = u / n as f32;
+    let dv = v / n as f32;
+
+    for i in 0..n {
+        for j in 0..n {
+            let a = origin + du * i as f32 + dv * j as f32;
+            push_quad(tris, [a, a + du, a + du + dv, a + dv], grid_uvs(i, j, n, n), material_id);
+        }
+    }
+}
+
+// square grid on the XZ plane facing +Y
+pub fn plane(center: Vec3, size: f32, subdivisions: u32, material_id: u32) -> Vec<Triangle> {
+    let mut tris = vec![];
+    let half = size * 0.5;
+
+    push_grid(
+        &mut tris,
+        center + Vec3::new(-half, 0.0, -half),
+        Vec3::new(0.0, 0.0, size),
+        Vec3::new(size, 0.0, 0.0),
+        subdivisions,
+        material_id,
+    );
+
+    tris
+}
+
+// axis aligned cube, each face split into subdivisions x subdivisions quads
+pub fn cube(center: Vec3, size: f32, subdivisions: u32, material_id: u32) -> Vec<Triangle> {
+    let mut tris = vec![];
+    let h = size * 0.5;
+    let x = Vec3::new(size, 0.0, 0.0);
+    let y = Vec3::new(0.0, size, 0.0);
+    let z = Vec3::new(0.0, 0.0, size);
+
+    // (corner offset, u, v) with cross(u, v) being the outward normal
+    let faces = [
+        (Vec3::new( h, -h, -h), y, z), // +x
+        (Vec3::new(-h, -h, -h), z, y), // -x
+        (Vec3::new(-h,  h, -h), z, x), // +y
+        (Vec3::new(-h, -h, -h), x, z), // -y
+        (Vec3::new(-h, -h,  h), x, y), // +z
+        (Vec3::new(-h, -h, -h), y, x), // -z
+    ];
+
+    for (offset, u, v) in faces {
+        push_grid(&mut tris, center + offset, u, v, subdivisions, material_id);
+    }
+
+    tris
+}
+
+// latitude/longitude sphere, segments around the Y axis and rings from pole to pole
+pub fn uv_sphere(
+    center: Vec3,
+    radius: f32,
+    segments: u32,
+    rings: u32,
+    material_id: u32,
+) -> Vec<Triangle> {
+    let mut tris = vec![];
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+
+    let point = |ring: u32, segment: u32| {
+        let theta = PI * ring as f32 / rings as f32;
+        // wrap the seam so both sides share bit-identical vertices
+        let phi = 2.0 * PI * (segment % segments) as f32 / segments as f32;
+        center + Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        ) * radius
+    };
+
+    for r in 0..rings {
+        for s in 0..segments {
+            push_quad(
+                &mut tris,
+                [point(r, s), point(r, s + 1), point(r + 1, s + 1), point(r + 1, s)],
+                grid_uvs(s, r, segments, rings),
+                material_id,
+            );
+        }
+    }
+
+    tris
+}
+
+// torus lying on the XZ plane around the Y axis
+pub fn torus(
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+    material_id: u32,
+) -> Vec<Triangle> {
+    let mut tris = vec![];
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+
+    let point = |i: u32, j: u32| {
+        let u = 2.0 * PI * (i % major_segments) as f32 / major_segments as f32;
+        let v = 2.0 * PI * (j % minor_segments) as f32 / minor_segments as f32;
+        let ring_radius = major_radius + minor_radius * v.cos();
+        center + Vec3::new(
+            ring_radius * u.cos(),
+            minor_radius * v.sin(),
+            ring_radius * u.sin(),
+        )
+    };
+
+    for i in 0..major_segments {
+        for j in 0..minor_segments {
+            push_quad(
+                &mut tris,
+                [point(i, j), point(i, j + 1), point(i + 1, j + 1), point(i + 1, j)],
+                grid_uvs(j, i, minor_segments, major_segments).map(|[v, u]| [u, v]),
+                material_id,
+            );
+        }
+    }
+
+    tris
+}
+
+// the 32-bit xorshift also used by the shader, seeded through a jenkins style mix
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        let mut x = seed.wrapping_add(0x9e3779b9);
+        x = x.wrapping_add(x << 10);
+        x ^= x >> 6;
+        x = x.wrapping_add(x << 3);
+        x ^= x >> 11;
+        x = x.wrapping_add(x << 15);
+        Self(x.max(1))
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn next_vec3(&mut self) -> Vec3 {
+        Vec3::new(self.next_f32(), self.next_f32(), self.next_f32()) * 2.0 - Vec3::all(1.0)
+    }
+}
+
+// grow hair strands out of a mesh surface, distributed uniformly by area
+// each strand leaves along the face normal and sags by `droop` (fraction of its length)
+pub fn scatter_hair(
+    mesh: &[Triangle],
+    strand_count: u32,
+    length: f32,
+    radius: f32,
+    droop: f32,
+    material_id: u32,
+    seed: u32,
+) -> Vec<Curve> {
+    let mut strands = vec![];
+
+    let mut cumulative_area = Vec::with_capacity(mesh.len());
+    let mut total_area = 0.0;
+    for tri in mesh.iter() {
+        total_area += tri.area();
+        cumulative_area.push(total_area);
+    }
+    if total_area <= 0.0 {
+        return strands;
+    }
+
+    let mut rng = Rng::new(seed);
+    let down = Vec3::new(0.0, -1.0, 0.0);
+
+    for _ in 0..strand_count {
+        let target = rng.next_f32() * total_area;
+        let tri_id = cumulative_area
+            .partition_point(|&area| area < target)
+            .min(mesh.len() - 1);
+        let tri = mesh[tri_id];
+
+        // uniform point on the triangle
+        let mut u = rng.next_f32();
+        let mut v = rng.next_f32();
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        let root = tri.vertex_0
+            + (tri.vertex_1 - tri.vertex_0) * u
+            + (tri.vertex_2 - tri.vertex_0) * v;
+
+        let normal = tri.normal();
+        let jitter = rng.next_vec3() * 0.2 * length;
+        let sag = down * droop * length;
+
+        let controls = [
+            root,
+            root + normal * length / 3.0,
+            root + normal * length * 2.0 / 3.0 + sag * 0.5 + jitter * 0.5,
+            root + normal * length + sag + jitter,
+        ];
+        strands.push(Curve::new(controls, radius, radius * 0.2, material_id));
+    }
+
+    strands
+}
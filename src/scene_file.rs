@@ -0,0 +1,306 @@
+use {
+    crate::{
+        animation::CameraPath,
+        graphics::{Background, RenderFormat, RenderMode, Sampler},
+        tracer_struct::{AreaLight, Camera, Curve, Disc, Fog, MAX_AREA_LIGHTS, MAX_MATERIALS, MAX_POINT_LIGHTS, MAX_PORTALS, MAX_SPHERES, Material, MaterialType, PhysicalCamera, PhysicalSky, PointLight, Portal, Sphere, SunLight, Triangle},
+        vec3::Vec3,
+    },
+    anyhow::{Context, Result, bail},
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+// render settings stored alongside the scene, mirrors the public fields of Uniforms
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub gamma_correction: f32,
+    pub psuedo_chromatic_aberration: f32,
+    pub render_mode: RenderMode,
+    // the simple gradient sky when None
+    pub sky: Option<PhysicalSky>,
+    pub background: Background,
+    pub manifold_caustics: bool,
+    // yaw in radians and radiance multiplier of the environment map
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+    // see Gfx::set_shutter
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+    // see Gfx::set_vignette and Gfx::set_physical_vignetting
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    pub physical_vignetting: bool,
+    pub sampler: Sampler,
+    // see Gfx::set_indirect_clamp and Gfx::set_outlier_rejection, 0 is off
+    pub indirect_clamp: f32,
+    pub outlier_rejection: f32,
+    // see Gfx::set_render_size, None follows the window
+    pub render_size: Option<(u32, u32)>,
+    // see Gfx::set_max_samples
+    pub max_samples: Option<u32>,
+    pub auto_save: Option<RenderFormat>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            gamma_correction: 2.2,
+            psuedo_chromatic_aberration: 0.0,
+            render_mode: RenderMode::PathTraced,
+            sky: None,
+            background: Background::Environment,
+            manifold_caustics: false,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            vignette_strength: 0.0,
+            vignette_radius: 0.5,
+            physical_vignetting: false,
+            sampler: Sampler::Random,
+            indirect_clamp: 0.0,
+            outlier_rejection: 0.0,
+            render_size: None,
+            max_samples: None,
+            auto_save: None,
+        }
+    }
+}
+
+// a mesh file pulled in when the scene is loaded, handy for hand written scenes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshReference {
+    pub path: String,
+    pub material_id: u32,
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+impl Default for MeshReference {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            material_id: 0,
+            translation: Vec3::zero(),
+            scale: 1.0,
+        }
+    }
+}
+
+// human editable description of a whole scene
+// written as RON, or JSON when the file name ends with .json
+// every field is optional when loading
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+    pub camera: Camera,
+    // when set the camera fov, width and apeture come from it
+    pub physical_camera: Option<PhysicalCamera>,
+    // a fly-through to play back or render with Gfx::start_camera_animation
+    pub camera_path: Option<CameraPath>,
+    // more viewpoints to switch between with Gfx::set_active_camera
+    pub cameras: BTreeMap<String, Camera>,
+    // which of cameras is active, camera itself when None
+    pub active_camera: Option<String>,
+    pub settings: RenderSettings,
+    pub materials: Vec<Material>,
+    pub spheres: Vec<Sphere>,
+    pub triangles: Vec<Triangle>,
+    pub curves: Vec<Curve>,
+    pub discs: Vec<Disc>,
+    pub portals: Vec<Portal>,
+    pub sun: Option<SunLight>,
+    pub fog: Option<Fog>,
+    pub point_lights: Vec<PointLight>,
+    pub area_lights: Vec<AreaLight>,
+    // .ies files, PointLight::ies_profile n refers to the nth
+    pub ies_profiles: Vec<String>,
+    pub meshes: Vec<MeshReference>,
+    // equirectangular .hdr/.exr used as the background
+    pub environment: Option<String>,
+}
+
+fn is_json(filename: &str) -> bool {
+    filename.to_lowercase().ends_with(".json")
+}
+
+impl SceneFile {
+    // other files the scene pulls in when loaded
+    pub fn dependencies(&self) -> Vec<String> {
+        self.meshes
+            .iter()
+            .map(|mesh| mesh.path.clone())
+            .chain(self.ies_profiles.iter().cloned())
+            .chain(self.environment.clone())
+            .collect()
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read scene file {}", filename))?;
+
+        let scene: Self = if is_json(filename) {
+            serde_json::from_str(&text)?
+        } else {
+            ron::from_str(&text)?
+        };
+        scene.validate().with_context(|| format!("invalid scene file {}", filename))?;
+
+        Ok(scene)
+    }
+
+    // more of something than the gpu scene holds or a material id past the materials
+    // would otherwise only fail, or panic, once the scene is added to Gfx
+    fn validate(&self) -> Result<()> {
+        let limits = [
+            ("materials", self.materials.len(), MAX_MATERIALS),
+            ("spheres", self.spheres.len(), MAX_SPHERES),
+            ("portals", self.portals.len(), MAX_PORTALS),
+            ("point lights", self.point_lights.len(), MAX_POINT_LIGHTS),
+            ("area lights", self.area_lights.len(), MAX_AREA_LIGHTS),
+        ];
+        for (name, count, max) in limits {
+            if count > max {
+                bail!("{} {}, at most {} are supported", count, name, max);
+            }
+        }
+
+        let mix_ids = self.materials
+            .iter()
+            .filter(|material| material.get_type() == MaterialType::Mix)
+            .flat_map(|material| [material.mix_material_a, material.mix_material_b]);
+        let material_ids = self.spheres
+            .iter()
+            .map(|sphere| sphere.material_id)
+            .chain(self.triangles.iter().map(|tri| tri.material_id))
+            .chain(self.curves.iter().map(|curve| curve.material_id))
+            .chain(self.discs.iter().map(|disc| disc.material_id))
+            .chain(self.meshes.iter().map(|mesh| mesh.material_id))
+            .chain(mix_ids);
+        for id in material_ids {
+            if id as usize >= self.materials.len() {
+                bail!("material {} is used but there are only {} materials", id, self.materials.len());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let text = if is_json(filename) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?
+        };
+
+        std::fs::write(filename, text)
+            .with_context(|| format!("failed to write scene file {}", filename))?;
+
+        Ok(())
+    }
+}
+
+// named camera viewpoints kept in their own file so they outlive scene edits
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPresets {
+    pub presets: BTreeMap<String, Camera>,
+}
+
+impl CameraPresets {
+    // a missing file is an empty set of presets
+    pub fn load(filename: &str) -> Result<Self> {
+        if !std::path::Path::new(filename).exists() {
+            return Ok(Self::default());
+        }
+
+        let text = std::fs::read_to_string(filename)
+            .with_context(|| format!("failed to read camera presets {}", filename))?;
+
+        let presets = if is_json(filename) {
+            serde_json::from_str(&text)?
+        } else {
+            ron::from_str(&text)?
+        };
+
+        Ok(presets)
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let text = if is_json(filename) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?
+        };
+
+        std::fs::write(filename, text)
+            .with_context(|| format!("failed to write camera presets {}", filename))?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Camera> {
+        self.presets.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, camera: Camera) {
+        self.presets.insert(name.to_string(), camera);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::tracer_struct::MixMaterial,
+    };
+
+    fn scene_with_materials(count: usize) -> SceneFile {
+        SceneFile {
+            materials: vec![Material::default(); count],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_scenes_pass() {
+        let mut scene = scene_with_materials(2);
+        scene.spheres.push(Sphere::new(Vec3::zero(), 1.0, 1));
+        scene.materials.push(MixMaterial::new(0, 1).build());
+        assert!(scene.validate().is_ok());
+        assert!(SceneFile::default().validate().is_ok());
+    }
+
+    #[test]
+    fn too_many_of_something_is_refused() {
+        let scene = scene_with_materials(MAX_MATERIALS + 1);
+        let error = scene.validate().unwrap_err().to_string();
+        assert!(error.contains("materials"), "{}", error);
+
+        let mut scene = scene_with_materials(1);
+        scene.spheres = vec![Sphere::new(Vec3::zero(), 1.0, 0); MAX_SPHERES + 1];
+        assert!(scene.validate().is_err());
+    }
+
+    #[test]
+    fn material_ids_past_the_materials_are_refused() {
+        let mut scene = scene_with_materials(1);
+        scene.spheres.push(Sphere::new(Vec3::zero(), 1.0, 1));
+        assert!(scene.validate().is_err());
+
+        let mut scene = scene_with_materials(1);
+        scene.materials.push(MixMaterial::new(0, 5).build());
+        assert!(scene.validate().is_err());
+    }
+
+    #[test]
+    fn load_reports_invalid_scenes() {
+        let path = std::env::temp_dir().join(format!("shrimpy_test_{}_invalid.json", std::process::id()));
+        std::fs::write(&path, r#"{"materials": [{}], "spheres": [{"center": [0, 0, 0], "radius": 1, "material_id": 3}]}"#)
+            .unwrap();
+        let error = SceneFile::load(&path.to_string_lossy()).unwrap_err();
+        assert!(format!("{:#}", error).contains("material 3"), "{:#}", error);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    graphics::Gfx,
+    mat4::Mat4,
+    tracer_struct::{Camera, Sphere, Triangle},
+};
+
+pub type NodeId = usize;
+
+pub enum NodeContent {
+    // pure grouping node, only carries a transform
+    Empty,
+    // triangles in the node's local space
+    Mesh(Vec<Triangle>),
+    Sphere(Sphere),
+    Camera(Camera),
+}
+
+pub struct Node {
+    pub name: String,
+    pub content: NodeContent,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    local_transform: Mat4,
+    // cached parent * local, None when it needs recomputing
+    world_transform: Option<Mat4>,
+}
+
+// a tree of transformed objects that gets flattened into the gpu scene
+// world transforms are computed lazily and only invalidated for the subtree that changed
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self { nodes: vec![] }
+    }
+
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        local_transform: Mat4,
+        content: NodeContent,
+    ) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            content,
+            parent,
+            children: vec![],
+            local_transform,
+            world_transform: None,
+        });
+
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+
+        id
+    }
+
+    pub fn get_node(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    pub fn get_local_transform(&self, id: NodeId) -> Mat4 {
+        self.nodes[id].local_transform
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, transform: Mat4) {
+        self.nodes[id].local_transform = transform;
+        self.invalidate(id);
+    }
+
+    // reparent a node, keeping its local transform
+    // a parent inside the node's own subtree would make a cycle and is refused
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> anyhow::Result<()> {
+        let mut ancestor = parent;
+        while let Some(ancestor_id) = ancestor {
+            if ancestor_id == id {
+                anyhow::bail!(
+                    "{} can not become a child of {}, it is one of its descendants",
+                    self.nodes[id].name,
+                    self.nodes[parent.unwrap()].name,
+                );
+            }
+            ancestor = self.nodes[ancestor_id].parent;
+        }
+
+        if let Some(old_parent) = self.nodes[id].parent {
+            self.nodes[old_parent].children.retain(|&child| child != id);
+        }
+        if let Some(new_parent) = parent {
+            self.nodes[new_parent].children.push(id);
+        }
+        self.nodes[id].parent = parent;
+        self.invalidate(id);
+        Ok(())
+    }
+
+    fn invalidate(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            let node = &mut self.nodes[node_id];
+            node.world_transform = None;
+            stack.extend_from_slice(&node.children);
+        }
+    }
+
+    pub fn get_world_transform(&mut self, id: NodeId) -> Mat4 {
+        if let Some(world) = self.nodes[id].world_transform {
+            return world;
+        }
+
+        let local = self.nodes[id].local_transform;
+        let world = match self.nodes[id].parent {
+            Some(parent) => self.get_world_transform(parent) * local,
+            None => local,
+        };
+        self.nodes[id].world_transform = Some(world);
+
+        world
+    }
+
+    // replace the geometry in gfx with the world space content of the graph
+    // the last camera node found (if any) becomes the active camera
+    // materials, lights, volumes and fog are left untouched
+    pub fn upload(&mut self, gfx: &mut Gfx) -> anyhow::Result<()> {
+        self.write_scene(gfx)?;
+        gfx.scene_update();
+        gfx.render_reset();
+        Ok(())
+    }
+
+    // like upload for a graph whose transforms changed but whose nodes did not, the
+    // bvh is refit instead of rebuilt so it is fast enough to do every frame
+    pub fn upload_transforms(&mut self, gfx: &mut Gfx) -> anyhow::Result<()> {
+        self.write_scene(gfx)?;
+        gfx.scene_refit();
+        gfx.render_reset();
+        Ok(())
+    }
+
+    fn write_scene(&mut self, gfx: &mut Gfx) -> anyhow::Result<()> {
+        gfx.scene_clear_geometry();
+
+        for id in 0..self.nodes.len() {
+            let world = self.get_world_transform(id);
+
+            match &self.nodes[id].content {
+                NodeContent::Empty => (),
+                NodeContent::Mesh(tris) => {
+                    let transformed: Vec<Triangle> = tris
+                        .iter()
+                        .map(|tri| {
+                            let mut tri = *tri;
+                            tri.vertex_0 = world.transform_point(tri.vertex_0);
+                            tri.vertex_1 = world.transform_point(tri.vertex_1);
+                            tri.vertex_2 = world.transform_point(tri.vertex_2);
+                            for normal in [&mut tri.normal_0, &mut tri.normal_1, &mut tri.normal_2] {
+                                if normal.length_squared() > 0.0 {
+                                    *normal = world.transform_normal(*normal).normalized();
+                                }
+                            }
+                            tri
+                        })
+                        .collect();
+                    gfx.scene_add_triangles(&transformed)?;
+                },
+                NodeContent::Sphere(sphere) => {
+                    let mut sphere = *sphere;
+                    sphere.center = world.transform_point(sphere.center);
+                    sphere.radius *= world.max_scale();
+                    gfx.scene_add_sphere(sphere);
+                },
+                NodeContent::Camera(camera) => {
+                    let mut camera = *camera;
+                    camera.position = world.transform_point(camera.position);
+                    camera.set_direction(world.transform_vector(camera.direction));
+                    *gfx.get_camera() = camera;
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::vec3::Vec3,
+    };
+
+    #[test]
+    fn world_transform_follows_the_parent() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("root", None, Mat4::translation(Vec3::new(1.0, 0.0, 0.0)), NodeContent::Empty);
+        let child = graph.add_node("child", None, Mat4::translation(Vec3::new(0.0, 2.0, 0.0)), NodeContent::Empty);
+        assert_eq!(graph.get_world_transform(child), Mat4::translation(Vec3::new(0.0, 2.0, 0.0)));
+
+        graph.set_parent(child, Some(root)).unwrap();
+        assert_eq!(graph.get_world_transform(child), Mat4::translation(Vec3::new(1.0, 2.0, 0.0)));
+
+        graph.set_local_transform(root, Mat4::identity());
+        assert_eq!(graph.get_world_transform(child), Mat4::translation(Vec3::new(0.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn set_parent_refuses_cycles() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node("a", None, Mat4::identity(), NodeContent::Empty);
+        let b = graph.add_node("b", Some(a), Mat4::identity(), NodeContent::Empty);
+        let c = graph.add_node("c", Some(b), Mat4::identity(), NodeContent::Empty);
+
+        assert!(graph.set_parent(a, Some(c)).is_err());
+        assert!(graph.set_parent(a, Some(a)).is_err());
+        // the failed calls left the tree alone
+        assert_eq!(graph.nodes[a].parent, None);
+        assert_eq!(graph.nodes[b].children, vec![c]);
+
+        graph.set_parent(c, Some(a)).unwrap();
+        assert_eq!(graph.nodes[a].children, vec![b, c]);
+        assert!(graph.nodes[b].children.is_empty());
+    }
+}
@@ -11,6 +11,84 @@ fn is_equal(a: f32, b: f32) -> bool  {
 }
 
 // Schlick's approximation for reflectance
+fn fresnel_schlick(cos_theta: f32, f0: vec3f) -> vec3f {
+    return f0 + (1.0 - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+// tangent, bitangent, normal (Duff et al. 2017)
+fn orthonormal_basis(n: vec3f) -> mat3x3f {
+    let s = select(-1.0, 1.0, n.z >= 0.0);
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+    let tangent = vec3f(1.0 + s * n.x * n.x * a, s * b, -s * n.x);
+    let bitangent = vec3f(b, s + n.y * n.y * a, -n.y);
+    return mat3x3f(tangent, bitangent, n);
+}
+
+// like orthonormal_basis but the tangent follows the surface, rotated around n
+fn tangent_basis(n: vec3f, tangent: vec3f, rotation: f32) -> mat3x3f {
+    let projected = tangent - n * dot(n, tangent);
+    let t = normalize(select(orthonormal_basis(n)[0], projected, dot(projected, projected) > 1e-8));
+    let rotated = t * cos(rotation) + cross(n, t) * sin(rotation);
+    return mat3x3f(rotated, cross(n, rotated), n);
+}
+
+// roughness stretched along the tangent by anisotropy (Kulla and Conty 2017)
+fn anisotropic_alpha(roughness: f32, anisotropy: f32) -> vec2f {
+    let alpha = roughness * roughness;
+    let aspect = sqrt(1.0 - 0.9 * clamp(anisotropy, 0.0, 1.0));
+    return max(vec2f(alpha / aspect, alpha * aspect), vec2f(1e-3));
+}
+
+// samples a GGX microfacet normal visible from v, both in tangent space (Heitz 2018)
+fn sample_ggx_vndf(v: vec3f, alpha: vec2f, u: vec2f) -> vec3f {
+    let vh = normalize(vec3f(alpha.x * v.x, alpha.y * v.y, v.z));
+    let len_sq = vh.x * vh.x + vh.y * vh.y;
+    let t1 = select(vec3f(1.0, 0.0, 0.0), vec3f(-vh.y, vh.x, 0.0) / sqrt(len_sq), len_sq > 0.0);
+    let t2 = cross(vh, t1);
+
+    let r = sqrt(u.x);
+    let phi = 2.0 * PI * u.y;
+    let p1 = r * cos(phi);
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * sqrt(1.0 - p1 * p1) + s * r * sin(phi);
+
+    let nh = p1 * t1 + p2 * t2 + sqrt(max(0.0, 1.0 - p1 * p1 - p2 * p2)) * vh;
+    return normalize(vec3f(alpha.x * nh.x, alpha.y * nh.y, max(0.0, nh.z)));
+}
+
+// smith masking for a tangent space direction
+fn smith_g1(w: vec3f, alpha: vec2f) -> f32 {
+    let a2_tan2 = (alpha.x * alpha.x * w.x * w.x + alpha.y * alpha.y * w.y * w.y) / (w.z * w.z);
+    return 2.0 / (1.0 + sqrt(1.0 + a2_tan2));
+}
+
+// reflectance of a thin film (thickness in nm) on top of a base with a per channel ior,
+// averaged over both polarizations with the airy summation at 650, 510 and 475 nm
+fn thin_film_fresnel(cos_i: f32, film_ior: f32, thickness: f32, base_ior: vec3f) -> vec3f {
+    let sin_i2 = 1.0 - cos_i * cos_i;
+    let sin_f2 = sin_i2 / (film_ior * film_ior);
+    if sin_f2 >= 1.0 {
+        return vec3f(1.0);
+    }
+    let cos_f = sqrt(1.0 - sin_f2);
+    let cos_b = sqrt(max(vec3f(0.0), 1.0 - sin_i2 / (base_ior * base_ior)));
+
+    // amplitude coefficients at the top and bottom interfaces
+    let r12_s = (cos_i - film_ior * cos_f) / (cos_i + film_ior * cos_f);
+    let r12_p = (film_ior * cos_i - cos_f) / (film_ior * cos_i + cos_f);
+    let r23_s = (film_ior * cos_f - base_ior * cos_b) / (film_ior * cos_f + base_ior * cos_b);
+    let r23_p = (base_ior * cos_f - film_ior * cos_b) / (base_ior * cos_f + film_ior * cos_b);
+
+    let phase = cos(4.0 * PI * film_ior * thickness * cos_f / vec3f(650.0, 510.0, 475.0));
+    let airy_s = (r12_s * r12_s + r23_s * r23_s + 2.0 * r12_s * r23_s * phase)
+        / (1.0 + r12_s * r12_s * r23_s * r23_s + 2.0 * r12_s * r23_s * phase);
+    let airy_p = (r12_p * r12_p + r23_p * r23_p + 2.0 * r12_p * r23_p * phase)
+        / (1.0 + r12_p * r12_p * r23_p * r23_p + 2.0 * r12_p * r23_p * phase);
+
+    return clamp(0.5 * (airy_s + airy_p), vec3f(0.0), vec3f(1.0));
+}
+
 fn reflectance_schlick(cosine: f32, ior: f32) -> f32 {
     var r0 = (1.0 - ior) / (1.0 + ior);
     r0 *= r0;
@@ -18,6 +96,46 @@ fn reflectance_schlick(cosine: f32, ior: f32) -> f32 {
     return r0 + (1.0 - r0) * icos * icos * icos * icos * icos;
 }
 
+const WAVELENGTH_MIN = 380.0;
+const WAVELENGTH_MAX = 780.0;
+
+// linear srgb of a single wavelength in nm through the multi lobe CIE fit of
+// Wyman et al. 2013, negative values are clipped and every channel is scaled
+// so that the average over uniformly sampled wavelengths is white
+fn wavelength_to_rgb(wavelength: f32) -> vec3f {
+    let lobe = cie_xyz(wavelength);
+    let rgb = max(mat3x3f(
+        vec3f(3.2406, -0.9689, 0.0557),
+        vec3f(-1.5372, 1.8758, -0.2040),
+        vec3f(-0.4986, 0.0415, 1.0570),
+    ) * lobe, vec3f(0.0));
+    return rgb * vec3f(2.2704, 3.4666, 3.6598);
+}
+
+fn piecewise_gaussian(x: f32, mu: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let t = (x - mu) / select(sigma_high, sigma_low, x < mu);
+    return exp(-0.5 * t * t);
+}
+
+// CIE 1931 xyz color matching functions
+fn cie_xyz(wavelength: f32) -> vec3f {
+    let x = 1.056 * piecewise_gaussian(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(wavelength, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(wavelength, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(wavelength, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(wavelength, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(wavelength, 459.0, 26.0, 13.8);
+    return vec3f(x, y, z);
+}
+
+// cauchy's n = a + b / wavelength^2 fitted to ior at the d line (587.6 nm) and the
+// abbe number (n_d - 1) / (n_F - n_C) with the F (486.1 nm) and C (656.3 nm) lines
+fn dispersive_ior(ior: f32, abbe_number: f32, wavelength: f32) -> f32 {
+    let b = (ior - 1.0) / (abbe_number * (1.0 / (486.1 * 486.1) - 1.0 / (656.3 * 656.3)));
+    return ior + b * (1.0 / (wavelength * wavelength) - 1.0 / (587.6 * 587.6));
+}
+
 // a slightly modified version of the "One-at-a-Time Hash" function by Bob Jenkins
 // see https://www.burtleburtle.net/bob/hash/doobs.html
 fn jenkins_hash(i: u32) -> u32 {
@@ -34,9 +152,20 @@ struct RNG {
     state: u32,
     cached_normal_sample: f32,
     has_cached: bool,
+    // for SAMPLER_SOBOL, which sample of the pixel this is and the next dimension
+    sample_index: u32,
+    dimension: u32,
+    sobol_seed: u32,
 };
 var<private> rng: RNG;
 
+const STEREO_OFF = 0u;
+const STEREO_SIDE_BY_SIDE = 1u;
+const STEREO_ANAGLYPH = 2u;
+
+// -1 while tracing for the left eye, 1 for the right one and 0 without stereo
+var<private> stereo_eye: f32;
+
 // the 32-bit "xor" function from Marsaglia G., "Xorshift RNGs", Section 3
 fn xorshift32() -> u32 {
     var x = rng.state;
@@ -50,13 +179,83 @@ fn xorshift32() -> u32 {
 fn init_rng(pixel: vec2u) {
     let time_seed = u32(uniforms.elapsed_seconds * 1000.0);
     let spatial_seed = pixel.x + pixel.y * uniforms.width;
-    let seed = spatial_seed ^ jenkins_hash(uniforms.frame_count) ^ jenkins_hash(time_seed);
+    let seed = spatial_seed ^ jenkins_hash(uniforms.frame_count + uniforms.sample_offset) ^ jenkins_hash(time_seed);
     rng.state = jenkins_hash(seed);
     rng.cached_normal_sample = 0.0;
     rng.has_cached = false;
+    // the pixel keeps its scrambling for every sample, only the index moves on
+    rng.sample_index = uniforms.frame_count - 1u + uniforms.sample_offset;
+    rng.dimension = 0u;
+    rng.sobol_seed = jenkins_hash(spatial_seed);
+}
+
+const SAMPLER_RANDOM = 0u;
+const SAMPLER_SOBOL = 1u;
+
+// direction numbers of the first four sobol dimensions (joe and kuo), 32 for each
+const SOBOL_DIRECTIONS = array<u32, 128>(
+    0x80000000u, 0x40000000u, 0x20000000u, 0x10000000u, 0x08000000u, 0x04000000u, 0x02000000u, 0x01000000u,
+    0x00800000u, 0x00400000u, 0x00200000u, 0x00100000u, 0x00080000u, 0x00040000u, 0x00020000u, 0x00010000u,
+    0x00008000u, 0x00004000u, 0x00002000u, 0x00001000u, 0x00000800u, 0x00000400u, 0x00000200u, 0x00000100u,
+    0x00000080u, 0x00000040u, 0x00000020u, 0x00000010u, 0x00000008u, 0x00000004u, 0x00000002u, 0x00000001u,
+    0x80000000u, 0xc0000000u, 0xa0000000u, 0xf0000000u, 0x88000000u, 0xcc000000u, 0xaa000000u, 0xff000000u,
+    0x80800000u, 0xc0c00000u, 0xa0a00000u, 0xf0f00000u, 0x88880000u, 0xcccc0000u, 0xaaaa0000u, 0xffff0000u,
+    0x80008000u, 0xc000c000u, 0xa000a000u, 0xf000f000u, 0x88008800u, 0xcc00cc00u, 0xaa00aa00u, 0xff00ff00u,
+    0x80808080u, 0xc0c0c0c0u, 0xa0a0a0a0u, 0xf0f0f0f0u, 0x88888888u, 0xccccccccu, 0xaaaaaaaau, 0xffffffffu,
+    0x80000000u, 0xc0000000u, 0x60000000u, 0x90000000u, 0xe8000000u, 0x5c000000u, 0x8e000000u, 0xc5000000u,
+    0x68800000u, 0x9cc00000u, 0xee600000u, 0x55900000u, 0x80680000u, 0xc09c0000u, 0x60ee0000u, 0x90550000u,
+    0xe8808000u, 0x5cc0c000u, 0x8e606000u, 0xc5909000u, 0x6868e800u, 0x9c9c5c00u, 0xeeee8e00u, 0x5555c500u,
+    0x8000e880u, 0xc0005cc0u, 0x60008e60u, 0x9000c590u, 0xe8006868u, 0x5c009c9cu, 0x8e00eeeeu, 0xc5005555u,
+    0x80000000u, 0xc0000000u, 0x20000000u, 0x50000000u, 0xf8000000u, 0x74000000u, 0xa2000000u, 0x93000000u,
+    0xd8800000u, 0x25400000u, 0x59e00000u, 0xe6d00000u, 0x78080000u, 0xb40c0000u, 0x82020000u, 0xc3050000u,
+    0x208f8000u, 0x51474000u, 0xfbea2000u, 0x75d93000u, 0xa0858800u, 0x914e5400u, 0xdbe79e00u, 0x25db6d00u,
+    0x58800080u, 0xe54000c0u, 0x79e00020u, 0xb6d00050u, 0x800800f8u, 0xc00c0074u, 0x200200a2u, 0x50050093u,
+);
+
+fn sobol(index: u32, dimension: u32) -> u32 {
+    var x = 0u;
+    var i = index;
+    for (var bit = 0u; i != 0u; bit += 1u) {
+        if (i & 1u) != 0u {
+            x ^= SOBOL_DIRECTIONS[dimension * 32u + bit];
+        }
+        i >>= 1u;
+    }
+    return x;
+}
+
+// hash based owen scrambling, see burley 2020, "Practical Hash-based Owen Scrambling"
+fn laine_karras_permutation(value: u32, seed: u32) -> u32 {
+    var x = value + seed;
+    x ^= x * 0x6c50b47cu;
+    x ^= x * 0xb82f1e52u;
+    x ^= x * 0xc7afe638u;
+    x ^= x * 0x8d22f6e6u;
+    return x;
+}
+
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    return reverseBits(laine_karras_permutation(reverseBits(value), seed));
+}
+
+fn hash_combine(seed: u32, value: u32) -> u32 {
+    return seed ^ (value + (seed << 6u) + (seed >> 2u));
+}
+
+// every four dimensions are a scrambled 4d sobol sequence, with the samples shuffled
+// differently for each group so the groups do not correlate
+fn sobol_sample(dimension: u32) -> f32 {
+    let group_seed = jenkins_hash(hash_combine(rng.sobol_seed, dimension / 4u));
+    let index = nested_uniform_scramble(rng.sample_index, group_seed);
+    let x = nested_uniform_scramble(sobol(index, dimension % 4u), hash_combine(group_seed, dimension % 4u));
+    return f32(x >> 8u) / 16777216.0;
 }
 
 fn rand() -> f32 {
+    if uniforms.sampler_kind == SAMPLER_SOBOL {
+        rng.dimension += 1u;
+        return sobol_sample(rng.dimension - 1u);
+    }
     return bitcast<f32>(0x3f800000u | (xorshift32() >> 9u)) - 1.0;
 }
 
@@ -88,52 +287,158 @@ fn rand_sphere() -> vec3f {
     ));
 }
 
-fn rand_circle() -> vec2f {
-    let angle = rand() * 2.0 * PI;
+// uniform in the unit circle for u uniform in the unit square
+fn sample_circle(u: vec2f) -> vec2f {
+    let angle = u.x * 2.0 * PI;
     let point_on_circle = vec2f(cos(angle), sin(angle));
-    return point_on_circle * sqrt(rand());
+    return point_on_circle * sqrt(u.y);
 }
 
-fn rand_polygon(n: i32) -> vec2f {
+// uniform in a regular n sided polygon inside the unit circle, the first corner at rotation
+fn sample_polygon(n: u32, rotation: f32, u: vec2f) -> vec2f {
     let angle = 2.0 * PI / f32(n);
-    let t = rand() * 2.0 * PI;
-    let r = sqrt(rand()); // radial falloff
+    let t = u.x * 2.0 * PI;
+    let r = sqrt(u.y); // radial falloff
     let a0 = floor(t / angle) * angle;
     let a1 = a0 + angle;
     let f = (t - a0) / angle;
 
-    let v0 = vec2f(cos(a0), sin(a0));
-    let v1 = vec2f(cos(a1), sin(a1));
+    let v0 = vec2f(cos(a0 + rotation), sin(a0 + rotation));
+    let v1 = vec2f(cos(a1 + rotation), sin(a1 + rotation));
     return mix(v0, v1, f) * r;
 }
 
 struct Camera {
     position: vec3f,
+    projection: u32,
     direction: vec3f,
     fov: f32,
     width: f32,
     focus_distance: f32,
     apeture: f32,
-    diverge_strength: f32,
+    filter_radius: f32,
     max_ray_bounces: u32,
+    exposure: f32,
+    aperture_blades: u32,
+    aperture_rotation: f32,
+    // only used on the cpu
+    yaw: f32,
+    pitch: f32,
+    pixel_filter: u32,
+    stereo: u32,
+    eye_separation: f32,
+    distortion_k1: f32,
+    distortion_k2: f32,
 }
 
+const MATERIAL_DIFFUSE = 0u;
+const MATERIAL_METAL = 1u;
+const MATERIAL_DIELECTRIC = 2u;
+const MATERIAL_EMISSIVE = 3u;
+const MATERIAL_VOLUME = 4u;
+const MATERIAL_PBR = 5u;
+const MATERIAL_PRINCIPLED = 6u;
+const MATERIAL_SUBSURFACE = 7u;
+const MATERIAL_CONDUCTOR = 8u;
+const MATERIAL_MIX = 9u;
+
+// bits of Material.flags
+const MATERIAL_CULL_BACKFACES = 1u;
+const MATERIAL_SINGLE_SIDED = 2u;
+const MATERIAL_HIDDEN_FROM_CAMERA = 4u;
+const MATERIAL_HIDDEN_IN_REFLECTIONS = 8u;
+
 struct Material {
     color: vec3f,
-    roughness_or_ior: f32,
+    material_type: u32,
+    roughness: f32,
+    ior: f32,
     emission_strength: f32,
     volume_density: f32,
+    metallic: f32,
+    specular: f32,
+    // texture ids, 0 means no texture
+    albedo_texture: u32,
+    roughness_texture: u32,
+    uv_scale: vec2f,
+    uv_offset: vec2f,
+    metallic_texture: u32,
+    // replaces the albedo as the emitted color, still scaled by emission_strength
+    emission_texture: u32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    transmission: f32,
+    anisotropy: f32,
+    anisotropy_rotation: f32,
+    // height map, the red channel is used
+    bump_texture: u32,
+    bump_strength: f32,
+    flags: u32,
+    // mean free path per channel of the subsurface random walk
+    subsurface_radius: vec3f,
+    // nm, 0 disables the film
+    thin_film_thickness: f32,
+    thin_film_ior: f32,
+    // tint per 1 / absorption_density units travelled inside, 0 disables it
+    absorption_density: f32,
+    absorption_color: vec3f,
+    // dielectric dispersion, 0 disables it
+    abbe_number: f32,
+    // complex ior per channel of a Conductor
+    conductor_eta: vec3f,
+    // chance of the surface being there, times the alpha of opacity_texture
+    opacity: f32,
+    conductor_k: vec3f,
+    opacity_texture: u32,
+    // mix_material_b is picked with probability mix_factor * mix_texture.r
+    mix_material_a: u32,
+    mix_material_b: u32,
+    mix_factor: f32,
+    mix_texture: u32,
 }
 
 struct Sphere {
     center: vec3f,
     radius: f32,
     material_id: u32,
+    light_mask: u32,
 }
 
 struct Triangle {
-    vertices: array<vec3f, 3>,
+    vertex_0: vec3f,
+    color_0: u32,
+    vertex_1: vec3f,
+    color_1: u32,
+    vertex_2: vec3f,
+    color_2: u32,
+    normal_0: vec3f,
+    light_mask: u32,
+    normal_1: vec3f,
+    normal_2: vec3f,
+    material_id: u32,
+    uv_0: vec2f,
+    uv_1: vec2f,
+    uv_2: vec2f,
+}
+
+struct Disc {
+    center: vec3f,
+    radius: f32,
+    normal: vec3f,
+    material_id: u32,
+}
+
+struct Curve {
+    control_0: vec3f,
+    radius_0: f32,
+    control_1: vec3f,
+    control_2: vec3f,
+    control_3: vec3f,
+    radius_1: f32,
     material_id: u32,
+    light_mask: u32,
 }
 
 struct BVHNode {
@@ -141,17 +446,104 @@ struct BVHNode {
     child1: u32,
     bbox_max: vec3f,
     child2: u32,
-    triangle_count: u32,
-    triangle_ids: array<u32, 4>,
+    // a leaf covers primitive_count entries of bvh_primitives from first_primitive
+    first_primitive: u32,
+    primitive_count: u32,
+}
+
+// a density grid filling an axis aligned box, densities are scaled by the
+// material's volume_density and scatter with its color
+struct Volume {
+    bbox_min: vec3f,
+    material_id: u32,
+    bbox_max: vec3f,
+    // largest voxel value, the majorant for tracking
+    max_density: f32,
+    resolution: vec3u,
+    // first voxel in volume_data, x varies fastest
+    data_offset: u32,
+}
+
+// an opening the environment shines through, spanned by edge_u and edge_v
+struct Portal {
+    corner: vec3f,
+    edge_u: vec3f,
+    edge_v: vec3f,
+}
+
+struct AreaLight {
+    corner: vec3f,
+    edge_u: vec3f,
+    edge_v: vec3f,
+    light_mask: u32,
+    emission: vec3f,
+    flags: u32,
+}
+
+struct PointLight {
+    position: vec3f,
+    inner_angle: f32,
+    direction: vec3f,
+    outer_angle: f32,
+    intensity: vec3f,
+    ies_profile: u32,
+    light_mask: u32,
+}
+
+struct SunLight {
+    direction: vec3f,
+    angular_diameter: f32,
+    irradiance: vec3f,
+    flags: u32,
+    light_mask: u32,
+}
+
+struct LightTreeNode {
+    bbox_min: vec3f,
+    power: f32,
+    bbox_max: vec3f,
+    child_or_triangle: u32,
 }
 
+const LIGHT_TREE_LEAF = 0x80000000u;
+
 struct Scene {
     materials: array<Material, 64>,
-    spheres: array<Sphere, 64>,
-    triangles: array<Triangle, 256>,
+    spheres: array<Sphere, 1024>,
     sphere_count: u32,
+    // the triangles, curves and discs themselves and the bvh over them are in group 1,
+    // the sphere bvh follows the triangle bvh rooted at 0, then the curve and disc bvh
     triangle_count: u32,
-    bvh: array<BVHNode, 96>,
+    curve_count: u32,
+    disc_count: u32,
+    sphere_bvh_root: u32,
+    curve_bvh_root: u32,
+    disc_bvh_root: u32,
+    volume_count: u32,
+    volumes: array<Volume, 16>,
+    portal_count: u32,
+    portals: array<Portal, 16>,
+    sun: SunLight,
+    point_light_count: u32,
+    point_lights: array<PointLight, 64>,
+    area_light_count: u32,
+    area_lights: array<AreaLight, 16>,
+    emissive_triangle_count: u32,
+    fog: Fog,
+}
+
+struct Fog {
+    color: vec3f,
+    density: f32,
+    height: f32,
+    falloff: f32,
+    anisotropy: f32,
+}
+
+struct PhysicalSky {
+    sun_direction: vec3f,
+    turbidity: f32,
+    intensity: f32,
 }
 
 struct Uniforms {
@@ -162,12 +554,87 @@ struct Uniforms {
     frame_count: u32,
     gamma_correction: f32,
     psuedo_chromatic_aberration: f32,
+    use_environment: u32,
+    render_mode: u32,
+    use_physical_sky: u32,
+    background: u32,
+    manifold_caustics: u32,
+    environment_rotation: f32,
+    sky: PhysicalSky,
+    background_bottom: vec3f,
+    environment_intensity: f32,
+    background_top: vec3f,
+    focus_overlay: u32,
+    camera_start_position: vec3f,
+    shutter_open: f32,
+    camera_start_direction: vec3f,
+    shutter_close: f32,
+    vignette_strength: f32,
+    vignette_radius: f32,
+    physical_vignetting: u32,
+    tile_size: u32,
+    blue_noise: u32,
+    sampler_kind: u32,
+    indirect_clamp: f32,
+    outlier_rejection: f32,
+    previous_camera: Camera,
+    reproject: u32,
+    previous_samples: u32,
+    // non zero when cs_denoise writes display_image instead of cs_trace
+    denoise: u32,
+    preview_scale: u32,
+    window_width: u32,
+    window_height: u32,
+    // added to the index of every sample, so gpus sharing a render trace different ones
+    sample_offset: u32,
 }
 
 @group(0) @binding(0) var<uniform> uniforms: Uniforms;
 @group(0) @binding(1) var<storage, read> scene: Scene;
 @group(0) @binding(2) var radiance_samples_old: texture_2d<f32>;
 @group(0) @binding(3) var radiance_samples_new: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(4) var environment: texture_2d<f32>;
+@group(0) @binding(5) var textures: texture_2d_array<f32>;
+@group(0) @binding(6) var texture_sampler: sampler;
+@group(0) @binding(7) var<storage, read> volume_data: array<f32>;
+// marginal cdf over the environment rows followed by one conditional cdf per row
+@group(0) @binding(8) var<storage, read> environment_cdf: array<f32>;
+@group(0) @binding(9) var<storage, read> light_tree: array<LightTreeNode>;
+@group(0) @binding(10) var<storage, read> ies_data: array<f32>;
+// tonemapped frame for the display pass, read there through display_image_read
+@group(0) @binding(11) var display_image: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(12) var display_image_read: texture_2d<f32>;
+@group(0) @binding(22) var display_sampler: sampler;
+// top left pixels of the tiles traced by this dispatch
+@group(0) @binding(13) var<storage, read> tile_origins: array<vec2u>;
+// four unrelated tileable blue noise masks, see blue_noise.rs
+@group(0) @binding(14) var blue_noise: texture_2d<f32>;
+// what the first hit through every pixel center looks like, see encode_guide, swapped
+// like radiance_samples
+@group(0) @binding(15) var guide_old: texture_2d<u32>;
+@group(0) @binding(16) var guide_new: texture_storage_2d<rgba32uint, write>;
+
+// one step of the edge avoiding a-trous filter, see cs_denoise
+struct DenoiseParams {
+    // pixels between the taps
+    step: u32,
+    // the first step reads the accumulated radiance, the last one writes display_image
+    first: u32,
+    last: u32,
+}
+
+// bound only to cs_denoise, radiance and guide are the ones the last sample wrote
+@group(0) @binding(17) var denoise_radiance: texture_2d<f32>;
+@group(0) @binding(18) var denoise_guide: texture_2d<u32>;
+@group(0) @binding(19) var denoise_input: texture_2d<f32>;
+@group(0) @binding(20) var denoise_output: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(21) var<uniform> denoise_params: DenoiseParams;
+
+@group(1) @binding(0) var<storage, read> curves: array<Curve>;
+@group(1) @binding(1) var<storage, read> bvh: array<BVHNode>;
+@group(1) @binding(2) var<storage, read> discs: array<Disc>;
+@group(1) @binding(3) var<storage, read> triangles: array<Triangle>;
+@group(1) @binding(4) var<storage, read> bvh_primitives: array<u32>;
 
 struct Ray {
     origin: vec3f,
@@ -180,38 +647,305 @@ struct HitInfo {
     normal: vec3f,
     material_id: u32,
     front_face: bool,
+    // interpolated vertex color, alpha is 0 when the primitive has none
+    vertex_color: vec4f,
+    uv: vec2f,
+    // direction of increasing u, zero when unknown
+    tangent: vec3f,
+    // set for triangles, only they are sampled as emitters
+    triangle: bool,
+    triangle_id: u32,
+    // the glass spheres manifold_caustics looks through
+    caustic_sphere: bool,
+    // which lights shine on the primitive, see light_linked
+    light_mask: u32,
+}
+
+// texture ids start at 1, layer 0 holds texture 1
+fn sample_texture(id: u32, uv: vec2f) -> vec4f {
+    return textureSampleLevel(textures, texture_sampler, uv, i32(id) - 1, 0.0);
+}
+
+// tilts the normal against the height gradient, taken with forward differences one
+// texel apart along the uv tangent frame
+// the height grows along the geometric normal, so back faces see dents instead of bumps
+// material color with vertex colors and the albedo texture applied
+fn surface_albedo(hit: HitInfo, material: Material, uv: vec2f) -> vec3f {
+    var albedo = material.color;
+    if hit.vertex_color.a > 0.0 {
+        albedo *= hit.vertex_color.rgb;
+    }
+    if material.albedo_texture != 0 {
+        albedo *= sample_texture(material.albedo_texture, uv).rgb;
+    }
+    return albedo;
+}
+
+fn bump_normal(normal: vec3f, tangent: vec3f, front_face: bool, uv: vec2f, material: Material) -> vec3f {
+    let texel = 1.0 / f32(textureDimensions(textures).x);
+    let height = sample_texture(material.bump_texture, uv).r;
+    let dh_du = sample_texture(material.bump_texture, uv + vec2f(texel, 0.0)).r - height;
+    let dh_dv = sample_texture(material.bump_texture, uv + vec2f(0.0, texel)).r - height;
+
+    let side = select(-1.0, 1.0, front_face);
+    let basis = tangent_basis(normal * side, tangent, 0.0);
+    let bumped = normalize(basis[2] - material.bump_strength * (dh_du * basis[0] + dh_dv * basis[1]));
+    return bumped * side;
+}
+
+// turns a world direction by the environment rotation around the up axis, a negative
+// sign goes from world to environment map space
+fn rotate_environment(direction: vec3f, sign: f32) -> vec3f {
+    let angle = sign * uniforms.environment_rotation;
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec3f(c * direction.x - s * direction.z, direction.y, s * direction.x + c * direction.z);
+}
+
+// equirectangular lookup with manual bilinear filtering (rgba32float is not filterable)
+fn sample_environment(direction: vec3f) -> vec3f {
+    let d = rotate_environment(normalize(direction), -1.0);
+    let uv = vec2f(
+        0.5 + atan2(d.z, d.x) / (2.0 * PI),
+        acos(clamp(d.y, -1.0, 1.0)) / PI,
+    );
+
+    let size = vec2i(textureDimensions(environment));
+    let p = uv * vec2f(size) - 0.5;
+    let p0 = vec2i(floor(p));
+    let f = p - floor(p);
+
+    // wrap horizontally, clamp at the poles
+    let x0 = (p0.x % size.x + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(p0.y, 0, size.y - 1);
+    let y1 = clamp(p0.y + 1, 0, size.y - 1);
+
+    let top = mix(textureLoad(environment, vec2i(x0, y0), 0), textureLoad(environment, vec2i(x1, y0), 0), f.x);
+    let bottom = mix(textureLoad(environment, vec2i(x0, y1), 0), textureLoad(environment, vec2i(x1, y1), 0), f.x);
+    return mix(top, bottom, f.y).rgb * uniforms.environment_intensity;
+}
+
+// Perez sky distribution with the five coefficients in a and e
+fn perez(cos_theta: f32, gamma: f32, a: vec3f, b: vec3f, c: vec3f, d: vec3f, e: vec3f) -> vec3f {
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / max(cos_theta, 0.01))) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+// converts kilocandela per square meter to something close to the gradient sky's range
+const SKY_LUMINANCE_SCALE = 0.1;
+
+// Preetham et al. 1999, "A Practical Analytic Model for Daylight"
+fn physical_sky_color(direction: vec3f) -> vec3f {
+    let sky = uniforms.sky;
+    let t = sky.turbidity;
+    let view = normalize(direction);
+    let sun = normalize(sky.sun_direction);
+
+    // the sun is kept just above the horizon, the model breaks down below it
+    let theta_sun = min(acos(clamp(sun.y, -1.0, 1.0)), PI / 2.0 - 0.01);
+    let theta = acos(clamp(max(view.y, 0.0), -1.0, 1.0));
+    let gamma = acos(clamp(dot(view, sun), -1.0, 1.0));
+
+    // coefficients for luminance Y and chromaticities x and y
+    let a = vec3f(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608);
+    let b = vec3f(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092);
+    let c = vec3f(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102);
+    let e = vec3f(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529);
+    let d = vec3f(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537);
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    let zenith_luminance = max((4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192, 0.0);
+    let theta_powers = vec4f(theta_sun * theta_sun * theta_sun, theta_sun * theta_sun, theta_sun, 1.0);
+    let turbidity_powers = vec3f(t * t, t, 1.0);
+    let zenith_x = dot(turbidity_powers, vec3f(
+        dot(theta_powers, vec4f(0.00166, -0.00375, 0.00209, 0.0)),
+        dot(theta_powers, vec4f(-0.02903, 0.06377, -0.03202, 0.00394)),
+        dot(theta_powers, vec4f(0.11693, -0.21196, 0.06052, 0.25886)),
+    ));
+    let zenith_y = dot(turbidity_powers, vec3f(
+        dot(theta_powers, vec4f(0.00275, -0.00610, 0.00317, 0.0)),
+        dot(theta_powers, vec4f(-0.04214, 0.08970, -0.04153, 0.00516)),
+        dot(theta_powers, vec4f(0.15346, -0.26756, 0.06670, 0.26688)),
+    ));
+    let zenith = vec3f(zenith_luminance, zenith_x, zenith_y);
+
+    let yxy = zenith * perez(cos(theta), gamma, a, b, c, d, e) / perez(1.0, theta_sun, a, b, c, d, e);
+
+    // Yxy to XYZ to linear sRGB
+    let luminance = yxy.x * SKY_LUMINANCE_SCALE * sky.intensity;
+    let xyz = vec3f(yxy.y / yxy.z * luminance, luminance, (1.0 - yxy.y - yxy.z) / yxy.z * luminance);
+    let rgb = vec3f(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+
+    // fade to night as the sun sets and darken the lower hemisphere like a ground
+    let daylight = smoothstep(-0.1, 0.05, sun.y);
+    let ground = select(1.0, 0.3, view.y < 0.0);
+    return max(rgb, vec3f(0.0)) * daylight * ground;
+}
+
+const BACKGROUND_ENVIRONMENT = 0u;
+const BACKGROUND_COLOR = 1u;
+const BACKGROUND_GRADIENT = 2u;
+const BACKGROUND_TRANSPARENT = 3u;
+
+// environment map and physical sky only show behind the scene when the background
+// is not a plain color or gradient
+fn sky_background() -> bool {
+    return uniforms.background == BACKGROUND_ENVIRONMENT || uniforms.background == BACKGROUND_TRANSPARENT;
+}
+
+fn environment_map_enabled() -> bool {
+    return uniforms.use_environment != 0u && sky_background();
 }
 
 fn sky_color(ray: Ray) -> vec3f {
+    if environment_map_enabled() {
+        return sample_environment(ray.direction);
+    }
+
+    var bottom = vec3f(1.0);
+    var top = vec3f(0.3, 0.5, 1.0);
+    if sky_background() {
+        if uniforms.use_physical_sky != 0u {
+            return physical_sky_color(ray.direction);
+        }
+    } else {
+        bottom = uniforms.background_bottom;
+        top = uniforms.background_top;
+    }
+
     let t = 0.5 * (normalize(ray.direction).y + 1.0);
-    return (1.0 - t) * vec3(1.0) + t * vec3(0.3, 0.5, 1.0);
-    // return vec3f(0.0);
+    return mix(bottom, top, t);
 }
 
-fn new_ray(pos: vec4f) -> Ray {
-    let aspect_ratio = f32(uniforms.width) / f32(uniforms.height);
+const PROJECTION_PERSPECTIVE = 0u;
+const PROJECTION_FISHEYE_EQUIDISTANT = 1u;
+const PROJECTION_FISHEYE_EQUISOLID = 2u;
+
+// angle from the view direction of a point radius away from the image center, the
+// edge of width lands on half the fov like it does for the perspective projection
+// larger than PI past the edge of a fisheye lens
+fn fisheye_angle(radius: f32) -> f32 {
+    let half_width = uniforms.camera.width * 0.5;
+    if uniforms.camera.projection == PROJECTION_FISHEYE_EQUISOLID {
+        let scale = half_width / (2.0 * sin(uniforms.camera.fov * 0.25));
+        let s = radius / (2.0 * scale);
+        return select(2.0 * asin(s), 2.0 * PI, s > 1.0);
+    }
+    return radius / half_width * uniforms.camera.fov * 0.5;
+}
+
+// image plane coordinates of a pixel, x grows to the right and y upwards
+fn image_uv(pos: vec4f) -> vec2f {
+    // side by side halves are images of their own
+    var width = uniforms.width;
+    var xy = pos.xy;
+    if uniforms.camera.stereo == STEREO_SIDE_BY_SIDE {
+        width = uniforms.width / 2u;
+        xy.x = select(xy.x, xy.x - f32(width), xy.x >= f32(width));
+    }
+    let aspect_ratio = f32(width) / f32(uniforms.height);
+    let uv = xy / vec2f(f32(width - 1), f32(uniforms.height - 1));
+    return (2.0 * uv - vec2f(1.0)) * vec2f(aspect_ratio, -1.0);
+}
+
+// where the lens makes a point of the image look, brown's radial model
+fn distort(image: vec2f) -> vec2f {
+    let r2 = dot(image, image);
+    return image * (1.0 + uniforms.camera.distortion_k1 * r2 + uniforms.camera.distortion_k2 * r2 * r2);
+}
+
+// false for pixels outside the image circle of a fisheye lens
+fn lens_covers(pos: vec4f) -> bool {
+    if uniforms.camera.projection == PROJECTION_PERSPECTIVE {
+        return true;
+    }
+    return fisheye_angle(length(distort(image_uv(pos)))) <= PI;
+}
 
-    var camera_right_direction = -normalize(cross(uniforms.camera.direction, vec3f(0.0, 1.0, 0.0)));
-    let camera_up_direction = normalize(cross(uniforms.camera.direction, camera_right_direction));
+// the camera's random numbers for this pixel and sample, xy for the pixel filter and zw
+// for the lens, from the blue noise masks stepped on by the golden ratio every sample so
+// the pixel keeps seeing well spread values over time too
+fn camera_samples(pos: vec4f) -> vec4f {
+    if uniforms.blue_noise == 0u || uniforms.sampler_kind == SAMPLER_SOBOL {
+        return vec4f(rand(), rand(), rand(), rand());
+    }
+    let mask = textureLoad(blue_noise, vec2u(pos.xy) % textureDimensions(blue_noise), 0);
+    return fract(mask + f32(uniforms.frame_count + uniforms.sample_offset) * 0.618034);
+}
+
+fn new_ray(pos: vec4f) -> Ray {
+    // motion blur, each sample sees the camera at a random time while the shutter is open
+    let time = mix(uniforms.shutter_open, uniforms.shutter_close, rand());
+    let u = camera_samples(pos);
 
     // offset ray origin for defocusing effect
-    // note that the jitter is in a circle pattern
-    // that also means the created boked shape is circle
-    // to create other bokeh shapes, just change the rand_circle() into
-    // rand_hexagon() or something similar
-    let defocus_jitter = vec3f(rand_circle() * uniforms.camera.apeture * 0.5, 0.0);
-    let origin_offset = camera_up_direction * defocus_jitter.y + camera_right_direction * defocus_jitter.x;
-    let ray_origin = uniforms.camera.position + origin_offset;
-
-    // random jitter for anti-aliasing
-    let jitter = rand_circle() * uniforms.camera.diverge_strength;
-    var uv = pos.xyz / vec3f(f32(uniforms.width - 1), f32(uniforms.height - 1), 1.0);
-    uv = (2.0 * uv - vec3f(1.0)) * vec3f(aspect_ratio, -1.0, 0.0);
-
-    uv = camera_up_direction * (uv.y + jitter.y) + camera_right_direction * (uv.x + jitter.x);
-    
-    let focal_length = uniforms.camera.width * 0.5 / tan(uniforms.camera.fov * 0.5);
-    let focus_direction = normalize(uv + uniforms.camera.direction * focal_length);
+    // the jitter follows the aperture shape, which is also the shape of the bokeh
+    var lens_point = sample_circle(u.zw);
+    if uniforms.camera.aperture_blades >= 3u {
+        lens_point = sample_polygon(uniforms.camera.aperture_blades, uniforms.camera.aperture_rotation, u.zw);
+    }
+
+    // anti-aliasing, a pixel is 2 / (height - 1) wide in image_uv
+    let jitter = sample_pixel_filter(u.xy) * 2.0 / f32(uniforms.height - 1);
+
+    return camera_ray(pos, time, lens_point, jitter);
+}
+
+const PIXEL_FILTER_BOX = 0u;
+const PIXEL_FILTER_TENT = 1u;
+const PIXEL_FILTER_GAUSSIAN = 2u;
+
+// offset from the pixel center in pixels, distributed like the pixel filter weights
+// so every sample counts the same
+fn sample_pixel_filter(u: vec2f) -> vec2f {
+    let radius = uniforms.camera.filter_radius;
+    if uniforms.camera.pixel_filter == PIXEL_FILTER_BOX {
+        return (2.0 * u - 1.0) * radius;
+    }
+    if uniforms.camera.pixel_filter == PIXEL_FILTER_TENT {
+        // inverse cdf of the triangle on each axis
+        let v = 2.0 * u;
+        let tent = select(1.0 - sqrt(2.0 - v), sqrt(v) - 1.0, v < vec2f(1.0));
+        return tent * radius;
+    }
+    // box-muller with the standard deviation at half the radius
+    let r = sqrt(-2.0 * log(max(u.x, 1e-7)));
+    let angle = 2.0 * PI * u.y;
+    return r * vec2f(cos(angle), sin(angle)) * 0.5 * radius;
+}
+
+// the ray through pixel pos at time from the unit lens_point of the aperture
+fn camera_ray(pos: vec4f, time: f32, lens_point: vec2f, jitter: vec2f) -> Ray {
+    let camera_position = mix(uniforms.camera_start_position, uniforms.camera.position, time);
+    let camera_direction = normalize(mix(uniforms.camera_start_direction, uniforms.camera.direction, time));
+
+    var camera_right_direction = -normalize(cross(camera_direction, vec3f(0.0, 1.0, 0.0)));
+    let camera_up_direction = normalize(cross(camera_direction, camera_right_direction));
+
+    let defocus_jitter = vec3f(lens_point * uniforms.camera.apeture * 0.5, 0.0);
+    // eyes are moved sideways and still aim at the focus point, so they converge there
+    let eye_offset = stereo_eye * uniforms.camera.eye_separation * 0.5;
+    let origin_offset = camera_up_direction * defocus_jitter.y + camera_right_direction * (defocus_jitter.x + eye_offset);
+    let ray_origin = camera_position + origin_offset;
+
+    let image = distort(image_uv(pos) + jitter);
+    let uv = camera_up_direction * image.y + camera_right_direction * image.x;
+
+    var focus_direction: vec3f;
+    if uniforms.camera.projection == PROJECTION_PERSPECTIVE {
+        let focal_length = uniforms.camera.width * 0.5 / tan(uniforms.camera.fov * 0.5);
+        focus_direction = normalize(uv + camera_direction * focal_length);
+    } else {
+        let radius = length(image);
+        let theta = min(fisheye_angle(radius), PI);
+        let outwards = select(vec3f(0.0), uv / radius, radius > 0.0);
+        focus_direction = normalize(camera_direction * cos(theta) + outwards * sin(theta));
+    }
 
     return Ray (
         ray_origin,
@@ -254,10 +988,22 @@ fn intersect_sphere(ray: Ray, sphere: Sphere) -> HitInfo {
 
     hit.point = ray.origin + ray.direction * hit.distance;
     hit.normal = (hit.point - sphere.center) / sphere.radius;
+    // equirectangular mapping, same layout as the environment map
+    hit.uv = vec2f(
+        0.5 + atan2(hit.normal.z, hit.normal.x) / (2.0 * PI),
+        acos(clamp(hit.normal.y, -1.0, 1.0)) / PI,
+    );
+    hit.tangent = vec3f(-hit.normal.z, 0.0, hit.normal.x);
     if !hit.front_face {
+        if (scene.materials[sphere.material_id].flags & MATERIAL_CULL_BACKFACES) != 0u {
+            hit.distance = -1.0;
+            return hit;
+        }
         hit.normal *= -1.0;
     }
     hit.material_id = sphere.material_id;
+    hit.light_mask = sphere.light_mask;
+    hit.caustic_sphere = caustic_sphere(sphere);
 
     return hit;
 }
@@ -266,8 +1012,8 @@ fn intersect_triangle(ray: Ray, tri: Triangle) -> HitInfo {
     var hit: HitInfo;
     hit.distance = -1.0;
 
-    var edge0 = tri.vertices[1] - tri.vertices[0];
-    var edge1 = tri.vertices[2] - tri.vertices[0];
+    var edge0 = tri.vertex_1 - tri.vertex_0;
+    var edge1 = tri.vertex_2 - tri.vertex_0;
 
     var normal = cross(edge0, edge1);
     var determinant = -dot(ray.direction, normal);
@@ -280,10 +1026,9 @@ fn intersect_triangle(ray: Ray, tri: Triangle) -> HitInfo {
 
     if determinant < 0.0 {
         // hit back face
-        // let material = scene.materials[tri.material_id];
-        // if material.roughness_or_ior >= 0.0 || material.volume_density >= 1.0 {
-        //     return hit;
-        // }
+        if (scene.materials[tri.material_id].flags & MATERIAL_CULL_BACKFACES) != 0u {
+            return hit;
+        }
 
         let temp = edge0;
         edge0 = edge1;
@@ -295,7 +1040,7 @@ fn intersect_triangle(ray: Ray, tri: Triangle) -> HitInfo {
     }
 
     let inv_det = 1.0 / determinant;
-    let ao = ray.origin - tri.vertices[0];
+    let ao = ray.origin - tri.vertex_0;
 
     let dst = dot(ao, normal) * inv_det;
     if dst < EPSILON {
@@ -323,20 +1068,36 @@ fn intersect_triangle(ray: Ray, tri: Triangle) -> HitInfo {
     hit.normal = normalize(normal);
     hit.distance = dst;
     hit.material_id = tri.material_id;
+    hit.light_mask = tri.light_mask;
+    hit.triangle = true;
 
-    // if calculate_uv {
-    //     let vt1 = tri.vert_texture[0];
-    //     let vt2 = tri.vert_texture[1];
-    //     let vt3 = tri.vert_texture[2];
-    //     let coord = w * vt1 + u * vt2 + v * vt3;
-    //     h.u = coord.x;
-    //     h.v = coord.y;
-    // }
+    // u and v weight vertex 1 and 2, unless the edges were swapped for a back face hit
+    let b1 = select(v, u, hit.front_face);
+    let b2 = select(u, v, hit.front_face);
+
+    if dot(tri.normal_0, tri.normal_0) > 0.0 {
+        let shading_normal = normalize(w * tri.normal_0 + b1 * tri.normal_1 + b2 * tri.normal_2);
+        // keep the shading normal on the same side as the geometric one
+        hit.normal = select(-shading_normal, shading_normal, dot(shading_normal, hit.normal) >= 0.0);
+    }
+
+    hit.vertex_color = w * unpack4x8unorm(tri.color_0)
+        + b1 * unpack4x8unorm(tri.color_1)
+        + b2 * unpack4x8unorm(tri.color_2);
+
+    hit.uv = w * tri.uv_0 + b1 * tri.uv_1 + b2 * tri.uv_2;
+
+    let duv1 = tri.uv_1 - tri.uv_0;
+    let duv2 = tri.uv_2 - tri.uv_0;
+    let uv_det = duv1.x * duv2.y - duv2.x * duv1.y;
+    let tangent = ((tri.vertex_1 - tri.vertex_0) * duv2.y - (tri.vertex_2 - tri.vertex_0) * duv1.y) / uv_det;
+    hit.tangent = select(vec3f(0.0), tangent, abs(uv_det) > 1e-12);
 
     return hit;
 }
 
-fn intersect_aabb(ray: Ray, box_min: vec3f, box_max: vec3f) -> bool {
+// entry and exit distances along the ray, the box is missed when x > y
+fn aabb_range(ray: Ray, box_min: vec3f, box_max: vec3f) -> vec2f {
     let inv_dir = 1.0 / ray.direction;
     let t_min = (box_min - ray.origin) * inv_dir;
     let t_max = (box_max - ray.origin) * inv_dir;
@@ -347,48 +1108,203 @@ fn intersect_aabb(ray: Ray, box_min: vec3f, box_max: vec3f) -> bool {
     let t_near = max(max(t1.x, t1.y), t1.z);
     let t_far = min(min(t2.x, t2.y), t2.z);
 
-    return t_near <= t_far;
+    return vec2f(t_near, t_far);
+}
+
+fn intersect_aabb(ray: Ray, box_min: vec3f, box_max: vec3f) -> bool {
+    let range = aabb_range(ray, box_min, box_max);
+    return range.x <= range.y;
+}
+
+fn intersect_disc(ray: Ray, disc: Disc) -> HitInfo {
+    var hit: HitInfo;
+    hit.distance = -1.0;
+
+    let denom = dot(disc.normal, ray.direction);
+    if is_equal_zero(denom) {
+        return hit; // ray is parallel to disc
+    }
+
+    let dst = dot(disc.center - ray.origin, disc.normal) / denom;
+    if dst < EPSILON {
+        return hit;
+    }
+
+    let point = ray.origin + ray.direction * dst;
+    let d = point - disc.center;
+    if dot(d, d) > disc.radius * disc.radius {
+        return hit;
+    }
+
+    // discs are two sided, always face the incoming ray
+    hit.front_face = true;
+    hit.distance = dst;
+    hit.point = point;
+    hit.normal = select(disc.normal, -disc.normal, denom > 0.0);
+    hit.material_id = disc.material_id;
+
+    return hit;
+}
+
+fn bezier_point(curve: Curve, t: f32) -> vec3f {
+    let s = 1.0 - t;
+    return s * s * s * curve.control_0
+        + 3.0 * s * s * t * curve.control_1
+        + 3.0 * s * t * t * curve.control_2
+        + t * t * t * curve.control_3;
+}
+
+// iq's ray-capsule intersection
+// see https://iquilezles.org/articles/intersectors/
+fn intersect_capsule(ray: Ray, pa: vec3f, pb: vec3f, radius: f32) -> f32 {
+    let ba = pb - pa;
+    let oa = ray.origin - pa;
+    let baba = dot(ba, ba);
+    let bard = dot(ba, ray.direction);
+    let baoa = dot(ba, oa);
+    let rdoa = dot(ray.direction, oa);
+    let oaoa = dot(oa, oa);
+
+    let a = baba - bard * bard;
+    var b = baba * rdoa - baoa * bard;
+    var c = baba * oaoa - baoa * baoa - radius * radius * baba;
+    var h = b * b - a * c;
+    if h < 0.0 {
+        return -1.0;
+    }
+
+    // body
+    let t = (-b - sqrt(h)) / a;
+    let y = baoa + t * bard;
+    if y > 0.0 && y < baba {
+        return t;
+    }
+
+    // caps
+    let oc = select(ray.origin - pb, oa, y <= 0.0);
+    b = dot(ray.direction, oc);
+    c = dot(oc, oc) - radius * radius;
+    h = b * b - c;
+    if h > 0.0 {
+        return -b - sqrt(h);
+    }
+    return -1.0;
+}
+
+const CURVE_SEGMENTS = 8u;
+
+// the curve is flattened into a chain of capsules
+// good enough for thin strands where the tessellation is sub-pixel anyway
+fn intersect_curve(ray: Ray, curve: Curve) -> HitInfo {
+    var hit: HitInfo;
+    hit.distance = -1.0;
+    hit.front_face = true;
+
+    // the control polygon's hull bounds the curve
+    let max_radius = max(curve.radius_0, curve.radius_1);
+    let box_min = min(min(curve.control_0, curve.control_1), min(curve.control_2, curve.control_3)) - vec3f(max_radius);
+    let box_max = max(max(curve.control_0, curve.control_1), max(curve.control_2, curve.control_3)) + vec3f(max_radius);
+    if !intersect_aabb(ray, box_min, box_max) {
+        return hit;
+    }
+
+    var closest = FLOAT_MAX;
+    var seg_a = vec3f(0.0);
+    var seg_b = vec3f(0.0);
+    var seg_radius = 0.0;
+    var p0 = curve.control_0;
+    for (var i = 1u; i <= CURVE_SEGMENTS; i += 1u) {
+        let t1 = f32(i) / f32(CURVE_SEGMENTS);
+        let p1 = bezier_point(curve, t1);
+        let radius = mix(curve.radius_0, curve.radius_1, t1 - 0.5 / f32(CURVE_SEGMENTS));
+
+        let t = intersect_capsule(ray, p0, p1, radius);
+        if t >= EPSILON && t < closest {
+            closest = t;
+            seg_a = p0;
+            seg_b = p1;
+            seg_radius = radius;
+        }
+        p0 = p1;
+    }
+
+    if closest == FLOAT_MAX {
+        return hit;
+    }
+
+    hit.distance = closest;
+    hit.point = ray.origin + ray.direction * closest;
+    let ba = seg_b - seg_a;
+    let pa = hit.point - seg_a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    hit.normal = normalize(pa - h * ba);
+    hit.material_id = curve.material_id;
+    hit.light_mask = curve.light_mask;
+
+    return hit;
 }
 
-fn intersect_bvh(ray: Ray) -> HitInfo {
+const BVH_STACK_SIZE = 64u;
+
+// what the leaves of the tree at root hold
+const BVH_TRIANGLES = 0u;
+const BVH_SPHERES = 1u;
+const BVH_CURVES = 2u;
+const BVH_DISCS = 3u;
+
+fn intersect_bvh(ray: Ray, root: u32, primitives: u32) -> HitInfo {
     var hit: HitInfo;
     hit.distance = FLOAT_MAX;
-    var stack: array<u32, 64>;
+    var stack: array<u32, BVH_STACK_SIZE>;
     var stack_ptr = 1u;
-    stack[0] = 0;
+    stack[0] = root;
 
     while stack_ptr > 0u {
         stack_ptr -= 1u;
         let node_index = stack[stack_ptr];
-        let node = scene.bvh[node_index];
+        let node = bvh[node_index];
 
         if !intersect_aabb(ray, node.bbox_min, node.bbox_max) {
             continue;
         }
 
-        if node.triangle_count != 0u {
-            // leaf node: test all triangles
-            for (var i = 0u; i < node.triangle_count; i += 1u) {
-                let tri_id = node.triangle_ids[i];
-                let tri = scene.triangles[tri_id];
-                let h = intersect_triangle(ray, tri);
-                if h.distance >= EPSILON && h.distance < hit.distance {
-                    hit = h;
+        if node.primitive_count != 0u {
+            // leaf node: test all primitives
+            for (var i = 0u; i < node.primitive_count; i += 1u) {
+                let primitive_id = bvh_primitives[node.first_primitive + i];
+                if primitives == BVH_SPHERES {
+                    let h = intersect_sphere(ray, scene.spheres[primitive_id]);
+                    if h.distance >= EPSILON && h.distance < hit.distance {
+                        hit = h;
+                    }
+                } else if primitives == BVH_CURVES {
+                    let h = intersect_curve(ray, curves[primitive_id]);
+                    if h.distance >= EPSILON && h.distance < hit.distance {
+                        hit = h;
+                    }
+                } else if primitives == BVH_DISCS {
+                    let h = intersect_disc(ray, discs[primitive_id]);
+                    if h.distance >= EPSILON && h.distance < hit.distance {
+                        hit = h;
+                    }
+                } else {
+                    let h = intersect_triangle(ray, triangles[primitive_id]);
+                    if h.distance >= EPSILON && h.distance < hit.distance {
+                        hit = h;
+                        hit.triangle_id = primitive_id;
+                    }
                 }
             }
         } else {
             // internal node: push children
-            stack[stack_ptr] = node.child1;
-            stack_ptr += 1u;
-            if stack_ptr >= 64 {
-                return hit;
-            }
-
-            stack[stack_ptr] = node.child2;
-            stack_ptr += 1u;
-            if stack_ptr >= 64 {
-                return hit;
+            // the stack only runs out on trees deeper than any sah split makes, the
+            // far side is skipped then rather than reading past it
+            if stack_ptr + 2u > BVH_STACK_SIZE {
+                continue;
             }
+            stack[stack_ptr] = node.child1;
+            stack[stack_ptr + 1u] = node.child2;
+            stack_ptr += 2u;
         }
     }
 
@@ -403,24 +1319,62 @@ fn get_ray_collision(ray: Ray) -> HitInfo {
     var closest_hit: HitInfo;
     closest_hit.distance = FLOAT_MAX;
 
-    // sphere
-    for(var i = 0u; i < scene.sphere_count; i += 1u) {
-        let hit = intersect_sphere(ray, scene.spheres[i]);
-        if hit.distance >= EPSILON && hit.distance < closest_hit.distance {
-            closest_hit = hit;
+    // sphere, through their own bvh once there are enough of them
+    if scene.sphere_count < 16 {
+        for(var i = 0u; i < scene.sphere_count; i += 1u) {
+            let hit = intersect_sphere(ray, scene.spheres[i]);
+            if hit.distance >= EPSILON && hit.distance < closest_hit.distance {
+                closest_hit = hit;
+            }
+        }
+    } else {
+        let bvh_hit = intersect_bvh(ray, scene.sphere_bvh_root, BVH_SPHERES);
+        if bvh_hit.distance >= EPSILON && bvh_hit.distance < closest_hit.distance {
+            closest_hit = bvh_hit;
+        }
+    }
+
+    // curve, likewise
+    if scene.curve_count < 16 {
+        for(var i = 0u; i < scene.curve_count; i += 1u) {
+            let hit = intersect_curve(ray, curves[i]);
+            if hit.distance >= EPSILON && hit.distance < closest_hit.distance {
+                closest_hit = hit;
+            }
+        }
+    } else {
+        let bvh_hit = intersect_bvh(ray, scene.curve_bvh_root, BVH_CURVES);
+        if bvh_hit.distance >= EPSILON && bvh_hit.distance < closest_hit.distance {
+            closest_hit = bvh_hit;
+        }
+    }
+
+    // disc, likewise
+    if scene.disc_count < 16 {
+        for(var i = 0u; i < scene.disc_count; i += 1u) {
+            let hit = intersect_disc(ray, discs[i]);
+            if hit.distance >= EPSILON && hit.distance < closest_hit.distance {
+                closest_hit = hit;
+            }
+        }
+    } else {
+        let bvh_hit = intersect_bvh(ray, scene.disc_bvh_root, BVH_DISCS);
+        if bvh_hit.distance >= EPSILON && bvh_hit.distance < closest_hit.distance {
+            closest_hit = bvh_hit;
         }
     }
 
     // use linear search if tris count is low
     if scene.triangle_count < 16 {
         for(var i = 0u; i < scene.triangle_count; i += 1u) {
-            let hit = intersect_triangle(ray, scene.triangles[i]);
+            let hit = intersect_triangle(ray, triangles[i]);
             if hit.distance >= EPSILON && hit.distance < closest_hit.distance {
                 closest_hit = hit;
+                closest_hit.triangle_id = i;
             }
         }
     } else {
-        let bvh_hit = intersect_bvh(ray);
+        let bvh_hit = intersect_bvh(ray, 0u, BVH_TRIANGLES);
         if bvh_hit.distance >= EPSILON && bvh_hit.distance < closest_hit.distance {
             closest_hit = bvh_hit;
         }
@@ -432,19 +1386,1325 @@ fn get_ray_collision(ray: Ray) -> HitInfo {
     return closest_hit;
 }
 
-fn path_trace(ray_pos: vec4f) -> vec3f {
-    var incomming_light = vec3f(0.0);
-    var ray_color = vec3f(1.0);
+// schlick fresnel, or the thin film interference over a base whose ior is recovered
+// from f0 (metals become a dielectric with a high ior)
+fn specular_fresnel(cos_theta: f32, f0: vec3f, material: Material) -> vec3f {
+    if material.thin_film_thickness <= 0.0 {
+        return fresnel_schlick(cos_theta, f0);
+    }
+    let sqrt_f0 = sqrt(clamp(f0, vec3f(0.0), vec3f(0.99)));
+    let base_ior = (1.0 + sqrt_f0) / (1.0 - sqrt_f0);
+    return thin_film_fresnel(clamp(cos_theta, 0.0, 1.0), material.thin_film_ior, material.thin_film_thickness, base_ior);
+}
 
-    var ray = new_ray(ray_pos);
+// exact unpolarized fresnel reflectance of a conductor with ior eta + ik
+fn fresnel_conductor(cos_theta: f32, eta: vec3f, k: vec3f) -> vec3f {
+    let cos2 = clamp(cos_theta * cos_theta, 0.0, 1.0);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
 
-    var surrounding_volume_density = 0.0;
-    var surrounding_volume_radiance = vec3f(0.0);
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = sqrt(t0 * t0 + 4.0 * eta2 * k2);
+    let a = sqrt(max(0.5 * (a2_plus_b2 + t0), vec3f(0.0)));
 
-    // // check surrounding
-    // for(var i = 0u; i < scene.sphere_count; i += 1u) {
-    //     let sphere = scene.spheres[i];
-    //     let d = ray.origin - sphere.center;
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    return 0.5 * (rp + rs);
+}
+
+struct Scatter {
+    direction: vec3f,
+    // zero when the path is absorbed
+    throughput: vec3f,
+}
+
+// GGX specular over a diffuse base, picked by their estimated weight
+// sheen brightens the diffuse lobe towards grazing angles
+// basis is the shading frame from tangent_basis
+fn scatter_microfacet(direction: vec3f, basis: mat3x3f, albedo: vec3f, material: Material) -> Scatter {
+    var scatter: Scatter;
+    let normal = basis[2];
+    let v = -direction * basis;
+    let n_dot_v = max(v.z, 1e-4);
+    let alpha = anisotropic_alpha(material.roughness, material.anisotropy);
+    let f0 = mix(vec3f(0.08 * material.specular), albedo, material.metallic);
+    let fresnel_view = fresnel_schlick(n_dot_v, f0);
+    let specular_chance = mix(clamp(max(fresnel_view.x, max(fresnel_view.y, fresnel_view.z)), 0.1, 0.9), 1.0, material.metallic);
+
+    if rand() < specular_chance {
+        let h = sample_ggx_vndf(vec3f(v.x, v.y, n_dot_v), alpha, vec2f(rand(), rand()));
+        let l = reflect(-v, h);
+        if l.z <= 0.0 {
+            return scatter;
+        }
+        // F * G2 / G1(v) with separable masking-shadowing
+        scatter.throughput = specular_fresnel(dot(v, h), f0, material) * smith_g1(l, alpha) / specular_chance;
+        scatter.direction = basis * l;
+    } else {
+        scatter.direction = normalize(normal + (1.0 - EPSILON) * rand_sphere());
+        // sheen is not divided by pi, so against the cosine pdf it gains a factor of pi
+        let cos_d = clamp(dot(scatter.direction, normalize(scatter.direction - direction)), 0.0, 1.0);
+        let tint = albedo / max(dot(albedo, vec3f(0.2126, 0.7152, 0.0722)), 1e-4);
+        let sheen = mix(vec3f(1.0), tint, material.sheen_tint) * material.sheen * pow(1.0 - cos_d, 5.0);
+        scatter.throughput = ((1.0 - fresnel_view) * albedo + sheen * PI) * (1.0 - material.metallic) / (1.0 - specular_chance);
+    }
+
+    return scatter;
+}
+
+// GGX reflection only, tinted by albedo on top of the measured fresnel
+fn scatter_conductor(direction: vec3f, basis: mat3x3f, albedo: vec3f, material: Material) -> Scatter {
+    var scatter: Scatter;
+    let v = -direction * basis;
+    let alpha = anisotropic_alpha(material.roughness, material.anisotropy);
+
+    let h = sample_ggx_vndf(vec3f(v.x, v.y, max(v.z, 1e-4)), alpha, vec2f(rand(), rand()));
+    let l = reflect(-v, h);
+    if l.z <= 0.0 {
+        return scatter;
+    }
+    let fresnel = fresnel_conductor(dot(v, h), material.conductor_eta, material.conductor_k);
+    scatter.throughput = albedo * fresnel * smith_g1(l, alpha);
+    scatter.direction = basis * l;
+
+    return scatter;
+}
+
+// the clearcoat is a colorless ior 1.5 GGX layer that can sit on top of any surface,
+// it is picked with the probability of reflecting off it and the base takes the rest
+fn clearcoat_chance(direction: vec3f, normal: vec3f, material: Material) -> f32 {
+    return material.clearcoat * fresnel_schlick(dot(-direction, normal), vec3f(0.04)).x;
+}
+
+fn scatter_clearcoat(direction: vec3f, normal: vec3f, material: Material, chance: f32) -> Scatter {
+    var scatter: Scatter;
+    let basis = orthonormal_basis(normal);
+    let v = -direction * basis;
+    let alpha = vec2f(max(material.clearcoat_roughness * material.clearcoat_roughness, 1e-3));
+
+    let h = sample_ggx_vndf(vec3f(v.x, v.y, max(v.z, 1e-4)), alpha, vec2f(rand(), rand()));
+    let l = reflect(-v, h);
+    if l.z <= 0.0 {
+        return scatter;
+    }
+    let fresnel = material.clearcoat * fresnel_schlick(dot(v, h), vec3f(0.04));
+    scatter.throughput = fresnel * smith_g1(l, alpha) / chance;
+    scatter.direction = basis * l;
+
+    return scatter;
+}
+
+// disney style layering: rough glass weighted by transmission over the
+// metallic/specular/sheen microfacet base, the clearcoat is handled by the caller
+fn scatter_principled(direction: vec3f, basis: mat3x3f, front_face: bool, albedo: vec3f, material: Material) -> Scatter {
+    var scatter: Scatter;
+    let normal = basis[2];
+    let v = -direction * basis;
+    let n_dot_v = max(v.z, 1e-4);
+
+    if rand() < material.transmission * (1.0 - material.metallic) {
+        let alpha = anisotropic_alpha(material.roughness, material.anisotropy);
+        let h = basis * sample_ggx_vndf(vec3f(v.x, v.y, n_dot_v), alpha, vec2f(rand(), rand()));
+        let ior = select(material.ior, 1.0 / material.ior, front_face);
+        let cos_theta = abs(dot(direction, h));
+        let cannot_refract = ior * ior * (1.0 - cos_theta * cos_theta) > 1.0;
+
+        if cannot_refract || reflectance_schlick(cos_theta, ior) > rand() {
+            scatter.direction = reflect(direction, h);
+            scatter.throughput = select(vec3f(0.0), vec3f(1.0), dot(scatter.direction, normal) > 0.0);
+        } else {
+            scatter.direction = refract(direction, h, ior);
+            scatter.throughput = select(vec3f(0.0), albedo, dot(scatter.direction, normal) < 0.0);
+        }
+        return scatter;
+    }
+
+    return scatter_microfacet(direction, basis, albedo, material);
+}
+
+fn volume_voxel(volume: Volume, p: vec3u) -> f32 {
+    let res = volume.resolution;
+    return volume_data[volume.data_offset + (p.z * res.y + p.y) * res.x + p.x];
+}
+
+// trilinearly filtered density at a world space point inside the box
+fn volume_density(volume: Volume, point: vec3f) -> f32 {
+    let res = vec3f(volume.resolution);
+    let local = (point - volume.bbox_min) / (volume.bbox_max - volume.bbox_min) * res - 0.5;
+    let p = clamp(local, vec3f(0.0), res - 1.0);
+    let p0 = vec3u(floor(p));
+    let p1 = min(p0 + 1u, volume.resolution - 1u);
+    let f = p - floor(p);
+
+    let c00 = mix(volume_voxel(volume, p0), volume_voxel(volume, vec3u(p1.x, p0.y, p0.z)), f.x);
+    let c10 = mix(volume_voxel(volume, vec3u(p0.x, p1.y, p0.z)), volume_voxel(volume, vec3u(p1.x, p1.y, p0.z)), f.x);
+    let c01 = mix(volume_voxel(volume, vec3u(p0.x, p0.y, p1.z)), volume_voxel(volume, vec3u(p1.x, p0.y, p1.z)), f.x);
+    let c11 = mix(volume_voxel(volume, vec3u(p0.x, p1.y, p1.z)), volume_voxel(volume, p1), f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+const VOLUME_MAX_STEPS = 256;
+
+// delta tracking, the distance of the first real collision before t_max or -1
+fn sample_volume_collision(ray: Ray, volume: Volume, t_max: f32) -> f32 {
+    let range = aabb_range(ray, volume.bbox_min, volume.bbox_max);
+    let majorant = volume.max_density * scene.materials[volume.material_id].volume_density;
+    let t_end = min(range.y, t_max);
+    if range.x > t_end || t_end < 0.0 || majorant <= 0.0 {
+        return -1.0;
+    }
+
+    var t = max(range.x, 0.0);
+    for (var step = 0; step < VOLUME_MAX_STEPS; step += 1) {
+        t -= log(1.0 - rand()) / majorant;
+        if t >= t_end {
+            break;
+        }
+        let density = volume_density(volume, ray.origin + ray.direction * t) * scene.materials[volume.material_id].volume_density;
+        if rand() * majorant < density {
+            return t;
+        }
+    }
+    return -1.0;
+}
+
+// the global fog is analytic, its optical depth has a closed form so shadow rays are
+// noise free and free flight distances are sampled exactly
+// rays are cut off at FOG_MAX_DISTANCE so uniform fog still lets the sky through
+const FOG_MAX_DISTANCE = 1e5;
+
+fn fog_enabled() -> bool {
+    return scene.fog.density > 0.0;
+}
+
+// where ray crosses the fog height, the two pieces either side have a simple density
+fn fog_split(ray: Ray, t_max: f32) -> f32 {
+    if abs(ray.direction.y) < 1e-8 {
+        return t_max;
+    }
+    let t = (scene.fog.height - ray.origin.y) / ray.direction.y;
+    return select(t_max, clamp(t, 0.0, t_max), t > 0.0);
+}
+
+// density at the start of a piece from t_start over length and how fast it falls off
+// along the ray, it only changes above the fog height where it falls off exponentially
+fn fog_piece(ray: Ray, t_start: f32, length: f32) -> vec2f {
+    let y = ray.origin.y + ray.direction.y * t_start;
+    let above = ray.origin.y + ray.direction.y * (t_start + 0.5 * length) > scene.fog.height;
+    let density = scene.fog.density * exp(-scene.fog.falloff * max(y - scene.fog.height, 0.0));
+    return vec2f(density, select(0.0, scene.fog.falloff * ray.direction.y, above));
+}
+
+fn fog_piece_depth(ray: Ray, t_start: f32, length: f32) -> f32 {
+    let piece = fog_piece(ray, t_start, length);
+    if abs(piece.y * length) < 1e-4 {
+        return piece.x * length;
+    }
+    return piece.x * (1.0 - exp(-piece.y * length)) / piece.y;
+}
+
+// inverse of fog_piece_depth, depth has to be less than the depth of the whole piece
+fn fog_piece_distance(ray: Ray, t_start: f32, length: f32, depth: f32) -> f32 {
+    let piece = fog_piece(ray, t_start, length);
+    if abs(piece.y * depth / piece.x) < 1e-4 {
+        return depth / piece.x;
+    }
+    return -log(1.0 - depth * piece.y / piece.x) / piece.y;
+}
+
+fn fog_transmittance(ray: Ray, t_max: f32) -> f32 {
+    if !fog_enabled() {
+        return 1.0;
+    }
+    let t_end = min(t_max, FOG_MAX_DISTANCE);
+    let split = fog_split(ray, t_end);
+    let depth = fog_piece_depth(ray, 0.0, split) + fog_piece_depth(ray, split, t_end - split);
+    return exp(-depth);
+}
+
+// distance to where the ray scatters in the fog, -1 when it gets through to t_max
+fn sample_fog_collision(ray: Ray, t_max: f32) -> f32 {
+    let t_end = min(t_max, FOG_MAX_DISTANCE);
+    let split = fog_split(ray, t_end);
+    var depth = -log(max(1.0 - rand(), 1e-8));
+
+    let first = fog_piece_depth(ray, 0.0, split);
+    if depth < first {
+        return fog_piece_distance(ray, 0.0, split, depth);
+    }
+    depth -= first;
+    if depth < fog_piece_depth(ray, split, t_end - split) {
+        return split + fog_piece_distance(ray, split, t_end - split, depth);
+    }
+    return -1.0;
+}
+
+// density of scattering from travelling along one direction into another at cos_theta,
+// positive g scatters forward
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+fn sample_henyey_greenstein(direction: vec3f, g: f32) -> vec3f {
+    var cos_theta = 1.0 - 2.0 * rand();
+    if abs(g) >= 1e-3 {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * rand());
+        cos_theta = (1.0 + g * g - s * s) / (2.0 * g);
+    }
+    let sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+    let phi = 2.0 * PI * rand();
+    return orthonormal_basis(direction) * vec3f(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// ratio tracking estimate of the transmittance through every grid and the fog up to t_max,
+// meant for shadow rays that only need to know how much light gets through
+fn volume_transmittance(ray: Ray, t_max: f32) -> f32 {
+    var transmittance = fog_transmittance(ray, t_max);
+    for (var i = 0u; i < scene.volume_count; i += 1u) {
+        let volume = scene.volumes[i];
+        let range = aabb_range(ray, volume.bbox_min, volume.bbox_max);
+        let scale = scene.materials[volume.material_id].volume_density;
+        let majorant = volume.max_density * scale;
+        let t_end = min(range.y, t_max);
+        if range.x > t_end || t_end < 0.0 || majorant <= 0.0 {
+            continue;
+        }
+
+        var t = max(range.x, 0.0);
+        for (var step = 0; step < VOLUME_MAX_STEPS; step += 1) {
+            t -= log(1.0 - rand()) / majorant;
+            if t >= t_end {
+                break;
+            }
+            let density = volume_density(volume, ray.origin + ray.direction * t) * scale;
+            transmittance *= 1.0 - density / majorant;
+        }
+    }
+    return transmittance;
+}
+
+// the single scattering albedo and extinction that make a random walk come out with
+// the requested diffuse color and scattering distance (Chiang et al. 2016)
+fn subsurface_albedo(color: vec3f) -> vec3f {
+    let a = clamp(color, vec3f(0.0), vec3f(0.999));
+    return 1.0 - exp(a * (-5.09406 + a * (2.61188 - a * 4.31805)));
+}
+
+fn subsurface_extinction(color: vec3f, radius: vec3f) -> vec3f {
+    let a = clamp(color, vec3f(0.0), vec3f(0.999));
+    let s = 1.9 - a + 3.5 * (a - 0.8) * (a - 0.8);
+    return 1.0 / max(radius * s, vec3f(1e-4));
+}
+
+// solid angle density of picking direction through the portals, portals are picked
+// uniformly and then sampled uniformly by area
+fn portal_pdf(origin: vec3f, direction: vec3f) -> f32 {
+    var pdf = 0.0;
+    for (var i = 0u; i < scene.portal_count; i += 1u) {
+        let portal = scene.portals[i];
+        let normal = cross(portal.edge_u, portal.edge_v);
+        let area = length(normal);
+        let denominator = dot(direction, normal);
+        if is_equal_zero(denominator) || area <= 0.0 {
+            continue;
+        }
+
+        let t = dot(portal.corner - origin, normal) / denominator;
+        if t <= 0.0 {
+            continue;
+        }
+        // barycentric like coordinates of the hit in the portal's edges
+        let p = origin + direction * t - portal.corner;
+        let u = dot(cross(p, portal.edge_v), normal) / (area * area);
+        let v = dot(cross(portal.edge_u, p), normal) / (area * area);
+        if u < 0.0 || u > 1.0 || v < 0.0 || v > 1.0 {
+            continue;
+        }
+
+        let cos_portal = abs(denominator) / area;
+        pdf += t * t / (cos_portal * area);
+    }
+    return pdf / f32(max(scene.portal_count, 1u));
+}
+
+// first index in the count long cdf starting at offset whose value exceeds u
+fn search_cdf(offset: u32, count: u32, u: f32) -> u32 {
+    var low = 0u;
+    var high = count - 1u;
+    while low < high {
+        let middle = (low + high) / 2u;
+        if environment_cdf[offset + middle] <= u {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+fn cdf_step(offset: u32, index: u32) -> f32 {
+    let previous = select(0.0, environment_cdf[offset + index - 1u], index > 0u);
+    return environment_cdf[offset + index] - previous;
+}
+
+// picks an environment texel by its brightness, then a uniform point inside it
+fn sample_environment_direction() -> vec3f {
+    let size = textureDimensions(environment);
+    let y = search_cdf(0u, size.y, rand());
+    let x = search_cdf(size.y + y * size.x, size.x, rand());
+
+    let uv = (vec2f(f32(x), f32(y)) + vec2f(rand(), rand())) / vec2f(size);
+    let phi = (uv.x - 0.5) * 2.0 * PI;
+    let theta = uv.y * PI;
+    return rotate_environment(vec3f(sin(theta) * cos(phi), cos(theta), sin(theta) * sin(phi)), 1.0);
+}
+
+// solid angle density of sample_environment_direction
+fn environment_pdf(direction: vec3f) -> f32 {
+    let size = textureDimensions(environment);
+    let d = rotate_environment(normalize(direction), -1.0);
+    let uv = vec2f(
+        0.5 + atan2(d.z, d.x) / (2.0 * PI),
+        acos(clamp(d.y, -1.0, 1.0)) / PI,
+    );
+    let texel = min(vec2u(uv * vec2f(size)), size - 1u);
+    let probability = cdf_step(0u, texel.y) * cdf_step(size.y + texel.y * size.x, texel.x);
+
+    let sin_theta = sqrt(max(1.0 - d.y * d.y, 0.0));
+    if sin_theta <= 0.0 {
+        return 0.0;
+    }
+    return probability * f32(size.x * size.y) / (2.0 * PI * PI * sin_theta);
+}
+
+// the environment is sampled through the portals when there are any, otherwise
+// by the brightness of the environment map, the procedural sky is not sampled
+fn environment_light_pdf(origin: vec3f, direction: vec3f) -> f32 {
+    if scene.portal_count > 0u {
+        return portal_pdf(origin, direction);
+    }
+    if environment_map_enabled() {
+        return environment_pdf(direction);
+    }
+    return 0.0;
+}
+
+fn environment_light_enabled() -> bool {
+    return scene.portal_count > 0u || environment_map_enabled();
+}
+
+// a direction towards a light and the radiance arriving along it, zero when shadowed
+// pdf is the solid angle density of having picked it, 0 for point lights which bsdf
+// sampling can never hit, their radiance is the irradiance they deliver instead
+// samplers take a zero normal for points inside a medium
+struct LightSample {
+    direction: vec3f,
+    radiance: vec3f,
+    pdf: f32,
+}
+
+// next event estimation of the environment, the light is only seen when nothing at all
+// is in the way
+fn sample_environment_light(point: vec3f, normal: vec3f) -> LightSample {
+    var sample: LightSample;
+    var direction: vec3f;
+    if scene.portal_count > 0u {
+        let index = min(u32(rand() * f32(scene.portal_count)), scene.portal_count - 1u);
+        let portal = scene.portals[index];
+        let target_point = portal.corner + portal.edge_u * rand() + portal.edge_v * rand();
+        direction = normalize(target_point - point);
+    } else {
+        direction = sample_environment_direction();
+    }
+
+    let light_pdf = environment_light_pdf(point, direction);
+    if dot(normal, direction) < 0.0 || light_pdf <= 0.0 {
+        return sample;
+    }
+
+    let shadow_ray = Ray(point + normal * EPSILON, direction);
+    if get_ray_collision(shadow_ray).distance >= EPSILON {
+        return sample;
+    }
+
+    sample.direction = direction;
+    sample.radiance = sky_color(shadow_ray) * volume_transmittance(shadow_ray, FLOAT_MAX);
+    sample.pdf = light_pdf;
+    return sample;
+}
+
+fn sun_enabled() -> bool {
+    return any(scene.sun.irradiance > vec3f(0.0));
+}
+
+fn sun_cos_max() -> f32 {
+    return cos(0.5 * scene.sun.angular_diameter);
+}
+
+// solid angle of the sun disc, kept above 0 so a point like sun stays finite
+fn sun_solid_angle() -> f32 {
+    return max(2.0 * PI * (1.0 - sun_cos_max()), 1e-7);
+}
+
+// the irradiance spread evenly over the disc
+fn sun_radiance(direction: vec3f) -> vec3f {
+    if dot(normalize(direction), normalize(scene.sun.direction)) < sun_cos_max() {
+        return vec3f(0.0);
+    }
+    return scene.sun.irradiance / sun_solid_angle();
+}
+
+// like sample_environment_light but for a uniformly sampled direction inside the sun disc
+fn sample_sun_light(point: vec3f, normal: vec3f) -> LightSample {
+    var sample: LightSample;
+    let cos_theta = 1.0 - rand() * (1.0 - sun_cos_max());
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * rand();
+    let direction = normalize(
+        orthonormal_basis(normalize(scene.sun.direction))
+        * vec3f(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta)
+    );
+
+    if dot(normal, direction) < 0.0 {
+        return sample;
+    }
+
+    let shadow_ray = Ray(point + normal * EPSILON, direction);
+    if get_ray_collision(shadow_ray).distance >= EPSILON {
+        return sample;
+    }
+
+    sample.direction = direction;
+    sample.radiance = scene.sun.irradiance / sun_solid_angle() * volume_transmittance(shadow_ray, FLOAT_MAX);
+    sample.pdf = 1.0 / sun_solid_angle();
+    return sample;
+}
+
+// spot cone falloff towards a direction leaving the light
+fn spot_falloff(light: PointLight, direction: vec3f) -> f32 {
+    if light.outer_angle >= PI {
+        return 1.0;
+    }
+    let cos_angle = dot(direction, normalize(light.direction));
+    return smoothstep(cos(light.outer_angle), cos(min(light.inner_angle, light.outer_angle)), cos_angle);
+}
+
+// measured intensity towards a direction leaving the light relative to its peak,
+// vertical angles are measured from the light direction
+const IES_RESOLUTION = 64u;
+fn ies_intensity(light: PointLight, direction: vec3f) -> f32 {
+    if light.ies_profile == 0u {
+        return 1.0;
+    }
+    let local = transpose(orthonormal_basis(normalize(light.direction))) * direction;
+    let vertical = acos(clamp(local.z, -1.0, 1.0)) / PI * f32(IES_RESOLUTION - 1u);
+    let horizontal = fract(atan2(local.y, local.x) / (2.0 * PI) + 1.0) * f32(IES_RESOLUTION);
+
+    let v0 = min(u32(vertical), IES_RESOLUTION - 1u);
+    let v1 = min(v0 + 1u, IES_RESOLUTION - 1u);
+    let h0 = min(u32(horizontal), IES_RESOLUTION - 1u);
+    let h1 = (h0 + 1u) % IES_RESOLUTION;
+    let tv = vertical - f32(v0);
+    let th = horizontal - f32(h0);
+
+    let base = (light.ies_profile - 1u) * IES_RESOLUTION * IES_RESOLUTION;
+    let a = mix(ies_data[base + h0 * IES_RESOLUTION + v0], ies_data[base + h0 * IES_RESOLUTION + v1], tv);
+    let b = mix(ies_data[base + h1 * IES_RESOLUTION + v0], ies_data[base + h1 * IES_RESOLUTION + v1], tv);
+    return mix(a, b, th);
+}
+
+// direct light from one random point light, scaled by the light count
+// rays can never hit them, so perfectly specular surfaces do not see point lights
+fn sample_point_light(point: vec3f, normal: vec3f, light_mask: u32) -> LightSample {
+    var sample: LightSample;
+    let count = scene.point_light_count;
+    let light = scene.point_lights[min(u32(rand() * f32(count)), count - 1u)];
+    if !light_linked(light.light_mask, light_mask) {
+        return sample;
+    }
+
+    let to_light = light.position - point;
+    let distance = length(to_light);
+    let direction = to_light / distance;
+    if dot(normal, direction) < 0.0 {
+        return sample;
+    }
+
+    let shadow_ray = Ray(point + normal * EPSILON, direction);
+    let blocker = get_ray_collision(shadow_ray);
+    if blocker.distance >= EPSILON && blocker.distance < distance {
+        return sample;
+    }
+
+    let transmittance = volume_transmittance(shadow_ray, distance);
+    sample.direction = direction;
+    let falloff = spot_falloff(light, -direction) * ies_intensity(light, -direction);
+    sample.radiance = light.intensity * falloff / (distance * distance) * f32(count) * transmittance;
+    return sample;
+}
+
+// an area light as seen from a point, the rectangle is projected onto the unit sphere
+// around it and sampled uniformly by solid angle
+// Urena et al. 2013, "An Area-Preserving Parametrization for Spherical Rectangles"
+struct SphericalRectangle {
+    origin: vec3f,
+    x: vec3f,
+    y: vec3f,
+    z: vec3f,
+    x0: f32,
+    y0: f32,
+    z0: f32,
+    x1: f32,
+    y1: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    solid_angle: f32,
+}
+
+fn spherical_rectangle(light: AreaLight, origin: vec3f) -> SphericalRectangle {
+    var rect: SphericalRectangle;
+    rect.origin = origin;
+    let width = length(light.edge_u);
+    let height = length(light.edge_v);
+    rect.x = light.edge_u / width;
+    rect.y = light.edge_v / height;
+    rect.z = cross(rect.x, rect.y);
+
+    let d = light.corner - origin;
+    rect.z0 = dot(d, rect.z);
+    // local frame with the rectangle below the origin
+    if rect.z0 > 0.0 {
+        rect.z = -rect.z;
+        rect.z0 = -rect.z0;
+    }
+    rect.x0 = dot(d, rect.x);
+    rect.y0 = dot(d, rect.y);
+    rect.x1 = rect.x0 + width;
+    rect.y1 = rect.y0 + height;
+
+    let v00 = vec3f(rect.x0, rect.y0, rect.z0);
+    let v01 = vec3f(rect.x0, rect.y1, rect.z0);
+    let v10 = vec3f(rect.x1, rect.y0, rect.z0);
+    let v11 = vec3f(rect.x1, rect.y1, rect.z0);
+    let n0 = normalize(cross(v00, v10));
+    let n1 = normalize(cross(v10, v11));
+    let n2 = normalize(cross(v11, v01));
+    let n3 = normalize(cross(v01, v00));
+
+    let g0 = acos(clamp(-dot(n0, n1), -1.0, 1.0));
+    let g1 = acos(clamp(-dot(n1, n2), -1.0, 1.0));
+    let g2 = acos(clamp(-dot(n2, n3), -1.0, 1.0));
+    let g3 = acos(clamp(-dot(n3, n0), -1.0, 1.0));
+    rect.b0 = n0.z;
+    rect.b1 = n2.z;
+    rect.k = 2.0 * PI - g2 - g3;
+    rect.solid_angle = g0 + g1 - rect.k;
+    return rect;
+}
+
+fn sample_spherical_rectangle(rect: SphericalRectangle, u: vec2f) -> vec3f {
+    let au = u.x * rect.solid_angle + rect.k;
+    let fu = (cos(au) * rect.b0 - rect.b1) / sin(au);
+    let cu = clamp(select(-1.0, 1.0, fu > 0.0) / sqrt(fu * fu + rect.b0 * rect.b0), -1.0, 1.0);
+    let xu = clamp(-(cu * rect.z0) / sqrt(max(1.0 - cu * cu, 1e-12)), rect.x0, rect.x1);
+
+    let d = sqrt(xu * xu + rect.z0 * rect.z0);
+    let h0 = rect.y0 / sqrt(d * d + rect.y0 * rect.y0);
+    let h1 = rect.y1 / sqrt(d * d + rect.y1 * rect.y1);
+    let hv = h0 + u.y * (h1 - h0);
+    let yv = select(rect.y1, hv * d / sqrt(1.0 - hv * hv), hv * hv < 1.0 - 1e-6);
+
+    return rect.origin + xu * rect.x + yv * rect.y + rect.z0 * rect.z;
+}
+
+// distance to the nearest area light lit from the ray's side, FLOAT_MAX when there is none
+// closer than t_max, the light is written to index
+fn intersect_area_lights(ray: Ray, t_max: f32, index: ptr<function, u32>) -> f32 {
+    var nearest = t_max;
+    var found = false;
+    for (var i = 0u; i < scene.area_light_count; i += 1u) {
+        let light = scene.area_lights[i];
+        let normal = cross(light.edge_u, light.edge_v);
+        let area = length(normal);
+        let denominator = dot(ray.direction, normal);
+        if denominator >= 0.0 || area <= 0.0 {
+            continue;
+        }
+
+        let t = dot(light.corner - ray.origin, normal) / denominator;
+        if t <= EPSILON || t >= nearest {
+            continue;
+        }
+        let p = ray.origin + ray.direction * t - light.corner;
+        let u = dot(cross(p, light.edge_v), normal) / (area * area);
+        let v = dot(cross(light.edge_u, p), normal) / (area * area);
+        if u < 0.0 || u > 1.0 || v < 0.0 || v > 1.0 {
+            continue;
+        }
+
+        nearest = t;
+        *index = i;
+        found = true;
+    }
+    return select(FLOAT_MAX, nearest, found);
+}
+
+// like sample_environment_light, for one random area light scaled by the light count
+fn sample_area_light(point: vec3f, normal: vec3f, light_mask: u32) -> LightSample {
+    var sample: LightSample;
+    let count = scene.area_light_count;
+    let light = scene.area_lights[min(u32(rand() * f32(count)), count - 1u)];
+    if !light_linked(light.light_mask, light_mask) {
+        return sample;
+    }
+    let light_normal = normalize(cross(light.edge_u, light.edge_v));
+    if dot(point - light.corner, light_normal) <= 0.0 {
+        return sample;
+    }
+
+    let rect = spherical_rectangle(light, point);
+    if rect.solid_angle <= 1e-7 {
+        return sample;
+    }
+    let to_light = sample_spherical_rectangle(rect, vec2f(rand(), rand())) - point;
+    let distance = length(to_light);
+    let direction = to_light / distance;
+    if dot(normal, direction) < 0.0 {
+        return sample;
+    }
+
+    let shadow_ray = Ray(point + normal * EPSILON, direction);
+    let blocker = get_ray_collision(shadow_ray);
+    if blocker.distance >= EPSILON && blocker.distance < distance {
+        return sample;
+    }
+
+    sample.direction = direction;
+    sample.radiance = light.emission * volume_transmittance(shadow_ray, distance);
+    sample.pdf = 1.0 / (rect.solid_angle * f32(count));
+    return sample;
+}
+
+// rough guess of the light a node sends to point, its power over the squared distance
+// kept from blowing up for points inside or right next to the node
+fn light_tree_importance(node: LightTreeNode, point: vec3f) -> f32 {
+    let to_center = 0.5 * (node.bbox_min + node.bbox_max) - point;
+    let extent = node.bbox_max - node.bbox_min;
+    return node.power / max(dot(to_center, to_center), 0.25 * dot(extent, extent) + 1e-6);
+}
+
+// probability of going down to the first child of an inner node
+fn light_tree_split(index: u32, point: vec3f) -> f32 {
+    let first = light_tree_importance(light_tree[index + 1u], point);
+    let second = light_tree_importance(light_tree[light_tree[index].child_or_triangle], point);
+    if first + second <= 0.0 {
+        return 0.5;
+    }
+    return first / (first + second);
+}
+
+// picks a glowing triangle for point, the probability of the pick goes to pdf
+fn sample_light_tree(point: vec3f, pdf: ptr<function, f32>) -> u32 {
+    var index = 0u;
+    *pdf = 1.0;
+    while (light_tree[index].child_or_triangle & LIGHT_TREE_LEAF) == 0u {
+        let split = light_tree_split(index, point);
+        if rand() < split {
+            *pdf *= split;
+            index += 1u;
+        } else {
+            *pdf *= 1.0 - split;
+            index = light_tree[index].child_or_triangle;
+        }
+    }
+    return light_tree[index].child_or_triangle & ~LIGHT_TREE_LEAF;
+}
+
+const LIGHT_TREE_STACK_SIZE = 32u;
+
+// probability of sample_light_tree picking the triangle containing light_point, only
+// nodes whose box holds the point can lead to it but boxes overlap, so this is a search
+fn light_tree_pdf(point: vec3f, triangle_id: u32, light_point: vec3f) -> f32 {
+    var stack_index: array<u32, LIGHT_TREE_STACK_SIZE>;
+    var stack_pdf: array<f32, LIGHT_TREE_STACK_SIZE>;
+    var stack_ptr = 1u;
+    stack_index[0] = 0u;
+    stack_pdf[0] = 1.0;
+
+    while stack_ptr > 0u {
+        stack_ptr -= 1u;
+        let index = stack_index[stack_ptr];
+        let pdf = stack_pdf[stack_ptr];
+        let node = light_tree[index];
+        if any(light_point < node.bbox_min - EPSILON) || any(light_point > node.bbox_max + EPSILON) {
+            continue;
+        }
+
+        if (node.child_or_triangle & LIGHT_TREE_LEAF) != 0u {
+            if (node.child_or_triangle & ~LIGHT_TREE_LEAF) == triangle_id {
+                return pdf;
+            }
+            continue;
+        }
+        if stack_ptr + 2u > LIGHT_TREE_STACK_SIZE {
+            break;
+        }
+
+        let split = light_tree_split(index, point);
+        stack_index[stack_ptr] = index + 1u;
+        stack_pdf[stack_ptr] = pdf * split;
+        stack_index[stack_ptr + 1u] = node.child_or_triangle;
+        stack_pdf[stack_ptr + 1u] = pdf * (1.0 - split);
+        stack_ptr += 2u;
+    }
+    return 0.0;
+}
+
+// solid angle density of sample_emissive_triangle from point reaching light_point on a
+// triangle with the cosine cos_light
+fn emissive_triangle_pdf(point: vec3f, triangle_id: u32, light_point: vec3f, cos_light: f32) -> f32 {
+    let tri = triangles[triangle_id];
+    let area = 0.5 * length(cross(tri.vertex_1 - tri.vertex_0, tri.vertex_2 - tri.vertex_0));
+    if cos_light <= 0.0 || area <= 0.0 {
+        return 0.0;
+    }
+    let to_light = light_point - point;
+    return light_tree_pdf(point, triangle_id, light_point) / area * dot(to_light, to_light) / cos_light;
+}
+
+// like sample_environment_light, for a point on a glowing triangle picked by the light tree
+fn sample_emissive_triangle(point: vec3f, normal: vec3f) -> LightSample {
+    var sample: LightSample;
+    var pick_pdf: f32;
+    let triangle_id = sample_light_tree(point, &pick_pdf);
+    let tri = triangles[triangle_id];
+
+    // uniform point on the triangle
+    let r1 = sqrt(rand());
+    let r2 = rand();
+    let light_point = tri.vertex_0 * (1.0 - r1) + tri.vertex_1 * r1 * (1.0 - r2) + tri.vertex_2 * r1 * r2;
+    let to_light = light_point - point;
+    let distance = length(to_light);
+    let direction = to_light / distance;
+
+    let light_cross = cross(tri.vertex_1 - tri.vertex_0, tri.vertex_2 - tri.vertex_0);
+    let area = 0.5 * length(light_cross);
+    let cos_light = abs(dot(direction, normalize(light_cross)));
+    if dot(normal, direction) < 0.0 || cos_light <= 0.0 || area <= 0.0 || pick_pdf <= 0.0 {
+        return sample;
+    }
+
+    // the shadow ray has to reach the light itself, its emission is read from that hit
+    // so textures and vertex colors count
+    let shadow_ray = Ray(point + normal * EPSILON, direction);
+    let light_hit = get_ray_collision(shadow_ray);
+    if light_hit.distance < EPSILON || abs(light_hit.distance - distance) > distance * 1e-3 + EPSILON {
+        return sample;
+    }
+    let material = scene.materials[light_hit.material_id];
+    if !light_hit.front_face && (material.flags & MATERIAL_SINGLE_SIDED) != 0u {
+        return sample;
+    }
+    let uv = light_hit.uv * material.uv_scale + material.uv_offset;
+    var emission = surface_albedo(light_hit, material, uv) * material.emission_strength;
+    if material.emission_texture != 0 {
+        emission = sample_texture(material.emission_texture, uv).rgb * material.emission_strength;
+    }
+
+    sample.direction = direction;
+    sample.radiance = emission * volume_transmittance(shadow_ray, distance);
+    sample.pdf = pick_pdf / area * distance * distance / cos_light;
+    return sample;
+}
+
+// f * cos of a bsdf towards a light and the density path_trace samples that direction with
+struct BsdfEval {
+    value: vec3f,
+    pdf: f32,
+}
+
+// GGX normal distribution for a tangent space half vector
+fn ggx_d(h: vec3f, alpha: vec2f) -> f32 {
+    let scaled = vec3f(h.x / alpha.x, h.y / alpha.y, h.z);
+    let denominator = dot(scaled, scaled);
+    return 1.0 / (PI * alpha.x * alpha.y * denominator * denominator);
+}
+
+// scatter_microfacet evaluated for a tangent space pair of directions
+fn eval_microfacet(v: vec3f, l: vec3f, albedo: vec3f, material: Material) -> BsdfEval {
+    var eval: BsdfEval;
+    let n_dot_v = max(v.z, 1e-4);
+    let alpha = anisotropic_alpha(material.roughness, material.anisotropy);
+    let f0 = mix(vec3f(0.08 * material.specular), albedo, material.metallic);
+    let fresnel_view = fresnel_schlick(n_dot_v, f0);
+    let specular_chance = mix(clamp(max(fresnel_view.x, max(fresnel_view.y, fresnel_view.z)), 0.1, 0.9), 1.0, material.metallic);
+
+    let h = normalize(v + l);
+    let g1_v = smith_g1(vec3f(v.x, v.y, n_dot_v), alpha);
+    let specular_pdf = g1_v * ggx_d(h, alpha) / (4.0 * n_dot_v);
+    let specular = specular_fresnel(dot(v, h), f0, material) * specular_pdf * smith_g1(l, alpha);
+
+    let cos_d = clamp(dot(l, h), 0.0, 1.0);
+    let tint = albedo / max(dot(albedo, vec3f(0.2126, 0.7152, 0.0722)), 1e-4);
+    let sheen = mix(vec3f(1.0), tint, material.sheen_tint) * material.sheen * pow(1.0 - cos_d, 5.0);
+    let diffuse = ((1.0 - fresnel_view) * albedo / PI + sheen) * (1.0 - material.metallic) * l.z;
+
+    eval.value = specular + diffuse;
+    eval.pdf = specular_chance * specular_pdf + (1.0 - specular_chance) * l.z / PI;
+    return eval;
+}
+
+// scatter_conductor evaluated for a tangent space pair of directions
+fn eval_conductor(v: vec3f, l: vec3f, albedo: vec3f, material: Material) -> BsdfEval {
+    var eval: BsdfEval;
+    let n_dot_v = max(v.z, 1e-4);
+    let alpha = anisotropic_alpha(material.roughness, material.anisotropy);
+    let h = normalize(v + l);
+    eval.pdf = smith_g1(vec3f(v.x, v.y, n_dot_v), alpha) * ggx_d(h, alpha) / (4.0 * n_dot_v);
+    eval.value = albedo * fresnel_conductor(dot(v, h), material.conductor_eta, material.conductor_k)
+        * eval.pdf * smith_g1(l, alpha);
+    return eval;
+}
+
+// surfaces whose whole bsdf eval_bsdf knows, the others only scatter
+fn light_sampled(material: Material) -> bool {
+    return material.material_type == MATERIAL_DIFFUSE
+        || material.material_type == MATERIAL_PBR
+        || material.material_type == MATERIAL_CONDUCTOR
+        || (material.material_type == MATERIAL_PRINCIPLED && material.transmission * (1.0 - material.metallic) <= 0.0);
+}
+
+// direction is the incoming ray, l points away from the surface, basis is the shading frame
+fn eval_bsdf(direction: vec3f, basis: mat3x3f, albedo: vec3f, material: Material, l_world: vec3f) -> BsdfEval {
+    var eval: BsdfEval;
+    let v = -direction * basis;
+    let l = l_world * basis;
+    if l.z <= 0.0 {
+        return eval;
+    }
+
+    if material.material_type == MATERIAL_DIFFUSE {
+        eval.value = albedo * l.z / PI;
+        eval.pdf = l.z / PI;
+    } else if material.material_type == MATERIAL_CONDUCTOR {
+        eval = eval_conductor(v, l, albedo, material);
+    } else if material.material_type == MATERIAL_PBR || material.material_type == MATERIAL_PRINCIPLED {
+        eval = eval_microfacet(v, l, albedo, material);
+    }
+    return eval;
+}
+
+// one light sample through the bsdf, weighted against bsdf sampling with the power heuristic
+fn light_contribution(sample: LightSample, direction: vec3f, basis: mat3x3f, albedo: vec3f, material: Material) -> vec3f {
+    if all(sample.radiance == vec3f(0.0)) {
+        return vec3f(0.0);
+    }
+    let bsdf = eval_bsdf(direction, basis, albedo, material, sample.direction);
+    if sample.pdf <= 0.0 {
+        return sample.radiance * bsdf.value;
+    }
+    let weight = sample.pdf * sample.pdf / (sample.pdf * sample.pdf + bsdf.pdf * bsdf.pdf);
+    return sample.radiance * bsdf.value / sample.pdf * weight;
+}
+
+// like light_contribution but for scattering in the fog
+fn fog_light_contribution(sample: LightSample, direction: vec3f) -> vec3f {
+    if all(sample.radiance == vec3f(0.0)) {
+        return vec3f(0.0);
+    }
+    let phase = henyey_greenstein(dot(direction, sample.direction), scene.fog.anisotropy);
+    if sample.pdf <= 0.0 {
+        return sample.radiance * phase;
+    }
+    let weight = sample.pdf * sample.pdf / (sample.pdf * sample.pdf + phase * phase);
+    return sample.radiance * phase / sample.pdf * weight;
+}
+
+// next event estimation from a point in the fog, light linking treats fog as a default surface
+fn fog_direct_light(point: vec3f, direction: vec3f) -> vec3f {
+    let normal = vec3f(0.0);
+    var light = vec3f(0.0);
+    if environment_light_enabled() {
+        light += fog_light_contribution(sample_environment_light(point, normal), direction);
+    }
+    if sun_enabled() && light_linked(scene.sun.light_mask, 0u) {
+        light += fog_light_contribution(sample_sun_light(point, normal), direction);
+    }
+    if scene.point_light_count > 0u {
+        light += fog_light_contribution(sample_point_light(point, normal, 0u), direction);
+    }
+    if scene.area_light_count > 0u {
+        light += fog_light_contribution(sample_area_light(point, normal, 0u), direction);
+    }
+    if scene.emissive_triangle_count > 0u {
+        light += fog_light_contribution(sample_emissive_triangle(point, normal), direction);
+    }
+    return light;
+}
+
+// light linking, a zero mask means the default set in bit 0
+fn light_linked(light_mask: u32, surface_mask: u32) -> bool {
+    return (select(light_mask, 1u, light_mask == 0u) & select(surface_mask, 1u, surface_mask == 0u)) != 0u;
+}
+
+// next event estimation of every kind of light at a surface, light_mask is the surface's
+fn direct_light(
+    direction: vec3f,
+    basis: mat3x3f,
+    albedo: vec3f,
+    material: Material,
+    point: vec3f,
+    normal: vec3f,
+    light_mask: u32,
+) -> vec3f {
+    var light = vec3f(0.0);
+    if environment_light_enabled() {
+        light += light_contribution(sample_environment_light(point, normal), direction, basis, albedo, material);
+    }
+    if sun_enabled() && light_linked(scene.sun.light_mask, light_mask) {
+        light += light_contribution(sample_sun_light(point, normal), direction, basis, albedo, material);
+    }
+    if scene.point_light_count > 0u {
+        light += light_contribution(sample_point_light(point, normal, light_mask), direction, basis, albedo, material);
+    }
+    if scene.area_light_count > 0u {
+        light += light_contribution(sample_area_light(point, normal, light_mask), direction, basis, albedo, material);
+    }
+    if scene.emissive_triangle_count > 0u {
+        light += light_contribution(sample_emissive_triangle(point, normal), direction, basis, albedo, material);
+    }
+    return light;
+}
+
+// manifold next event estimation of caustics that smooth glass spheres cast on diffuse
+// surfaces, bsdf sampling rarely finds a small light through the glass
+// a path refracted through a sphere stays in the plane through its center, the shading
+// point and the light, so the manifold walk becomes a root search over the angle of
+// the point where the path leaves the sphere towards the shading point
+const CAUSTIC_SEARCH_STEPS = 16;
+const CAUSTIC_BISECTION_STEPS = 24;
+const CAUSTIC_SUN = 0u;
+const CAUSTIC_POINT_LIGHT = 1u;
+const CAUSTIC_AREA_LIGHT = 2u;
+const CAUSTIC_STAGE_DIFFUSE = 1u;
+const CAUSTIC_STAGE_INSIDE = 2u;
+const CAUSTIC_STAGE_THROUGH = 3u;
+
+fn caustic_sphere(sphere: Sphere) -> bool {
+    let material = scene.materials[sphere.material_id];
+    return material.material_type == MATERIAL_DIELECTRIC
+        && material.abbe_number == 0.0
+        && material.thin_film_thickness == 0.0
+        && material.albedo_texture == 0u
+        && material.opacity >= 1.0
+        && material.opacity_texture == 0u
+        && material.bump_texture == 0u;
+}
+
+// a path in the plane of the sphere (centered at the origin) from the shading point,
+// into the sphere at p1, out again at p2 and on along direction
+struct CausticPath {
+    p1: vec2f,
+    p2: vec2f,
+    direction: vec2f,
+    // from the shading point towards p1
+    to_p1: vec2f,
+}
+
+fn cross_2d(a: vec2f, b: vec2f) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+fn caustic_path(origin: vec2f, angle: f32, radius: f32, ior: f32) -> CausticPath {
+    var path: CausticPath;
+    path.p1 = radius * vec2f(cos(angle), sin(angle));
+    path.to_p1 = normalize(path.p1 - origin);
+    let inside = refract(path.to_p1, path.p1 / radius, 1.0 / ior);
+    path.p2 = path.p1 - 2.0 * dot(path.p1, inside) * inside;
+    path.direction = refract(inside, -path.p2 / radius, ior);
+    return path;
+}
+
+// how far the path misses the light, 2 when it leaves the wrong way
+fn caustic_error(path: CausticPath, target_point: vec2f, directional: bool) -> f32 {
+    let to_light = normalize(select(target_point - path.p2, target_point, directional));
+    if dot(path.direction, path.direction) < 0.5 || dot(path.direction, to_light) <= 0.0 {
+        return 2.0;
+    }
+    return cross_2d(path.direction, to_light);
+}
+
+// follows a light ray through the sphere and returns where it crosses the line through
+// the shading point perpendicular to arrival, along perpendicular
+fn caustic_offset(origin: vec2f, direction: vec2f, radius: f32, ior: f32, point: vec2f, arrival: vec2f) -> f32 {
+    let b = dot(origin, direction);
+    let c = dot(origin, origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant <= 0.0 {
+        return FLOAT_MAX;
+    }
+    let entry = origin + direction * (-b - sqrt(discriminant));
+    let inside = refract(direction, entry / radius, 1.0 / ior);
+    let exit = entry - 2.0 * dot(entry, inside) * inside;
+    let leaving = refract(inside, -exit / radius, ior);
+    let t = dot(point - exit, arrival) / dot(leaving, arrival);
+    return dot(exit + leaving * t - point, vec2f(-arrival.y, arrival.x));
+}
+
+// what the light sends along direction, radiant intensity for point and area lights
+// and irradiance for the sun
+fn caustic_emission(kind: u32, index: u32, direction: vec3f) -> vec3f {
+    if kind == CAUSTIC_SUN {
+        return scene.sun.irradiance;
+    }
+    if kind == CAUSTIC_POINT_LIGHT {
+        let light = scene.point_lights[index];
+        return light.intensity * spot_falloff(light, direction) * ies_intensity(light, direction);
+    }
+    let light = scene.area_lights[index];
+    let light_normal = normalize(cross(light.edge_u, light.edge_v));
+    return light.emission * max(dot(light_normal, direction), 0.0) * length(cross(light.edge_u, light.edge_v));
+}
+
+// irradiance at point from a light at light_point (or towards it when directional) over
+// every refracted path through the sphere
+fn caustic_irradiance(
+    sphere: Sphere,
+    point: vec3f,
+    normal: vec3f,
+    light_point: vec3f,
+    directional: bool,
+    kind: u32,
+    index: u32,
+    ior_shift: f32,
+) -> vec3f {
+    let material = scene.materials[sphere.material_id];
+    let radius = sphere.radius;
+    let ior = material.ior + ior_shift * pow(1.02, material.ior);
+
+    let from_center = point - sphere.center;
+    let distance = length(from_center);
+    if distance <= radius {
+        return vec3f(0.0);
+    }
+    let target_3d = select(light_point - sphere.center, light_point, directional);
+    if !directional && dot(target_3d, target_3d) <= radius * radius {
+        return vec3f(0.0);
+    }
+
+    // plane coordinates, the shading point on +x and the light on the +y side
+    let e1 = from_center / distance;
+    var perpendicular = target_3d - e1 * dot(target_3d, e1);
+    if dot(perpendicular, perpendicular) < 1e-12 {
+        perpendicular = orthonormal_basis(e1)[0];
+    }
+    let e2 = normalize(perpendicular);
+    let origin = vec2f(distance, 0.0);
+    let target_point = vec2f(dot(target_3d, e1), dot(target_3d, e2));
+
+    // only the cap seen from the shading point can be reached
+    let limit = acos(radius / distance) * 0.999;
+    var irradiance = vec3f(0.0);
+    var previous_angle = -limit;
+    var previous_error = caustic_error(caustic_path(origin, previous_angle, radius, ior), target_point, directional);
+    for (var step = 1; step <= CAUSTIC_SEARCH_STEPS; step += 1) {
+        let angle = -limit + 2.0 * limit * f32(step) / f32(CAUSTIC_SEARCH_STEPS);
+        let error = caustic_error(caustic_path(origin, angle, radius, ior), target_point, directional);
+        let bracketed = abs(previous_error) <= 1.0 && abs(error) <= 1.0 && previous_error * error <= 0.0;
+        let low_angle = previous_angle;
+        let low_error = previous_error;
+        previous_angle = angle;
+        previous_error = error;
+        if !bracketed {
+            continue;
+        }
+
+        var low = low_angle;
+        var high = angle;
+        var low_sign = sign(low_error);
+        for (var i = 0; i < CAUSTIC_BISECTION_STEPS; i += 1) {
+            let middle = 0.5 * (low + high);
+            let middle_error = caustic_error(caustic_path(origin, middle, radius, ior), target_point, directional);
+            if sign(middle_error) == low_sign {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let path = caustic_path(origin, 0.5 * (low + high), radius, ior);
+
+        let p1 = sphere.center + e1 * path.p1.x + e2 * path.p1.y;
+        let p2 = sphere.center + e1 * path.p2.x + e2 * path.p2.y;
+        let to_p1 = e1 * path.to_p1.x + e2 * path.to_p1.y;
+        let leaving = e1 * path.direction.x + e2 * path.direction.y;
+        let cos_point = dot(normal, to_p1);
+        if cos_point <= 0.0 {
+            continue;
+        }
+
+        // both open segments have to be clear
+        let to_sphere = get_ray_collision(Ray(point + normal * EPSILON, to_p1));
+        if to_sphere.distance >= EPSILON && to_sphere.distance < length(p1 - point) * 0.999 {
+            continue;
+        }
+        let light_distance = select(length(light_point - p2), FLOAT_MAX, directional);
+        let to_light = get_ray_collision(Ray(p2 + leaving * EPSILON, leaving));
+        if to_light.distance >= EPSILON && to_light.distance < light_distance {
+            continue;
+        }
+
+        // how much the sphere focuses the light, the spread of light rays leaving the light
+        // over the spread where they arrive, in the plane from following two neighbouring
+        // light rays and across it from rotating the plane around the axis to the light
+        let arrival = path.to_p1;
+        var spread_in_plane: f32;
+        var spread_across: f32;
+        if directional {
+            let incoming = -path.direction;
+            let side = vec2f(-incoming.y, incoming.x);
+            let h = radius * 1e-3;
+            let start = path.p2 - incoming * 2.0 * radius;
+            let offset_a = caustic_offset(start + side * h, incoming, radius, ior, origin, arrival);
+            let offset_b = caustic_offset(start - side * h, incoming, radius, ior, origin, arrival);
+            spread_in_plane = 2.0 * h / abs(offset_a - offset_b);
+            let axis = normalize(target_point);
+            spread_across = abs(cross_2d(axis, path.p2)) / max(abs(cross_2d(axis, origin)), radius * 1e-3);
+        } else {
+            let emitted = normalize(path.p2 - target_point);
+            let h = 1e-3;
+            let rotated_a = vec2f(emitted.x * cos(h) - emitted.y * sin(h), emitted.x * sin(h) + emitted.y * cos(h));
+            let rotated_b = vec2f(emitted.x * cos(h) + emitted.y * sin(h), -emitted.x * sin(h) + emitted.y * cos(h));
+            let offset_a = caustic_offset(target_point, rotated_a, radius, ior, origin, arrival);
+            let offset_b = caustic_offset(target_point, rotated_b, radius, ior, origin, arrival);
+            spread_in_plane = 2.0 * h / abs(offset_a - offset_b);
+            let axis = normalize(target_point);
+            spread_across = abs(cross_2d(axis, emitted)) / max(abs(cross_2d(axis, origin)), radius * 1e-3);
+        }
+        if !(spread_in_plane < FLOAT_MAX) {
+            continue;
+        }
+
+        // the same throughput a traced path gets from the two refractions
+        let entry_cos = abs(dot(path.to_p1, path.p1 / radius));
+        let exit_cos = abs(dot(normalize(path.p2 - path.p1), path.p2 / radius));
+        var transmittance = material.color * material.color
+            * (1.0 - reflectance_schlick(entry_cos, 1.0 / ior))
+            * (1.0 - reflectance_schlick(exit_cos, ior));
+        if material.absorption_density > 0.0 {
+            let optical_depth = length(path.p2 - path.p1) * material.absorption_density;
+            transmittance *= exp(log(max(material.absorption_color, vec3f(1e-6))) * optical_depth);
+        }
+
+        irradiance += caustic_emission(kind, index, -leaving) * transmittance * spread_in_plane * spread_across * cos_point;
+    }
+    return irradiance;
+}
+
+// caustic light on a diffuse surface through one randomly picked glass sphere, from one
+// light of each kind, paths like these are skipped when bsdf sampling finds them
+fn manifold_caustics(point: vec3f, normal: vec3f, albedo: vec3f, light_mask: u32, ior_shift: f32) -> vec3f {
+    var sphere_count = 0u;
+    for (var i = 0u; i < scene.sphere_count; i += 1u) {
+        if caustic_sphere(scene.spheres[i]) {
+            sphere_count += 1u;
+        }
+    }
+    if sphere_count == 0u {
+        return vec3f(0.0);
+    }
+    var pick = min(u32(rand() * f32(sphere_count)), sphere_count - 1u);
+    var sphere: Sphere;
+    for (var i = 0u; i < scene.sphere_count; i += 1u) {
+        if caustic_sphere(scene.spheres[i]) {
+            if pick == 0u {
+                sphere = scene.spheres[i];
+                break;
+            }
+            pick -= 1u;
+        }
+    }
+
+    var irradiance = vec3f(0.0);
+    if sun_enabled() && light_linked(scene.sun.light_mask, light_mask) {
+        irradiance += caustic_irradiance(
+            sphere, point, normal, normalize(scene.sun.direction), true, CAUSTIC_SUN, 0u, ior_shift,
+        );
+    }
+    if scene.point_light_count > 0u {
+        let count = scene.point_light_count;
+        let index = min(u32(rand() * f32(count)), count - 1u);
+        let light = scene.point_lights[index];
+        if light_linked(light.light_mask, light_mask) {
+            irradiance += caustic_irradiance(
+                sphere, point, normal, light.position, false, CAUSTIC_POINT_LIGHT, index, ior_shift,
+            ) * f32(count);
+        }
+    }
+    if scene.area_light_count > 0u {
+        let count = scene.area_light_count;
+        let index = min(u32(rand() * f32(count)), count - 1u);
+        let light = scene.area_lights[index];
+        if light_linked(light.light_mask, light_mask) {
+            let light_point = light.corner + light.edge_u * rand() + light.edge_v * rand();
+            irradiance += caustic_irradiance(
+                sphere, point, normal, light_point, false, CAUSTIC_AREA_LIGHT, index, ior_shift,
+            ) * f32(count);
+        }
+    }
+    return albedo / PI * irradiance * f32(sphere_count);
+}
+
+// camera rays skip HIDDEN_FROM_CAMERA emitters and rays leaving a mirror or glossy bounce
+// skip HIDDEN_IN_REFLECTIONS ones, diffuse bounces and light sampling still see both
+fn emitter_visible(flags: u32, bounces: u32, specular_bounce: bool) -> bool {
+    if bounces == 0u {
+        return (flags & MATERIAL_HIDDEN_FROM_CAMERA) == 0u;
+    }
+    return !specular_bounce || (flags & MATERIAL_HIDDEN_IN_REFLECTIONS) == 0u;
+}
+
+const MAX_TRANSPARENT_HITS = 64u;
+const MAX_MIX_DEPTH = 4;
+
+// light reaching the camera over more than one bounce is scaled down to at most
+// indirect_clamp in every channel, rare bright paths then leave no fireflies at the cost
+// of a little energy, 0 turns it off
+fn clamp_indirect(light: vec3f, bounces: u32) -> vec3f {
+    let peak = max(light.r, max(light.g, light.b));
+    if uniforms.indirect_clamp <= 0.0 || bounces == 0u || peak <= uniforms.indirect_clamp {
+        return light;
+    }
+    return light * (uniforms.indirect_clamp / peak);
+}
+
+fn path_trace(ray_pos: vec4f) -> vec4f {
+    var incomming_light = vec3f(0.0);
+    // 0 when the camera looks straight through to a transparent background
+    var alpha = 1.0;
+    var ray_color = vec3f(1.0);
+
+    var ray = new_ray(ray_pos);
+
+    var surrounding_volume_density = 0.0;
+    var surrounding_volume_radiance = vec3f(0.0);
+
+    // set while the ray walks inside a subsurface material
+    var subsurface_sigma = vec3f(0.0);
+    var subsurface_scattering = vec3f(0.0);
+
+    // // check surrounding
+    // for(var i = 0u; i < scene.sphere_count; i += 1u) {
+    //     let sphere = scene.spheres[i];
+    //     let d = ray.origin - sphere.center;
     //     if dot(d, d) < sphere.radius * sphere.radius {
     //         let material = scene.materials[sphere.material_id];
     //         surrounding_volume_density += material.volume_density;
@@ -452,6 +2712,25 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
     //     }
     // }
 
+    // hero wavelength of the path once a dispersive refraction made it monochromatic,
+    // 0 while it still carries every wavelength
+    var wavelength = 0.0;
+
+    // pdf of the last bounce when eval_bsdf knows its bsdf, for weighting lights and the sky
+    // against sampling them directly, 0 when the bounce could not have been sampled that way
+    // or the ray went through something a shadow ray would have been blocked by since
+    var light_bsdf_pdf = 0.0;
+    // the last bounce was not diffuse, for emitter_visible
+    var specular_bounce = false;
+    // light_mask of the surface the ray left, linked lights are only seen from there
+    var surface_light_mask = 0u;
+    // how far the path got through diffuse surface, into a glass sphere and out again,
+    // a light seen after all three was already found by manifold_caustics
+    var caustic_stage = 0u;
+
+    // surfaces skipped through their opacity, capped so stacks of cutouts terminate
+    var transparent_hits = 0u;
+
     var chromatic_aberration_diff = 0.0;
     if uniforms.psuedo_chromatic_aberration > 0.0 {
         let channel = u32(rand() * 100) % 3;
@@ -469,17 +2748,193 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
 
     var bounces = 0u;
     while bounces < uniforms.camera.max_ray_bounces {
-        let hit = get_ray_collision(ray);
+        var hit = get_ray_collision(ray);
+        let last_bsdf_pdf = light_bsdf_pdf;
+        light_bsdf_pdf = 0.0;
+        let last_specular_bounce = specular_bounce;
+        specular_bounce = false;
+        let last_light_mask = surface_light_mask;
+        surface_light_mask = 0u;
+        let last_caustic_stage = caustic_stage;
+        caustic_stage = 0u;
+
+        // the nearest real collision over all grids decides if the ray scatters in a volume
+        var volume_distance = select(FLOAT_MAX, hit.distance, hit.distance >= EPSILON);
+        var fog_scatter = false;
+        if fog_enabled() {
+            let t = sample_fog_collision(ray, volume_distance);
+            if t >= 0.0 {
+                volume_distance = t;
+                fog_scatter = true;
+            }
+        }
+        var volume_material = 0u;
+        var in_volume = false;
+        for (var i = 0u; i < scene.volume_count; i += 1u) {
+            let t = sample_volume_collision(ray, scene.volumes[i], volume_distance);
+            if t >= 0.0 {
+                volume_distance = t;
+                volume_material = scene.volumes[i].material_id;
+                in_volume = true;
+                fog_scatter = false;
+            }
+        }
+        if fog_scatter {
+            ray.origin += ray.direction * volume_distance;
+            ray_color *= scene.fog.color;
+            incomming_light += clamp_indirect(ray_color * fog_direct_light(ray.origin, ray.direction), bounces);
+            let direction = sample_henyey_greenstein(ray.direction, scene.fog.anisotropy);
+            light_bsdf_pdf = henyey_greenstein(dot(ray.direction, direction), scene.fog.anisotropy);
+            ray.direction = direction;
+            bounces += 1;
+            continue;
+        }
+        if in_volume {
+            let material = scene.materials[volume_material];
+            incomming_light += clamp_indirect(ray_color * material.color * material.emission_strength, bounces);
+            ray_color *= material.color;
+            ray.origin += ray.direction * volume_distance;
+            ray.direction = rand_sphere();
+            bounces += 1;
+            continue;
+        }
+
+        var area_light_index = 0u;
+        let area_light_distance = intersect_area_lights(
+            ray,
+            select(FLOAT_MAX, hit.distance, hit.distance >= EPSILON),
+            &area_light_index,
+        );
+        if area_light_distance < FLOAT_MAX
+            && emitter_visible(scene.area_lights[area_light_index].flags, bounces, last_specular_bounce)
+            && (bounces == 0u || light_linked(scene.area_lights[area_light_index].light_mask, last_light_mask))
+            && last_caustic_stage != CAUSTIC_STAGE_THROUGH {
+            let light = scene.area_lights[area_light_index];
+            var weight = 1.0;
+            if last_bsdf_pdf > 0.0 {
+                let rect = spherical_rectangle(light, ray.origin);
+                let light_pdf = 1.0 / (max(rect.solid_angle, 1e-7) * f32(scene.area_light_count));
+                weight = last_bsdf_pdf * last_bsdf_pdf / (last_bsdf_pdf * last_bsdf_pdf + light_pdf * light_pdf);
+            }
+            incomming_light += clamp_indirect(ray_color * light.emission * weight, bounces);
+            break;
+        }
 
         if hit.distance < EPSILON {
-            incomming_light += ray_color * sky_color(ray);
+            if bounces == 0u && uniforms.background == BACKGROUND_TRANSPARENT {
+                alpha = 0.0;
+                break;
+            }
+
+            var weight = 1.0;
+            if last_bsdf_pdf > 0.0 && environment_light_enabled() {
+                let light_pdf = environment_light_pdf(ray.origin, ray.direction);
+                weight = last_bsdf_pdf * last_bsdf_pdf / (last_bsdf_pdf * last_bsdf_pdf + light_pdf * light_pdf);
+            }
+            incomming_light += clamp_indirect(ray_color * sky_color(ray) * weight, bounces);
+
+            let sun_linked = bounces == 0u || light_linked(scene.sun.light_mask, last_light_mask);
+            let sun_seen = sun_linked && last_caustic_stage != CAUSTIC_STAGE_THROUGH;
+            if sun_enabled() && sun_seen && emitter_visible(scene.sun.flags, bounces, last_specular_bounce) {
+                var sun_weight = 1.0;
+                if last_bsdf_pdf > 0.0 {
+                    let light_pdf = 1.0 / sun_solid_angle();
+                    sun_weight = last_bsdf_pdf * last_bsdf_pdf / (last_bsdf_pdf * last_bsdf_pdf + light_pdf * light_pdf);
+                }
+                incomming_light += clamp_indirect(ray_color * sun_radiance(ray.direction) * sun_weight, bounces);
+            }
+            break;
+        }
+
+        if any(subsurface_sigma > vec3f(0.0)) {
+            // one channel picks the distance, spectral MIS weights all of them
+            let channel = min(u32(rand() * 3.0), 2u);
+            let distance = -log(max(rand(), 1e-8)) / subsurface_sigma[channel];
+            let transmittance = exp(-subsurface_sigma * min(distance, hit.distance));
+            if distance < hit.distance {
+                let pdf = dot(subsurface_sigma * transmittance, vec3f(1.0 / 3.0));
+                ray_color *= subsurface_scattering * subsurface_sigma * transmittance / pdf;
+                ray.origin += ray.direction * distance;
+                ray.direction = rand_sphere();
+                bounces += 1;
+                continue;
+            }
+            ray_color *= transmittance / dot(transmittance, vec3f(1.0 / 3.0));
+        }
+
+        var material = scene.materials[hit.material_id];
+        // mixes pick one of their inputs per ray, nesting is followed a few levels deep
+        for (var depth = 0; depth < MAX_MIX_DEPTH && material.material_type == MATERIAL_MIX; depth += 1) {
+            var factor = material.mix_factor;
+            if material.mix_texture != 0 {
+                factor *= sample_texture(material.mix_texture, hit.uv * material.uv_scale + material.uv_offset).r;
+            }
+            material = scene.materials[select(material.mix_material_a, material.mix_material_b, rand() < factor)];
+        }
+        if material.material_type == MATERIAL_MIX {
+            break;
+        }
+        let uv = hit.uv * material.uv_scale + material.uv_offset;
+
+        // alpha cutout, the ray continues as if the surface was not there
+        var opacity = material.opacity;
+        if material.opacity_texture != 0 {
+            opacity *= sample_texture(material.opacity_texture, uv).a;
+        }
+        if opacity < 1.0 && transparent_hits < MAX_TRANSPARENT_HITS && rand() >= opacity {
+            transparent_hits += 1u;
+            specular_bounce = last_specular_bounce;
+            surface_light_mask = last_light_mask;
+            ray.origin = hit.point + ray.direction * EPSILON;
+            continue;
+        }
+
+        // nothing leaves the back of a single sided surface
+        if !hit.front_face && (material.flags & MATERIAL_SINGLE_SIDED) != 0u {
             break;
         }
 
-        let material = scene.materials[hit.material_id];
+        if material.bump_texture != 0 {
+            hit.normal = bump_normal(hit.normal, hit.tangent, hit.front_face, uv, material);
+        }
+        let albedo = surface_albedo(hit, material, uv);
+        // same channels as the glTF metallic-roughness texture
+        if material.roughness_texture != 0 {
+            material.roughness *= sample_texture(material.roughness_texture, uv).g;
+        }
+        if material.metallic_texture != 0 {
+            material.metallic *= sample_texture(material.metallic_texture, uv).b;
+        }
+        var emission = albedo * material.emission_strength;
+        if material.emission_texture != 0 {
+            emission = sample_texture(material.emission_texture, uv).rgb * material.emission_strength;
+        }
+        if !emitter_visible(material.flags, bounces, last_specular_bounce) {
+            emission = vec3f(0.0);
+        }
+        // glowing triangles are also sampled directly from the surface the ray left
+        if hit.triangle && last_bsdf_pdf > 0.0 && scene.emissive_triangle_count > 0u {
+            let light_pdf = emissive_triangle_pdf(
+                ray.origin,
+                hit.triangle_id,
+                hit.point,
+                abs(dot(ray.direction, hit.normal)),
+            );
+            emission *= last_bsdf_pdf * last_bsdf_pdf / (last_bsdf_pdf * last_bsdf_pdf + light_pdf * light_pdf);
+        }
+
+        // leaving through a back face means the whole segment was inside the object
+        if !hit.front_face && material.absorption_density > 0.0 {
+            let optical_depth = hit.distance * material.absorption_density;
+            ray_color *= exp(log(max(material.absorption_color, vec3f(1e-6))) * optical_depth);
+        }
 
-        let new_ray_color = ray_color * material.color;
+        let new_ray_color = ray_color * albedo;
+        // what the path throughput is multiplied by after scattering
+        var throughput = albedo;
         if new_ray_color.x == new_ray_color.y && new_ray_color.x == new_ray_color.z && new_ray_color.x == 0.0 {
+            // a black surface can still glow through its emission texture
+            incomming_light += clamp_indirect(ray_color * emission, bounces);
             break;
         }
 
@@ -490,7 +2945,7 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
                 // hit the particle
                 let transmittance = exp(-surrounding_volume_density * scattering_distance);
                 let radiance = surrounding_volume_radiance * (1.0 - transmittance);
-                incomming_light += ray_color * radiance;
+                incomming_light += clamp_indirect(ray_color * radiance, bounces);
                 ray_color *= transmittance;
                 ray.origin += ray.direction * scattering_distance;
                 ray.direction = rand_sphere();
@@ -499,7 +2954,12 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
             }
         }
 
-        if material.volume_density < 1.0 {
+        if material.material_type == MATERIAL_EMISSIVE {
+            incomming_light += clamp_indirect(ray_color * emission, bounces);
+            break;
+        }
+
+        if material.material_type == MATERIAL_VOLUME {
             if !hit.front_face {
                 surrounding_volume_density -= material.volume_density;
                 surrounding_volume_radiance -= material.emission_strength * material.color;
@@ -511,35 +2971,155 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
                 surrounding_volume_density += material.volume_density;
                 surrounding_volume_radiance += material.emission_strength * material.color;
             }
+            specular_bounce = last_specular_bounce;
+            surface_light_mask = last_light_mask;
             ray.origin = hit.point + ray.direction * EPSILON;
             // recalculate again to account for smoke
             continue;
         }
 
-        if material.roughness_or_ior > 0.0 {
-            // calculate scattering direction
-            let diffuse_direction = normalize(hit.normal + (1.0 - EPSILON) * rand_sphere());
+        incomming_light += clamp_indirect(ray_color * emission, bounces);
+
+        // calculate scattering direction
+        specular_bounce = material.material_type != MATERIAL_DIFFUSE;
+        surface_light_mask = hit.light_mask;
+        let diffuse_direction = normalize(hit.normal + (1.0 - EPSILON) * rand_sphere());
+        // only the outside is coated
+        let coat_chance = select(0.0, clearcoat_chance(ray.direction, hit.normal, material), hit.front_face);
+        if coat_chance > 0.0 && rand() < coat_chance {
+            let scatter = scatter_clearcoat(ray.direction, hit.normal, material, coat_chance);
+            if all(scatter.throughput == vec3f(0.0)) {
+                break;
+            }
+            specular_bounce = true;
+            ray.direction = scatter.direction;
+            throughput = scatter.throughput;
+        } else if material.material_type == MATERIAL_DIFFUSE {
+            let basis = orthonormal_basis(hit.normal);
+            incomming_light += clamp_indirect(ray_color * direct_light(ray.direction, basis, albedo, material, hit.point, hit.normal, hit.light_mask), bounces);
+            if uniforms.manifold_caustics != 0u {
+                let caustics = manifold_caustics(
+                    hit.point, hit.normal, albedo, hit.light_mask, uniforms.psuedo_chromatic_aberration * chromatic_aberration_diff,
+                );
+                incomming_light += clamp_indirect(ray_color * caustics, bounces);
+                caustic_stage = CAUSTIC_STAGE_DIFFUSE;
+            }
+            ray.direction = diffuse_direction;
+            light_bsdf_pdf = max(dot(hit.normal, diffuse_direction), 0.0) / PI;
+        } else if material.material_type == MATERIAL_METAL {
+            if material.thin_film_thickness > 0.0 {
+                throughput = specular_fresnel(dot(-ray.direction, hit.normal), albedo, material);
+            }
             let specular_direction = reflect(ray.direction, hit.normal);
-            ray.direction = mix(specular_direction, diffuse_direction, material.roughness_or_ior);
+            ray.direction = normalize(mix(specular_direction, diffuse_direction, material.roughness));
+        } else if material.material_type == MATERIAL_PBR
+            || material.material_type == MATERIAL_PRINCIPLED
+            || material.material_type == MATERIAL_CONDUCTOR {
+            let basis = tangent_basis(hit.normal, hit.tangent, material.anisotropy_rotation);
+            if light_sampled(material) {
+                incomming_light += clamp_indirect(ray_color * direct_light(ray.direction, basis, albedo, material, hit.point, hit.normal, hit.light_mask), bounces);
+            }
+            var scatter: Scatter;
+            if material.material_type == MATERIAL_PBR {
+                scatter = scatter_microfacet(ray.direction, basis, albedo, material);
+            } else if material.material_type == MATERIAL_CONDUCTOR {
+                scatter = scatter_conductor(ray.direction, basis, albedo, material);
+            } else {
+                scatter = scatter_principled(ray.direction, basis, hit.front_face, albedo, material);
+            }
+            if all(scatter.throughput == vec3f(0.0)) {
+                break;
+            }
+            if light_sampled(material) {
+                light_bsdf_pdf = eval_bsdf(ray.direction, basis, albedo, material, scatter.direction).pdf;
+            }
+            ray.direction = scatter.direction;
+            throughput = scatter.throughput;
+        } else if material.material_type == MATERIAL_SUBSURFACE {
+            // the boundary reflects specularly, otherwise the ray crosses it diffusely
+            // the color comes from the random walk, not from the boundary
+            throughput = vec3f(1.0);
+            if reflectance_schlick(abs(dot(ray.direction, hit.normal)), material.ior) > rand() {
+                ray.direction = reflect(ray.direction, hit.normal);
+            } else {
+                ray.direction = normalize(-hit.normal + (1.0 - EPSILON) * rand_sphere());
+                specular_bounce = false;
+                if hit.front_face {
+                    subsurface_sigma = subsurface_extinction(albedo, material.subsurface_radius);
+                    subsurface_scattering = subsurface_albedo(albedo);
+                } else {
+                    subsurface_sigma = vec3f(0.0);
+                }
+            }
+        } else if material.abbe_number > 0.0 && wavelength == 0.0 {
+            // hero wavelength sampling: reflection does not depend on the wavelength so
+            // it keeps four stratified ones, only refraction commits to the hero (the first)
+            let cos_theta = abs(dot(ray.direction, hit.normal));
+            let strata = fract(rand() + vec4f(0.0, 0.25, 0.5, 0.75));
+            let wavelengths = WAVELENGTH_MIN + (WAVELENGTH_MAX - WAVELENGTH_MIN) * strata;
+
+            var reflectances = vec4f(1.0);
+            var spectral_reflection = vec3f(0.0);
+            for (var i = 0; i < 4; i += 1) {
+                let base_ior = dispersive_ior(material.ior, material.abbe_number, wavelengths[i]);
+                let ior = select(base_ior, 1.0 / base_ior, hit.front_face);
+                if ior * ior * (1.0 - cos_theta * cos_theta) <= 1.0 {
+                    reflectances[i] = reflectance_schlick(cos_theta, ior);
+                }
+                spectral_reflection += wavelength_to_rgb(wavelengths[i]) * reflectances[i] * 0.25;
+            }
+            let reflect_chance = dot(reflectances, vec4f(0.25));
+
+            if reflect_chance > rand() {
+                ray.direction = reflect(ray.direction, hit.normal);
+                throughput *= spectral_reflection / reflect_chance;
+            } else {
+                // the hero is totally reflected while another wavelength refracts
+                if reflectances.x >= 1.0 {
+                    break;
+                }
+                wavelength = wavelengths.x;
+                let base_ior = dispersive_ior(material.ior, material.abbe_number, wavelength);
+                ray.direction = refract(ray.direction, hit.normal, select(base_ior, 1.0 / base_ior, hit.front_face));
+                throughput *= wavelength_to_rgb(wavelength) * (1.0 - reflectances.x) / (1.0 - reflect_chance);
+            }
         } else {
             let cos_theta = abs(dot(ray.direction, hit.normal));
 
-            var base_ior = -material.roughness_or_ior;
+            var base_ior = material.ior;
+            if material.abbe_number > 0.0 {
+                base_ior = dispersive_ior(material.ior, material.abbe_number, wavelength);
+            }
             base_ior += uniforms.psuedo_chromatic_aberration * chromatic_aberration_diff * pow(1.02, base_ior);
             let ior = select(base_ior, 1.0 / base_ior, hit.front_face);
             let cannot_refract = ior * ior * (1.0 - cos_theta * cos_theta) > 1.0;
 
-            if cannot_refract || reflectance_schlick(cos_theta, ior) > rand() {
+            // the film only coats the outside
+            var reflectance = vec3f(reflectance_schlick(cos_theta, ior));
+            if material.thin_film_thickness > 0.0 && hit.front_face {
+                reflectance = thin_film_fresnel(cos_theta, material.thin_film_ior, material.thin_film_thickness, vec3f(base_ior));
+            }
+            let reflect_chance = dot(reflectance, vec3f(1.0 / 3.0));
+
+            if cannot_refract || reflect_chance > rand() {
                 ray.direction = reflect(ray.direction, hit.normal);
+                if !cannot_refract {
+                    throughput *= reflectance / reflect_chance;
+                }
             } else {
                 ray.direction = refract(ray.direction, hit.normal, ior);
+                throughput *= (1.0 - reflectance) / (1.0 - reflect_chance);
+                if hit.caustic_sphere && hit.front_face && last_caustic_stage == CAUSTIC_STAGE_DIFFUSE {
+                    caustic_stage = CAUSTIC_STAGE_INSIDE;
+                } else if hit.caustic_sphere && !hit.front_face && last_caustic_stage == CAUSTIC_STAGE_INSIDE {
+                    caustic_stage = CAUSTIC_STAGE_THROUGH;
+                }
             }
         }
         ray.origin = hit.point + ray.direction * EPSILON;
 
         // ray_color *= hit.normal * 0.5 + vec3f(0.5);
-        ray_color = new_ray_color;
-        incomming_light += ray_color * material.emission_strength;
+        ray_color *= throughput;
 
         bounces += 1;
     }
@@ -547,33 +3127,430 @@ fn path_trace(ray_pos: vec4f) -> vec3f {
     if uniforms.psuedo_chromatic_aberration > 0.0 {
         incomming_light *= 3.0;
     }
-    return incomming_light;
+    return vec4f(incomming_light, alpha);
 }
 
-@fragment
-fn fs_display(
-    @builtin(position) pos: vec4f,
-) -> @location(0) vec4f {
+const RENDER_MODE_TOON = 1u;
+const TOON_BANDS = 3.0;
+const TOON_LIGHT_DIRECTION = vec3f(0.4, 0.8, 0.45);
+
+// depth or normal jumps between this pixel and its neighbours mark an outline
+fn toon_edge(center: HitInfo, pos: vec4f) -> bool {
+    for (var i = 0; i < 2; i += 1) {
+        let offset = select(vec4f(0.0, 1.0, 0.0, 0.0), vec4f(1.0, 0.0, 0.0, 0.0), i == 0);
+        let neighbour = get_ray_collision(new_ray(pos + offset));
+        let center_hit = center.distance >= EPSILON;
+        let neighbour_hit = neighbour.distance >= EPSILON;
+        if center_hit != neighbour_hit {
+            return true;
+        }
+        if center_hit && (
+            abs(neighbour.distance - center.distance) > 0.05 * center.distance
+            || dot(neighbour.normal, center.normal) < 0.8
+            || neighbour.material_id != center.material_id
+        ) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// how sharp the surface seen through pixel pos is, 1 where its circle of confusion
+// fits in a pixel fading to 0 at a few pixels, for the focus overlay
+fn focus_overlay_tint(pos: vec4f) -> f32 {
+    let ray = camera_ray(pos, 1.0, vec2f(0.0), vec2f(0.0));
+    let hit = get_ray_collision(ray);
+    if hit.distance < EPSILON {
+        return 0.0;
+    }
+
+    // sharp at focus_distance along the ray, the blur circle grows with the aperture
+    let focus_distance = uniforms.camera.focus_distance;
+    let blur = uniforms.camera.apeture * abs(hit.distance - focus_distance) / hit.distance;
+    let focal_length = uniforms.camera.width * 0.5 / tan(uniforms.camera.fov * 0.5);
+    let pixel = focus_distance * 2.0 / (f32(uniforms.height) * focal_length);
+    // with a pinhole everything is sharp, the focal plane is still drawn as a thin band
+    if uniforms.camera.apeture <= 0.0 {
+        return 0.5 * (1.0 - smoothstep(0.0, 0.01, abs(hit.distance / focus_distance - 1.0)));
+    }
+    return 0.5 * (1.0 - smoothstep(pixel, 4.0 * pixel, blur));
+}
+
+// non photorealistic integrator: one primary hit lit by a fixed key light in a few
+// flat bands, with hard shadows, a rim light and black outlines
+fn toon_shade(pos: vec4f) -> vec4f {
+    let ray = new_ray(pos);
+    let hit = get_ray_collision(ray);
+    if toon_edge(hit, pos) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+    if hit.distance < EPSILON {
+        if uniforms.background == BACKGROUND_TRANSPARENT {
+            return vec4f(0.0);
+        }
+        return vec4f(sky_color(ray), 1.0);
+    }
+
+    let material = scene.materials[hit.material_id];
+    let uv = hit.uv * material.uv_scale + material.uv_offset;
+    let albedo = surface_albedo(hit, material, uv);
+    if material.material_type == MATERIAL_EMISSIVE {
+        return vec4f(albedo * material.emission_strength, 1.0);
+    }
+
+    let light = normalize(TOON_LIGHT_DIRECTION);
+    var n_dot_l = max(dot(hit.normal, light), 0.0);
+    let shadow = get_ray_collision(Ray(hit.point + hit.normal * EPSILON, light));
+    if shadow.distance >= EPSILON {
+        n_dot_l = 0.0;
+    }
+    let band = ceil(n_dot_l * TOON_BANDS) / TOON_BANDS;
+
+    let rim = smoothstep(0.6, 0.7, pow(1.0 - max(dot(hit.normal, -ray.direction), 0.0), 2.0));
+    return vec4f(albedo * (0.25 + 0.75 * band) + vec3f(0.3) * rim, 1.0);
+}
+
+// how much of the light through pixel pos reaches the sensor
+fn vignetting(pos: vec4f) -> f32 {
+    let image = distort(image_uv(pos));
+    var factor = 1.0;
+    if uniforms.physical_vignetting != 0u {
+        // cos^4 of the angle off the optical axis
+        var cos_theta: f32;
+        if uniforms.camera.projection == PROJECTION_PERSPECTIVE {
+            let focal_length = uniforms.camera.width * 0.5 / tan(uniforms.camera.fov * 0.5);
+            cos_theta = focal_length / sqrt(focal_length * focal_length + dot(image, image));
+        } else {
+            cos_theta = max(cos(min(fisheye_angle(length(image)), PI)), 0.0);
+        }
+        factor *= cos_theta * cos_theta * cos_theta * cos_theta;
+    }
+    if uniforms.vignette_strength > 0.0 && uniforms.vignette_radius < 1.0 {
+        let corner = length(image_uv(vec4f(0.0)));
+        let r = length(image_uv(pos)) / corner;
+        factor *= 1.0 - uniforms.vignette_strength * smoothstep(uniforms.vignette_radius, 1.0, r);
+    }
+    return factor;
+}
+
+// one sample of pixel pos for the current eye
+fn render_sample(pos: vec4f) -> vec4f {
+    if !lens_covers(pos) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+    if uniforms.render_mode == RENDER_MODE_TOON {
+        return toon_shade(pos);
+    }
+    return path_trace(pos);
+}
+
+// a sample more than outlier_rejection times as bright as the average of the samples
+// before it is scaled down to that, once there are enough of them to trust the average
+fn reject_outlier(sample: vec3f, accumulated: vec3f) -> vec3f {
+    let previous_samples = uniforms.frame_count - 1u;
+    if uniforms.outlier_rejection <= 0.0 || previous_samples < 16u {
+        return sample;
+    }
+    let weights = vec3f(0.2126, 0.7152, 0.0722);
+    let limit = uniforms.outlier_rejection * dot(accumulated, weights) / f32(previous_samples);
+    let luminance = dot(sample, weights);
+    if luminance <= limit {
+        return sample;
+    }
+    return sample * (limit / luminance);
+}
+
+fn octahedral_encode(normal: vec3f) -> vec2f {
+    let p = normal.xy / (abs(normal.x) + abs(normal.y) + abs(normal.z));
+    if normal.z >= 0.0 {
+        return p;
+    }
+    return (1.0 - abs(p.yx)) * select(vec2f(-1.0), vec2f(1.0), p >= vec2f(0.0));
+}
+
+fn octahedral_decode(p: vec2f) -> vec3f {
+    var normal = vec3f(p, 1.0 - abs(p.x) - abs(p.y));
+    let fold = max(-normal.z, 0.0);
+    normal.x += select(fold, -fold, normal.x >= 0.0);
+    normal.y += select(fold, -fold, normal.y >= 0.0);
+    return normalize(normal);
+}
+
+struct Guide {
+    // -1 for the sky
+    depth: f32,
+    // facing the camera
+    normal: vec3f,
+    albedo: vec3f,
+}
+
+// the guide packed into the integer texel of guide_new
+fn encode_guide(guide: Guide) -> vec4u {
+    return vec4u(
+        bitcast<u32>(guide.depth),
+        pack2x16snorm(octahedral_encode(guide.normal)),
+        pack4x8unorm(vec4f(guide.albedo, 0.0)),
+        0u,
+    );
+}
+
+fn decode_guide(texel: vec4u) -> Guide {
+    return Guide(
+        bitcast<f32>(texel.x),
+        octahedral_decode(unpack2x16snorm(texel.y)),
+        unpack4x8unorm(texel.z).rgb,
+    );
+}
+
+// the first hit through the pixel center
+fn trace_guide(pos: vec4f) -> Guide {
+    let ray = camera_ray(pos, 1.0, vec2f(0.0), vec2f(0.0));
+    let hit = get_ray_collision(ray);
+    if hit.distance < EPSILON {
+        return Guide(-1.0, -ray.direction, vec3f(1.0));
+    }
+
+    let material = scene.materials[hit.material_id];
+    let uv = hit.uv * material.uv_scale + material.uv_offset;
+    let normal = select(-hit.normal, hit.normal, dot(hit.normal, ray.direction) < 0.0);
+    return Guide(hit.distance, normal, min(surface_albedo(hit, material, uv), vec3f(1.0)));
+}
+
+// the pixel the previous camera saw direction through, image_uv and camera_ray the
+// other way around for a perspective camera
+fn previous_pixel(direction: vec3f) -> vec2i {
+    let camera = uniforms.previous_camera;
+    let right = -normalize(cross(camera.direction, vec3f(0.0, 1.0, 0.0)));
+    let up = normalize(cross(camera.direction, right));
+    let forward = dot(direction, camera.direction);
+    if forward <= 0.0 {
+        return vec2i(-1);
+    }
+
+    let focal_length = camera.width * 0.5 / tan(camera.fov * 0.5);
+    let image = vec2f(dot(direction, right), dot(direction, up)) * focal_length / forward;
+    let aspect_ratio = f32(uniforms.width) / f32(uniforms.height);
+    let uv = (image / vec2f(aspect_ratio, -1.0) + vec2f(1.0)) * 0.5;
+    // pixel centers are half a pixel in
+    return vec2i(floor(uv * vec2f(f32(uniforms.width - 1u), f32(uniforms.height - 1u))));
+}
+
+// how far apart the depth of a hit and the depth seen where it was reprojected to may
+// be, relative to the distance, before it counts as something else in front
+const REPROJECTION_DEPTH_TOLERANCE = 0.03;
+
+// the accumulated radiance the previous camera had where the first hit through pos is,
+// scaled to frame_count - 1 samples, false when it was hidden or off screen then
+fn reprojected_history(pos: vec4f, depth: f32, history: ptr<function, vec4f>) -> bool {
+    let ray = camera_ray(pos, 1.0, vec2f(0.0), vec2f(0.0));
+    // the sky only depends on the direction
+    var direction = ray.direction;
+    if depth >= 0.0 {
+        direction = ray.origin + ray.direction * depth - uniforms.previous_camera.position;
+    }
+
+    let full_pixel = previous_pixel(direction);
+    if any(full_pixel < vec2i(0)) || full_pixel.x >= i32(uniforms.width) || full_pixel.y >= i32(uniforms.height) {
+        return false;
+    }
+    let pixel = full_pixel / i32(uniforms.preview_scale);
+    let previous_depth = bitcast<f32>(textureLoad(guide_old, pixel, 0).x);
+    if depth < 0.0 || previous_depth < 0.0 {
+        if depth >= 0.0 || previous_depth >= 0.0 {
+            return false;
+        }
+    } else if abs(previous_depth - length(direction)) > REPROJECTION_DEPTH_TOLERANCE * previous_depth {
+        return false;
+    }
 
+    let scale = f32(uniforms.frame_count - 1u) / f32(uniforms.previous_samples);
+    *history = textureLoad(radiance_samples_old, pixel, 0) * scale;
+    return true;
+}
+
+// one invocation per pixel of the tile at tile_origins[id.z], workgroups must match
+// TRACE_WORKGROUP_SIZE
+// in a preview a pixel is a block of preview_scale pixels, accumulated into the top left
+// of the textures and drawn over the whole block
+@compute @workgroup_size(8, 8)
+fn cs_trace(@builtin(global_invocation_id) id: vec3u) {
+    let scale = uniforms.preview_scale;
+    let pixel = tile_origins[id.z] + id.xy;
+    let size = (vec2u(uniforms.width, uniforms.height) + scale - 1u) / scale;
+    if id.x >= uniforms.tile_size || id.y >= uniforms.tile_size
+        || pixel.x >= size.x || pixel.y >= size.y {
+        return;
+    }
+    // pixel centers like a fragment position, the middle of the block in a preview
+    let pos = vec4f((vec2f(pixel) + 0.5) * f32(scale), 0.0, 1.0);
 
     init_rng(vec2u(pos.xy));
 
-    // load previous progress
-    var color: vec4f;
-    if uniforms.frame_count > 1 {
-        color = textureLoad(radiance_samples_old, vec2u(pos.xy), 0);
+    // load previous progress, right after a camera move from where the pixel's first hit
+    // was on screen before, see Gfx::render_reproject
+    var color = vec4f(0.0);
+    var guide: vec4u;
+    var has_history = false;
+    if uniforms.reproject != 0u {
+        guide = encode_guide(trace_guide(pos));
+        has_history = reprojected_history(pos, bitcast<f32>(guide.x), &color);
+    } else if uniforms.frame_count > 1 {
+        color = textureLoad(radiance_samples_old, pixel, 0);
+        guide = textureLoad(guide_old, pixel, 0);
+        has_history = true;
     } else {
-        color = vec4f(0.0);
+        guide = encode_guide(trace_guide(pos));
     }
+    textureStore(guide_new, pixel, guide);
 
     // save new progress and render
-    var path_traced = vec4f(path_trace(pos), 1.0);
-    color += path_traced;
-    textureStore(radiance_samples_new, vec2u(pos.xy), color);
+    var path_traced: vec4f;
+    if uniforms.camera.stereo == STEREO_ANAGLYPH {
+        stereo_eye = -1.0;
+        let left = render_sample(pos);
+        stereo_eye = 1.0;
+        let right = render_sample(pos);
+        path_traced = vec4f(left.r, right.g, right.b, max(left.a, right.a));
+    } else {
+        if uniforms.camera.stereo == STEREO_SIDE_BY_SIDE {
+            stereo_eye = select(-1.0, 1.0, pos.x >= f32(uniforms.width / 2u));
+        }
+        path_traced = render_sample(pos);
+    }
+    // part of the radiance so saved images have it too
+    path_traced = vec4f(path_traced.rgb * vignetting(pos), path_traced.a);
+    // a pixel without history counts its first sample for all frame_count of them
+    if has_history {
+        path_traced = vec4f(reject_outlier(path_traced.rgb, color.rgb), path_traced.a);
+        color += path_traced;
+    } else {
+        color = path_traced * f32(uniforms.frame_count);
+    }
+    textureStore(radiance_samples_new, pixel, color);
+    if uniforms.denoise != 0u && scale == 1u {
+        return;
+    }
+
+    let average = color / f32(uniforms.frame_count);
+    // alpha is coverage and stays linear
+    var exposed = average.rgb * uniforms.camera.exposure;
+    if uniforms.focus_overlay != 0u {
+        exposed = mix(exposed, vec3f(0.0, 1.0, 0.2), focus_overlay_tint(pos));
+    }
+    let display = vec4f(pow(exposed, vec3f(1.0 / uniforms.gamma_correction)), average.a);
+    for (var y = 0u; y < scale; y += 1u) {
+        for (var x = 0u; x < scale; x += 1u) {
+            let covered = pixel * scale + vec2u(x, y);
+            if covered.x < uniforms.width && covered.y < uniforms.height {
+                textureStore(display_image, covered, display);
+            }
+        }
+    }
+}
+
+// taps of the b3 spline kernel from the center out
+const DENOISE_KERNEL = array<f32, 3>(3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0);
+// how quickly taps stop counting as they differ from the center, see cs_denoise
+const DENOISE_NORMAL_POWER = 64.0;
+const DENOISE_DEPTH_SIGMA = 0.02;
+const DENOISE_ALBEDO_SIGMA = 0.1;
+const DENOISE_LUMINANCE_SIGMA = 4.0;
+
+fn denoise_load(pixel: vec2i) -> vec4f {
+    if denoise_params.first != 0u {
+        return textureLoad(denoise_radiance, pixel, 0) / f32(uniforms.frame_count);
+    }
+    return textureLoad(denoise_input, pixel, 0);
+}
+
+// a 5x5 blur with its taps step pixels apart, each weighted down by how different the
+// normal, depth, albedo and brightness there are, so edges and textures stay sharp
+// (Dammertz et al. 2010), run with growing steps it reaches far at little cost
+@compute @workgroup_size(8, 8)
+fn cs_denoise(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= uniforms.width || id.y >= uniforms.height {
+        return;
+    }
+    let pixel = vec2i(id.xy);
+    let size = vec2i(i32(uniforms.width), i32(uniforms.height));
+    let center = denoise_load(pixel);
+    let guide = decode_guide(textureLoad(denoise_guide, pixel, 0));
+    let weights = vec3f(0.2126, 0.7152, 0.0722);
+    let luminance = dot(center.rgb, weights);
+    // the noise of an average shrinks with the square root of its samples
+    let luminance_sigma = DENOISE_LUMINANCE_SIGMA * (luminance + 1e-3) / sqrt(f32(uniforms.frame_count));
+
+    var sum = vec3f(0.0);
+    var total = 0.0;
+    for (var y = -2; y <= 2; y += 1) {
+        for (var x = -2; x <= 2; x += 1) {
+            let offset = vec2i(x, y) * i32(denoise_params.step);
+            let tap = pixel + offset;
+            if any(tap < vec2i(0)) || any(tap >= size) {
+                continue;
+            }
+            let color = denoise_load(tap);
+            let other = decode_guide(textureLoad(denoise_guide, tap, 0));
+
+            var weight = DENOISE_KERNEL[abs(x)] * DENOISE_KERNEL[abs(y)];
+            weight *= pow(max(dot(guide.normal, other.normal), 0.0), DENOISE_NORMAL_POWER);
+            if guide.depth < 0.0 || other.depth < 0.0 {
+                // the sky only blends with more sky
+                weight *= select(0.0, 1.0, guide.depth < 0.0 && other.depth < 0.0);
+            } else {
+                let depth_sigma = DENOISE_DEPTH_SIGMA * guide.depth * length(vec2f(offset));
+                weight *= exp(-abs(guide.depth - other.depth) / max(depth_sigma, EPSILON));
+            }
+            let albedo_difference = guide.albedo - other.albedo;
+            weight *= exp(-dot(albedo_difference, albedo_difference) / (DENOISE_ALBEDO_SIGMA * DENOISE_ALBEDO_SIGMA));
+            weight *= exp(-abs(luminance - dot(color.rgb, weights)) / luminance_sigma);
 
-    return pow(color / f32(uniforms.frame_count), vec4f(1.0 / uniforms.gamma_correction));
-    // return pow(path_traced, vec4f(1.0 / uniforms.gamma_correction));
-    // return path_traced;
+            sum += color.rgb * weight;
+            total += weight;
+        }
+    }
+
+    // the center always counts, alpha is coverage and is not filtered
+    let filtered = vec4f(sum / total, center.a);
+    textureStore(denoise_output, pixel, filtered);
+    if denoise_params.last != 0u {
+        let exposed = filtered.rgb * uniforms.camera.exposure;
+        let display = vec4f(pow(exposed, vec3f(1.0 / uniforms.gamma_correction)), filtered.a);
+        textureStore(display_image, pixel, display);
+    }
+}
+
+// most taps along each side of the box the frame is shrunk with
+const DISPLAY_MAX_TAPS = 8u;
+
+// the frame fitted into the window without stretching, bilinear when it is enlarged
+// and averaged over a box of the pixels under each window pixel when it is shrunk
+@fragment
+fn fs_display(
+    @builtin(position) pos: vec4f,
+) -> @location(0) vec4f {
+    let window = vec2f(f32(uniforms.window_width), f32(uniforms.window_height));
+    let render = vec2f(f32(uniforms.width), f32(uniforms.height));
+    // window pixels per frame pixel
+    let fit = min(window.x / render.x, window.y / render.y);
+    let fitted = render * fit;
+    let uv = (pos.xy - (window - fitted) * 0.5) / fitted;
+    if any(uv < vec2f(0.0)) || any(uv > vec2f(1.0)) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+    if fit >= 1.0 {
+        return textureSampleLevel(display_image_read, display_sampler, uv, 0.0);
+    }
+
+    let taps = min(u32(ceil(1.0 / fit)), DISPLAY_MAX_TAPS);
+    var sum = vec4f(0.0);
+    for (var y = 0u; y < taps; y += 1u) {
+        for (var x = 0u; x < taps; x += 1u) {
+            let offset = ((vec2f(f32(x), f32(y)) + 0.5) / f32(taps) - 0.5) / fitted;
+            sum += textureSampleLevel(display_image_read, display_sampler, uv + offset, 0.0);
+        }
+    }
+    return sum / f32(taps * taps);
 }
 
 var<private> vertices: array<vec2f, 6> = array<vec2f, 6>(
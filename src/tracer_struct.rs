@@ -1,44 +1,255 @@
 use {
+    crate::metals::{self, ComplexIor},
     crate::vec3::Vec3,
     bytemuck::{Pod, Zeroable},
+    serde::{Deserialize, Serialize},
 };
 
+// how directions around the camera land on the image
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective = 0,
+    // image distance proportional to the angle from the view direction
+    FisheyeEquidistant = 1,
+    // equal areas on the image cover equal solid angles
+    FisheyeEquisolid = 2,
+}
+
+impl Projection {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => Projection::FisheyeEquidistant,
+            2 => Projection::FisheyeEquisolid,
+            _ => Projection::Perspective,
+        }
+    }
+}
+
+// the weights samples around a pixel center get, this is the anti-aliasing
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PixelFilter {
+    // every point within the radius counts the same, sharp but aliases more
+    Box = 0,
+    // falls off linearly to the radius
+    Tent = 1,
+    // smooth and soft, the radius is two standard deviations
+    #[default]
+    Gaussian = 2,
+}
+
+impl PixelFilter {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => PixelFilter::Box,
+            1 => PixelFilter::Tent,
+            _ => PixelFilter::Gaussian,
+        }
+    }
+}
+
+// two eyes eye_separation apart looking at the focus distance
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StereoMode {
+    #[default]
+    Off = 0,
+    // left eye on the left half of the frame, right eye on the right half
+    SideBySide = 1,
+    // red from the left eye and cyan from the right one, both traced for every sample
+    Anaglyph = 2,
+}
+
+impl StereoMode {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => StereoMode::SideBySide,
+            2 => StereoMode::Anaglyph,
+            _ => StereoMode::Off,
+        }
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-// size 64
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 96
 pub struct Camera {
     pub position: Vec3,
-    _pad0: u32,
+    // a Projection, see get_projection/set_projection
+    // fisheye fov is the angle across width and may go past 180 degrees, pixels past
+    // the edge of the lens are black
+    pub projection: u32,
     pub direction: Vec3,
     pub fov: f32,
     pub width: f32,
     pub focus_distance: f32,
     pub apeture: f32,
-    pub diverge_strength: f32,
+    // in pixels, how far from the pixel center samples are taken, 0 turns anti-aliasing off
+    pub filter_radius: f32,
     pub max_ray_bounces: u32,
-    _pad1: [u32; 3]
+    // what radiance is scaled by before display, see photometry for setting it from ev
+    pub exposure: f32,
+    // the lens opening is a regular polygon with this many sides, a circle below 3
+    pub aperture_blades: u32,
+    // radians, turns the polygon in the image
+    pub aperture_rotation: f32,
+    // orientation pan and tilt work in, direction is derived from it and is what the
+    // shader reads, both are recovered from direction when it is written directly
+    #[serde(skip)]
+    yaw: f32,
+    #[serde(skip)]
+    pitch: f32,
+    // a PixelFilter, see get_pixel_filter/set_pixel_filter
+    pub pixel_filter: u32,
+    // a StereoMode, see get_stereo_mode/set_stereo_mode
+    pub stereo: u32,
+    // distance between the eyes in scene units, about 0.065 for people in meters
+    pub eye_separation: f32,
+    // radial lens distortion, positive is barrel and negative pincushion, the image point
+    // at distance r (1 at the top edge) looks where r * (1 + k1 r^2 + k2 r^4) would
+    pub distortion_k1: f32,
+    pub distortion_k2: f32,
+    #[serde(skip)]
+    _pad1: u32,
+}
+
+// looking straight up or down leaves yaw undefined, tilting stops just short of it
+const MAX_PITCH: f32 = 89.9 * std::f32::consts::PI / 180.0;
+
+fn direction_from_angles(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), -pitch.cos() * yaw.cos())
 }
 
 impl Camera {
     pub fn new() -> Self {
         Camera {
             position: Vec3::zero(),
-            _pad0: 0,
+            projection: Projection::Perspective as u32,
             direction: Vec3::new(0.0, 0.0, -1.0),
-            fov: 75.0 * 0.01745329251,
+            fov: 75.0_f32.to_radians(),
             width: 1.0,
             focus_distance: 2.0,
             apeture: 0.02,
-            diverge_strength: 0.004,
+            filter_radius: 1.5,
             max_ray_bounces: 50,
-            _pad1: [0; 3],
+            exposure: 1.0,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            pixel_filter: PixelFilter::Gaussian as u32,
+            stereo: StereoMode::Off as u32,
+            eye_separation: 0.065,
+            distortion_k1: 0.0,
+            distortion_k2: 0.0,
+            _pad1: 0,
         }
     }
 
-    pub fn get_right_direction(&self) -> Vec3 {
-        let world_up = Vec3::new(0.0, 1.0, 0.0);
+    pub fn get_projection(&self) -> Projection {
+        Projection::from_u32(self.projection)
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection as u32;
+    }
 
-        -self.direction.cross(&world_up).normalized()
+    pub fn get_stereo_mode(&self) -> StereoMode {
+        StereoMode::from_u32(self.stereo)
+    }
+
+    pub fn set_stereo_mode(&mut self, stereo: StereoMode) {
+        self.stereo = stereo as u32;
+    }
+
+    // width over height of what one eye sees of a frame
+    pub fn aspect_ratio(&self, width: u32, height: u32) -> f32 {
+        let width = match self.get_stereo_mode() {
+            StereoMode::SideBySide => width / 2,
+            _ => width,
+        };
+        width as f32 / height as f32
+    }
+
+    pub fn get_pixel_filter(&self) -> PixelFilter {
+        PixelFilter::from_u32(self.pixel_filter)
+    }
+
+    pub fn set_pixel_filter(&mut self, filter: PixelFilter, radius: f32) {
+        self.pixel_filter = filter as u32;
+        self.filter_radius = radius.max(0.0);
+    }
+
+    // yaw around the world up axis from -z towards +x and pitch above the horizon, in radians
+    pub fn orientation(&self) -> (f32, f32) {
+        let stored = direction_from_angles(self.yaw, self.pitch);
+        if stored.dot(&self.direction.normalized()) > 1.0 - 1e-6 {
+            return (self.yaw, self.pitch);
+        }
+
+        let d = self.direction.normalized();
+        (d.x().atan2(-d.z()), d.y().clamp(-1.0, 1.0).asin())
+    }
+
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw.rem_euclid(2.0 * std::f32::consts::PI);
+        self.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.direction = direction_from_angles(self.yaw, self.pitch);
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3) {
+        self.direction = direction.normalized();
+        (self.yaw, self.pitch) = self.orientation();
+    }
+
+    // roll is not supported, up only decides which way the image faces when looking
+    // straight along the world up axis
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let d = (target - self.position).normalized();
+        let pitch = d.y().clamp(-1.0, 1.0).asin();
+        let yaw = if d.x().abs() + d.z().abs() > 1e-4 {
+            d.x().atan2(-d.z())
+        } else if d.y() > 0.0 {
+            // looking up the top of the image is the way the camera faced before tilting
+            (-up.x()).atan2(up.z())
+        } else {
+            up.x().atan2(-up.z())
+        };
+        self.set_orientation(yaw, pitch);
+    }
+
+    // half the view angles across the height and the width of the image
+    fn half_view_angles(&self, aspect_ratio: f32) -> (f32, f32) {
+        // fisheye angles grow about linearly with the image distance, the edge of a very
+        // wide one bends too much for a bounding sphere to stay in view
+        if self.get_projection() != Projection::Perspective {
+            let half = self.fov * 0.5 / (self.width * 0.5);
+            let limit = 80.0_f32.to_radians();
+            return (half.min(limit), (half * aspect_ratio).min(limit));
+        }
+        let focal_length = self.width * 0.5 / (self.fov * 0.5).tan();
+        ((1.0 / focal_length).atan(), (aspect_ratio / focal_length).atan())
+    }
+
+    // keeps the view direction and backs away from the box until it is all in view,
+    // focused on its center
+    pub fn frame_bounds(&mut self, bbox_min: Vec3, bbox_max: Vec3, aspect_ratio: f32) {
+        let center = (bbox_min + bbox_max) * 0.5;
+        let radius = ((bbox_max - bbox_min) * 0.5).length().max(1e-3);
+        let (vertical, horizontal) = self.half_view_angles(aspect_ratio);
+        let distance = radius / vertical.min(horizontal).sin();
+
+        self.position = center - self.direction.normalized() * distance;
+        self.focus_distance = distance;
+    }
+
+    // from yaw alone so it stays defined when looking straight up or down
+    pub fn get_right_direction(&self) -> Vec3 {
+        let (yaw, _) = self.orientation();
+        Vec3::new(-yaw.cos(), 0.0, -yaw.sin())
     }
 
     pub fn get_up_direction(&self) -> Vec3 {
@@ -57,60 +268,661 @@ impl Camera {
         self.position += self.get_up_direction() * ammount;
     }
 
-    // TODO: change this to use an angle instead
-    pub fn pan(&mut self, ammount: f32) {
-        self.direction += self.get_right_direction() * ammount;
-        self.direction = self.direction.normalized();
+    // turns towards get_right_direction by angle radians
+    pub fn pan(&mut self, angle: f32) {
+        let (yaw, pitch) = self.orientation();
+        self.set_orientation(yaw - angle, pitch);
+    }
+
+    // turns towards get_up_direction by angle radians
+    pub fn tilt(&mut self, angle: f32) {
+        let (yaw, pitch) = self.orientation();
+        self.set_orientation(yaw, pitch + angle);
     }
 
-    // TODO: change this to use an angle instead
-    pub fn tilt(&mut self, ammount: f32) {
-        self.direction += self.get_up_direction() * ammount;
-        self.direction = self.direction.normalized();
+    // what orbit mode turns around by default, the point in focus
+    pub fn focus_point(&self) -> Vec3 {
+        self.position + self.direction * self.focus_distance
+    }
+
+    // turntable rotation around pivot, yaw around the world up axis and pitch towards it
+    // in radians, the distance is kept and the camera ends up looking at the pivot
+    pub fn orbit(&mut self, pivot: Vec3, yaw: f32, pitch: f32) {
+        let offset = self.position - pivot;
+        let distance = offset.length();
+        if distance <= 0.0 {
+            return;
+        }
+
+        // stop short of the poles where yaw is undefined
+        let pitch = ((offset.y() / distance).clamp(-1.0, 1.0).asin() + pitch).clamp(-1.55, 1.55);
+        let yaw = offset.z().atan2(offset.x()) + yaw;
+        let offset = Vec3::new(pitch.cos() * yaw.cos(), pitch.sin(), pitch.cos() * yaw.sin()) * distance;
+
+        self.position = pivot + offset;
+        self.set_direction(-offset);
+    }
+
+    // moves along the line to pivot so it ends up distance away, still looking at it
+    pub fn set_orbit_distance(&mut self, pivot: Vec3, distance: f32) {
+        let offset = self.position - pivot;
+        if offset.length() <= 0.0 {
+            return;
+        }
+        self.position = pivot + offset.normalized() * distance.max(1e-3);
+        self.set_direction(-offset);
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// a real camera body and lens, turned into fov, width and apeture by apply
+// lengths are in millimeters and the scene is taken to be in meters
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalCamera {
+    pub focal_length: f32,
+    pub sensor_width: f32,
+    pub sensor_height: f32,
+    pub f_number: f32,
+}
+
+impl PhysicalCamera {
+    // a 50mm lens on a full frame sensor
+    pub fn new(focal_length: f32, f_number: f32) -> Self {
+        Self {
+            focal_length,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number,
+        }
+    }
+
+    // when the image and the sensor have different aspect ratios the sensor is fitted
+    // inside the image like a film gate, the other axis sees more than the sensor
+    pub fn apply(&self, camera: &mut Camera, aspect_ratio: f32) {
+        // half the image plane extent of the fitted axis, y spans [-1, 1] in the shader
+        let (extent, sensor) = if self.sensor_width / self.sensor_height > aspect_ratio {
+            (aspect_ratio, self.sensor_width)
+        } else {
+            (1.0, self.sensor_height)
+        };
+        let tangent = sensor * 0.5 / self.focal_length;
+
+        camera.width = 2.0 * extent;
+        camera.fov = match camera.get_projection() {
+            Projection::Perspective => 2.0 * tangent.atan(),
+            Projection::FisheyeEquidistant => 2.0 * tangent,
+            Projection::FisheyeEquisolid => 4.0 * (tangent * 0.5).min(1.0).asin(),
+        };
+        camera.apeture = self.focal_length / self.f_number / 1000.0;
+    }
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        Self::new(50.0, 2.8)
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MaterialType {
+    // lambertian
+    #[default]
+    Diffuse = 0,
+    // mirror blended towards diffuse by roughness
+    Metal = 1,
+    // glass like, refracts with ior
+    Dielectric = 2,
+    // pure light source, rays stop here
+    Emissive = 3,
+    // participating medium with volume_density
+    Volume = 4,
+    // metallic-roughness GGX microfacet surface over a diffuse base
+    Pbr = 5,
+    // disney style: Pbr plus sheen and rough transmission
+    Principled = 6,
+    // random walk below a specular boundary, color is the resulting diffuse color
+    Subsurface = 7,
+    // GGX metal with a measured complex ior, color only tints the reflection
+    Conductor = 8,
+    // picks mix_material_a or mix_material_b per ray, see MixMaterial
+    Mix = 9,
+}
+
+impl MaterialType {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => MaterialType::Metal,
+            2 => MaterialType::Dielectric,
+            3 => MaterialType::Emissive,
+            4 => MaterialType::Volume,
+            5 => MaterialType::Pbr,
+            6 => MaterialType::Principled,
+            7 => MaterialType::Subsurface,
+            8 => MaterialType::Conductor,
+            9 => MaterialType::Mix,
+            _ => MaterialType::Diffuse,
+        }
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-// size 32
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(from = "SerializedMaterial", into = "SerializedMaterial")]
+// size 208
 pub struct Material {
     pub color: Vec3,
-    pub roughness_or_ior: f32,
+    // a MaterialType, see get_type/set_type
+    pub material_type: u32,
+    pub roughness: f32,
+    pub ior: f32,
+    // any material type may glow, Emissive ones only glow
     pub emission_strength: f32,
     pub volume_density: f32,
-    _pad0: [u32; 2],
+    // Pbr and Principled, specular scales the dielectric reflectance (0.5 is 4%)
+    pub metallic: f32,
+    pub specular: f32,
+    // texture ids, 0 means none
+    // albedo multiplies color, the green channel of roughness_texture multiplies
+    // roughness and the blue channel of metallic_texture multiplies metallic
+    pub albedo_texture: u32,
+    pub roughness_texture: u32,
+    // applied to the hit uv before texture lookups
+    pub uv_scale: [f32; 2],
+    pub uv_offset: [f32; 2],
+    pub metallic_texture: u32,
+    // emitted color instead of the albedo, scaled by emission_strength
+    pub emission_texture: u32,
+    // Principled only, sheen_tint blends the sheen from white to the albedo hue
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    // strength of a glossy ior 1.5 layer over any surface type (car paint, lacquer)
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    // chance of refracting like a rough dielectric with ior
+    pub transmission: f32,
+    // Pbr and Principled, 0 is isotropic and 1 stretches highlights along the uv tangent
+    // (the u direction) rotated by anisotropy_rotation radians
+    pub anisotropy: f32,
+    pub anisotropy_rotation: f32,
+    // grayscale height map tilting the shading normal, bump_strength scales the
+    // height difference between neighbouring texels (1 tilts a full step by 45 degrees)
+    pub bump_texture: u32,
+    pub bump_strength: f32,
+    // CULL_BACKFACES, SINGLE_SIDED, HIDDEN_FROM_CAMERA and HIDDEN_IN_REFLECTIONS bits,
+    // surfaces are double sided and visible by default
+    pub flags: u32,
+    // Subsurface only, how far light travels below the surface per color channel
+    pub subsurface_radius: Vec3,
+    // interference film over Metal, Dielectric, Pbr and Principled surfaces (soap
+    // bubbles, oil slicks), thickness in nanometers with 0 disabling it
+    pub thin_film_thickness: f32,
+    pub thin_film_ior: f32,
+    // light travelling through the inside of a closed object is tinted by
+    // absorption_color once per 1 / absorption_density units, 0 disables it
+    pub absorption_density: f32,
+    _pad1: [u32; 2],
+    pub absorption_color: Vec3,
+    // Dielectric only, ior is taken at 587.6 nm and a cauchy fit spreads it by the
+    // abbe number (flint glass is ~35, crown glass ~60), 0 disables dispersion
+    pub abbe_number: f32,
+    // Conductor only, see the metals module for measured values
+    pub conductor_eta: Vec3,
+    // chance of a ray hitting the surface instead of passing straight through,
+    // multiplied by the alpha channel of opacity_texture (leaves, fences, decals)
+    pub opacity: f32,
+    pub conductor_k: Vec3,
+    pub opacity_texture: u32,
+    // Mix only, b is chosen with probability mix_factor times the red channel of mix_texture
+    pub mix_material_a: u32,
+    pub mix_material_b: u32,
+    pub mix_factor: f32,
+    pub mix_texture: u32,
 }
 
 impl Material {
-    pub fn new(color: Vec3, roughness_or_ior: f32, emission_strength: f32, volume_density: f32) -> Self {
+    // rays pass through the back of triangles and spheres as if they were not there,
+    // for open geometry that should only block from one side (cutaway walls)
+    pub const CULL_BACKFACES: u32 = 1;
+    // back faces are hit but absorb everything and do not glow, instead of being shaded
+    // with a flipped normal, stops light leaking through one sided planes and lights
+    pub const SINGLE_SIDED: u32 = 2;
+    // the glow is not seen by camera rays or by rays leaving a mirror or glossy bounce,
+    // the surface still lights everything else, also used by the flags of lights
+    pub const HIDDEN_FROM_CAMERA: u32 = 4;
+    pub const HIDDEN_IN_REFLECTIONS: u32 = 8;
+
+    pub fn new(material_type: MaterialType, color: Vec3) -> Self {
         Self {
+            material_type: material_type as u32,
             color,
-            roughness_or_ior,
+            ..Default::default()
+        }
+    }
+
+    pub fn diffuse(color: Vec3) -> Self {
+        Self::new(MaterialType::Diffuse, color)
+    }
+
+    // rough brightness of the light leaving a surface of this material, for picking which
+    // emitters to sample
+    pub fn emitter_power(&self) -> f32 {
+        if self.material_type == MaterialType::Volume as u32 || self.material_type == MaterialType::Mix as u32 {
+            return 0.0;
+        }
+        let color = if self.emission_texture != 0 { Vec3::all(1.0) } else { self.color };
+        self.emission_strength * (0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()).max(0.0)
+    }
+
+    pub fn metal(color: Vec3, roughness: f32) -> Self {
+        Self {
+            roughness,
+            ..Self::new(MaterialType::Metal, color)
+        }
+    }
+
+    pub fn dielectric(color: Vec3, ior: f32) -> Self {
+        Self {
+            ior,
+            ..Self::new(MaterialType::Dielectric, color)
+        }
+    }
+
+    pub fn emissive(color: Vec3, emission_strength: f32) -> Self {
+        Self {
             emission_strength,
+            ..Self::new(MaterialType::Emissive, color)
+        }
+    }
+
+    pub fn volume(color: Vec3, volume_density: f32) -> Self {
+        Self {
             volume_density,
-            _pad0: [0; 2],
+            ..Self::new(MaterialType::Volume, color)
+        }
+    }
+
+    pub fn pbr(color: Vec3, metallic: f32, roughness: f32) -> Self {
+        Self {
+            metallic,
+            roughness,
+            ..Self::new(MaterialType::Pbr, color)
+        }
+    }
+
+    pub fn principled(color: Vec3, metallic: f32, roughness: f32) -> Self {
+        Self {
+            metallic,
+            roughness,
+            ..Self::new(MaterialType::Principled, color)
+        }
+    }
+
+    // clear surface, the color comes from the path length through the interior
+    pub fn dispersive_dielectric(color: Vec3, ior: f32, abbe_number: f32) -> Self {
+        Self {
+            abbe_number,
+            ..Self::dielectric(color, ior)
+        }
+    }
+
+    pub fn absorbing_dielectric(absorption_color: Vec3, absorption_density: f32, ior: f32) -> Self {
+        Self {
+            absorption_color,
+            absorption_density,
+            ..Self::dielectric(Vec3::all(1.0), ior)
+        }
+    }
+
+    pub fn conductor(ior: ComplexIor, roughness: f32) -> Self {
+        Self {
+            roughness,
+            conductor_eta: ior.eta,
+            conductor_k: ior.k,
+            ..Self::new(MaterialType::Conductor, Vec3::all(1.0))
+        }
+    }
+
+    // polished metals, lower the roughness field for brushed or worn ones
+    pub fn gold() -> Self {
+        Self::conductor(metals::GOLD, 0.1)
+    }
+
+    pub fn silver() -> Self {
+        Self::conductor(metals::SILVER, 0.1)
+    }
+
+    pub fn copper() -> Self {
+        Self::conductor(metals::COPPER, 0.1)
+    }
+
+    pub fn aluminum() -> Self {
+        Self::conductor(metals::ALUMINUM, 0.1)
+    }
+
+    pub fn iron() -> Self {
+        Self::conductor(metals::IRON, 0.1)
+    }
+
+    pub fn chromium() -> Self {
+        Self::conductor(metals::CHROMIUM, 0.1)
+    }
+
+    pub fn subsurface(color: Vec3, subsurface_radius: Vec3) -> Self {
+        Self {
+            subsurface_radius,
+            ..Self::new(MaterialType::Subsurface, color)
         }
     }
 
-    pub fn default() -> Self {
+    // the old encoding: a negative roughness_or_ior is the ior of a dielectric,
+    // otherwise it blends mirror to diffuse, and volume_density < 1 makes a volume
+    pub fn from_legacy(color: Vec3, roughness_or_ior: f32, emission_strength: f32, volume_density: f32) -> Self {
+        let mut material = if volume_density < 1.0 {
+            Self::volume(color, volume_density)
+        } else if roughness_or_ior < 0.0 {
+            Self::dielectric(color, -roughness_or_ior)
+        } else if roughness_or_ior < 1.0 {
+            Self::metal(color, roughness_or_ior)
+        } else {
+            Self::diffuse(color)
+        };
+        material.emission_strength = emission_strength;
+        material
+    }
+
+    pub fn get_type(&self) -> MaterialType {
+        MaterialType::from_u32(self.material_type)
+    }
+
+    pub fn set_type(&mut self, material_type: MaterialType) {
+        self.material_type = material_type as u32;
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
         Self {
             color: Vec3::all(1.0),
-            roughness_or_ior: 1.0,
+            material_type: MaterialType::Diffuse as u32,
+            roughness: 1.0,
+            ior: 1.5,
             emission_strength: 0.0,
             volume_density: 1.0,
-            _pad0: [0; 2],
+            metallic: 0.0,
+            specular: 0.5,
+            albedo_texture: 0,
+            roughness_texture: 0,
+            uv_scale: [1.0; 2],
+            uv_offset: [0.0; 2],
+            metallic_texture: 0,
+            emission_texture: 0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            anisotropy: 0.0,
+            anisotropy_rotation: 0.0,
+            bump_texture: 0,
+            bump_strength: 1.0,
+            flags: 0,
+            subsurface_radius: Vec3::all(0.1),
+            thin_film_thickness: 0.0,
+            thin_film_ior: 1.33,
+            absorption_density: 0.0,
+            _pad1: [0; 2],
+            absorption_color: Vec3::all(1.0),
+            abbe_number: 0.0,
+            // gold unless set
+            conductor_eta: metals::GOLD.eta,
+            opacity: 1.0,
+            conductor_k: metals::GOLD.k,
+            opacity_texture: 0,
+            mix_material_a: 0,
+            mix_material_b: 0,
+            mix_factor: 0.5,
+            mix_texture: 0,
+        }
+    }
+}
+
+// blends two already added materials, e.g. rust over metal:
+// MixMaterial::new(metal_id, rust_id).mask(rust_mask_texture).build()
+// the shader picks one of them per ray, so the inputs may be of any type (even Mix)
+pub struct MixMaterial {
+    material: Material,
+}
+
+impl MixMaterial {
+    pub fn new(material_a: u32, material_b: u32) -> Self {
+        let mut material = Material::new(MaterialType::Mix, Vec3::all(1.0));
+        material.mix_material_a = material_a;
+        material.mix_material_b = material_b;
+        Self { material }
+    }
+
+    // 0 is all of a, 1 all of b
+    pub fn factor(mut self, factor: f32) -> Self {
+        self.material.mix_factor = factor;
+        self
+    }
+
+    // the red channel multiplies the factor, so a lone mask wants factor 1
+    pub fn mask(mut self, texture_id: u32) -> Self {
+        self.material.mix_texture = texture_id;
+        self
+    }
+
+    pub fn uv_transform(mut self, uv_scale: [f32; 2], uv_offset: [f32; 2]) -> Self {
+        self.material.uv_scale = uv_scale;
+        self.material.uv_offset = uv_offset;
+        self
+    }
+
+    pub fn build(self) -> Material {
+        self.material
+    }
+}
+
+// what scene files store, scenes written before material_type existed
+// have roughness_or_ior instead and are converted with Material::from_legacy
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct SerializedMaterial {
+    color: Vec3,
+    material_type: MaterialType,
+    roughness: f32,
+    ior: f32,
+    emission_strength: f32,
+    volume_density: f32,
+    metallic: f32,
+    specular: f32,
+    albedo_texture: u32,
+    roughness_texture: u32,
+    metallic_texture: u32,
+    emission_texture: u32,
+    uv_scale: [f32; 2],
+    uv_offset: [f32; 2],
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    transmission: f32,
+    anisotropy: f32,
+    anisotropy_rotation: f32,
+    bump_texture: u32,
+    bump_strength: f32,
+    cull_backfaces: bool,
+    single_sided: bool,
+    hidden_from_camera: bool,
+    hidden_in_reflections: bool,
+    subsurface_radius: Vec3,
+    thin_film_thickness: f32,
+    thin_film_ior: f32,
+    absorption_color: Vec3,
+    absorption_density: f32,
+    abbe_number: f32,
+    conductor_eta: Vec3,
+    conductor_k: Vec3,
+    opacity: f32,
+    opacity_texture: u32,
+    mix_material_a: u32,
+    mix_material_b: u32,
+    mix_factor: f32,
+    mix_texture: u32,
+    // a name from the metals module, overrides conductor_eta and conductor_k
+    #[serde(skip_serializing)]
+    metal: Option<String>,
+    #[serde(skip_serializing, deserialize_with = "deserialize_legacy_field")]
+    roughness_or_ior: Option<f32>,
+}
+
+// written as a plain number, not as Some(..)
+fn deserialize_legacy_field<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    f32::deserialize(deserializer).map(Some)
+}
+
+impl Default for SerializedMaterial {
+    fn default() -> Self {
+        Material::default().into()
+    }
+}
+
+impl From<SerializedMaterial> for Material {
+    fn from(m: SerializedMaterial) -> Self {
+        let metal = m.metal.as_deref().and_then(|name| {
+            let metal = metals::find(name);
+            if metal.is_none() {
+                println!("unknown metal {}", name);
+            }
+            metal
+        });
+        match m.roughness_or_ior {
+            Some(roughness_or_ior) => {
+                Material::from_legacy(m.color, roughness_or_ior, m.emission_strength, m.volume_density)
+            },
+            None => Material {
+                color: m.color,
+                material_type: m.material_type as u32,
+                roughness: m.roughness,
+                ior: m.ior,
+                emission_strength: m.emission_strength,
+                volume_density: m.volume_density,
+                metallic: m.metallic,
+                specular: m.specular,
+                albedo_texture: m.albedo_texture,
+                roughness_texture: m.roughness_texture,
+                uv_scale: m.uv_scale,
+                uv_offset: m.uv_offset,
+                metallic_texture: m.metallic_texture,
+                emission_texture: m.emission_texture,
+                sheen: m.sheen,
+                sheen_tint: m.sheen_tint,
+                clearcoat: m.clearcoat,
+                clearcoat_roughness: m.clearcoat_roughness,
+                transmission: m.transmission,
+                anisotropy: m.anisotropy,
+                anisotropy_rotation: m.anisotropy_rotation,
+                bump_texture: m.bump_texture,
+                bump_strength: m.bump_strength,
+                flags: (m.cull_backfaces as u32 * Material::CULL_BACKFACES)
+                    | (m.single_sided as u32 * Material::SINGLE_SIDED)
+                    | (m.hidden_from_camera as u32 * Material::HIDDEN_FROM_CAMERA)
+                    | (m.hidden_in_reflections as u32 * Material::HIDDEN_IN_REFLECTIONS),
+                subsurface_radius: m.subsurface_radius,
+                thin_film_thickness: m.thin_film_thickness,
+                thin_film_ior: m.thin_film_ior,
+                absorption_density: m.absorption_density,
+                _pad1: [0; 2],
+                absorption_color: m.absorption_color,
+                abbe_number: m.abbe_number,
+                conductor_eta: metal.map_or(m.conductor_eta, |metal| metal.eta),
+                opacity: m.opacity,
+                conductor_k: metal.map_or(m.conductor_k, |metal| metal.k),
+                opacity_texture: m.opacity_texture,
+                mix_material_a: m.mix_material_a,
+                mix_material_b: m.mix_material_b,
+                mix_factor: m.mix_factor,
+                mix_texture: m.mix_texture,
+            },
+        }
+    }
+}
+
+impl From<Material> for SerializedMaterial {
+    fn from(m: Material) -> Self {
+        Self {
+            color: m.color,
+            material_type: m.get_type(),
+            roughness: m.roughness,
+            ior: m.ior,
+            emission_strength: m.emission_strength,
+            volume_density: m.volume_density,
+            metallic: m.metallic,
+            specular: m.specular,
+            albedo_texture: m.albedo_texture,
+            roughness_texture: m.roughness_texture,
+            metallic_texture: m.metallic_texture,
+            emission_texture: m.emission_texture,
+            uv_scale: m.uv_scale,
+            uv_offset: m.uv_offset,
+            sheen: m.sheen,
+            sheen_tint: m.sheen_tint,
+            clearcoat: m.clearcoat,
+            clearcoat_roughness: m.clearcoat_roughness,
+            transmission: m.transmission,
+            anisotropy: m.anisotropy,
+            anisotropy_rotation: m.anisotropy_rotation,
+            bump_texture: m.bump_texture,
+            bump_strength: m.bump_strength,
+            cull_backfaces: m.flags & Material::CULL_BACKFACES != 0,
+            single_sided: m.flags & Material::SINGLE_SIDED != 0,
+            hidden_from_camera: m.flags & Material::HIDDEN_FROM_CAMERA != 0,
+            hidden_in_reflections: m.flags & Material::HIDDEN_IN_REFLECTIONS != 0,
+            subsurface_radius: m.subsurface_radius,
+            thin_film_thickness: m.thin_film_thickness,
+            thin_film_ior: m.thin_film_ior,
+            absorption_color: m.absorption_color,
+            absorption_density: m.absorption_density,
+            abbe_number: m.abbe_number,
+            conductor_eta: m.conductor_eta,
+            conductor_k: m.conductor_k,
+            opacity: m.opacity,
+            opacity_texture: m.opacity_texture,
+            mix_material_a: m.mix_material_a,
+            mix_material_b: m.mix_material_b,
+            mix_factor: m.mix_factor,
+            mix_texture: m.mix_texture,
+            metal: None,
+            roughness_or_ior: None,
         }
     }
 }
 
+// light linking: the sun, point and area lights only shine on surfaces whose light_mask
+// shares a bit with their own, a mask of 0 counts as this one so by default everything
+// is linked, discs always have this mask and glowing surfaces and the environment are not linked
+pub const LIGHT_MASK_DEFAULT: u32 = 1;
+
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
 // size 32
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
     pub material_id: u32,
-    _pad0: [u32; 3],
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    #[serde(skip)]
+    _pad0: [u32; 2],
 }
 
 impl Sphere {
@@ -119,59 +931,81 @@ impl Sphere {
             center,
             radius,
             material_id,
-            _pad0: [0; 3],
+            light_mask: 0,
+            _pad0: [0; 2],
         }
     }
+}
 
-    pub fn default() -> Self {
+impl Default for Sphere {
+    fn default() -> Self {
         Self {
             radius: 1.0,
             material_id: 0,
             center: Vec3::zero(),
-            _pad0: [0; 3],
+            light_mask: 0,
+            _pad0: [0; 2],
         }
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-// size 64
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 128
 pub struct Triangle {
     pub vertex_0: Vec3,
-    _pad0: u32,
+    // packed rgba8 vertex colors multiplied with the material color
+    // an alpha of 0 means the triangle has no vertex colors
+    pub color_0: u32,
     pub vertex_1: Vec3,
-    _pad1: u32,
+    pub color_1: u32,
     pub vertex_2: Vec3,
-    _pad2: u32,
+    pub color_2: u32,
+    // shading normals, all zero means flat shading with the face normal
+    pub normal_0: Vec3,
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    pub normal_1: Vec3,
+    #[serde(skip)]
+    _pad1: u32,
+    pub normal_2: Vec3,
     pub material_id: u32,
-    _pad3: [u32; 3],
+    // texture coordinates with v pointing down the image
+    pub uv_0: [f32; 2],
+    pub uv_1: [f32; 2],
+    pub uv_2: [f32; 2],
+    #[serde(skip)]
+    _pad2: [u32; 2],
 }
 
 impl Triangle {
     pub fn new(vertices: [Vec3; 3], material_id: u32) -> Self {
         Self {
             vertex_0: vertices[0],
-            _pad0: 0,
             vertex_1: vertices[1],
-            _pad1: 0,
             vertex_2: vertices[2],
-            _pad2: 0,
             material_id,
-            _pad3: [0; 3],
+            ..Default::default()
         }
     }
 
-    pub fn default() -> Self {
-        Self {
-            vertex_0: Vec3::zero(),
-            _pad0: 0,
-            vertex_1: Vec3::zero(),
-            _pad1: 0,
-            vertex_2: Vec3::zero(),
-            _pad2: 0,
-            material_id: 0,
-            _pad3: [0; 3],
-        }
+    pub fn set_normals(&mut self, normals: [Vec3; 3]) {
+        self.normal_0 = normals[0];
+        self.normal_1 = normals[1];
+        self.normal_2 = normals[2];
+    }
+
+    pub fn set_uvs(&mut self, uvs: [[f32; 2]; 3]) {
+        self.uv_0 = uvs[0];
+        self.uv_1 = uvs[1];
+        self.uv_2 = uvs[2];
+    }
+
+    pub fn set_colors(&mut self, colors: [Vec3; 3]) {
+        self.color_0 = pack_color(colors[0]);
+        self.color_1 = pack_color(colors[1]);
+        self.color_2 = pack_color(colors[2]);
     }
 
     pub fn bounding_box(self) -> (Vec3, Vec3) {
@@ -202,80 +1036,221 @@ impl Triangle {
     pub fn center(self) -> Vec3 {
         (self.vertex_0 + self.vertex_1 + self.vertex_2) / 3.0
     }
-}
 
-const TRIANGLES_PER_LEAF: usize = 7;
+    pub fn normal(self) -> Vec3 {
+        (self.vertex_1 - self.vertex_0).cross(&(self.vertex_2 - self.vertex_0)).normalized()
+    }
+
+    pub fn area(self) -> f32 {
+        (self.vertex_1 - self.vertex_0).cross(&(self.vertex_2 - self.vertex_0)).length() * 0.5
+    }
+}
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-// size 64
-pub struct BVHNode {
-    pub bbox_min: Vec3,
-    pub child1: u32,
-    pub bbox_max: Vec3,
-    pub child2: u32,
-    pub triangle_count: u32,
-    pub triangle_ids: [u32; TRIANGLES_PER_LEAF],
-    // _pad0: [u32; 3],
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 32
+// double sided oriented disc, used to splat point clouds
+pub struct Disc {
+    pub center: Vec3,
+    pub radius: f32,
+    pub normal: Vec3,
+    pub material_id: u32,
 }
 
-impl BVHNode {
-    pub fn default() -> Self {
+impl Disc {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material_id: u32) -> Self {
         Self {
-            bbox_min: Vec3::zero(),
-            child1: 0,
-            bbox_max: Vec3::zero(),
-            child2: 0,
-            triangle_count: 0,
-            triangle_ids: [0; TRIANGLES_PER_LEAF],
-            // _pad0: [0; 3],
+            center,
+            radius,
+            normal: normal.normalized(),
+            material_id,
         }
     }
+}
 
-    pub fn bvh_build(
-        tris: &mut [Triangle],
-        tri_indices: &mut [usize],
-        tree: &mut Vec<BVHNode>,
-        max_triangles_per_leaf: usize
-    ) -> u32 {
-        let node_index = tree.len() as u32;
-
-        // compute bbox for current node
-        let mut bbox_min = Vec3::all(f32::INFINITY);
-        let mut bbox_max = Vec3::all(f32::NEG_INFINITY);
-        for i in tri_indices.iter() {
-            let (tris_bbox_min, tris_bbox_max) = tris[*i].bounding_box();
-            bbox_min = bbox_min.min(tris_bbox_min);
-            bbox_max = bbox_max.max(tris_bbox_max);
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 80
+// cubic bezier segment swept by a radius linearly interpolated from radius_0 to radius_1
+pub struct Curve {
+    pub control_0: Vec3,
+    pub radius_0: f32,
+    pub control_1: Vec3,
+    #[serde(skip)]
+    _pad0: u32,
+    pub control_2: Vec3,
+    #[serde(skip)]
+    _pad1: u32,
+    pub control_3: Vec3,
+    pub radius_1: f32,
+    pub material_id: u32,
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    #[serde(skip)]
+    _pad2: [u32; 2],
+}
+
+impl Curve {
+    pub fn new(controls: [Vec3; 4], radius_0: f32, radius_1: f32, material_id: u32) -> Self {
+        Self {
+            control_0: controls[0],
+            radius_0,
+            control_1: controls[1],
+            _pad0: 0,
+            control_2: controls[2],
+            _pad1: 0,
+            control_3: controls[3],
+            radius_1,
+            material_id,
+            light_mask: 0,
+            _pad2: [0; 2],
         }
+    }
+}
 
-        for i in 0..3  {
-            if (bbox_max[i] - bbox_min[i]).abs() < 1e-4 {
-                bbox_max[i] += 0.01;
-                bbox_min[i] -= 0.01;
-            }
+// packs a [0, 1] color into rgba8 with full alpha, matching unpack4x8unorm in the shader
+pub fn pack_color(color: Vec3) -> u32 {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u32;
+    channel(color.x()) | channel(color.y()) << 8 | channel(color.z()) << 16 | 255 << 24
+}
+
+// what a bvh can be built over
+pub trait BVHPrimitive {
+    fn bvh_bounds(&self) -> (Vec3, Vec3);
+    fn bvh_center(&self) -> Vec3;
+}
+
+impl BVHPrimitive for Triangle {
+    fn bvh_bounds(&self) -> (Vec3, Vec3) {
+        self.bounding_box()
+    }
+
+    fn bvh_center(&self) -> Vec3 {
+        self.center()
+    }
+}
+
+impl BVHPrimitive for Sphere {
+    fn bvh_bounds(&self) -> (Vec3, Vec3) {
+        (self.center - Vec3::all(self.radius), self.center + Vec3::all(self.radius))
+    }
+
+    fn bvh_center(&self) -> Vec3 {
+        self.center
+    }
+}
+
+// the control points hull the curve
+impl BVHPrimitive for Curve {
+    fn bvh_bounds(&self) -> (Vec3, Vec3) {
+        let radius = Vec3::all(self.radius_0.max(self.radius_1));
+        [self.control_1, self.control_2, self.control_3].iter().fold(
+            (self.control_0 - radius, self.control_0 + radius),
+            |(min, max), &control| (min.min(control - radius), max.max(control + radius)),
+        )
+    }
+
+    fn bvh_center(&self) -> Vec3 {
+        let (min, max) = self.bvh_bounds();
+        (min + max) * 0.5
+    }
+}
+
+impl BVHPrimitive for Disc {
+    fn bvh_bounds(&self) -> (Vec3, Vec3) {
+        (self.center - Vec3::all(self.radius), self.center + Vec3::all(self.radius))
+    }
+
+    fn bvh_center(&self) -> Vec3 {
+        self.center
+    }
+}
+
+// relative costs of stepping into a node and of testing one primitive, for the
+// surface area heuristic
+const BVH_TRAVERSAL_COST: f32 = 1.0;
+const BVH_INTERSECTION_COST: f32 = 1.0;
+
+fn box_surface_area(bbox_min: Vec3, bbox_max: Vec3) -> f32 {
+    let d = (bbox_max - bbox_min).max(Vec3::zero());
+    2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 48
+// an opening (window, doorway) the environment shines through, diffuse surfaces
+// sample the environment light through portals instead of hoping to escape by chance
+// the parallelogram is spanned by edge_u and edge_v from corner, it is never hit by rays
+pub struct Portal {
+    pub corner: Vec3,
+    #[serde(skip)]
+    _pad0: u32,
+    pub edge_u: Vec3,
+    #[serde(skip)]
+    _pad1: u32,
+    pub edge_v: Vec3,
+    #[serde(skip)]
+    _pad2: u32,
+}
+
+impl Portal {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3) -> Self {
+        Self {
+            corner,
+            edge_u,
+            edge_v,
+            ..Default::default()
         }
+    }
+}
 
-        // create leaf node
-        if tri_indices.len() <= TRIANGLES_PER_LEAF {
-            let mut node = BVHNode::default();
-            node.bbox_min = bbox_min;
-            node.bbox_max = bbox_max;
-            node.triangle_count = tri_indices.len() as u32;
-            node.triangle_ids = {
-                let mut triangle_ids = [0; TRIANGLES_PER_LEAF];
-                for i in 0..tri_indices.len() {
-                    triangle_ids[i] = tri_indices[i] as u32;
-                }
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+// size 32
+// hierarchy over the glowing triangles, surfaces sampling them walk down from the root
+// picking the child that likely sends them more light, built by Gfx::scene_update
+pub struct LightTreeNode {
+    pub bbox_min: Vec3,
+    // emitted power of every triangle below
+    pub power: f32,
+    pub bbox_max: Vec3,
+    // second child of an inner node, the first one directly follows it
+    // leaves hold their triangle instead, marked with LEAF
+    pub child_or_triangle: u32,
+}
 
-                triangle_ids
-            };
-            tree.push(node);
+impl LightTreeNode {
+    pub const LEAF: u32 = 1 << 31;
+
+    // emitters are (triangle id, power) pairs, nodes are appended depth first
+    pub fn build(tris: &[Triangle], emitters: &mut [(u32, f32)], tree: &mut Vec<LightTreeNode>) -> u32 {
+        let node_index = tree.len() as u32;
 
+        let mut bbox_min = Vec3::all(f32::INFINITY);
+        let mut bbox_max = Vec3::all(f32::NEG_INFINITY);
+        let mut power = 0.0;
+        for (triangle_id, triangle_power) in emitters.iter() {
+            let (tri_bbox_min, tri_bbox_max) = tris[*triangle_id as usize].bounding_box();
+            bbox_min = bbox_min.min(tri_bbox_min);
+            bbox_max = bbox_max.max(tri_bbox_max);
+            power += triangle_power;
+        }
+
+        if let [(triangle_id, _)] = emitters {
+            tree.push(LightTreeNode {
+                bbox_min,
+                power,
+                bbox_max,
+                child_or_triangle: *triangle_id | LightTreeNode::LEAF,
+            });
             return node_index;
         }
 
-        // find longest axis
+        // split at the median along the longest axis
         let dbox = bbox_max - bbox_min;
         let axis = if dbox[0] > dbox[1] && dbox[0] > dbox[2] {
             0
@@ -284,23 +1259,385 @@ impl BVHNode {
         } else {
             2
         };
+        emitters.sort_by(|a, b| {
+            let a_center = tris[a.0 as usize].center();
+            let b_center = tris[b.0 as usize].center();
+            a_center[axis].total_cmp(&b_center[axis])
+        });
+
+        // placeholder so the first child lands right after its parent
+        tree.push(LightTreeNode::default());
+
+        let mid = emitters.len() / 2;
+        let (first, second) = emitters.split_at_mut(mid);
+        LightTreeNode::build(tris, first, tree);
+        let second_child = LightTreeNode::build(tris, second, tree);
+
+        tree[node_index as usize] = LightTreeNode {
+            bbox_min,
+            power,
+            bbox_max,
+            child_or_triangle: second_child,
+        };
+
+        node_index
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 64
+// a rectangular light spanned by edge_u and edge_v from corner, sampled directly by
+// diffuse surfaces, the edges must be perpendicular
+// it emits on the side cross(edge_u, edge_v) points to and does not block light
+pub struct AreaLight {
+    pub corner: Vec3,
+    #[serde(skip)]
+    _pad0: u32,
+    pub edge_u: Vec3,
+    #[serde(skip)]
+    _pad1: u32,
+    pub edge_v: Vec3,
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    // emitted radiance
+    pub emission: Vec3,
+    // Material::HIDDEN_FROM_CAMERA and HIDDEN_IN_REFLECTIONS bits, a hidden light is
+    // passed through as if it was not there
+    pub flags: u32,
+}
+
+impl AreaLight {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, emission: Vec3) -> Self {
+        Self {
+            corner,
+            edge_u,
+            edge_v,
+            emission,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 64
+// an infinitely small light sampled directly by diffuse surfaces, rays never hit it
+// spot lights are point lights that fade out between the inner and outer cone angle
+pub struct PointLight {
+    pub position: Vec3,
+    // cone angles in radians from direction, an outer angle of pi or more shines everywhere
+    pub inner_angle: f32,
+    pub direction: Vec3,
+    pub outer_angle: f32,
+    // radiant intensity, light per unit solid angle
+    // with an ies profile this is the intensity in its brightest direction
+    pub intensity: Vec3,
+    // measured distribution around direction from Gfx::load_ies_profile, 0 for none
+    pub ies_profile: u32,
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    #[serde(skip)]
+    _pad0: [u32; 3],
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self::new(Vec3::zero(), Vec3::all(1.0))
+    }
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self {
+            position,
+            inner_angle: std::f32::consts::PI,
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            outer_angle: std::f32::consts::PI,
+            intensity,
+            ies_profile: 0,
+            light_mask: 0,
+            _pad0: [0; 3],
+        }
+    }
+
+    pub fn spot(position: Vec3, direction: Vec3, intensity: Vec3, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            position,
+            inner_angle,
+            direction: direction.normalized(),
+            outer_angle,
+            intensity,
+            ies_profile: 0,
+            light_mask: 0,
+            _pad0: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 48
+// a distant disc light like the sun, diffuse surfaces sample it directly
+// a zero irradiance turns it off
+pub struct SunLight {
+    // towards the light
+    pub direction: Vec3,
+    // in radians, the real sun spans about half a degree
+    pub angular_diameter: f32,
+    // light arriving at a surface facing the sun
+    pub irradiance: Vec3,
+    // Material::HIDDEN_FROM_CAMERA and HIDDEN_IN_REFLECTIONS bits for the disc
+    pub flags: u32,
+    // see LIGHT_MASK_DEFAULT
+    pub light_mask: u32,
+    #[serde(skip)]
+    _pad0: [u32; 3],
+}
+
+impl Default for SunLight {
+    fn default() -> Self {
+        Self::new(Vec3::new(0.0, 1.0, 0.0), Vec3::all(0.0))
+    }
+}
+
+impl SunLight {
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        Self {
+            direction: direction.normalized(),
+            angular_diameter: 0.53f32.to_radians(),
+            irradiance,
+            flags: 0,
+            light_mask: 0,
+            _pad0: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 32
+// participating medium filling the whole scene for haze and god rays, its density is
+// constant up to height and falls off exponentially above
+// a zero density turns it off
+pub struct Fog {
+    // scattering albedo
+    pub color: Vec3,
+    // extinction per unit length at and below height
+    pub density: f32,
+    pub height: f32,
+    // per unit of height above height, 0 for the same density everywhere
+    pub falloff: f32,
+    // henyey-greenstein g, towards 1 light keeps going forward which brightens god rays
+    // seen against the light
+    pub anisotropy: f32,
+    #[serde(skip)]
+    _pad0: u32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl Fog {
+    pub fn new(density: f32, height: f32, falloff: f32) -> Self {
+        Self {
+            color: Vec3::all(1.0),
+            density,
+            height,
+            falloff,
+            anisotropy: 0.0,
+            _pad0: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+// size 32
+// Preetham daylight sky, x points east, y up and -z north
+pub struct PhysicalSky {
+    // towards the sun
+    pub sun_direction: Vec3,
+    // haziness of the atmosphere, 2 is a clear day and 10 is hazy
+    pub turbidity: f32,
+    // scales the sky radiance, 1 keeps the zenith of a clear noon sky near 1
+    pub intensity: f32,
+    #[serde(skip)]
+    _pad0: [u32; 3],
+}
+
+impl Default for PhysicalSky {
+    fn default() -> Self {
+        Self::from_time_of_day(12.0, 45.0, 172)
+    }
+}
+
+impl PhysicalSky {
+    pub fn new(sun_direction: Vec3, turbidity: f32) -> Self {
+        Self {
+            sun_direction: sun_direction.normalized(),
+            turbidity,
+            intensity: 1.0,
+            _pad0: [0; 3],
+        }
+    }
+
+    // local solar time for a clock time in hours at a longitude in degrees east, in a time
+    // zone utc_offset hours ahead of utc, ignoring the equation of time
+    pub fn solar_time(clock_time: f32, longitude: f32, utc_offset: f32) -> f32 {
+        clock_time + longitude / 15.0 - utc_offset
+    }
+
+    // sun position for a local solar time in hours at a latitude in degrees,
+    // day_of_year counts from 1 on january 1st
+    pub fn sun_direction(time_of_day: f32, latitude: f32, day_of_year: u32) -> Vec3 {
+        let declination = (-23.44f32).to_radians()
+            * (2.0 * std::f32::consts::PI / 365.0 * (day_of_year as f32 + 10.0)).cos();
+        let hour_angle = ((time_of_day - 12.0) * 15.0).to_radians();
+        let latitude = latitude.to_radians();
+
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+        let up = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+
+        Vec3::new(east, up, -north).normalized()
+    }
+
+    pub fn from_time_of_day(time_of_day: f32, latitude: f32, day_of_year: u32) -> Self {
+        Self::new(Self::sun_direction(time_of_day, latitude, day_of_year), 2.5)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+// size 48
+// a density grid filling an axis aligned box, the voxels live in a separate buffer
+// the material's volume_density scales the grid and its color is the scattering albedo
+pub struct Volume {
+    pub bbox_min: Vec3,
+    pub material_id: u32,
+    pub bbox_max: Vec3,
+    // largest voxel value, the majorant for tracking
+    pub max_density: f32,
+    pub resolution: [u32; 3],
+    // first voxel in the volume buffer, x varies fastest
+    pub data_offset: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+// size 48
+pub struct BVHNode {
+    pub bbox_min: Vec3,
+    pub child1: u32,
+    pub bbox_max: Vec3,
+    pub child2: u32,
+    // a leaf covers primitive_count entries of the bvh primitive list from first_primitive
+    pub first_primitive: u32,
+    pub primitive_count: u32,
+    _pad0: [u32; 2],
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BVHBuildOptions {
+    pub max_primitives_per_leaf: usize,
+    // how many buckets split candidates are taken from, fewer than 2 splits at the
+    // median of the longest axis instead
+    pub sah_bins: usize,
+    // once the tree would grow past this many nodes the rest becomes larger leaves
+    pub max_nodes: usize,
+}
+
+impl Default for BVHBuildOptions {
+    fn default() -> Self {
+        Self {
+            max_primitives_per_leaf: 8,
+            sah_bins: 16,
+            max_nodes: usize::MAX,
+        }
+    }
+}
+
+impl BVHNode {
+    // indices is reordered into the bvh primitive list the leaves point into, nodes
+    // are appended to tree so several trees can share it
+    pub fn bvh_build<T: BVHPrimitive>(
+        primitives: &[T],
+        indices: &mut [usize],
+        tree: &mut Vec<BVHNode>,
+        options: BVHBuildOptions,
+    ) -> u32 {
+        // the budget counts the nodes of every tree in it, the root always fits
+        let max_nodes = options.max_nodes.max(tree.len() + 1);
+        BVHNode::build_node(primitives, indices, 0, tree, max_nodes, &options)
+    }
+
+    // indices starts at first in the whole list, every node pushed is below max_nodes
+    fn build_node<T: BVHPrimitive>(
+        primitives: &[T],
+        indices: &mut [usize],
+        first: usize,
+        tree: &mut Vec<BVHNode>,
+        max_nodes: usize,
+        options: &BVHBuildOptions,
+    ) -> u32 {
+        let node_index = tree.len() as u32;
+
+        // compute bbox for current node
+        let (bbox_min, bbox_max) = BVHNode::leaf_bounds(primitives, indices.iter().copied());
 
-        // sort along axis
-        tri_indices.sort_by(|&a, &b| {
-            let a_center = &tris[a].center();
-            let b_center = &tris[b].center();
-            a_center[axis].partial_cmp(&b_center[axis]).unwrap()
+        // create leaf node, also when the budget has no room left for two children
+        if indices.len() <= options.max_primitives_per_leaf.max(1) || node_index as usize + 3 > max_nodes {
+            tree.push(BVHNode {
+                bbox_min,
+                bbox_max,
+                first_primitive: first as u32,
+                primitive_count: indices.len() as u32,
+                ..Default::default()
+            });
+
+            return node_index;
+        }
+
+        let (axis, mid) = BVHNode::find_sah_split(primitives, indices, options.sah_bins).unwrap_or_else(|| {
+            // find longest axis
+            let dbox = bbox_max - bbox_min;
+            let axis = if dbox[0] > dbox[1] && dbox[0] > dbox[2] {
+                0
+            } else if dbox[1] > dbox[2] {
+                1
+            } else {
+                2
+            };
+            (axis, indices.len() / 2)
+        });
+
+        // sort along axis, the primitives in the bins left of a sah split come first
+        indices.sort_by(|&a, &b| {
+            let a_center = &primitives[a].bvh_center();
+            let b_center = &primitives[b].bvh_center();
+            a_center[axis].total_cmp(&b_center[axis])
         });
 
         // push dummy parent node before creating children
         // to preserve node_index
         tree.push(BVHNode::default());
 
-        let mid = tri_indices.len() / 2;
-        let (left_indices, right_indices) = tri_indices.split_at_mut(mid);
+        let (left_indices, right_indices) = indices.split_at_mut(mid);
 
-        let child1 = BVHNode::bvh_build(tris, left_indices, tree, max_triangles_per_leaf);
-        let child2 = BVHNode::bvh_build(tris, right_indices, tree, max_triangles_per_leaf);
+        // one node is held back for the second child
+        let child1 = BVHNode::build_node(primitives, left_indices, first, tree, max_nodes - 1, options);
+        let child2 = BVHNode::build_node(primitives, right_indices, first + mid, tree, max_nodes, options);
 
         // update parent node
         let current_node = &mut tree[node_index as usize];
@@ -308,35 +1645,401 @@ impl BVHNode {
         current_node.child2 = child2;
         current_node.bbox_min = bbox_min;
         current_node.bbox_max = bbox_max;
-        current_node.triangle_count = 0;
-        current_node.triangle_ids = [0; TRIANGLES_PER_LEAF];
 
         node_index
     }
+
+    // bounds of the primitives, widened along flat axes so rays still hit them
+    fn leaf_bounds<T: BVHPrimitive>(primitives: &[T], indices: impl Iterator<Item = usize>) -> (Vec3, Vec3) {
+        let mut bbox_min = Vec3::all(f32::INFINITY);
+        let mut bbox_max = Vec3::all(f32::NEG_INFINITY);
+        for i in indices {
+            let (primitive_bbox_min, primitive_bbox_max) = primitives[i].bvh_bounds();
+            bbox_min = bbox_min.min(primitive_bbox_min);
+            bbox_max = bbox_max.max(primitive_bbox_max);
+        }
+
+        for i in 0..3  {
+            if (bbox_max[i] - bbox_min[i]).abs() < 1e-4 {
+                bbox_max[i] += 0.01;
+                bbox_min[i] -= 0.01;
+            }
+        }
+
+        (bbox_min, bbox_max)
+    }
+
+    // recompute the bounds of tree[nodes] after its primitives moved, keeping the shape
+    // children always come after their parent so one backwards pass is enough
+    pub fn bvh_refit<T: BVHPrimitive>(
+        primitives: &[T],
+        primitive_ids: &[u32],
+        tree: &mut [BVHNode],
+        nodes: std::ops::Range<usize>,
+    ) {
+        for node_index in nodes.rev() {
+            let node = tree[node_index];
+            let (bbox_min, bbox_max) = if node.primitive_count > 0 {
+                let first = node.first_primitive as usize;
+                let ids = &primitive_ids[first..first + node.primitive_count as usize];
+                BVHNode::leaf_bounds(primitives, ids.iter().map(|id| *id as usize))
+            } else {
+                let child1 = tree[node.child1 as usize];
+                let child2 = tree[node.child2 as usize];
+                (child1.bbox_min.min(child2.bbox_min), child1.bbox_max.max(child2.bbox_max))
+            };
+            tree[node_index].bbox_min = bbox_min;
+            tree[node_index].bbox_max = bbox_max;
+        }
+    }
+
+    // the axis and the number of primitives going left of the cheapest split between
+    // sah_bins equal buckets of primitive centers, None when there is nothing to bin
+    fn find_sah_split<T: BVHPrimitive>(primitives: &[T], indices: &[usize], sah_bins: usize) -> Option<(usize, usize)> {
+        if sah_bins < 2 {
+            return None;
+        }
+
+        let mut center_min = Vec3::all(f32::INFINITY);
+        let mut center_max = Vec3::all(f32::NEG_INFINITY);
+        for i in indices.iter() {
+            let center = primitives[*i].bvh_center();
+            center_min = center_min.min(center);
+            center_max = center_max.max(center);
+        }
+
+        // (cost, axis, left count)
+        let mut best: Option<(f32, usize, usize)> = None;
+        for axis in 0..3 {
+            let extent = center_max[axis] - center_min[axis];
+            if extent <= 0.0 {
+                continue;
+            }
+
+            // (bbox min, bbox max, primitive count) of every bucket
+            let mut bins = vec![(Vec3::all(f32::INFINITY), Vec3::all(f32::NEG_INFINITY), 0); sah_bins];
+            for i in indices.iter() {
+                let center = primitives[*i].bvh_center();
+                let bin = (((center[axis] - center_min[axis]) / extent * sah_bins as f32) as usize).min(sah_bins - 1);
+                let (primitive_bbox_min, primitive_bbox_max) = primitives[*i].bvh_bounds();
+                bins[bin].0 = bins[bin].0.min(primitive_bbox_min);
+                bins[bin].1 = bins[bin].1.max(primitive_bbox_max);
+                bins[bin].2 += 1;
+            }
+
+            // areas and counts of everything right of each split, swept from the end
+            let mut right_area = vec![0.0; sah_bins];
+            let mut right_count = vec![0; sah_bins];
+            let (mut sweep_min, mut sweep_max, mut sweep_count) = (Vec3::all(f32::INFINITY), Vec3::all(f32::NEG_INFINITY), 0);
+            for bin in (1..sah_bins).rev() {
+                sweep_min = sweep_min.min(bins[bin].0);
+                sweep_max = sweep_max.max(bins[bin].1);
+                sweep_count += bins[bin].2;
+                right_area[bin] = box_surface_area(sweep_min, sweep_max);
+                right_count[bin] = sweep_count;
+            }
+
+            let (mut sweep_min, mut sweep_max, mut sweep_count) = (Vec3::all(f32::INFINITY), Vec3::all(f32::NEG_INFINITY), 0);
+            for bin in 1..sah_bins {
+                sweep_min = sweep_min.min(bins[bin - 1].0);
+                sweep_max = sweep_max.max(bins[bin - 1].1);
+                sweep_count += bins[bin - 1].2;
+                if sweep_count == 0 || right_count[bin] == 0 {
+                    continue;
+                }
+
+                // the parent's area is the same for every candidate, so it is left out
+                let cost = box_surface_area(sweep_min, sweep_max) * sweep_count as f32
+                    + right_area[bin] * right_count[bin] as f32;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, sweep_count));
+                }
+            }
+        }
+
+        best.map(|(_, axis, left_count)| (axis, left_count))
+    }
+
+    // expected cost of tracing a random ray through the tree rooted at tree[0] by the
+    // surface area heuristic, lower is better
+    pub fn sah_cost(tree: &[BVHNode]) -> f32 {
+        let Some(root) = tree.first() else {
+            return 0.0;
+        };
+        let root_area = box_surface_area(root.bbox_min, root.bbox_max);
+        if root_area <= 0.0 {
+            return 0.0;
+        }
+
+        tree.iter()
+            .map(|node| {
+                let probability = box_surface_area(node.bbox_min, node.bbox_max) / root_area;
+                if node.primitive_count > 0 {
+                    probability * node.primitive_count as f32 * BVH_INTERSECTION_COST
+                } else {
+                    probability * BVH_TRAVERSAL_COST
+                }
+            })
+            .sum()
+    }
 }
 
+// how many of each fit in the arrays of Scene, the shader has the same sizes
+pub const MAX_MATERIALS: usize = 64;
+pub const MAX_SPHERES: usize = 1024;
+pub const MAX_VOLUMES: usize = 16;
+pub const MAX_PORTALS: usize = 16;
+pub const MAX_POINT_LIGHTS: usize = 64;
+pub const MAX_AREA_LIGHTS: usize = 16;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct Scene {
-    pub materials: [Material; 64],
-    pub spheres: [Sphere; 64],
-    pub triangles: [Triangle; 256],
+    pub materials: [Material; MAX_MATERIALS],
+    pub spheres: [Sphere; MAX_SPHERES],
     pub sphere_count: u32,
+    // the triangles, curves and discs themselves and the bvh over them are in separate
+    // buffers, the sphere bvh follows the triangle bvh rooted at 0, then the curve and
+    // disc bvh
     pub triangle_count: u32,
-    _pad0: [u32; 2],
-    pub bvh: [BVHNode; 96],
+    pub curve_count: u32,
+    pub disc_count: u32,
+    pub sphere_bvh_root: u32,
+    pub curve_bvh_root: u32,
+    pub disc_bvh_root: u32,
+    pub volume_count: u32,
+    pub volumes: [Volume; MAX_VOLUMES],
+    pub portal_count: u32,
+    _pad0: [u32; 3],
+    pub portals: [Portal; MAX_PORTALS],
+    pub sun: SunLight,
+    pub point_light_count: u32,
+    _pad1: [u32; 3],
+    pub point_lights: [PointLight; MAX_POINT_LIGHTS],
+    pub area_light_count: u32,
+    _pad2: [u32; 3],
+    pub area_lights: [AreaLight; MAX_AREA_LIGHTS],
+    // the light tree over them is in a separate buffer
+    pub emissive_triangle_count: u32,
+    _pad3: [u32; 3],
+    pub fog: Fog,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Self {
-            materials: [Material::default(); 64],
-            spheres: [Sphere::default(); 64],
-            triangles: [Triangle::default(); 256],
+            materials: [Material::default(); MAX_MATERIALS],
+            spheres: [Sphere::default(); MAX_SPHERES],
             sphere_count: 0,
             triangle_count: 0,
-            _pad0: [0; 2],
-            bvh: [BVHNode::default(); 96],
+            curve_count: 0,
+            disc_count: 0,
+            sphere_bvh_root: 0,
+            curve_bvh_root: 0,
+            disc_bvh_root: 0,
+            volume_count: 0,
+            volumes: [Volume::default(); MAX_VOLUMES],
+            portal_count: 0,
+            _pad0: [0; 3],
+            portals: [Portal::default(); MAX_PORTALS],
+            sun: SunLight::default(),
+            point_light_count: 0,
+            _pad1: [0; 3],
+            point_lights: [PointLight::default(); MAX_POINT_LIGHTS],
+            area_light_count: 0,
+            _pad2: [0; 3],
+            area_lights: [AreaLight::default(); MAX_AREA_LIGHTS],
+            emissive_triangle_count: 0,
+            _pad3: [0; 3],
+            fog: Fog::default(),
+        }
+    }
+
+    // box around every sphere and the given triangles, curves and discs, None for an
+    // empty scene
+    pub fn bounds(&self, triangles: &[Triangle], curves: &[Curve], discs: &[Disc]) -> Option<(Vec3, Vec3)> {
+        let mut bounds: Option<(Vec3, Vec3)> = None;
+        let mut grow = |point: Vec3, radius: f32| {
+            let (low, high) = (point - Vec3::all(radius), point + Vec3::all(radius));
+            bounds = Some(match bounds {
+                Some((min, max)) => (min.min(low), max.max(high)),
+                None => (low, high),
+            });
+        };
+
+        for sphere in &self.spheres[..self.sphere_count as usize] {
+            grow(sphere.center, sphere.radius);
+        }
+        for tri in triangles {
+            grow(tri.vertex_0, 0.0);
+            grow(tri.vertex_1, 0.0);
+            grow(tri.vertex_2, 0.0);
+        }
+        // the control points hull the curve
+        for curve in curves {
+            let radius = curve.radius_0.max(curve.radius_1);
+            for control in [curve.control_0, curve.control_1, curve.control_2, curve.control_3] {
+                grow(control, radius);
+            }
+        }
+        for disc in discs {
+            grow(disc.center, disc.radius);
+        }
+
+        bounds
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discs(count: usize) -> Vec<Disc> {
+        (0..count)
+            .map(|i| {
+                let cluster = (i % 4) as f32 * 10.0;
+                let offset = (i / 4) as f32 * 0.25;
+                Disc::new(Vec3::new(cluster + offset, offset * 0.5, -offset), Vec3::new(0.0, 1.0, 0.0), 0.1, 0)
+            })
+            .collect()
+    }
+
+    fn contains(node: &BVHNode, (min, max): (Vec3, Vec3)) -> bool {
+        (0..3).all(|i| node.bbox_min[i] <= min[i] && max[i] <= node.bbox_max[i])
+    }
+
+    // every primitive is in exactly one leaf and every node bounds what is below it
+    fn check_tree<T: BVHPrimitive>(prims: &[T], indices: &[usize], tree: &[BVHNode], root: usize) {
+        let mut seen = vec![0; prims.len()];
+        let mut stack = vec![root];
+        while let Some(node_index) = stack.pop() {
+            let node = &tree[node_index];
+            if node.primitive_count > 0 {
+                let first = node.first_primitive as usize;
+                for &i in &indices[first..first + node.primitive_count as usize] {
+                    seen[i] += 1;
+                    assert!(contains(node, prims[i].bvh_bounds()), "leaf {} misses {}", node_index, i);
+                }
+            } else {
+                for child in [node.child1 as usize, node.child2 as usize] {
+                    assert!(child > node_index, "children come after their parent");
+                    assert!(contains(node, (tree[child].bbox_min, tree[child].bbox_max)));
+                    stack.push(child);
+                }
+            }
+        }
+        assert!(seen.iter().all(|&count| count == 1), "{:?}", seen);
+    }
+
+    #[test]
+    fn bvh_build_covers_every_primitive_once() {
+        let prims = discs(200);
+        for sah_bins in [0, 16] {
+            let mut indices: Vec<usize> = (0..prims.len()).collect();
+            let mut tree = vec![];
+            let options = BVHBuildOptions { sah_bins, ..Default::default() };
+            let root = BVHNode::bvh_build(&prims, &mut indices, &mut tree, options);
+            check_tree(&prims, &indices, &tree, root as usize);
+        }
+    }
+
+    #[test]
+    fn bvh_build_can_share_a_tree() {
+        let prims = discs(40);
+        let mut tree = vec![];
+        let mut indices: Vec<usize> = (0..prims.len()).collect();
+        BVHNode::bvh_build(&prims, &mut indices, &mut tree, BVHBuildOptions::default());
+
+        let mut second_indices: Vec<usize> = (0..prims.len()).collect();
+        let second_root = BVHNode::bvh_build(&prims, &mut second_indices, &mut tree, BVHBuildOptions::default());
+        assert!(second_root as usize > 0);
+        check_tree(&prims, &second_indices, &tree, second_root as usize);
+    }
+
+    #[test]
+    fn bvh_build_stays_within_the_node_budget() {
+        let prims = discs(500);
+        for max_nodes in [1, 2, 3, 10, 101] {
+            let mut indices: Vec<usize> = (0..prims.len()).collect();
+            let mut tree = vec![];
+            let options = BVHBuildOptions { max_nodes, ..Default::default() };
+            let root = BVHNode::bvh_build(&prims, &mut indices, &mut tree, options);
+            assert!(tree.len() <= max_nodes, "{} nodes for a budget of {}", tree.len(), max_nodes);
+            check_tree(&prims, &indices, &tree, root as usize);
+        }
+    }
+
+    #[test]
+    fn bvh_refit_bounds_moved_primitives() {
+        let mut prims = discs(100);
+        let mut indices: Vec<usize> = (0..prims.len()).collect();
+        let mut tree = vec![];
+        let root = BVHNode::bvh_build(&prims, &mut indices, &mut tree, BVHBuildOptions::default());
+
+        for (i, disc) in prims.iter_mut().enumerate() {
+            disc.center += Vec3::new(0.0, i as f32 * 0.5, 3.0);
         }
+        let primitive_ids: Vec<u32> = indices.iter().map(|i| *i as u32).collect();
+        let node_count = tree.len();
+        BVHNode::bvh_refit(&prims, &primitive_ids, &mut tree, 0..node_count);
+        check_tree(&prims, &indices, &tree, root as usize);
+    }
+
+    #[test]
+    fn sah_splits_are_no_worse_than_median_splits() {
+        let prims = discs(400);
+        let cost = |sah_bins| {
+            let mut indices: Vec<usize> = (0..prims.len()).collect();
+            let mut tree = vec![];
+            let options = BVHBuildOptions { sah_bins, ..Default::default() };
+            BVHNode::bvh_build(&prims, &mut indices, &mut tree, options);
+            BVHNode::sah_cost(&tree)
+        };
+        assert!(cost(16) <= cost(0), "sah {} median {}", cost(16), cost(0));
+    }
+
+    fn material_from_json(json: &str) -> Material {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn legacy_materials_are_converted() {
+        let glass = material_from_json(r#"{"color": [1, 1, 1], "roughness_or_ior": -1.33}"#);
+        assert_eq!(glass.get_type(), MaterialType::Dielectric);
+        assert_eq!(glass.ior, 1.33);
+
+        let metal = material_from_json(r#"{"color": [1, 0, 0], "roughness_or_ior": 0.25}"#);
+        assert_eq!(metal.get_type(), MaterialType::Metal);
+        assert_eq!(metal.roughness, 0.25);
+
+        let diffuse = material_from_json(r#"{"roughness_or_ior": 1.0, "emission_strength": 3.0}"#);
+        assert_eq!(diffuse.get_type(), MaterialType::Diffuse);
+        assert_eq!(diffuse.emission_strength, 3.0);
+
+        let volume = material_from_json(r#"{"roughness_or_ior": 1.0, "volume_density": 0.5}"#);
+        assert_eq!(volume.get_type(), MaterialType::Volume);
+        assert_eq!(volume.volume_density, 0.5);
+    }
+
+    #[test]
+    fn materials_round_trip_without_the_legacy_field() {
+        let mut material = Material::dielectric(Vec3::new(0.9, 0.8, 0.7), 1.7);
+        material.emission_strength = 2.0;
+        let json = serde_json::to_string(&material).unwrap();
+        assert!(!json.contains("roughness_or_ior"), "{}", json);
+
+        let loaded = material_from_json(&json);
+        assert_eq!(loaded.get_type(), MaterialType::Dielectric);
+        assert_eq!(loaded.ior, 1.7);
+        assert_eq!(loaded.emission_strength, 2.0);
+
+        // every field has a default
+        assert_eq!(material_from_json("{}").get_type(), MaterialType::Diffuse);
     }
 }
@@ -1,8 +1,9 @@
 use {
-    bytemuck::{Pod, Zeroable}, core::f32, std::ops
+    bytemuck::{Pod, Zeroable}, core::f32, serde::{Deserialize, Serialize}, std::ops
 };
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(transparent)]
 #[repr(C)]
 pub struct Vec3([f32; 3]);
 
@@ -12,8 +13,14 @@ impl Default for Vec3 {
     }
 }
 
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        Vec3(v)
+    }
+}
+
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Vec3 {
         Vec3([x, y, z])
     }
 